@@ -19,7 +19,9 @@ use std::str::FromStr;
 // use std::sync::Arc;
 // extern crate clap;
 use akari::film::Film;
+use akari::integrator::Integrator;
 use akari::profile_fn;
+use akari::scene::Scene;
 use akari::{api, rayon};
 // use clap::{App, Arg};
 
@@ -35,8 +37,55 @@ struct AppOptions {
     pub scene: Option<String>,
     pub algorithm: Option<String>,
     pub accel: Option<String>,
+    pub frames: Option<FrameRange>,
     pub launch_as_remote: bool,
 }
+// inclusive range of frames to render, written as `first:last` or as a single frame
+#[derive(Debug, Clone, Copy)]
+struct FrameRange {
+    first: i32,
+    last: i32,
+}
+impl FromStr for FrameRange {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |x: &str| {
+            x.trim()
+                .parse::<i32>()
+                .map_err(|_| format!("invalid frame number {}", x))
+        };
+        let (first, last) = match s.split_once(':') {
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None => (parse(s)?, parse(s)?),
+        };
+        if first > last {
+            return Err(format!("frame range {} is empty", s));
+        }
+        Ok(Self { first, last })
+    }
+}
+// expands the printf-style frame number in `pattern`, e.g. `out_%04d.exr` -> `out_0012.exr`
+fn frame_output(pattern: &str, frame: i32) -> Option<String> {
+    let start = pattern.find('%')?;
+    let rest = &pattern[start + 1..];
+    let end = rest.find('d')?;
+    let width = &rest[..end];
+    if !width.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let width = if width.is_empty() {
+        0
+    } else {
+        width.parse().ok()?
+    };
+    Some(format!(
+        "{}{:0width$}{}",
+        &pattern[..start],
+        frame,
+        &rest[end + 1..],
+        width = width
+    ))
+}
 
 fn usage() -> String {
     let mut s = String::new();
@@ -45,6 +94,15 @@ fn usage() -> String {
 
 Usage: 
     akr-cli [OPTIONS] -s <SCENE FILE>
+    akr-cli validate <SCENE FILE>...
+    akr-cli pack <SCENE FILE> <BUNDLE FILE>
+
+Commands:
+    validate                reports every problem found in the scene files and
+                            their includes without rendering, exits with 1 if any
+    pack                    writes the scene, its includes and all meshes and
+                            textures into a single bundle file, which can be
+                            passed to --scene and validate like a scene file
 
 Options:
 
@@ -56,6 +114,8 @@ Options:
                             must be suppied unless --resume is supplied
     -a, --as name           acceleration structure, one of ('embree', 'bvh', 'qbvh')
     -o, --output file       output file, overrides settings in <RENDEDER FILE>
+                            with --frames, a pattern such as out_%04d.exr
+    -f, --frames first:last renders frames first to last of the scene's keyframes
     -t, --threads count     specifiy number of threads
     -q, --quiet             suppress all loggings except error
     
@@ -110,19 +170,25 @@ fn render_main(options: AppOptions) {
             "bvh".into()
         });
     let ooc = OocOptions { enable_ooc: false };
-    let scene = if let Some(scene) = &options.scene {
-        let path = Path::new(scene);
-        api::load_scene::<LocalFileResolver>(path, false, accel.as_str(), ooc)
+    let scene_path = if let Some(scene) = &options.scene {
+        Path::new(scene)
     } else {
         log::error!("no filed provided");
         exit(1);
     };
-    if scene.lights.is_empty() {
-        log::error!("scene has no light!");
+    let load_error = |e: api::SceneLoadError| -> ! {
+        log::error!("failed to load scene: {}", e);
         exit(1);
-    }
+    };
     let output = options.output.clone().unwrap_or("out.png".into());
-    let mut integrator = if let Some(algorithm) = &options.algorithm {
+    if options.frames.is_some() && frame_output(&output, 0).is_none() {
+        log::error!(
+            "output {} has no frame number, use a pattern such as out_%04d.exr",
+            output
+        );
+        exit(1);
+    }
+    let integrator = if let Some(algorithm) = &options.algorithm {
         let path = Path::new(algorithm);
         api::load_integrator(path)
     } else {
@@ -131,7 +197,28 @@ fn render_main(options: AppOptions) {
     };
     log::info!("acceleration structure: {}", accel);
     log::info!("rendering with {} threads", rayon::current_num_threads());
-    let (film, time) = profile_fn(|| -> Film { integrator.as_mut().render(&scene) });
+    if let Some(frames) = options.frames {
+        let mut animation =
+            api::load_animation(scene_path, accel.as_str(), ooc).unwrap_or_else(|e| load_error(e));
+        for frame in frames.first..=frames.last {
+            let scene = animation.scene_at(frame).unwrap_or_else(|e| load_error(e));
+            log::info!("rendering frame {}", frame);
+            let output = frame_output(&output, frame).unwrap();
+            render_scene(&scene, integrator.as_ref(), &output);
+        }
+    } else {
+        let scene = api::load_scene::<LocalFileResolver>(scene_path, false, accel.as_str(), ooc)
+            .unwrap_or_else(|e| load_error(e));
+        render_scene(&scene, integrator.as_ref(), &output);
+    }
+}
+fn render_scene(scene: &Scene, integrator: &dyn Integrator, output: &str) {
+    if scene.lights.is_empty() {
+        log::error!("scene has no light!");
+        exit(1);
+    }
+    let (mut film, time) = profile_fn(|| -> Film { integrator.render(scene) });
+    film.apply_mask(scene.camera.as_ref());
     log::info!("took {}s", time);
     log::info!(
         "traced {} rays, average {}M rays/s",
@@ -143,6 +230,9 @@ fn render_main(options: AppOptions) {
     // }
     if output.ends_with(".exr") {
         film.write_exr(&output);
+    } else if film.is_masked() {
+        let image = film.to_rgba_image();
+        image.save(output).unwrap();
     } else {
         let image = film.to_rgb_image();
         image.save(output).unwrap();
@@ -188,6 +278,13 @@ fn parse_options(args: Vec<String>) -> AppOptions {
             options.algorithm = Some(render);
         } else if let Some(threads) = parse_int!("--threads", "-t") {
             options.num_threads = Some(threads);
+        } else if let Some(output) = parse_str!("--output", "-o") {
+            options.output = Some(output);
+        } else if let Some(frames) =
+            parse_arg::<FrameRange>(&args, &mut pos, "--frames", Some("-f"))
+                .unwrap_or_else(on_err!())
+        {
+            options.frames = Some(frames);
         } else {
             eprintln!("unrecognized option {}", args[pos]);
             exit(-1);
@@ -195,7 +292,59 @@ fn parse_options(args: Vec<String>) -> AppOptions {
     }
     options
 }
+fn validate_main(scenes: &[String]) {
+    if scenes.is_empty() {
+        eprintln!("no scene file provided");
+        println!("{}", usage());
+        exit(-1);
+    }
+    let mut failed = false;
+    for scene in scenes {
+        match api::validate_scene(Path::new(scene)) {
+            Ok(problems) if problems.is_empty() => println!("{}: ok", scene),
+            Ok(problems) => {
+                failed = true;
+                for problem in problems {
+                    println!("{}: {}", scene, problem);
+                }
+            }
+            Err(e) => {
+                failed = true;
+                println!("{}: {}", scene, e);
+            }
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
+fn pack_main(args: &[String]) {
+    let (scene, bundle) = match args {
+        [scene, bundle] => (scene, bundle),
+        _ => {
+            eprintln!("expected a scene file and a bundle file");
+            println!("{}", usage());
+            exit(-1);
+        }
+    };
+    akari::init(Config::default());
+    if let Err(e) = api::pack_scene(Path::new(scene), Path::new(bundle)) {
+        log::error!("failed to pack scene: {}", e);
+        exit(1);
+    }
+}
 fn real_main(args: Vec<String>) {
+    match args.first().map(|s| s.as_str()) {
+        Some("validate") => {
+            validate_main(&args[1..]);
+            return;
+        }
+        Some("pack") => {
+            pack_main(&args[1..]);
+            return;
+        }
+        _ => {}
+    }
     let options = parse_options(args);
     render_main(options);
 }
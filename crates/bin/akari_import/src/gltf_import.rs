@@ -0,0 +1,540 @@
+use crate::{default_principled, merge_bsdfs, trs_from_matrix, write_mesh};
+use akari::scenegraph::node;
+use akari::shape::TriangleMesh;
+use akari::*;
+use gltf::camera::Projection;
+use gltf::khr_lights_punctual::Kind;
+use gltf::mesh::Mode;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::exit;
+
+/* glTF 2.0 import
+ * every primitive becomes a .mesh file next to the model, written once however often it is instanced,
+ * nodes become shapes placed by their world transform
+ * light intensities are copied as is, glTF uses photometric units
+ */
+
+struct GltfImporter<'a> {
+    dir: &'a Path,
+    stem: String,
+    buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
+    // by image index, None if the image could not be extracted
+    image_paths: HashMap<usize, Option<String>>,
+    // by (mesh, primitive)
+    mesh_paths: HashMap<(usize, usize), String>,
+    // by material index, None for the default material
+    material_names: HashMap<Option<usize>, String>,
+    bsdfs: HashMap<String, node::Bsdf>,
+    shapes: Vec<node::Shape>,
+    lights: Vec<node::Light>,
+    camera: Option<node::Camera>,
+    res: (u32, u32),
+}
+
+// glTF uv origin is the top left corner, image textures flip v
+fn flip_v(uv: [f32; 2]) -> [f32; 2] {
+    [uv[0], 1.0 - uv[1]]
+}
+
+impl<'a> GltfImporter<'a> {
+    fn image_path(&mut self, image: gltf::Image) -> Option<String> {
+        if let Some(path) = self.image_paths.get(&image.index()) {
+            return path.clone();
+        }
+        let path = match image.source() {
+            gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                Some(uri.replace("%20", " ").replace("\\", "/"))
+            }
+            gltf::image::Source::View { view, mime_type } => {
+                let ext = match mime_type {
+                    "image/jpeg" => "jpg",
+                    _ => "png",
+                };
+                let name = format!("{}_image_{}.{}", self.stem, image.index(), ext);
+                let data = &self.buffers[view.buffer().index()];
+                std::fs::write(
+                    self.dir.join(&name),
+                    &data[view.offset()..view.offset() + view.length()],
+                )
+                .unwrap();
+                Some(name)
+            }
+            // embedded as a data uri, saved from the decoded pixels
+            gltf::image::Source::Uri { .. } => {
+                let name = format!("{}_image_{}.png", self.stem, image.index());
+                let data = &self.images[image.index()];
+                let (w, h, pixels) = (data.width, data.height, data.pixels.clone());
+                let saved = match data.format {
+                    gltf::image::Format::R8G8B8 => ::image::RgbImage::from_raw(w, h, pixels)
+                        .map(|img| img.save(self.dir.join(&name))),
+                    gltf::image::Format::R8G8B8A8 => ::image::RgbaImage::from_raw(w, h, pixels)
+                        .map(|img| img.save(self.dir.join(&name))),
+                    gltf::image::Format::R8 => ::image::GrayImage::from_raw(w, h, pixels)
+                        .map(|img| img.save(self.dir.join(&name))),
+                    _ => None,
+                };
+                match saved {
+                    Some(Ok(())) => Some(name),
+                    _ => {
+                        println!(
+                            "warning: cannot save embedded image {} in format {:?}, ignored",
+                            image.index(),
+                            data.format
+                        );
+                        None
+                    }
+                }
+            }
+        };
+        self.image_paths.insert(image.index(), path.clone());
+        path
+    }
+    fn texture_path(&mut self, info: &gltf::texture::Info, what: &str) -> Option<String> {
+        if info.tex_coord() != 0 {
+            println!(
+                "warning: {} uses texcoord set {}, only set 0 is imported",
+                what,
+                info.tex_coord()
+            );
+        }
+        self.image_path(info.texture().source())
+    }
+    fn convert_material(&mut self, material: &gltf::Material) -> String {
+        if let Some(name) = self.material_names.get(&material.index()) {
+            return name.clone();
+        }
+        let base = match (material.index(), material.name()) {
+            (None, _) => format!("{}_default", self.stem),
+            (Some(_), Some(name)) if !name.is_empty() => name.to_string(),
+            (Some(i), _) => format!("{}_material_{}", self.stem, i),
+        };
+        // glTF material names are not unique
+        let mut name = base.clone();
+        let mut i = 1;
+        while self.material_names.values().any(|n| *n == name) {
+            i += 1;
+            name = format!("{}_{}", base, i);
+        }
+        self.material_names.insert(material.index(), name.clone());
+        let pbr = material.pbr_metallic_roughness();
+        let mut bsdf = default_principled();
+        match &mut bsdf {
+            node::Bsdf::Principled {
+                color,
+                metallic,
+                roughness,
+                specular,
+                ior,
+                transmission,
+                emission,
+                ..
+            } => {
+                let base = pbr.base_color_factor();
+                *color = match pbr.base_color_texture() {
+                    Some(info) => {
+                        if base[..3].iter().any(|x| *x != 1.0) {
+                            println!(
+                                "warning: {}: base color factor is ignored for textured base color",
+                                name
+                            );
+                        }
+                        match self.texture_path(&info, &name) {
+                            Some(path) => node::SpectrumTexture::Image {
+                                path,
+                                colorspace: "srgb".into(),
+                                cache: None,
+                            },
+                            None => node::SpectrumTexture::SRgbLinear {
+                                values: [base[0], base[1], base[2]],
+                            },
+                        }
+                    }
+                    None => node::SpectrumTexture::SRgbLinear {
+                        values: [base[0], base[1], base[2]],
+                    },
+                };
+                *metallic = node::FloatTexture::Float(pbr.metallic_factor());
+                *roughness = node::FloatTexture::Float(pbr.roughness_factor());
+                // roughness in green, metallic in blue, scaled by the factors
+                if let Some(info) = pbr.metallic_roughness_texture() {
+                    if let Some(path) = self.texture_path(&info, &name) {
+                        if pbr.metallic_factor() != 0.0 {
+                            *metallic = node::FloatTexture::CachedImage {
+                                path: path.clone(),
+                                cache: None,
+                                channel: Some(node::ImageChannel::B),
+                            };
+                        }
+                        if pbr.roughness_factor() != 0.0 {
+                            *roughness = node::FloatTexture::CachedImage {
+                                path,
+                                cache: None,
+                                channel: Some(node::ImageChannel::G),
+                            };
+                        }
+                        if ![0.0, 1.0].contains(&pbr.metallic_factor())
+                            || ![0.0, 1.0].contains(&pbr.roughness_factor())
+                        {
+                            println!(
+                                "warning: {}: metallic/roughness factors are ignored for textured metallic/roughness",
+                                name
+                            );
+                        }
+                    }
+                }
+                // glTF dielectrics reflect 4% at normal incidence
+                *specular = node::FloatTexture::Float(0.5);
+                *ior = node::FloatTexture::Float(material.ior().unwrap_or(1.5));
+                if let Some(t) = material.transmission() {
+                    *transmission = node::FloatTexture::Float(t.transmission_factor());
+                }
+                let factor = material.emissive_factor();
+                if factor.iter().any(|x| *x > 0.0) {
+                    *emission = match material
+                        .emissive_texture()
+                        .and_then(|info| self.texture_path(&info, &name))
+                    {
+                        Some(path) => {
+                            if factor.iter().any(|x| *x != 1.0) {
+                                println!(
+                                    "warning: {}: emissive factor is ignored for textured emission",
+                                    name
+                                );
+                            }
+                            node::SpectrumTexture::Image {
+                                path,
+                                colorspace: "srgb".into(),
+                                cache: None,
+                            }
+                        }
+                        None => node::SpectrumTexture::SRgbLinear { values: factor },
+                    };
+                }
+                if material.normal_texture().is_some() {
+                    println!("warning: {}: normal texture is ignored", name);
+                }
+                if material.alpha_mode() != gltf::material::AlphaMode::Opaque {
+                    println!("warning: {}: alpha mode is ignored", name);
+                }
+            }
+            _ => unreachable!(),
+        }
+        self.bsdfs.insert(name.clone(), bsdf);
+        name
+    }
+    // writes the primitive on first use and returns the path of its .mesh file
+    fn convert_primitive(
+        &mut self,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+    ) -> Option<String> {
+        let key = (mesh.index(), primitive.index());
+        if let Some(path) = self.mesh_paths.get(&key) {
+            return Some(path.clone());
+        }
+        let mesh_name = match mesh.name() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("mesh{}", mesh.index()),
+        };
+        if primitive.mode() != Mode::Triangles {
+            println!(
+                "warning: {} primitive {} is not a triangle list, ignored",
+                mesh_name,
+                primitive.index()
+            );
+            return None;
+        }
+        let buffers = &self.buffers;
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let vertices: Vec<[f32; 3]> = match reader.read_positions() {
+            Some(positions) => positions.collect(),
+            None => {
+                println!(
+                    "warning: {} primitive {} has no positions, ignored",
+                    mesh_name,
+                    primitive.index()
+                );
+                return None;
+            }
+        };
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        let indices: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|f| [f[0], f[1], f[2]])
+            .collect();
+        let normals: Vec<[f32; 3]> = reader
+            .read_normals()
+            .map_or(vec![], |normals| normals.collect());
+        let texcoords: Vec<[f32; 2]> = reader
+            .read_tex_coords(0)
+            .map_or(vec![], |uv| uv.into_f32().map(flip_v).collect());
+        let imported = TriangleMesh {
+            name: mesh_name.clone(),
+            vertices,
+            normal_indices: if normals.is_empty() {
+                vec![]
+            } else {
+                indices.clone()
+            },
+            normals,
+            texcoord_indices: if texcoords.is_empty() {
+                vec![]
+            } else {
+                indices.clone()
+            },
+            texcoords,
+            indices,
+            colors: vec![],
+        };
+        // mesh names are neither unique nor safe to use in file names
+        let path = format!(
+            "{}_mesh{}_{}.mesh",
+            self.stem,
+            mesh.index(),
+            primitive.index()
+        );
+        write_mesh(&self.dir.join(&path), &imported);
+        self.mesh_paths.insert(key, path.clone());
+        Some(path)
+    }
+    fn convert_camera(&mut self, camera: &gltf::Camera, world: Mat4) {
+        if self.camera.is_some() {
+            println!(
+                "warning: camera {} ignored, only the first camera is imported",
+                camera.index()
+            );
+            return;
+        }
+        let (trs, _) = trs_from_matrix(world);
+        let transform = node::Transform::TRS(trs);
+        self.camera = Some(match camera.projection() {
+            Projection::Perspective(p) => {
+                let aspect = p
+                    .aspect_ratio()
+                    .unwrap_or(self.res.0 as f32 / self.res.1 as f32);
+                let res = ((self.res.1 as f32 * aspect).round() as u32, self.res.1);
+                // the field of view spans the longer side of the film
+                let fov = if aspect > 1.0 {
+                    2.0 * ((0.5 * p.yfov()).tan() * aspect).atan()
+                } else {
+                    p.yfov()
+                };
+                node::Camera::Perspective {
+                    res,
+                    fov: fov.to_degrees(),
+                    lens_radius: 0.0,
+                    focal: 1.0,
+                    transform,
+                    shutter: (0.0, 0.0),
+                }
+            }
+            Projection::Orthographic(o) => {
+                let aspect = o.xmag() / o.ymag();
+                node::Camera::Orthographic {
+                    res: ((self.res.1 as f32 * aspect).round() as u32, self.res.1),
+                    width: 2.0 * o.xmag(),
+                    transform,
+                    shutter: (0.0, 0.0),
+                }
+            }
+        });
+    }
+    fn convert_light(&mut self, light: &gltf::khr_lights_punctual::Light, world: Mat4) {
+        let emission = node::SpectrumTexture::SRgbLinear {
+            values: (Vec3::from(light.color()) * light.intensity()).into(),
+        };
+        if light.range().is_some() {
+            println!("warning: range of light {} is ignored", light.index());
+        }
+        let (trs, _) = trs_from_matrix(world);
+        self.lights.push(match light.kind() {
+            Kind::Point => node::Light::Point {
+                pos: trs.translate,
+                emission,
+            },
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => node::Light::Spot {
+                transform: node::Transform::TRS(trs),
+                emission,
+                falloff: inner_cone_angle.to_degrees(),
+                max_angle: outer_cone_angle.to_degrees(),
+            },
+            Kind::Directional => node::Light::Directional {
+                direction: world
+                    .transform_vector3(vec3(0.0, 0.0, -1.0))
+                    .normalize()
+                    .into(),
+                emission,
+                angular_diameter: 0.0,
+            },
+        });
+    }
+    fn convert_node(&mut self, node: &gltf::Node, parent: Mat4) {
+        let world = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            let (trs, exact) = trs_from_matrix(world);
+            if !exact {
+                println!(
+                    "warning: node {} is sheared, its transform is approximated",
+                    node.index()
+                );
+            }
+            let transform = if world == Mat4::IDENTITY {
+                None
+            } else {
+                Some(node::Transform::TRS(trs))
+            };
+            for primitive in mesh.primitives() {
+                if let Some(path) = self.convert_primitive(&mesh, &primitive) {
+                    let bsdf = self.convert_material(&primitive.material());
+                    self.shapes.push(node::Shape::Mesh {
+                        path,
+                        bsdf,
+                        transform: transform.clone(),
+                    });
+                }
+            }
+        }
+        if let Some(camera) = node.camera() {
+            self.convert_camera(&camera, world);
+        }
+        if let Some(light) = node.light() {
+            self.convert_light(&light, world);
+        }
+        if node.skin().is_some() {
+            println!(
+                "warning: skin of node {} is ignored, meshes are imported in bind pose",
+                node.index()
+            );
+        }
+        for child in node.children() {
+            self.convert_node(&child, world);
+        }
+    }
+}
+
+pub(crate) fn import_gltf(path: &str, scene: &mut node::Scene, forced: bool) {
+    let (document, buffers, images) = match gltf::import(path) {
+        Ok(imported) => imported,
+        Err(e) => {
+            eprintln!("failed to load {}: {}", path, e);
+            exit(1);
+        }
+    };
+    let model_path = Path::new(path);
+    let res = match &scene.camera {
+        Some(node::Camera::Perspective { res, .. })
+        | Some(node::Camera::Orthographic { res, .. }) => *res,
+        _ => (512, 512),
+    };
+    let mut importer = GltfImporter {
+        dir: model_path.parent().unwrap(),
+        stem: model_path.file_stem().unwrap().to_str().unwrap().into(),
+        buffers,
+        images,
+        image_paths: HashMap::new(),
+        mesh_paths: HashMap::new(),
+        material_names: HashMap::new(),
+        bsdfs: HashMap::new(),
+        shapes: vec![],
+        lights: vec![],
+        camera: None,
+        res,
+    };
+    let gltf_scene = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(s) => s,
+        None => {
+            eprintln!("{} has no scene", path);
+            exit(1);
+        }
+    };
+    for node in gltf_scene.nodes() {
+        importer.convert_node(&node, Mat4::IDENTITY);
+    }
+    if document.animations().next().is_some() {
+        println!("warning: animations are ignored");
+    }
+    println!("# of meshes: {}", importer.mesh_paths.len());
+    println!("# of materials: {}", importer.bsdfs.len());
+    println!("# of lights: {}", importer.lights.len());
+    merge_bsdfs(scene, &importer.bsdfs, forced);
+    scene.shapes.extend(importer.shapes);
+    scene.lights.extend(importer.lights);
+    if let Some(camera) = importer.camera {
+        scene.camera = Some(camera);
+    }
+}
+
+mod test {
+    #[test]
+    fn test_gltf_duplicate_names() {
+        use super::*;
+        use akari::util::binserde::Decode;
+        let dir = akari::util::TempDirGuard::new("gltf");
+        // two triangles of different sizes, meshes and materials share their names
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{
+                "byteLength": 72,
+                "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAAAAAAAAQAAAAAAAAAAAAAAAAAAAAEAAAAAA"
+            }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 72 }],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [2, 2, 0] }
+            ],
+            "materials": [
+                { "name": "paint", "pbrMetallicRoughness": { "baseColorFactor": [1, 0, 0, 1] } },
+                { "name": "paint", "pbrMetallicRoughness": { "baseColorFactor": [0, 0, 1, 1] } }
+            ],
+            "meshes": [
+                { "name": "part", "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] },
+                { "name": "part", "primitives": [{ "attributes": { "POSITION": 1 }, "material": 1 }] }
+            ],
+            "nodes": [{ "mesh": 0 }, { "mesh": 1 }, { "mesh": 0 }],
+            "scenes": [{ "nodes": [0, 1, 2] }]
+        }"#;
+        let path = dir.join("dup.gltf");
+        std::fs::write(&path, gltf).unwrap();
+        let mut scene = node::Scene::default();
+        import_gltf(path.to_str().unwrap(), &mut scene, false);
+        let shapes: Vec<(&String, &String)> = scene
+            .shapes
+            .iter()
+            .map(|s| match s {
+                node::Shape::Mesh { path, bsdf, .. } => (path, bsdf),
+            })
+            .collect();
+        assert_eq!(shapes.len(), 3);
+        assert_ne!(shapes[0].0, shapes[1].0);
+        assert_eq!(shapes[0].0, shapes[2].0);
+        assert_ne!(shapes[0].1, shapes[1].1);
+        assert_eq!(shapes[0].1, shapes[2].1);
+        let extent = |path: &String| {
+            let mut file = std::fs::File::open(dir.join(path)).unwrap();
+            TriangleMesh::decode(&mut file).unwrap().vertices[1][0]
+        };
+        assert_eq!(extent(shapes[0].0), 1.0);
+        assert_eq!(extent(shapes[1].0), 2.0);
+        assert_eq!(scene.bsdfs.len(), 2);
+        match &scene.bsdfs[shapes[1].1] {
+            node::Bsdf::Principled {
+                color: node::SpectrumTexture::SRgbLinear { values },
+                ..
+            } => assert_eq!(*values, [0.0, 0.0, 1.0]),
+            _ => panic!("base color not imported"),
+        }
+    }
+}
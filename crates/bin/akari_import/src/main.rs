@@ -4,6 +4,7 @@ use akari::linear_to_srgb;
 // use akari::shape::*;
 use akari::scenegraph::node;
 use akari::scenegraph::node::LookAt;
+use akari::shape::TriangleMesh;
 use akari::util::binserde;
 use akari::*;
 use std::collections::HashMap;
@@ -16,13 +17,15 @@ use std::process::exit;
 // use akari::api;
 use clap::{App, Arg};
 
-fn import(path: &str, scene: &mut node::Scene, forced: bool, generate_normal: Option<f32>) {
-    let (imported_models, models, materials) = akari::shape::load_model(path, generate_normal);
-    let mut cvt_mat: HashMap<String, node::Bsdf> = HashMap::new();
-    let mut cvt_names = vec![];
-    println!("# of models: {}", models.len());
-    println!("# of materials: {}", materials.len());
-    let default_bsdf = node::Bsdf::Principled {
+mod gltf_import;
+mod mitsuba_import;
+mod mtl_import;
+mod pbrt_import;
+mod ply_import;
+
+// principled bsdf that imported materials start from
+pub(crate) fn default_principled() -> node::Bsdf {
+    node::Bsdf::Principled {
         color: node::SpectrumTexture::SRgb {
             values: [0.8, 0.8, 0.8],
         },
@@ -49,7 +52,140 @@ fn import(path: &str, scene: &mut node::Scene, forced: bool, generate_normal: Op
         emission: node::SpectrumTexture::SRgbLinear {
             values: [0.0, 0.0, 0.0],
         },
+        opacity: node::FloatTexture::Float(1.0),
+        normal_map: None,
+    }
+}
+pub(crate) fn merge_bsdfs(
+    scene: &mut node::Scene,
+    bsdfs: &HashMap<String, node::Bsdf>,
+    forced: bool,
+) {
+    let named = &mut scene.bsdfs;
+    for (k, v) in bsdfs.iter() {
+        if let Some(_) = named.insert(k.clone(), v.clone()) {
+            println!("warning! overrided previous material");
+            if forced {
+                println!("force mode, overriding");
+            } else {
+                println!("exiting, pass -f to force override");
+                exit(1);
+            }
+        }
+    }
+}
+// copy of `bsdf` that emits `emission`, for shapes carrying an area light
+// the emission needs a principled bsdf, diffuse colors are carried over
+pub(crate) fn add_emissive_bsdf(
+    bsdfs: &mut HashMap<String, node::Bsdf>,
+    bsdf: &str,
+    emission: node::SpectrumTexture,
+) -> String {
+    let mut emissive = match bsdfs.get(bsdf) {
+        Some(b @ node::Bsdf::Principled { .. }) => b.clone(),
+        Some(node::Bsdf::Diffuse { color }) => {
+            let mut b = default_principled();
+            if let node::Bsdf::Principled { color: c, .. } = &mut b {
+                *c = color.clone();
+            }
+            b
+        }
+        _ => {
+            println!(
+                "warning: area emitter on {} is imported with a diffuse bsdf",
+                bsdf
+            );
+            default_principled()
+        }
     };
+    if let node::Bsdf::Principled { emission: e, .. } = &mut emissive {
+        *e = emission;
+    }
+    let mut name = format!("{}_emissive", bsdf);
+    let mut i = 1;
+    while bsdfs.contains_key(&name) {
+        i += 1;
+        name = format!("{}_emissive_{}", bsdf, i);
+    }
+    bsdfs.insert(name.clone(), emissive);
+    name
+}
+// normal incidence reflectance of conductors by chemical symbol, linear rgb
+pub(crate) fn conductor_reflectance(name: &str) -> Option<[f32; 3]> {
+    Some(match name {
+        "Ag" => [0.972, 0.960, 0.915],
+        "Al" => [0.913, 0.922, 0.924],
+        "Au" => [1.0, 0.782, 0.344],
+        "Cr" => [0.549, 0.556, 0.554],
+        "Cu" => [0.955, 0.638, 0.538],
+        "Fe" => [0.562, 0.565, 0.578],
+        "Ni" => [0.660, 0.609, 0.526],
+        "Pt" => [0.673, 0.637, 0.585],
+        "Ti" => [0.542, 0.497, 0.449],
+        "W" => [0.504, 0.498, 0.478],
+        _ => return None,
+    })
+}
+pub(crate) fn write_mesh(path: &Path, mesh: &TriangleMesh) {
+    let mut file = BufWriter::new(File::create(path).unwrap());
+    binserde::Encode::encode(mesh, &mut file).unwrap();
+}
+// translation, euler angles in degrees and scale of an affine matrix, in the order
+// `load_transform` composes them for `CoordinateSystem::Akari`: T * Ry * Rx * Rz * S
+// the flag is false when the matrix has shear and cannot be reproduced exactly
+pub(crate) fn trs_from_matrix(m: Mat4) -> (node::TRS, bool) {
+    let translate = m.w_axis.truncate();
+    let linear = Mat3::from_cols(
+        m.x_axis.truncate(),
+        m.y_axis.truncate(),
+        m.z_axis.truncate(),
+    );
+    let mut scale = vec3(
+        linear.x_axis.length(),
+        linear.y_axis.length(),
+        linear.z_axis.length(),
+    );
+    if linear.determinant() < 0.0 {
+        scale.x = -scale.x;
+    }
+    let r = Mat3::from_cols(
+        linear.x_axis / scale.x,
+        linear.y_axis / scale.y,
+        linear.z_axis / scale.z,
+    );
+    // r[row][col] = r.col(col)[row]
+    let sx = (-r.z_axis.y).clamp(-1.0, 1.0);
+    let x = sx.asin();
+    let (y, z) = if sx.abs() < 0.9999 {
+        (r.z_axis.x.atan2(r.z_axis.z), r.x_axis.y.atan2(r.y_axis.y))
+    } else {
+        // gimbal lock, the rotation around z is folded into y
+        ((-r.x_axis.z).atan2(r.x_axis.x), 0.0)
+    };
+    let rotation = Mat3::from_rotation_y(y) * Mat3::from_rotation_x(x) * Mat3::from_rotation_z(z);
+    let exact = (rotation * Mat3::from_diagonal(scale) - linear)
+        .to_cols_array()
+        .iter()
+        .all(|d| d.abs() < 1e-4 * scale.abs().max_element().max(1.0));
+    (
+        node::TRS {
+            translate: translate.into(),
+            rotate: [x.to_degrees(), y.to_degrees(), z.to_degrees()],
+            scale: scale.into(),
+            coord_sys: node::CoordinateSystem::Akari,
+        },
+        exact,
+    )
+}
+fn import(path: &str, scene: &mut node::Scene, forced: bool, generate_normal: Option<f32>) {
+    let (imported_models, models, materials) = akari::shape::load_model(path, generate_normal);
+    let mut cvt_mat: HashMap<String, node::Bsdf> = HashMap::new();
+    let mut cvt_names = vec![];
+    println!("# of models: {}", models.len());
+    println!("# of materials: {}", materials.len());
+    let default_bsdf = default_principled();
+    let mtl_fields = mtl_import::read_mtl_fields(Path::new(path));
+    let obj_dir = Path::new(path).parent().unwrap();
     for (i, m) in materials.iter().enumerate() {
         let name = if m.name.is_empty() {
             let path = Path::new(path);
@@ -146,6 +282,16 @@ fn import(path: &str, scene: &mut node::Scene, forced: bool, generate_normal: Op
             }
             _ => unreachable!(),
         };
+        if let Some(fields) = mtl_fields.get(&m.name) {
+            let ignored = mtl_import::apply_mtl_fields(&mut bsdf, fields, obj_dir);
+            if !ignored.is_empty() {
+                println!(
+                    "warning: material {}: ignored MTL fields: {}",
+                    name,
+                    ignored.join(", ")
+                );
+            }
+        }
         cvt_mat.insert(name.clone(), bsdf);
     }
     let mut cvt_models = vec![];
@@ -164,10 +310,7 @@ fn import(path: &str, scene: &mut node::Scene, forced: bool, generate_normal: Op
         //     let mut file = File::create(model_path).unwrap();
         //     bson_data.to_writer(&mut file).unwrap();
         // }
-        {
-            let mut file = File::create(model_path).unwrap();
-            binserde::Encode::encode(&imported_models[i], &mut file).unwrap();
-        }
+        write_mesh(&model_path, &imported_models[i]);
         let j: node::Shape = if let Some(id) = mesh.material_id {
             node::Shape::Mesh {
                 path: model_name,
@@ -183,20 +326,7 @@ fn import(path: &str, scene: &mut node::Scene, forced: bool, generate_normal: Op
         };
         cvt_models.push(j)
     }
-    {
-        let named = &mut scene.bsdfs;
-        for (k, v) in cvt_mat.iter() {
-            if let Some(_) = named.insert(k.clone(), v.clone()) {
-                println!("warning! overrided previous material");
-                if forced {
-                    println!("force mode, overriding");
-                } else {
-                    println!("exiting, pass -f to force override");
-                    exit(1);
-                }
-            }
-        }
-    }
+    merge_bsdfs(scene, &cvt_mat, forced);
     {
         for shape in cvt_models.into_iter() {
             scene.shapes.push(shape);
@@ -258,33 +388,47 @@ fn main() {
                 serde_json::from_value(json).unwrap()
             }
             Err(_) => node::Scene {
+                include: vec![],
+                groups: vec![],
                 bsdfs: HashMap::new(),
                 lights: vec![],
                 shapes: vec![],
-                camera: node::Camera::Perspective {
+                shaders: HashMap::new(),
+                camera: Some(node::Camera::Perspective {
                     res: (512, 512),
                     fov: 80.0,
                     focal: 1.0,
                     lens_radius: 0.0,
+                    shutter: (0.0, 0.0),
                     transform: node::Transform::LookAt(LookAt {
                         eye: [0.0, 0.0, 0.0],
                         center: [0.0, 0.0, -1.0],
                         up: [0.0, 1.0, 0.0],
                     }),
-                },
+                }),
             },
         }
     };
-    import(
-        matches.value_of("model").unwrap(),
-        &mut scene,
-        forced,
-        if generate_normal {
-            Some(face_angle)
-        } else {
-            None
-        },
-    );
+    let model = matches.value_of("model").unwrap();
+    let ext = Path::new(model).extension().map_or(String::new(), |ext| {
+        ext.to_string_lossy().to_ascii_lowercase()
+    });
+    match ext.as_str() {
+        "gltf" | "glb" => gltf_import::import_gltf(model, &mut scene, forced),
+        "ply" => ply_import::import_ply(model, &mut scene, forced),
+        "xml" => mitsuba_import::import_mitsuba(model, &mut scene, forced),
+        "pbrt" => pbrt_import::import_pbrt(model, &mut scene, forced),
+        _ => import(
+            model,
+            &mut scene,
+            forced,
+            if generate_normal {
+                Some(face_angle)
+            } else {
+                None
+            },
+        ),
+    }
     {
         let path = matches.value_of("scene").unwrap();
         let file = File::create(path).unwrap();
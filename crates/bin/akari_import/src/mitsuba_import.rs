@@ -0,0 +1,1320 @@
+use crate::ply_import::read_ply;
+use crate::{
+    add_emissive_bsdf, conductor_reflectance, default_principled, merge_bsdfs, trs_from_matrix,
+    write_mesh,
+};
+use akari::flate2::read::ZlibDecoder;
+use akari::scenegraph::node;
+use akari::shape::TriangleMesh;
+use akari::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/* Mitsuba 3 XML import
+ * shapes are converted to .mesh files next to the scene, area emitters become the emission of
+ * a principled copy of the shape's bsdf
+ * mitsuba cameras and spot lights look down +z, akari ones down -z
+ */
+
+type XmlNode<'a> = roxmltree::Node<'a, 'a>;
+
+// Mitsuba's default film is 768x576
+const DEFAULT_RES: (u32, u32) = (768, 576);
+// nesting limit of <include>, deeper files are skipped like recursive ones
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+fn named_ior(name: &str) -> Option<f32> {
+    Some(match name {
+        "vacuum" => 1.0,
+        "helium" => 1.00004,
+        "hydrogen" => 1.00013,
+        "air" => 1.00028,
+        "carbon dioxide" => 1.00045,
+        "water" => 1.3330,
+        "acetone" => 1.36,
+        "ethanol" => 1.361,
+        "carbon tetrachloride" => 1.461,
+        "glycerol" => 1.4729,
+        "benzene" => 1.501,
+        "silicone oil" => 1.52045,
+        "bromine" => 1.661,
+        "water ice" => 1.31,
+        "fused quartz" => 1.458,
+        "pyrex" => 1.470,
+        "acrylic glass" => 1.49,
+        "polypropylene" => 1.49,
+        "bk7" => 1.5046,
+        "sodium chloride" => 1.544,
+        "amber" => 1.55,
+        "pet" => 1.5750,
+        "diamond" => 2.419,
+        _ => return None,
+    })
+}
+
+fn parse_floats(s: &str) -> Option<Vec<f32>> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse().ok())
+        .collect()
+}
+
+fn is_hdr(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    path.ends_with(".exr") || path.ends_with(".hdr") || path.ends_with(".pfm")
+}
+
+// 4 vertices in the xy plane spanning [-1, 1], facing +z
+fn rectangle() -> TriangleMesh {
+    TriangleMesh {
+        name: "rectangle".into(),
+        vertices: vec![
+            [-1.0, -1.0, 0.0],
+            [1.0, -1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [-1.0, 1.0, 0.0],
+        ],
+        normals: vec![[0.0, 0.0, 1.0]],
+        texcoords: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+        indices: vec![[0, 1, 2], [0, 2, 3]],
+        normal_indices: vec![[0, 0, 0], [0, 0, 0]],
+        texcoord_indices: vec![[0, 1, 2], [0, 2, 3]],
+        colors: vec![],
+    }
+}
+
+// [-1, 1]^3, one quad per face
+fn cube() -> TriangleMesh {
+    let mut mesh = TriangleMesh {
+        name: "cube".into(),
+        vertices: vec![],
+        normals: vec![],
+        texcoords: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+        indices: vec![],
+        normal_indices: vec![],
+        texcoord_indices: vec![],
+        colors: vec![],
+    };
+    for axis in 0..3 {
+        for sign in [-1.0f32, 1.0] {
+            let mut n = Vec3::ZERO;
+            n[axis] = sign;
+            // tangent frame with u x v = n
+            let mut u = Vec3::ZERO;
+            u[(axis + 1) % 3] = sign;
+            let v = n.cross(u);
+            let base = mesh.vertices.len() as u32;
+            for (a, b) in [(-1.0f32, -1.0f32), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                mesh.vertices.push((n + a * u + b * v).into());
+            }
+            let normal = mesh.normals.len() as u32;
+            mesh.normals.push(n.into());
+            for face in [[0, 1, 2], [0, 2, 3]] {
+                mesh.indices
+                    .push([base + face[0], base + face[1], base + face[2]]);
+                mesh.normal_indices.push([normal; 3]);
+                mesh.texcoord_indices.push(face);
+            }
+        }
+    }
+    mesh
+}
+
+fn invalid_data(what: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+fn read_floats<R: Read>(reader: &mut R, n: usize, double: bool) -> io::Result<Vec<f32>> {
+    let size = if double { 8 } else { 4 };
+    let mut buf = vec![0u8; n * size];
+    reader.read_exact(&mut buf)?;
+    Ok(if double {
+        buf.chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+            .collect()
+    } else {
+        buf.chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect()
+    })
+}
+
+/* Mitsuba .serialized meshes
+ * every shape is a u16 magic and version followed by a zlib stream,
+ * the file ends with the offsets of the shapes and their count
+ */
+const SERIALIZED_MAGIC: u16 = 0x041C;
+const SERIALIZED_HAS_NORMALS: u32 = 0x0001;
+const SERIALIZED_HAS_TEXCOORDS: u32 = 0x0002;
+const SERIALIZED_HAS_COLORS: u32 = 0x0008;
+const SERIALIZED_DOUBLE_PRECISION: u32 = 0x2000;
+
+fn read_serialized(path: &Path, index: usize) -> io::Result<TriangleMesh> {
+    let mut file = File::open(path)?;
+    let read_header = |file: &mut File| -> io::Result<u16> {
+        let mut buf = [0u8; 4];
+        file.read_exact(&mut buf)?;
+        if u16::from_le_bytes([buf[0], buf[1]]) != SERIALIZED_MAGIC {
+            return Err(invalid_data("not a serialized mesh".into()));
+        }
+        Ok(u16::from_le_bytes([buf[2], buf[3]]))
+    };
+    let version = read_header(&mut file)?;
+    file.seek(SeekFrom::End(-4))?;
+    let count = read_u32(&mut file)? as usize;
+    if index >= count {
+        return Err(invalid_data(format!(
+            "shape_index {} out of {} shapes",
+            index, count
+        )));
+    }
+    let offset = if version >= 4 {
+        file.seek(SeekFrom::End(-4 - 8 * (count - index) as i64))?;
+        read_u64(&mut file)?
+    } else {
+        file.seek(SeekFrom::End(-4 - 4 * (count - index) as i64))?;
+        read_u32(&mut file)? as u64
+    };
+    file.seek(SeekFrom::Start(offset))?;
+    let version = read_header(&mut file)?;
+    let mut reader = ZlibDecoder::new(BufReader::new(file));
+    let flags = read_u32(&mut reader)?;
+    let mut name = vec![];
+    if version >= 4 {
+        let mut c = [0u8; 1];
+        loop {
+            reader.read_exact(&mut c)?;
+            if c[0] == 0 {
+                break;
+            }
+            name.push(c[0]);
+        }
+    }
+    let vertex_count = read_u64(&mut reader)? as usize;
+    let triangle_count = read_u64(&mut reader)? as usize;
+    let double = flags & SERIALIZED_DOUBLE_PRECISION != 0;
+    let vec3s =
+        |v: Vec<f32>| -> Vec<[f32; 3]> { v.chunks_exact(3).map(|x| [x[0], x[1], x[2]]).collect() };
+    let vertices = vec3s(read_floats(&mut reader, 3 * vertex_count, double)?);
+    let normals = if flags & SERIALIZED_HAS_NORMALS != 0 {
+        vec3s(read_floats(&mut reader, 3 * vertex_count, double)?)
+    } else {
+        vec![]
+    };
+    // mitsuba puts the uv origin at the top left
+    let texcoords: Vec<[f32; 2]> = if flags & SERIALIZED_HAS_TEXCOORDS != 0 {
+        read_floats(&mut reader, 2 * vertex_count, double)?
+            .chunks_exact(2)
+            .map(|x| [x[0], 1.0 - x[1]])
+            .collect()
+    } else {
+        vec![]
+    };
+    let colors = if flags & SERIALIZED_HAS_COLORS != 0 {
+        vec3s(read_floats(&mut reader, 3 * vertex_count, double)?)
+    } else {
+        vec![]
+    };
+    let mut indices = Vec::with_capacity(triangle_count);
+    for _ in 0..triangle_count {
+        let mut face = [0u32; 3];
+        for i in &mut face {
+            *i = if vertex_count > u32::MAX as usize {
+                read_u64(&mut reader)? as u32
+            } else {
+                read_u32(&mut reader)?
+            };
+        }
+        if face.iter().any(|i| *i as usize >= vertex_count) {
+            return Err(invalid_data(format!(
+                "face {:?} references a vertex out of {}",
+                face, vertex_count
+            )));
+        }
+        indices.push(face);
+    }
+    Ok(TriangleMesh {
+        name: String::from_utf8_lossy(&name).into(),
+        vertices,
+        normal_indices: if normals.is_empty() {
+            vec![]
+        } else {
+            indices.clone()
+        },
+        normals,
+        texcoord_indices: if texcoords.is_empty() {
+            vec![]
+        } else {
+            indices.clone()
+        },
+        texcoords,
+        indices,
+        colors,
+    })
+}
+
+// a texture declared with an id, converted where it is referenced
+#[derive(Clone)]
+struct Bitmap {
+    path: String,
+    raw: bool,
+}
+
+struct MitsubaImporter {
+    dir: PathBuf,
+    stem: String,
+    // $name substitutions from <default>
+    defaults: Vec<(String, String)>,
+    // ids of bsdfs declared at the top level, they keep their id as name
+    bsdf_ids: HashMap<String, String>,
+    textures: HashMap<String, Bitmap>,
+    bsdfs: HashMap<String, node::Bsdf>,
+    shapes: Vec<node::Shape>,
+    lights: Vec<node::Light>,
+    camera: Option<node::Camera>,
+    anonymous_bsdfs: usize,
+    meshes: usize,
+    // canonical paths of the files being converted, outermost first
+    includes: Vec<PathBuf>,
+}
+
+impl MitsubaImporter {
+    fn attr(&self, node: XmlNode, name: &str) -> Option<String> {
+        let mut value = node.attribute(name)?.to_string();
+        for (k, v) in &self.defaults {
+            value = value.replace(&format!("${}", k), v);
+        }
+        Some(value)
+    }
+    fn describe(&self, node: XmlNode) -> String {
+        match (node.attribute("type"), node.attribute("id")) {
+            (Some(ty), Some(id)) => format!("{} {} ({})", node.tag_name().name(), id, ty),
+            (Some(ty), None) => format!("{} {}", node.tag_name().name(), ty),
+            _ => node.tag_name().name().into(),
+        }
+    }
+    // the child property or nested object with the given name attribute
+    fn child<'a>(&self, node: XmlNode<'a>, name: &str) -> Option<XmlNode<'a>> {
+        node.children()
+            .find(|c| c.is_element() && c.attribute("name") == Some(name))
+    }
+    fn float(&self, node: XmlNode, name: &str, default: f32) -> f32 {
+        match self.child(node, name) {
+            Some(c) if ["float", "integer"].contains(&c.tag_name().name()) => {
+                match self.attr(c, "value").and_then(|v| v.trim().parse().ok()) {
+                    Some(v) => v,
+                    None => {
+                        println!("warning: invalid {} of {}", name, self.describe(node));
+                        default
+                    }
+                }
+            }
+            Some(c) => {
+                println!(
+                    "warning: {} of {} is a {}, only constants are supported",
+                    name,
+                    self.describe(node),
+                    c.tag_name().name()
+                );
+                default
+            }
+            None => default,
+        }
+    }
+    fn string(&self, node: XmlNode, name: &str) -> Option<String> {
+        self.child(node, name)
+            .filter(|c| c.tag_name().name() == "string")
+            .and_then(|c| self.attr(c, "value"))
+    }
+    fn boolean(&self, node: XmlNode, name: &str, default: bool) -> bool {
+        self.child(node, name)
+            .filter(|c| c.tag_name().name() == "boolean")
+            .and_then(|c| self.attr(c, "value"))
+            .map_or(default, |v| v == "true")
+    }
+    fn vector(&self, node: XmlNode, default: f32) -> Option<Vec3> {
+        if let Some(value) = self.attr(node, "value") {
+            return match parse_floats(&value)?.as_slice() {
+                [x] => Some(Vec3::splat(*x)),
+                [x, y, z] => Some(vec3(*x, *y, *z)),
+                _ => None,
+            };
+        }
+        let axis = |a: &str| match self.attr(node, a) {
+            Some(v) => v.trim().parse().ok(),
+            None => Some(default),
+        };
+        Some(vec3(axis("x")?, axis("y")?, axis("z")?))
+    }
+    fn bitmap(&self, node: XmlNode) -> Option<Bitmap> {
+        match node.tag_name().name() {
+            "ref" => {
+                let id = self.attr(node, "id")?;
+                let bitmap = self.textures.get(&id).cloned();
+                if bitmap.is_none() {
+                    println!("warning: texture {} is not defined", id);
+                }
+                bitmap
+            }
+            "texture" if node.attribute("type") == Some("bitmap") => {
+                match self.string(node, "filename") {
+                    Some(path) => Some(Bitmap {
+                        path: path.replace("\\", "/"),
+                        raw: self.boolean(node, "raw", false),
+                    }),
+                    None => {
+                        println!("warning: bitmap without filename ignored");
+                        None
+                    }
+                }
+            }
+            _ => {
+                println!("warning: {} is not supported", self.describe(node));
+                None
+            }
+        }
+    }
+    fn spectrum(&self, node: XmlNode, name: &str) -> Option<node::SpectrumTexture> {
+        let c = self.child(node, name)?;
+        let what = format!("{} of {}", name, self.describe(node));
+        let invalid = || {
+            println!("warning: invalid {}", what);
+            None
+        };
+        match c.tag_name().name() {
+            "rgb" => match parse_floats(&self.attr(c, "value")?)?.as_slice() {
+                [v] => Some(node::SpectrumTexture::SRgbLinear { values: [*v; 3] }),
+                [r, g, b] => Some(node::SpectrumTexture::SRgbLinear {
+                    values: [*r, *g, *b],
+                }),
+                _ => invalid(),
+            },
+            "float" => match self.attr(c, "value").and_then(|v| v.trim().parse().ok()) {
+                Some(v) => Some(node::SpectrumTexture::SRgbLinear { values: [v; 3] }),
+                None => invalid(),
+            },
+            "spectrum" => {
+                let value = match self.attr(c, "value") {
+                    Some(value) => value,
+                    None => {
+                        println!("warning: {} from a file is not supported", what);
+                        return None;
+                    }
+                };
+                if let Some([v]) = parse_floats(&value).as_deref() {
+                    return Some(node::SpectrumTexture::SRgbLinear { values: [*v; 3] });
+                }
+                let samples: Option<Vec<[f32; 2]>> = value
+                    .split(',')
+                    .map(|pair| {
+                        let (w, v) = pair.trim().split_once(':')?;
+                        Some([w.trim().parse().ok()?, v.trim().parse().ok()?])
+                    })
+                    .collect();
+                match samples {
+                    Some(values) => Some(node::SpectrumTexture::Tabulated {
+                        values,
+                        normalize: false,
+                        scale: 1.0,
+                    }),
+                    None => invalid(),
+                }
+            }
+            "blackbody" => match self
+                .attr(c, "temperature")
+                .and_then(|t| t.trim().parse().ok())
+            {
+                Some(temperature) => Some(node::SpectrumTexture::Blackbody {
+                    temperature,
+                    scale: self.float(c, "scale", 1.0),
+                }),
+                None => invalid(),
+            },
+            "texture" if c.attribute("type") == Some("mesh_attribute") => {
+                match self.string(c, "name").as_deref() {
+                    Some("vertex_color") => Some(node::SpectrumTexture::VertexColor {}),
+                    _ => invalid(),
+                }
+            }
+            "texture" | "ref" => self.bitmap(c).map(|b| node::SpectrumTexture::Image {
+                colorspace: if b.raw || is_hdr(&b.path) {
+                    "linear".into()
+                } else {
+                    "srgb".into()
+                },
+                path: b.path,
+                cache: None,
+            }),
+            _ => invalid(),
+        }
+    }
+    fn spectrum_or(&self, node: XmlNode, name: &str, default: f32) -> node::SpectrumTexture {
+        self.spectrum(node, name)
+            .unwrap_or(node::SpectrumTexture::SRgbLinear {
+                values: [default; 3],
+            })
+    }
+    fn float_texture(&self, node: XmlNode, name: &str, default: f32) -> node::FloatTexture {
+        match self.child(node, name) {
+            Some(c) if ["texture", "ref"].contains(&c.tag_name().name()) => match self.bitmap(c) {
+                Some(b) => node::FloatTexture::Image(b.path),
+                None => node::FloatTexture::Float(default),
+            },
+            _ => node::FloatTexture::Float(self.float(node, name, default)),
+        }
+    }
+    // composed like mitsuba does, every element is applied after the previous ones
+    fn transform(&self, node: XmlNode, name: &str) -> Mat4 {
+        let mut m = Mat4::IDENTITY;
+        let t = match self.child(node, name) {
+            Some(t) if t.tag_name().name() == "transform" => t,
+            _ => return m,
+        };
+        for op in t.children().filter(|c| c.is_element()) {
+            let step = match op.tag_name().name() {
+                "translate" => self.vector(op, 0.0).map(Mat4::from_translation),
+                "scale" => self.vector(op, 1.0).map(Mat4::from_scale),
+                "rotate" => {
+                    let angle: Option<f32> =
+                        self.attr(op, "angle").and_then(|a| a.trim().parse().ok());
+                    match (self.vector(op, 0.0), angle) {
+                        (Some(axis), Some(angle)) if axis.length() > 0.0 => {
+                            Some(Mat4::from_axis_angle(axis.normalize(), angle.to_radians()))
+                        }
+                        _ => None,
+                    }
+                }
+                "matrix" => match self.attr(op, "value").and_then(|v| parse_floats(&v)) {
+                    Some(v) if v.len() == 16 => Some(Mat4::from_cols_slice(&v).transpose()),
+                    Some(v) if v.len() == 9 => {
+                        Some(Mat4::from_mat3(Mat3::from_cols_slice(&v).transpose()))
+                    }
+                    _ => None,
+                },
+                "lookat" => {
+                    let point = |a: &str| {
+                        self.attr(op, a)
+                            .and_then(|v| parse_floats(&v))
+                            .and_then(|v| (v.len() == 3).then(|| vec3(v[0], v[1], v[2])))
+                    };
+                    match (point("origin"), point("target"), point("up")) {
+                        (Some(origin), Some(target), Some(up)) => {
+                            let dir = (target - origin).normalize();
+                            let left = up.normalize().cross(dir).normalize();
+                            let up = dir.cross(left);
+                            Some(Mat4::from_cols(
+                                left.extend(0.0),
+                                up.extend(0.0),
+                                dir.extend(0.0),
+                                origin.extend(1.0),
+                            ))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match step {
+                Some(step) => m = step * m,
+                None => println!(
+                    "warning: invalid {} in {} of {}, ignored",
+                    op.tag_name().name(),
+                    name,
+                    self.describe(node)
+                ),
+            }
+        }
+        m
+    }
+    fn convert_bsdf(&mut self, node: XmlNode) -> Option<node::Bsdf> {
+        let ty = node.attribute("type").unwrap_or("");
+        let nested = || {
+            node.children()
+                .find(|c| c.is_element() && c.tag_name().name() == "bsdf")
+        };
+        let principled = |f: &mut dyn FnMut(&mut node::Bsdf)| {
+            let mut bsdf = default_principled();
+            f(&mut bsdf);
+            bsdf
+        };
+        Some(match ty {
+            "twosided" => return self.convert_bsdf(nested()?),
+            "mask" | "bumpmap" | "normalmap" => {
+                println!(
+                    "warning: {} is ignored, using the bsdf it wraps",
+                    self.describe(node)
+                );
+                return self.convert_bsdf(nested()?);
+            }
+            "diffuse" => node::Bsdf::Diffuse {
+                color: self.spectrum_or(node, "reflectance", 0.5),
+            },
+            "dielectric" | "roughdielectric" => {
+                let ior = |name: &str, default: f32| match self.child(node, name) {
+                    Some(c) if c.tag_name().name() == "string" => {
+                        let value = self.attr(c, "value").unwrap_or_default();
+                        named_ior(&value.to_ascii_lowercase()).unwrap_or_else(|| {
+                            println!("warning: unknown ior {}", value);
+                            default
+                        })
+                    }
+                    _ => self.float(node, name, default),
+                };
+                let ior = ior("int_ior", 1.5046) / ior("ext_ior", 1.000277);
+                if ty == "dielectric" {
+                    node::Bsdf::Glass {
+                        ior,
+                        dispersion: 0.0,
+                        kr: self.spectrum_or(node, "specular_reflectance", 1.0),
+                        kt: self.spectrum_or(node, "specular_transmittance", 1.0),
+                    }
+                } else {
+                    let alpha = self.float(node, "alpha", 0.1);
+                    principled(&mut |bsdf| {
+                        if let node::Bsdf::Principled {
+                            color,
+                            roughness,
+                            specular,
+                            ior: principled_ior,
+                            transmission,
+                            ..
+                        } = bsdf
+                        {
+                            *color = node::SpectrumTexture::SRgbLinear { values: [1.0; 3] };
+                            *roughness = node::FloatTexture::Float(alpha.sqrt());
+                            *specular = node::FloatTexture::Float(0.5);
+                            *principled_ior = node::FloatTexture::Float(ior);
+                            *transmission = node::FloatTexture::Float(1.0);
+                        }
+                    })
+                }
+            }
+            "conductor" | "roughconductor" => {
+                let alpha = if ty == "conductor" {
+                    0.0
+                } else {
+                    self.float(node, "alpha", 0.1)
+                };
+                if self.child(node, "alpha_u").is_some() {
+                    println!(
+                        "warning: anisotropic roughness of {} is ignored",
+                        self.describe(node)
+                    );
+                }
+                // normal incidence reflectance from eta and k, or from the named material
+                let f0 = match (self.child(node, "eta"), self.child(node, "k")) {
+                    (Some(_), Some(_)) => {
+                        let rgb = |name: &str| match self.spectrum(node, name) {
+                            Some(node::SpectrumTexture::SRgbLinear { values }) => {
+                                Vec3::from(values)
+                            }
+                            _ => {
+                                println!(
+                                    "warning: {} of {} must be rgb",
+                                    name,
+                                    self.describe(node)
+                                );
+                                Vec3::ONE
+                            }
+                        };
+                        let (eta, k) = (rgb("eta"), rgb("k"));
+                        let one = Vec3::ONE;
+                        (((eta - one) * (eta - one) + k * k) / ((eta + one) * (eta + one) + k * k))
+                            .into()
+                    }
+                    _ => {
+                        let material = self.string(node, "material").unwrap_or("none".into());
+                        (if material == "none" {
+                            Some([1.0; 3])
+                        } else {
+                            conductor_reflectance(&material)
+                        })
+                        .unwrap_or_else(|| {
+                            println!("warning: unknown conductor {}", material);
+                            [1.0; 3]
+                        })
+                    }
+                };
+                let tint = self.spectrum(node, "specular_reflectance");
+                principled(&mut |bsdf| {
+                    if let node::Bsdf::Principled {
+                        color,
+                        metallic,
+                        roughness,
+                        ..
+                    } = bsdf
+                    {
+                        *color = match &tint {
+                            Some(node::SpectrumTexture::SRgbLinear { values }) => {
+                                node::SpectrumTexture::SRgbLinear {
+                                    values: (Vec3::from(*values) * Vec3::from(f0)).into(),
+                                }
+                            }
+                            Some(tex) => tex.clone(),
+                            None => node::SpectrumTexture::SRgbLinear { values: f0 },
+                        };
+                        *metallic = node::FloatTexture::Float(1.0);
+                        *roughness = node::FloatTexture::Float(alpha.sqrt());
+                    }
+                })
+            }
+            "plastic" | "roughplastic" => {
+                let alpha = if ty == "plastic" {
+                    0.0
+                } else {
+                    self.float(node, "alpha", 0.1)
+                };
+                let diffuse = self.spectrum_or(node, "diffuse_reflectance", 0.5);
+                principled(&mut |bsdf| {
+                    if let node::Bsdf::Principled {
+                        color,
+                        roughness,
+                        specular,
+                        ..
+                    } = bsdf
+                    {
+                        *color = diffuse.clone();
+                        *roughness = node::FloatTexture::Float(alpha.sqrt());
+                        *specular = node::FloatTexture::Float(0.5);
+                    }
+                })
+            }
+            "principled" => {
+                let color = self.spectrum_or(node, "base_color", 0.5);
+                let metallic = self.float_texture(node, "metallic", 0.0);
+                let roughness = self.float_texture(node, "roughness", 0.5);
+                let specular = self.float(node, "specular", 0.5);
+                let specular_tint = self.float_texture(node, "spec_tint", 0.0);
+                let anisotropic = self.float_texture(node, "anisotropic", 0.0);
+                let sheen = self.float_texture(node, "sheen", 0.0);
+                let sheen_tint = self.float_texture(node, "sheen_tint", 0.0);
+                let clearcoat = self.float_texture(node, "clearcoat", 0.0);
+                let clearcoat_gloss = self.float(node, "clearcoat_gloss", 0.0);
+                let transmission = self.float_texture(node, "spec_trans", 0.0);
+                let eta = self.float(node, "eta", 1.5);
+                principled(&mut |bsdf| {
+                    if let node::Bsdf::Principled {
+                        color: c,
+                        metallic: m,
+                        roughness: r,
+                        specular: s,
+                        specular_tint: st,
+                        anisotropic: a,
+                        sheen: sh,
+                        sheen_tint: sht,
+                        clearcoat: cc,
+                        clearcoat_roughness: ccr,
+                        ior,
+                        transmission: t,
+                        ..
+                    } = bsdf
+                    {
+                        *c = color.clone();
+                        *m = metallic.clone();
+                        *r = roughness.clone();
+                        *s = node::FloatTexture::Float(specular);
+                        *st = specular_tint.clone();
+                        *a = anisotropic.clone();
+                        *sh = sheen.clone();
+                        *sht = sheen_tint.clone();
+                        *cc = clearcoat.clone();
+                        *ccr = node::FloatTexture::Float(1.0 - clearcoat_gloss);
+                        *ior = node::FloatTexture::Float(eta);
+                        *t = transmission.clone();
+                    }
+                })
+            }
+            _ => {
+                println!("warning: {} is not supported", self.describe(node));
+                return None;
+            }
+        })
+    }
+    // name of the bsdf, converting it if it is declared inline
+    fn bsdf_name(&mut self, node: XmlNode) -> Option<String> {
+        if node.tag_name().name() == "ref" {
+            let id = self.attr(node, "id")?;
+            let name = self.bsdf_ids.get(&id).cloned();
+            if name.is_none() {
+                println!("warning: bsdf {} is not defined", id);
+            }
+            return name;
+        }
+        let bsdf = self.convert_bsdf(node)?;
+        let name = match node.attribute("id") {
+            Some(id) => id.to_string(),
+            None => {
+                self.anonymous_bsdfs += 1;
+                format!("{}_bsdf_{}", self.stem, self.anonymous_bsdfs)
+            }
+        };
+        if let Some(id) = node.attribute("id") {
+            self.bsdf_ids.insert(id.into(), name.clone());
+        }
+        self.bsdfs.insert(name.clone(), bsdf);
+        Some(name)
+    }
+    // mitsuba's default bsdf, a 50% diffuse
+    fn default_bsdf(&mut self) -> String {
+        let name = format!("{}_default", self.stem);
+        self.bsdfs
+            .entry(name.clone())
+            .or_insert(node::Bsdf::Diffuse {
+                color: node::SpectrumTexture::SRgbLinear { values: [0.5; 3] },
+            });
+        name
+    }
+    fn load_meshes(&self, node: XmlNode, ty: &str) -> Option<Vec<TriangleMesh>> {
+        let filename = match self.string(node, "filename") {
+            Some(f) => f.replace("\\", "/"),
+            None => {
+                println!("warning: {} has no filename, ignored", self.describe(node));
+                return None;
+            }
+        };
+        let path = self.dir.join(&filename);
+        let flip = |mut mesh: TriangleMesh| {
+            for uv in &mut mesh.texcoords {
+                uv[1] = 1.0 - uv[1];
+            }
+            mesh
+        };
+        let result = match ty {
+            "obj" => {
+                if !path.exists() {
+                    Err(io::Error::from(io::ErrorKind::NotFound))
+                } else {
+                    // materials are given by the scene, a missing mtllib does not matter
+                    tobj::load_obj(
+                        &path,
+                        &tobj::LoadOptions {
+                            triangulate: true,
+                            ..Default::default()
+                        },
+                    )
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+                    .map(|(models, _)| {
+                        let meshes = models.iter().map(|m| {
+                            let mut mesh = akari::shape::mesh_from_obj_model(m, None);
+                            if mesh.name.is_empty() {
+                                mesh.name = filename.clone();
+                            }
+                            mesh
+                        });
+                        // mitsuba flips obj texcoords unless told otherwise, akari never does
+                        if self.boolean(node, "flip_tex_coords", true) {
+                            meshes.collect()
+                        } else {
+                            meshes.map(flip).collect()
+                        }
+                    })
+                }
+            }
+            "ply" => read_ply(&path).map(|mesh| vec![flip(mesh)]),
+            _ => read_serialized(&path, self.float(node, "shape_index", 0.0) as usize)
+                .map(|mesh| vec![mesh]),
+        };
+        match result {
+            Ok(meshes) => Some(meshes),
+            Err(e) => {
+                println!("warning: cannot read {}: {}, ignored", filename, e);
+                None
+            }
+        }
+    }
+    fn convert_shape(&mut self, node: XmlNode) {
+        let ty = node.attribute("type").unwrap_or("");
+        let mut meshes = match ty {
+            "obj" | "ply" | "serialized" => match self.load_meshes(node, ty) {
+                Some(meshes) => meshes,
+                None => return,
+            },
+            "rectangle" => vec![rectangle()],
+            "cube" => vec![cube()],
+            _ => {
+                println!("warning: {} is not supported, ignored", self.describe(node));
+                return;
+            }
+        };
+        for flag in ["face_normals", "flip_normals"] {
+            if self.boolean(node, flag, false) {
+                println!("warning: {} of {} is ignored", flag, self.describe(node));
+            }
+        }
+        let mut bsdf = None;
+        let mut radiance = None;
+        for c in node.children().filter(|c| c.is_element()) {
+            match c.tag_name().name() {
+                "bsdf" | "ref" => {
+                    if let Some(name) = self.bsdf_name(c) {
+                        bsdf = Some(name);
+                    }
+                }
+                "emitter" if c.attribute("type") == Some("area") => {
+                    radiance = Some(self.spectrum_or(c, "radiance", 1.0));
+                }
+                "emitter" | "medium" => {
+                    println!(
+                        "warning: {} of {} is not supported",
+                        self.describe(c),
+                        self.describe(node)
+                    );
+                }
+                _ => {}
+            }
+        }
+        let mut bsdf = bsdf.unwrap_or_else(|| self.default_bsdf());
+        if let Some(radiance) = radiance {
+            bsdf = add_emissive_bsdf(&mut self.bsdfs, &bsdf, radiance);
+        }
+        let to_world = self.transform(node, "to_world");
+        let (trs, exact) = trs_from_matrix(to_world);
+        let transform = if to_world == Mat4::IDENTITY {
+            None
+        } else if exact {
+            Some(node::Transform::TRS(trs))
+        } else {
+            // sheared, baked into the vertices instead
+            let normal_matrix = Mat3::from_mat4(to_world).inverse().transpose();
+            for mesh in &mut meshes {
+                for v in &mut mesh.vertices {
+                    *v = to_world.transform_point3((*v).into()).into();
+                }
+                for n in &mut mesh.normals {
+                    *n = (normal_matrix * Vec3::from(*n)).normalize().into();
+                }
+            }
+            None
+        };
+        for mesh in meshes {
+            self.meshes += 1;
+            let path = format!("{}_{}.mesh", self.stem, self.meshes);
+            write_mesh(&self.dir.join(&path), &mesh);
+            self.shapes.push(node::Shape::Mesh {
+                path,
+                bsdf: bsdf.clone(),
+                transform: transform.clone(),
+            });
+        }
+    }
+    fn convert_emitter(&mut self, node: XmlNode) {
+        let to_world = self.transform(node, "to_world");
+        let ty = node.attribute("type").unwrap_or("");
+        let light = match ty {
+            "point" => node::Light::Point {
+                pos: match self.child(node, "position") {
+                    Some(p) => self.vector(p, 0.0).unwrap_or(Vec3::ZERO),
+                    None => to_world.transform_point3(Vec3::ZERO),
+                }
+                .into(),
+                emission: self.spectrum_or(node, "intensity", 1.0),
+            },
+            "spot" => {
+                let cutoff = self.float(node, "cutoff_angle", 20.0);
+                let (trs, _) = trs_from_matrix(to_world * Mat4::from_rotation_y(PI));
+                node::Light::Spot {
+                    transform: node::Transform::TRS(trs),
+                    emission: self.spectrum_or(node, "intensity", 1.0),
+                    falloff: self.float(node, "beam_width", cutoff * 0.75),
+                    max_angle: cutoff,
+                }
+            }
+            "directional" => node::Light::Directional {
+                direction: match self.child(node, "direction") {
+                    Some(d) => self.vector(d, 0.0).unwrap_or(Vec3::Z),
+                    None => to_world.transform_vector3(Vec3::Z),
+                }
+                .normalize()
+                .into(),
+                emission: self.spectrum_or(node, "irradiance", 1.0),
+                angular_diameter: 0.0,
+            },
+            "envmap" => {
+                let path = match self.string(node, "filename") {
+                    Some(path) => path.replace("\\", "/"),
+                    None => {
+                        println!("warning: envmap without filename ignored");
+                        return;
+                    }
+                };
+                if self.float(node, "scale", 1.0) != 1.0 {
+                    println!("warning: scale of envmap {} is ignored", path);
+                }
+                let (trs, _) = trs_from_matrix(to_world);
+                node::Light::Environment {
+                    emission: node::SpectrumTexture::Image {
+                        colorspace: if is_hdr(&path) { "linear" } else { "srgb" }.into(),
+                        path,
+                        cache: None,
+                    },
+                    transform: (to_world != Mat4::IDENTITY).then(|| node::Transform::TRS(trs)),
+                }
+            }
+            "constant" => node::Light::Environment {
+                emission: self.spectrum_or(node, "radiance", 1.0),
+                transform: None,
+            },
+            "area" => {
+                println!("warning: area emitter outside of a shape ignored");
+                return;
+            }
+            _ => {
+                println!("warning: {} is not supported, ignored", self.describe(node));
+                return;
+            }
+        };
+        self.lights.push(light);
+    }
+    fn convert_sensor(&mut self, node: XmlNode) {
+        let ty = node.attribute("type").unwrap_or("");
+        if self.camera.is_some() {
+            println!(
+                "warning: {} ignored, only the first sensor is imported",
+                self.describe(node)
+            );
+            return;
+        }
+        if !["perspective", "thinlens"].contains(&ty) {
+            println!("warning: {} is not supported, ignored", self.describe(node));
+            return;
+        }
+        let res = match node
+            .children()
+            .find(|c| c.is_element() && c.tag_name().name() == "film")
+        {
+            Some(film) => (
+                self.float(film, "width", DEFAULT_RES.0 as f32) as u32,
+                self.float(film, "height", DEFAULT_RES.1 as f32) as u32,
+            ),
+            None => DEFAULT_RES,
+        };
+        let (w, h) = (res.0 as f32, res.1 as f32);
+        let (fov, axis) = match (self.child(node, "fov"), self.string(node, "focal_length")) {
+            (Some(_), _) => (
+                self.float(node, "fov", 90.0).to_radians(),
+                self.string(node, "fov_axis").unwrap_or("x".into()),
+            ),
+            // 35mm equivalent focal length
+            (None, focal_length) => {
+                let f: f32 = focal_length
+                    .as_deref()
+                    .unwrap_or("50mm")
+                    .trim_end_matches("mm")
+                    .parse()
+                    .unwrap_or(50.0);
+                (
+                    2.0 * (36.0f32.hypot(24.0) / (2.0 * f)).atan(),
+                    "diagonal".into(),
+                )
+            }
+        };
+        // tangents of the half angles along x and y
+        let t = (0.5 * fov).tan();
+        let (tan_x, tan_y) = match axis.as_str() {
+            "y" => (t * w / h, t),
+            "diagonal" => (t * w / w.hypot(h), t * h / w.hypot(h)),
+            "smaller" if h < w => (t * w / h, t),
+            "larger" if h > w => (t * w / h, t),
+            "x" | "smaller" | "larger" => (t, t * h / w),
+            _ => {
+                println!("warning: unknown fov_axis {}, using x", axis);
+                (t, t * h / w)
+            }
+        };
+        let to_world = self.transform(node, "to_world");
+        let (trs, exact) = trs_from_matrix(to_world * Mat4::from_rotation_y(PI));
+        if !exact || trs.scale.iter().any(|s| (s.abs() - 1.0).abs() > 1e-3) {
+            println!("warning: scale and shear of the sensor transform are ignored");
+        }
+        let (lens_radius, focal) = if ty == "thinlens" {
+            (
+                self.float(node, "aperture_radius", 0.0),
+                self.float(node, "focus_distance", 1.0),
+            )
+        } else {
+            (0.0, 1.0)
+        };
+        self.camera = Some(node::Camera::Perspective {
+            res,
+            // the field of view spans the longer side of the film
+            fov: (2.0 * tan_x.max(tan_y).atan()).to_degrees(),
+            lens_radius,
+            focal,
+            transform: node::Transform::TRS(trs),
+            shutter: (0.0, 0.0),
+        });
+    }
+    fn convert_scene(&mut self, root: XmlNode) {
+        for c in root.children().filter(|c| c.is_element()) {
+            match c.tag_name().name() {
+                "default" => {
+                    if let (Some(name), Some(value)) = (c.attribute("name"), c.attribute("value")) {
+                        self.defaults.push((name.into(), value.into()));
+                        // longer names first, so $spp does not eat $spp_max
+                        self.defaults.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+                    }
+                }
+                "include" => match self.attr(c, "filename") {
+                    Some(filename) => {
+                        let path = self.dir.join(filename.replace("\\", "/"));
+                        let canonical = canonical_path(&path);
+                        if self.includes.contains(&canonical)
+                            || self.includes.len() >= MAX_INCLUDE_DEPTH
+                        {
+                            println!("warning: {} is included recursively, ignored", filename);
+                            continue;
+                        }
+                        let text = read_xml(&path);
+                        let doc = parse_xml(&path, &text);
+                        self.includes.push(canonical);
+                        self.convert_scene(doc.root_element());
+                        self.includes.pop();
+                    }
+                    None => println!("warning: include without filename ignored"),
+                },
+                "bsdf" => {
+                    if c.attribute("id").is_none() {
+                        println!("warning: {} without id is never used", self.describe(c));
+                    }
+                    self.bsdf_name(c);
+                }
+                "texture" => match (c.attribute("id"), self.bitmap(c)) {
+                    (Some(id), Some(bitmap)) => {
+                        self.textures.insert(id.into(), bitmap);
+                    }
+                    (None, _) => println!("warning: {} without id is never used", self.describe(c)),
+                    _ => {}
+                },
+                "shape" => self.convert_shape(c),
+                "emitter" => self.convert_emitter(c),
+                "sensor" => self.convert_sensor(c),
+                "integrator" | "sampler" | "film" | "rfilter" => {
+                    println!("warning: {} is ignored", self.describe(c));
+                }
+                _ => println!("warning: {} is not supported, ignored", self.describe(c)),
+            }
+        }
+    }
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+fn read_xml(path: &Path) -> String {
+    match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
+fn parse_xml<'a>(path: &Path, text: &'a str) -> roxmltree::Document<'a> {
+    match roxmltree::Document::parse(text) {
+        Ok(doc) => doc,
+        Err(e) => {
+            eprintln!("failed to parse {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
+
+pub(crate) fn import_mitsuba(path: &str, scene: &mut node::Scene, forced: bool) {
+    let model_path = Path::new(path);
+    let text = read_xml(model_path);
+    let doc = parse_xml(model_path, &text);
+    let root = doc.root_element();
+    if root.tag_name().name() != "scene" {
+        eprintln!("{} is not a mitsuba scene", path);
+        exit(1);
+    }
+    match root.attribute("version") {
+        Some(v) if !v.starts_with('3') => {
+            println!(
+                "warning: scene version {}, only mitsuba 3 scenes are supported",
+                v
+            )
+        }
+        _ => {}
+    }
+    let mut importer = MitsubaImporter {
+        dir: model_path.parent().unwrap().into(),
+        stem: model_path.file_stem().unwrap().to_str().unwrap().into(),
+        defaults: vec![],
+        bsdf_ids: HashMap::new(),
+        textures: HashMap::new(),
+        bsdfs: HashMap::new(),
+        shapes: vec![],
+        lights: vec![],
+        camera: None,
+        anonymous_bsdfs: 0,
+        meshes: 0,
+        includes: vec![canonical_path(model_path)],
+    };
+    importer.convert_scene(root);
+    println!("# of meshes: {}", importer.meshes);
+    println!("# of materials: {}", importer.bsdfs.len());
+    println!("# of lights: {}", importer.lights.len());
+    merge_bsdfs(scene, &importer.bsdfs, forced);
+    scene.shapes.extend(importer.shapes);
+    scene.lights.extend(importer.lights);
+    if let Some(camera) = importer.camera {
+        scene.camera = Some(camera);
+    }
+}
+
+mod test {
+    #[test]
+    fn test_mitsuba_scene() {
+        use super::*;
+        use akari::util::binserde::Decode;
+        let dir = akari::util::TempDirGuard::new("mitsuba");
+        let text = r#"<scene version="3.0.0">
+            <default name="alpha" value="0.25"/>
+            <bsdf type="roughconductor" id="gold">
+                <string name="material" value="Au"/>
+                <float name="alpha" value="$alpha"/>
+            </bsdf>
+            <shape type="cube">
+                <ref id="gold"/>
+                <transform name="to_world">
+                    <scale value="2"/>
+                    <rotate y="1" angle="90"/>
+                    <translate x="1" y="2" z="3"/>
+                </transform>
+            </shape>
+            <shape type="rectangle">
+                <emitter type="area">
+                    <rgb name="radiance" value="10, 8, 6"/>
+                </emitter>
+            </shape>
+            <shape type="obj">
+                <string name="filename" value="tri.obj"/>
+            </shape>
+            <shape type="obj">
+                <string name="filename" value="broken.obj"/>
+            </shape>
+            <emitter type="point">
+                <point name="position" x="0" y="4" z="0"/>
+                <rgb name="intensity" value="5"/>
+            </emitter>
+            <sensor type="perspective">
+                <float name="fov" value="45"/>
+                <transform name="to_world">
+                    <lookat origin="0, 0, 5" target="0, 0, 0" up="0, 1, 0"/>
+                </transform>
+                <film type="hdrfilm">
+                    <integer name="width" value="200"/>
+                    <integer name="height" value="100"/>
+                </film>
+            </sensor>
+            <include filename="sub/../scene.xml"/>
+        </scene>"#;
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        // an obj whose mtllib is missing still loads, a malformed one is skipped
+        std::fs::write(
+            dir.join("tri.obj"),
+            "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.obj"), "v 0 0\nf 1 2 3\n").unwrap();
+        let path = dir.join("scene.xml");
+        std::fs::write(&path, text).unwrap();
+        let mut scene = node::Scene::default();
+        // the scene includes itself, which is skipped instead of recursing forever
+        import_mitsuba(path.to_str().unwrap(), &mut scene, false);
+
+        assert_eq!(scene.shapes.len(), 3);
+        let node::Shape::Mesh {
+            path: cube_path,
+            bsdf,
+            transform,
+        } = &scene.shapes[0];
+        assert_eq!(bsdf, "gold");
+        match &scene.bsdfs["gold"] {
+            node::Bsdf::Principled {
+                roughness: node::FloatTexture::Float(r),
+                metallic: node::FloatTexture::Float(m),
+                ..
+            } => {
+                assert!((r - 0.5).abs() < 1e-5);
+                assert_eq!(*m, 1.0);
+            }
+            _ => panic!("expected a principled bsdf"),
+        }
+        // scaled, then rotated and translated like mitsuba composes them
+        let trs = match transform {
+            Some(node::Transform::TRS(trs)) => trs,
+            _ => panic!("expected a trs transform"),
+        };
+        let [x, y, z] = trs.rotate;
+        let m = Mat4::from_translation(trs.translate.into())
+            * Mat4::from_rotation_y(y.to_radians())
+            * Mat4::from_rotation_x(x.to_radians())
+            * Mat4::from_rotation_z(z.to_radians())
+            * Mat4::from_scale(trs.scale.into());
+        let p = m.transform_point3(vec3(1.0, 0.0, 0.0));
+        assert!((p - vec3(1.0, 2.0, 1.0)).length() < 1e-4);
+        // faces of the cube wind outwards
+        let cube =
+            TriangleMesh::decode(&mut std::fs::File::open(dir.join(cube_path)).unwrap()).unwrap();
+        for (face, n) in cube.indices.iter().zip(cube.normal_indices.iter()) {
+            let v = |i: u32| Vec3::from(cube.vertices[i as usize]);
+            let ng = (v(face[1]) - v(face[0])).cross(v(face[2]) - v(face[0]));
+            assert!(ng.dot(cube.normals[n[0] as usize].into()) > 0.0);
+        }
+
+        let node::Shape::Mesh { bsdf, .. } = &scene.shapes[1];
+        match &scene.bsdfs[bsdf] {
+            node::Bsdf::Principled {
+                emission: node::SpectrumTexture::SRgbLinear { values },
+                ..
+            } => assert_eq!(*values, [10.0, 8.0, 6.0]),
+            _ => panic!("area emitter not imported"),
+        }
+        let node::Shape::Mesh { path: tri_path, .. } = &scene.shapes[2];
+        let tri =
+            TriangleMesh::decode(&mut std::fs::File::open(dir.join(tri_path)).unwrap()).unwrap();
+        assert_eq!(tri.indices, vec![[0, 1, 2]]);
+
+        assert_eq!(scene.lights.len(), 1);
+        match &scene.lights[0] {
+            node::Light::Point {
+                pos,
+                emission: node::SpectrumTexture::SRgbLinear { values },
+            } => {
+                assert_eq!(*pos, [0.0, 4.0, 0.0]);
+                assert_eq!(*values, [5.0; 3]);
+            }
+            _ => panic!("point light not imported"),
+        }
+
+        match &scene.camera {
+            Some(node::Camera::Perspective {
+                res,
+                fov,
+                transform: node::Transform::TRS(trs),
+                ..
+            }) => {
+                assert_eq!(*res, (200, 100));
+                assert!((fov - 45.0).abs() < 1e-3);
+                // akari cameras look down -z, the rotation of the lookat cancels out
+                assert!((Vec3::from(trs.translate) - vec3(0.0, 0.0, 5.0)).length() < 1e-5);
+                assert!(trs.rotate.iter().all(|a| a.abs() < 1e-3));
+            }
+            _ => panic!("sensor not imported"),
+        }
+    }
+}
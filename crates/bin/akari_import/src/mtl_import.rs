@@ -0,0 +1,464 @@
+use akari::scenegraph::node;
+use akari::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/* MTL fields beyond what tobj exposes
+ * the .mtl files are scanned again so that absent fields can be told apart from zero ones,
+ * tobj defaults `d` to 0 and keeps Ke, norm, etc. in an untyped map
+ */
+
+// the fields read by `import` through tobj
+const BASE_FIELDS: [&str; 6] = ["newmtl", "kd", "ks", "ns", "map_kd", "map_ks"];
+
+// material name -> (field, value) in file order
+pub(crate) type MtlFields = HashMap<String, Vec<(String, String)>>;
+
+pub(crate) fn parse_mtl(text: &str) -> MtlFields {
+    let mut materials = MtlFields::new();
+    let mut current: Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(char::is_whitespace) {
+            Some((key, value)) => (key, value.trim()),
+            None => (line, ""),
+        };
+        if key == "newmtl" {
+            current = Some(value.into());
+            materials.entry(value.into()).or_default();
+        } else if let Some(name) = &current {
+            materials
+                .get_mut(name)
+                .unwrap()
+                .push((key.into(), value.into()));
+        }
+    }
+    materials
+}
+
+// fields of every material in the libraries referenced by the obj file
+pub(crate) fn read_mtl_fields(obj_path: &Path) -> MtlFields {
+    let dir = obj_path.parent().unwrap();
+    let mut materials = MtlFields::new();
+    let obj = match fs::read_to_string(obj_path) {
+        Ok(obj) => obj,
+        Err(_) => return materials,
+    };
+    for line in obj.lines() {
+        let line = line.trim();
+        if let Some(libs) = line.strip_prefix("mtllib") {
+            if !line[6..].starts_with(char::is_whitespace) {
+                continue;
+            }
+            // tobj takes the rest of the line as a single file name
+            let lib = libs.trim();
+            match fs::read_to_string(dir.join(lib)) {
+                Ok(text) => materials.extend(parse_mtl(&text)),
+                Err(e) => println!("warning: cannot read {}: {}", lib, e),
+            }
+        }
+    }
+    materials
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TextureMap {
+    pub(crate) path: String,
+    // -bm
+    pub(crate) bump_multiplier: f32,
+    // -imfchan
+    pub(crate) channel: Option<String>,
+    // options that are parsed but not mapped
+    pub(crate) ignored: Vec<String>,
+}
+
+// `[-option args]... file`, the file name may contain spaces
+pub(crate) fn parse_texture_map(value: &str) -> Option<TextureMap> {
+    let tokens: Vec<&str> = value.split_whitespace().collect();
+    let mut map = TextureMap {
+        path: String::new(),
+        bump_multiplier: 1.0,
+        channel: None,
+        ignored: vec![],
+    };
+    let mut i = 0;
+    while i < tokens.len() && tokens[i].starts_with('-') {
+        let option = tokens[i];
+        i += 1;
+        let arity = match option {
+            // up to three numbers
+            "-o" | "-s" | "-t" => tokens[i..]
+                .iter()
+                .take(3)
+                .take_while(|t| t.parse::<f32>().is_ok())
+                .count(),
+            "-mm" => 2,
+            _ => 1,
+        };
+        let args = &tokens[i.min(tokens.len())..(i + arity).min(tokens.len())];
+        i += arity;
+        match (option, args) {
+            ("-bm", [bm]) => match bm.parse() {
+                Ok(bm) => map.bump_multiplier = bm,
+                Err(_) => map.ignored.push(option.into()),
+            },
+            ("-imfchan", [c]) => map.channel = Some(c.to_string()),
+            _ => map.ignored.push(option.into()),
+        }
+    }
+    if i >= tokens.len() {
+        return None;
+    }
+    map.path = tokens[i..].join(" ").replace("\\", "/");
+    Some(map)
+}
+
+fn parse_floats(value: &str) -> Option<Vec<f32>> {
+    value.split_whitespace().map(|v| v.parse().ok()).collect()
+}
+
+fn parse_rgb(value: &str) -> Option<[f32; 3]> {
+    match parse_floats(value)?.as_slice() {
+        [v] => Some([*v; 3]),
+        [r, g, b] => Some([*r, *g, *b]),
+        _ => None,
+    }
+}
+
+// bakes a height map into a tangent space normal map, heights are in texels
+pub(crate) fn bake_normal_map(bump: &::image::DynamicImage, strength: f32) -> ::image::RgbImage {
+    let height = bump.to_luma16();
+    let (w, h) = height.dimensions();
+    let at = |x: i64, y: i64| {
+        let x = x.rem_euclid(w as i64) as u32;
+        let y = y.rem_euclid(h as i64) as u32;
+        height.get_pixel(x, y)[0] as f32 / 65535.0
+    };
+    ::image::RgbImage::from_fn(w, h, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let dhdx = 0.5 * (at(x + 1, y) - at(x - 1, y));
+        // rows go down while v goes up
+        let dhdy = 0.5 * (at(x, y - 1) - at(x, y + 1));
+        let n = vec3(-strength * dhdx, -strength * dhdy, 1.0).normalize();
+        let rgb = (n + Vec3::ONE) * 0.5 * 255.0;
+        ::image::Rgb([
+            rgb.x.round() as u8,
+            rgb.y.round() as u8,
+            rgb.z.round() as u8,
+        ])
+    })
+}
+
+// a height map has a single channel or is gray, normal maps are mostly blue
+fn is_normal_map(image: &::image::DynamicImage) -> bool {
+    let rgb = image.to_rgb8();
+    let n = (rgb.width() * rgb.height()).max(1) as f32;
+    let mut avg = Vec3::ZERO;
+    for px in rgb.pixels() {
+        avg += vec3(px[0] as f32, px[1] as f32, px[2] as f32) / 255.0;
+    }
+    avg /= n;
+    avg.z > 0.7 && (avg.x - 0.5).abs() < 0.15 && (avg.y - 0.5).abs() < 0.15
+}
+
+fn invalid(key: &str, ignored: &mut Vec<String>) {
+    ignored.push(format!("{} (invalid)", key));
+}
+
+fn open_image(dir: &Path, path: &str) -> Option<::image::DynamicImage> {
+    match ::image::open(dir.join(path)) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            println!("warning: cannot read {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Maps the emission, opacity, ior and normal map fields of `fields` onto `bsdf`,
+/// returns the fields (and texture options) that were not mapped.
+/// `dir` is the directory of the obj file, texture paths stay relative to it.
+pub(crate) fn apply_mtl_fields(
+    bsdf: &mut node::Bsdf,
+    fields: &[(String, String)],
+    dir: &Path,
+) -> Vec<String> {
+    let (emission, opacity, ior, normal_map) = match bsdf {
+        node::Bsdf::Principled {
+            emission,
+            opacity,
+            ior,
+            normal_map,
+            ..
+        } => (emission, opacity, ior, normal_map),
+        _ => unreachable!(),
+    };
+    let mut ignored = vec![];
+    let get = |key: &str| {
+        fields
+            .iter()
+            .rev()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    };
+    let texture = |key: &str, ignored: &mut Vec<String>| -> Option<TextureMap> {
+        let map = parse_texture_map(get(key)?);
+        match &map {
+            Some(map) => {
+                ignored.extend(map.ignored.iter().map(|o| format!("{} {}", key, o)));
+            }
+            None => invalid(key, ignored),
+        }
+        map
+    };
+
+    let ke = get("Ke").and_then(|v| {
+        let rgb = parse_rgb(v);
+        if rgb.is_none() {
+            invalid("Ke", &mut ignored);
+        }
+        rgb
+    });
+    if let Some(map) = texture("map_Ke", &mut ignored) {
+        if ke.map_or(false, |ke| ke != [1.0; 3]) {
+            ignored.push("Ke (scale of map_Ke)".into());
+        }
+        *emission = node::SpectrumTexture::Image {
+            path: map.path,
+            colorspace: "srgb".into(),
+            cache: None,
+        };
+    } else if let Some(ke) = ke {
+        *emission = node::SpectrumTexture::SRgbLinear { values: ke };
+    }
+
+    let d = match (get("d"), get("Tr")) {
+        (Some(d), tr) => {
+            if tr.is_some() {
+                ignored.push("Tr (d takes precedence)".into());
+            }
+            parse_floats(d)
+                .and_then(|d| d.first().copied())
+                .or_else(|| {
+                    invalid("d", &mut ignored);
+                    None
+                })
+        }
+        (None, Some(tr)) => parse_floats(tr)
+            .and_then(|tr| tr.first().map(|tr| 1.0 - tr))
+            .or_else(|| {
+                invalid("Tr", &mut ignored);
+                None
+            }),
+        (None, None) => None,
+    };
+    if let Some(map) = texture("map_d", &mut ignored) {
+        if d.map_or(false, |d| d != 1.0) {
+            ignored.push("d (scale of map_d)".into());
+        }
+        let channel = match map.channel.as_deref() {
+            Some("r") => Some(node::ImageChannel::R),
+            Some("g") => Some(node::ImageChannel::G),
+            Some("b") => Some(node::ImageChannel::B),
+            Some("m") => Some(node::ImageChannel::A),
+            Some("l") => None,
+            Some(c) => {
+                ignored.push(format!("map_d -imfchan {}", c));
+                None
+            }
+            // an alpha channel is the matte, otherwise the luminance is used
+            None => open_image(dir, &map.path)
+                .filter(|image| image.color().has_alpha())
+                .map(|_| node::ImageChannel::A),
+        };
+        *opacity = node::FloatTexture::CachedImage {
+            path: map.path,
+            cache: None,
+            channel,
+        };
+    } else if let Some(d) = d {
+        *opacity = node::FloatTexture::Float(d.clamp(0.0, 1.0));
+    }
+
+    if let Some(ni) = get("Ni") {
+        match parse_floats(ni).as_deref() {
+            // exporters write 0 or 1 when the index is not set
+            Some([ni]) if *ni > 1.0 => *ior = node::FloatTexture::Float(*ni),
+            Some([_]) => ignored.push(format!("Ni {}", ni)),
+            _ => invalid("Ni", &mut ignored),
+        }
+    }
+
+    // norm is always a normal map, bump maps are told apart by their colors
+    let bump_keys = ["norm", "map_bump", "bump"];
+    let bumps: Vec<&str> = bump_keys
+        .iter()
+        .copied()
+        .filter(|k| get(k).is_some())
+        .collect();
+    if let Some(key) = bumps.first() {
+        ignored.extend(
+            bumps[1..]
+                .iter()
+                .map(|k| format!("{} ({} takes precedence)", k, key)),
+        );
+        if let Some(map) = texture(key, &mut ignored) {
+            if map.channel.is_some() {
+                ignored.push(format!("{} -imfchan", key));
+            }
+            let image = open_image(dir, &map.path);
+            match image {
+                Some(image) if *key != "norm" && !is_normal_map(&image) => {
+                    let src = Path::new(&map.path);
+                    let baked = src
+                        .with_file_name(format!(
+                            "{}_normal.png",
+                            src.file_stem().unwrap().to_string_lossy()
+                        ))
+                        .to_string_lossy()
+                        .replace("\\", "/");
+                    // never overwrite a file next to the source texture, an earlier bake is reused
+                    let target = dir.join(&baked);
+                    if target.exists() {
+                        println!(
+                            "warning: {} exists, using it as the normal map of {}",
+                            baked, map.path
+                        );
+                        *normal_map = Some(baked);
+                    } else {
+                        match bake_normal_map(&image, map.bump_multiplier).save(&target) {
+                            Ok(()) => {
+                                println!("baked height map {} into {}", map.path, baked);
+                                *normal_map = Some(baked);
+                            }
+                            Err(e) => println!("warning: cannot write {}: {}", baked, e),
+                        }
+                    }
+                }
+                _ => {
+                    if map.bump_multiplier != 1.0 {
+                        ignored.push(format!("{} -bm", key));
+                    }
+                    *normal_map = Some(map.path);
+                }
+            }
+        }
+    }
+
+    let handled = [
+        "ke", "map_ke", "d", "tr", "map_d", "ni", "norm", "map_bump", "bump",
+    ];
+    for (key, _) in fields {
+        let lower = key.to_ascii_lowercase();
+        if !handled.contains(&lower.as_str()) && !BASE_FIELDS.contains(&lower.as_str()) {
+            ignored.push(key.clone());
+        }
+    }
+    let mut seen = HashSet::new();
+    ignored.retain(|x| seen.insert(x.clone()));
+    ignored
+}
+
+mod test {
+    #[test]
+    fn test_mtl_fields() {
+        use super::*;
+        let text = "
+            # cornell box light
+            newmtl light
+            Kd 0.78 0.78 0.78
+            Ke 17 12 4
+            illum 2
+            newmtl leaves
+            map_Kd leaves.png
+            map_d -imfchan m -s 2 2 leaves.png
+            Ni 1.0
+            illum 2
+            refl -type sphere env.png
+            illum 2
+        ";
+        let materials = parse_mtl(text);
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials["light"][1], ("Ke".into(), "17 12 4".into()));
+
+        let map = parse_texture_map("-bm 0.5 -o 0.1 0.2 my bump.png").unwrap();
+        assert_eq!(map.path, "my bump.png");
+        assert_eq!(map.bump_multiplier, 0.5);
+        assert_eq!(map.ignored, vec!["-o".to_string()]);
+        assert!(parse_texture_map("-bm 0.5").is_none());
+
+        let mut bsdf = crate::default_principled();
+        let ignored = apply_mtl_fields(&mut bsdf, &materials["light"], Path::new("."));
+        assert_eq!(ignored, vec!["illum".to_string()]);
+        match &bsdf {
+            node::Bsdf::Principled {
+                emission: node::SpectrumTexture::SRgbLinear { values },
+                opacity: node::FloatTexture::Float(o),
+                ..
+            } => {
+                assert_eq!(*values, [17.0, 12.0, 4.0]);
+                assert_eq!(*o, 1.0);
+            }
+            _ => panic!("emission not mapped"),
+        }
+
+        let mut bsdf = crate::default_principled();
+        let ignored = apply_mtl_fields(&mut bsdf, &materials["leaves"], Path::new("."));
+        assert_eq!(
+            ignored,
+            vec![
+                "map_d -s".to_string(),
+                "Ni 1.0".to_string(),
+                "illum".to_string(),
+                "refl".to_string()
+            ]
+        );
+        assert!(matches!(
+            &bsdf,
+            node::Bsdf::Principled {
+                opacity: node::FloatTexture::CachedImage {
+                    channel: Some(node::ImageChannel::A),
+                    ..
+                },
+                ..
+            }
+        ));
+
+        let flat = ::image::DynamicImage::ImageLuma8(::image::GrayImage::from_pixel(
+            4,
+            4,
+            ::image::Luma([128]),
+        ));
+        let baked = bake_normal_map(&flat, 1.0);
+        assert_eq!(baked.get_pixel(1, 1).0, [128, 128, 255]);
+        assert!(!is_normal_map(&flat));
+    }
+    #[test]
+    fn test_bake_existing_target() {
+        use super::*;
+        let dir = akari::util::TempDirGuard::new("mtl");
+        ::image::GrayImage::from_pixel(4, 4, ::image::Luma([128]))
+            .save(dir.join("height.png"))
+            .unwrap();
+        let existing = ::image::RgbImage::from_pixel(2, 2, ::image::Rgb([1, 2, 3]));
+        existing.save(dir.join("height_normal.png")).unwrap();
+        let materials = parse_mtl("newmtl wall\nmap_bump height.png\n");
+        let mut bsdf = crate::default_principled();
+        apply_mtl_fields(&mut bsdf, &materials["wall"], &dir);
+        let kept = ::image::open(dir.join("height_normal.png"))
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(kept, existing);
+        assert!(matches!(
+            &bsdf,
+            node::Bsdf::Principled {
+                normal_map: Some(path),
+                ..
+            } if path == "height_normal.png"
+        ));
+    }
+}
@@ -0,0 +1,1413 @@
+use crate::ply_import::read_ply;
+use crate::{
+    add_emissive_bsdf, conductor_reflectance, default_principled, merge_bsdfs, trs_from_matrix,
+    write_mesh,
+};
+use akari::scenegraph::node;
+use akari::shape::TriangleMesh;
+use akari::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/* pbrt-v4 import
+ * pbrt is left handed, when the camera would otherwise need a reflection the whole scene is
+ * mirrored along x: meshes are written mirrored and every transform M becomes F * M * F
+ * object definitions become hidden groups and object instances groups instancing them
+ * light spectra are copied as is, rgb emission is relative to the illuminant like in pbrt
+ */
+
+// nesting limit of Include and Import, deeper files are skipped like recursive ones
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+#[derive(Clone, Debug)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Open,
+    Close,
+}
+
+fn line_of(text: &str, pos: usize) -> usize {
+    text[..pos].matches('\n').count() + 1
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '#' => {
+                while let Some((_, c)) = chars.next() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '[' => tokens.push(Token::Open),
+            ']' => tokens.push(Token::Close),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => s.push('\n'),
+                            Some((_, 't')) => s.push('\t'),
+                            Some((_, c)) => s.push(c),
+                            None => {
+                                return Err(format!(
+                                    "unterminated string on line {}",
+                                    line_of(text, i)
+                                ))
+                            }
+                        },
+                        Some((_, c)) => s.push(c),
+                        None => {
+                            return Err(format!("unterminated string on line {}", line_of(text, i)))
+                        }
+                    }
+                }
+                tokens.push(Token::Quoted(s));
+            }
+            c if c.is_whitespace() => {}
+            _ => {
+                let mut end = i + c.len_utf8();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '"' | '[' | ']' | '#') {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Word(text[i..end].into()));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// directives are the only bare words starting with an upper case letter
+fn is_directive(token: &Token) -> bool {
+    matches!(token, Token::Word(w) if w.starts_with(|c: char| c.is_ascii_uppercase()))
+}
+
+#[derive(Clone)]
+struct Param {
+    ty: String,
+    name: String,
+    values: Vec<String>,
+}
+#[derive(Clone, Default)]
+struct Params(Vec<Param>);
+impl Params {
+    fn parse(tokens: &[Token]) -> Result<Self, String> {
+        let mut params = vec![];
+        let mut tokens = tokens.iter();
+        while let Some(token) = tokens.next() {
+            let decl = match token {
+                Token::Quoted(decl) => decl,
+                t => return Err(format!("expected a parameter, found {:?}", t)),
+            };
+            let (ty, name) = match decl.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [ty, name] => (ty.to_string(), name.to_string()),
+                _ => return Err(format!("invalid parameter \"{}\"", decl)),
+            };
+            let mut values = vec![];
+            match tokens.next() {
+                Some(Token::Open) => loop {
+                    match tokens.next() {
+                        Some(Token::Close) => break,
+                        Some(Token::Word(v)) | Some(Token::Quoted(v)) => values.push(v.clone()),
+                        _ => return Err(format!("unterminated value of {}", name)),
+                    }
+                },
+                Some(Token::Word(v)) | Some(Token::Quoted(v)) => values.push(v.clone()),
+                _ => return Err(format!("missing value of {}", name)),
+            }
+            params.push(Param { ty, name, values });
+        }
+        Ok(Self(params))
+    }
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|p| p.name == name)
+    }
+    fn floats(&self, name: &str) -> Option<Vec<f32>> {
+        let p = self.get(name)?;
+        let values: Option<Vec<f32>> = p.values.iter().map(|v| v.parse().ok()).collect();
+        if values.is_none() {
+            println!("warning: parameter {} is not numeric, ignored", name);
+        }
+        values
+    }
+    fn float(&self, name: &str, default: f32) -> f32 {
+        match self.floats(name).as_deref() {
+            Some([v]) => *v,
+            _ => default,
+        }
+    }
+    fn string(&self, name: &str) -> Option<String> {
+        self.get(name).and_then(|p| p.values.first().cloned())
+    }
+    fn boolean(&self, name: &str, default: bool) -> bool {
+        self.string(name).map_or(default, |v| v == "true")
+    }
+}
+
+struct Directive {
+    name: String,
+    strings: Vec<String>,
+    numbers: Vec<f32>,
+    params: Params,
+}
+impl Directive {
+    fn parse(name: String, args: &[Token]) -> Result<Self, String> {
+        let positional = match name.as_str() {
+            "Texture" => 3,
+            "MediumInterface" => 2,
+            "Shape" | "Material" | "Film" | "Camera" | "Sampler" | "Integrator" | "PixelFilter"
+            | "Accelerator" | "ColorSpace" | "LightSource" | "AreaLightSource" | "Include"
+            | "Import" | "CoordinateSystem" | "CoordSysTransform" | "ObjectBegin"
+            | "ObjectInstance" | "NamedMaterial" | "MakeNamedMaterial" | "MakeNamedMedium"
+            | "Attribute" => 1,
+            _ => 0,
+        };
+        let mut strings = vec![];
+        let mut rest = args;
+        while strings.len() < positional {
+            match rest.first() {
+                Some(Token::Quoted(s)) => {
+                    strings.push(s.clone());
+                    rest = &rest[1..];
+                }
+                _ if name == "MediumInterface" && !strings.is_empty() => break,
+                _ => return Err(format!("{} expects {} name(s)", name, positional)),
+            }
+        }
+        let numeric = [
+            "Translate",
+            "Scale",
+            "Rotate",
+            "LookAt",
+            "Transform",
+            "ConcatTransform",
+            "TransformTimes",
+        ];
+        let mut numbers = vec![];
+        let mut params = Params::default();
+        if numeric.contains(&name.as_str()) {
+            for token in rest {
+                match token {
+                    Token::Word(w) => numbers.push(
+                        w.parse()
+                            .map_err(|_| format!("{} expects numbers, found {}", name, w))?,
+                    ),
+                    Token::Open | Token::Close => {}
+                    t => return Err(format!("{} expects numbers, found {:?}", name, t)),
+                }
+            }
+        } else {
+            params = Params::parse(rest).map_err(|e| format!("{}: {}", name, e))?;
+        }
+        let expected = match name.as_str() {
+            "Translate" | "Scale" => 3,
+            "Rotate" => 4,
+            "LookAt" => 9,
+            "Transform" | "ConcatTransform" => 16,
+            "TransformTimes" => 2,
+            _ => numbers.len(),
+        };
+        if numbers.len() != expected {
+            return Err(format!("{} expects {} numbers", name, expected));
+        }
+        Ok(Self {
+            name,
+            strings,
+            numbers,
+            params,
+        })
+    }
+}
+
+// refractive index at the d line of the named glasses
+fn glass_ior(name: &str) -> Option<f32> {
+    Some(match name {
+        "glass-BK7" => 1.5168,
+        "glass-BAF10" => 1.6700,
+        "glass-FK51A" => 1.4866,
+        "glass-LASF9" => 1.8503,
+        "glass-F5" => 1.6034,
+        "glass-F10" => 1.7283,
+        "glass-F11" => 1.7847,
+        _ => return None,
+    })
+}
+
+// pbrt's diffuse default
+fn default_diffuse() -> node::Bsdf {
+    node::Bsdf::Diffuse {
+        color: node::SpectrumTexture::SRgbLinear { values: [0.5; 3] },
+    }
+}
+
+fn scaled(tex: node::SpectrumTexture, s: f32) -> node::SpectrumTexture {
+    if s == 1.0 {
+        return tex;
+    }
+    match tex {
+        node::SpectrumTexture::SRgbLinear { values } => node::SpectrumTexture::SRgbLinear {
+            values: (Vec3::from(values) * s).into(),
+        },
+        node::SpectrumTexture::Blackbody { temperature, scale } => {
+            node::SpectrumTexture::Blackbody {
+                temperature,
+                scale: scale * s,
+            }
+        }
+        node::SpectrumTexture::Named { name, scale } => node::SpectrumTexture::Named {
+            name,
+            scale: scale * s,
+        },
+        node::SpectrumTexture::Tabulated {
+            values,
+            normalize,
+            scale,
+        } => node::SpectrumTexture::Tabulated {
+            values,
+            normalize,
+            scale: scale * s,
+        },
+        tex => {
+            println!("warning: scale {} of a textured emission is ignored", s);
+            tex
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ImageMap {
+    path: String,
+    linear: bool,
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Mat4,
+    // None for the interface material, whose shapes only bound media
+    material: Option<String>,
+    area_light: Option<node::SpectrumTexture>,
+    reverse_orientation: bool,
+    // transform directives only affect the end of the shutter interval
+    end_time_only: bool,
+}
+
+struct PbrtImporter {
+    dir: PathBuf,
+    stem: String,
+    mirror: Mat4,
+    state: GraphicsState,
+    attributes: Vec<GraphicsState>,
+    transforms: Vec<Mat4>,
+    coordinate_systems: HashMap<String, Mat4>,
+    // pbrt material name -> bsdf name, None for interface materials
+    materials: HashMap<String, Option<String>>,
+    textures: HashMap<String, Option<ImageMap>>,
+    res: (u32, u32),
+    // type, parameters and world from camera transform
+    camera_directive: Option<(String, Params, Mat4)>,
+    camera: Option<node::Camera>,
+    object: Option<node::Group>,
+    bsdfs: HashMap<String, node::Bsdf>,
+    shapes: Vec<node::Shape>,
+    lights: Vec<node::Light>,
+    groups: Vec<node::Group>,
+    anonymous_materials: usize,
+    meshes: usize,
+    instances: usize,
+    warned_motion: bool,
+    // canonical paths of the files being parsed, outermost first
+    includes: Vec<PathBuf>,
+}
+
+impl PbrtImporter {
+    fn group_name(&self, object: &str) -> String {
+        format!("{}_{}", self.stem, object)
+    }
+    fn spectrum(&self, params: &Params, name: &str) -> Option<node::SpectrumTexture> {
+        let p = params.get(name)?;
+        let invalid = || {
+            println!("warning: invalid {} {}, ignored", p.ty, name);
+            None
+        };
+        match p.ty.as_str() {
+            "rgb" => match params.floats(name).as_deref() {
+                Some([r, g, b]) => Some(node::SpectrumTexture::SRgbLinear {
+                    values: [*r, *g, *b],
+                }),
+                _ => invalid(),
+            },
+            "float" => match params.floats(name).as_deref() {
+                Some([v]) => Some(node::SpectrumTexture::SRgbLinear { values: [*v; 3] }),
+                _ => invalid(),
+            },
+            "blackbody" => match params.floats(name).as_deref() {
+                Some([temperature]) => Some(node::SpectrumTexture::Blackbody {
+                    temperature: *temperature,
+                    scale: 1.0,
+                }),
+                _ => invalid(),
+            },
+            "spectrum" => match p.values.as_slice() {
+                [named] if named.parse::<f32>().is_err() => {
+                    if named_spectrum(named).is_some() {
+                        Some(node::SpectrumTexture::Named {
+                            name: named.clone(),
+                            scale: 1.0,
+                        })
+                    } else {
+                        println!("warning: spectrum {} is not supported, ignored", named);
+                        None
+                    }
+                }
+                _ => match params.floats(name) {
+                    Some(v) if v.len() >= 4 && v.len() % 2 == 0 => {
+                        Some(node::SpectrumTexture::Tabulated {
+                            values: v.chunks_exact(2).map(|x| [x[0], x[1]]).collect(),
+                            normalize: false,
+                            scale: 1.0,
+                        })
+                    }
+                    _ => invalid(),
+                },
+            },
+            "texture" => self.texture_param(p).map(|t| node::SpectrumTexture::Image {
+                path: t.path,
+                colorspace: if t.linear { "linear" } else { "srgb" }.into(),
+                cache: None,
+            }),
+            _ => invalid(),
+        }
+    }
+    fn spectrum_or(&self, params: &Params, name: &str, default: f32) -> node::SpectrumTexture {
+        self.spectrum(params, name)
+            .unwrap_or(node::SpectrumTexture::SRgbLinear {
+                values: [default; 3],
+            })
+    }
+    fn float_texture(&self, params: &Params, name: &str, default: f32) -> node::FloatTexture {
+        match params.get(name) {
+            Some(p) if p.ty == "texture" => match self.texture_param(p) {
+                Some(t) => node::FloatTexture::Image(t.path),
+                None => node::FloatTexture::Float(default),
+            },
+            _ => node::FloatTexture::Float(params.float(name, default)),
+        }
+    }
+    fn texture(&self, name: &str) -> Option<ImageMap> {
+        match self.textures.get(name) {
+            Some(t) => t.clone(),
+            None => {
+                println!("warning: texture {} is not defined", name);
+                None
+            }
+        }
+    }
+    // the texture a "texture" parameter refers to, the brackets may be empty
+    fn texture_param(&self, p: &Param) -> Option<ImageMap> {
+        match p.values.first() {
+            Some(name) => self.texture(name),
+            None => {
+                println!("warning: texture {} names no texture, ignored", p.name);
+                None
+            }
+        }
+    }
+    // akari roughness, whose square is the microfacet alpha
+    fn roughness(&self, params: &Params, prefix: &str) -> f32 {
+        let get = |name: &str| match params.get(&format!("{}{}", prefix, name)) {
+            Some(p) if p.ty == "texture" => {
+                println!("warning: textured {}{} is not supported", prefix, name);
+                None
+            }
+            Some(_) => Some(params.float(&format!("{}{}", prefix, name), 0.0)),
+            None => None,
+        };
+        let r = match (get("roughness"), get("uroughness"), get("vroughness")) {
+            (_, Some(u), Some(v)) => {
+                if u != v {
+                    println!("warning: anisotropic roughness is averaged");
+                }
+                0.5 * (u + v)
+            }
+            (Some(r), _, _) => r,
+            _ => 0.0,
+        };
+        let alpha = if params.boolean("remaproughness", true) {
+            r.sqrt()
+        } else {
+            r
+        };
+        alpha.sqrt()
+    }
+    fn conductor_color(&self, params: &Params, prefix: &str) -> node::SpectrumTexture {
+        if let Some(reflectance) = self.spectrum(params, &format!("{}reflectance", prefix)) {
+            return reflectance;
+        }
+        let eta = format!("{}eta", prefix);
+        let k = format!("{}k", prefix);
+        let rgb = |name: &str| match self.spectrum(params, name) {
+            Some(node::SpectrumTexture::SRgbLinear { values }) => Some(Vec3::from(values)),
+            _ => None,
+        };
+        let f0 = match (params.get(&eta), params.get(&k)) {
+            (None, None) => conductor_reflectance("Cu").unwrap(),
+            (Some(p), _) if p.ty == "spectrum" => {
+                let named = p.values.first().map_or("", String::as_str);
+                named
+                    .strip_prefix("metal-")
+                    .and_then(|m| m.strip_suffix("-eta"))
+                    .and_then(conductor_reflectance)
+                    .unwrap_or_else(|| {
+                        println!(
+                            "warning: conductor {} is not supported, using copper",
+                            named
+                        );
+                        conductor_reflectance("Cu").unwrap()
+                    })
+            }
+            _ => match (rgb(&eta), rgb(&k)) {
+                (Some(eta), Some(k)) => {
+                    let one = Vec3::ONE;
+                    (((eta - one) * (eta - one) + k * k) / ((eta + one) * (eta + one) + k * k))
+                        .into()
+                }
+                _ => {
+                    println!("warning: conductor eta and k must both be rgb, using copper");
+                    conductor_reflectance("Cu").unwrap()
+                }
+            },
+        };
+        node::SpectrumTexture::SRgbLinear { values: f0 }
+    }
+    fn convert_material(&self, ty: &str, params: &Params) -> Option<node::Bsdf> {
+        if params.get("displacement").is_some() || params.get("normalmap").is_some() {
+            println!(
+                "warning: displacement and normal maps of {} are ignored",
+                ty
+            );
+        }
+        let principled = |f: &mut dyn FnMut(&mut node::Bsdf)| {
+            let mut bsdf = default_principled();
+            f(&mut bsdf);
+            Some(bsdf)
+        };
+        match ty {
+            "diffuse" => Some(node::Bsdf::Diffuse {
+                color: self.spectrum_or(params, "reflectance", 0.5),
+            }),
+            "coateddiffuse" => {
+                let reflectance = self.spectrum_or(params, "reflectance", 0.5);
+                let r = self.roughness(params, "");
+                principled(&mut |bsdf| {
+                    if let node::Bsdf::Principled {
+                        color,
+                        roughness,
+                        specular,
+                        ..
+                    } = bsdf
+                    {
+                        *color = reflectance.clone();
+                        *roughness = node::FloatTexture::Float(r);
+                        *specular = node::FloatTexture::Float(0.5);
+                    }
+                })
+            }
+            "conductor" | "coatedconductor" => {
+                let prefix = if ty == "conductor" {
+                    ""
+                } else {
+                    println!("warning: coating of coatedconductor is ignored");
+                    "conductor."
+                };
+                let f0 = self.conductor_color(params, prefix);
+                let r = self.roughness(params, prefix);
+                principled(&mut |bsdf| {
+                    if let node::Bsdf::Principled {
+                        color,
+                        metallic,
+                        roughness,
+                        ..
+                    } = bsdf
+                    {
+                        *color = f0.clone();
+                        *metallic = node::FloatTexture::Float(1.0);
+                        *roughness = node::FloatTexture::Float(r);
+                    }
+                })
+            }
+            "dielectric" | "thindielectric" => {
+                if ty == "thindielectric" {
+                    println!("warning: thindielectric is imported as a solid dielectric");
+                }
+                let eta = match params.get("eta") {
+                    Some(p) if p.ty == "spectrum" => {
+                        let named = p.values.first().map_or("", String::as_str);
+                        glass_ior(named).unwrap_or_else(|| {
+                            println!("warning: eta {} is not supported, using 1.5", named);
+                            1.5
+                        })
+                    }
+                    _ => params.float("eta", 1.5),
+                };
+                let r = self.roughness(params, "");
+                if r == 0.0 {
+                    Some(node::Bsdf::Glass {
+                        ior: eta,
+                        dispersion: 0.0,
+                        kr: node::SpectrumTexture::SRgbLinear { values: [1.0; 3] },
+                        kt: node::SpectrumTexture::SRgbLinear { values: [1.0; 3] },
+                    })
+                } else {
+                    principled(&mut |bsdf| {
+                        if let node::Bsdf::Principled {
+                            color,
+                            roughness,
+                            specular,
+                            ior,
+                            transmission,
+                            ..
+                        } = bsdf
+                        {
+                            *color = node::SpectrumTexture::SRgbLinear { values: [1.0; 3] };
+                            *roughness = node::FloatTexture::Float(r);
+                            *specular = node::FloatTexture::Float(0.5);
+                            *ior = node::FloatTexture::Float(eta);
+                            *transmission = node::FloatTexture::Float(1.0);
+                        }
+                    })
+                }
+            }
+            "mix" => {
+                let first = params
+                    .get("materials")
+                    .and_then(|p| p.values.first())
+                    .and_then(|m| self.materials.get(m))
+                    .and_then(|m| self.bsdfs.get(m.as_ref()?))
+                    .cloned();
+                println!("warning: mix is imported as its first material");
+                Some(first.unwrap_or_else(default_diffuse))
+            }
+            "interface" => None,
+            _ => {
+                println!("warning: material {} is not supported, using diffuse", ty);
+                Some(default_diffuse())
+            }
+        }
+    }
+    fn add_material(&mut self, name: Option<&str>, ty: &str, params: &Params) -> Option<String> {
+        let bsdf = self.convert_material(ty, params)?;
+        let bsdf_name = match name {
+            Some(name) => name.to_string(),
+            None => {
+                self.anonymous_materials += 1;
+                format!("{}_material_{}", self.stem, self.anonymous_materials)
+            }
+        };
+        self.bsdfs.insert(bsdf_name.clone(), bsdf);
+        Some(bsdf_name)
+    }
+    fn finish_camera(&mut self) {
+        let (ty, params, world_from_camera) = self.camera_directive.take().unwrap_or((
+            "perspective".into(),
+            Params::default(),
+            Mat4::IDENTITY,
+        ));
+        // akari cameras look down -z, pbrt ones down +z
+        let m = world_from_camera * Mat4::from_scale(vec3(1.0, 1.0, -1.0));
+        if m.determinant() < 0.0 {
+            self.mirror = Mat4::from_scale(vec3(-1.0, 1.0, 1.0));
+        }
+        let (trs, exact) = trs_from_matrix(self.mirror * m);
+        if !exact || trs.scale.iter().any(|s| (s - 1.0).abs() > 1e-3) {
+            println!("warning: scale and shear of the camera transform are ignored");
+        }
+        let transform = node::Transform::TRS(trs);
+        let res = self.res;
+        let aspect = res.0 as f32 / res.1 as f32;
+        self.camera = Some(match ty.as_str() {
+            "orthographic" => node::Camera::Orthographic {
+                res,
+                width: match params.floats("screenwindow").as_deref() {
+                    Some([x0, x1, _, _]) => x1 - x0,
+                    _ => 2.0 * aspect.max(1.0),
+                },
+                transform,
+                shutter: (0.0, 0.0),
+            },
+            _ => {
+                if ty != "perspective" {
+                    println!("warning: {} camera is imported as perspective", ty);
+                }
+                // pbrt's field of view spans the shorter side of the film, akari's the longer
+                let fov = params.float("fov", 90.0).to_radians();
+                let t = (0.5 * fov).tan() * aspect.max(1.0 / aspect);
+                node::Camera::Perspective {
+                    res,
+                    fov: (2.0 * t.atan()).to_degrees(),
+                    lens_radius: params.float("lensradius", 0.0),
+                    focal: params.float("focaldistance", 1e6),
+                    transform,
+                    shutter: (0.0, 0.0),
+                }
+            }
+        });
+    }
+    // object to world in the mirrored scene
+    fn world_transform(&self, m: Mat4) -> Mat4 {
+        self.mirror * m * self.mirror
+    }
+    fn add_mesh(&mut self, mut mesh: TriangleMesh) {
+        let material = match &self.state.material {
+            Some(m) => m.clone(),
+            None => {
+                println!("warning: shape with the interface material ignored");
+                return;
+            }
+        };
+        if self.mirror != Mat4::IDENTITY {
+            for v in &mut mesh.vertices {
+                v[0] = -v[0];
+            }
+            for n in &mut mesh.normals {
+                n[0] = -n[0];
+            }
+            for faces in [
+                &mut mesh.indices,
+                &mut mesh.normal_indices,
+                &mut mesh.texcoord_indices,
+            ] {
+                for f in faces.iter_mut() {
+                    f.swap(1, 2);
+                }
+            }
+        }
+        if self.state.reverse_orientation {
+            println!("warning: ReverseOrientation is ignored");
+        }
+        let m = self.world_transform(self.state.ctm);
+        let (trs, exact) = trs_from_matrix(m);
+        let transform = if m == Mat4::IDENTITY {
+            None
+        } else if exact {
+            Some(node::Transform::TRS(trs))
+        } else {
+            // sheared, baked into the vertices instead
+            let normal_matrix = Mat3::from_mat4(m).inverse().transpose();
+            for v in &mut mesh.vertices {
+                *v = m.transform_point3((*v).into()).into();
+            }
+            for n in &mut mesh.normals {
+                *n = (normal_matrix * Vec3::from(*n)).normalize().into();
+            }
+            None
+        };
+        let bsdf = match &self.state.area_light {
+            Some(emission) => add_emissive_bsdf(&mut self.bsdfs, &material, emission.clone()),
+            None => material,
+        };
+        self.meshes += 1;
+        let path = format!("{}_{}.mesh", self.stem, self.meshes);
+        write_mesh(&self.dir.join(&path), &mesh);
+        let shape = node::Shape::Mesh {
+            path,
+            bsdf,
+            transform,
+        };
+        match &mut self.object {
+            Some(group) => group.shapes.push(shape),
+            None => self.shapes.push(shape),
+        }
+    }
+    fn convert_shape(&mut self, ty: &str, params: &Params) {
+        if params.get("alpha").is_some() {
+            println!("warning: alpha of {} is ignored", ty);
+        }
+        let mesh = match ty {
+            "trianglemesh" | "bilinearmesh" | "loopsubdiv" => {
+                if ty == "loopsubdiv" {
+                    println!("warning: loopsubdiv is imported without subdivision");
+                }
+                let p = params.floats("P").unwrap_or_default();
+                let vertices: Vec<[f32; 3]> =
+                    p.chunks_exact(3).map(|v| [v[0], v[1], v[2]]).collect();
+                let indices: Vec<u32> = match params.floats("indices") {
+                    Some(indices) => indices.iter().map(|i| *i as u32).collect(),
+                    None if ty == "trianglemesh" && vertices.len() == 3 => vec![0, 1, 2],
+                    None if ty == "bilinearmesh" && vertices.len() == 4 => vec![0, 1, 2, 3],
+                    None => vec![],
+                };
+                let indices: Vec<[u32; 3]> = if ty == "bilinearmesh" {
+                    // p00 p10 p01 p11
+                    indices
+                        .chunks_exact(4)
+                        .flat_map(|q| [[q[0], q[1], q[3]], [q[0], q[3], q[2]]])
+                        .collect()
+                } else {
+                    indices
+                        .chunks_exact(3)
+                        .map(|f| [f[0], f[1], f[2]])
+                        .collect()
+                };
+                if indices.is_empty()
+                    || indices
+                        .iter()
+                        .any(|f| f.iter().any(|i| *i as usize >= vertices.len()))
+                {
+                    println!("warning: {} with invalid P or indices ignored", ty);
+                    return;
+                }
+                let normals: Vec<[f32; 3]> = params
+                    .floats("N")
+                    .filter(|n| n.len() == p.len())
+                    .map_or(vec![], |n| {
+                        n.chunks_exact(3).map(|v| [v[0], v[1], v[2]]).collect()
+                    });
+                let texcoords: Vec<[f32; 2]> = params
+                    .floats("uv")
+                    .or_else(|| params.floats("st"))
+                    .filter(|uv| uv.len() / 2 == vertices.len())
+                    .map_or(vec![], |uv| {
+                        uv.chunks_exact(2).map(|v| [v[0], v[1]]).collect()
+                    });
+                TriangleMesh {
+                    name: ty.into(),
+                    vertices,
+                    normal_indices: if normals.is_empty() {
+                        vec![]
+                    } else {
+                        indices.clone()
+                    },
+                    normals,
+                    texcoord_indices: if texcoords.is_empty() {
+                        vec![]
+                    } else {
+                        indices.clone()
+                    },
+                    texcoords,
+                    indices,
+                    colors: vec![],
+                }
+            }
+            "plymesh" => {
+                let filename = match params.string("filename") {
+                    Some(f) => f.replace("\\", "/"),
+                    None => {
+                        println!("warning: plymesh without filename ignored");
+                        return;
+                    }
+                };
+                if params.get("displacement").is_some() {
+                    println!("warning: displacement of {} is ignored", filename);
+                }
+                match read_ply(&self.dir.join(&filename)) {
+                    Ok(mesh) => mesh,
+                    Err(e) => {
+                        println!("warning: cannot read {}: {}, ignored", filename, e);
+                        return;
+                    }
+                }
+            }
+            _ => {
+                println!("warning: shape {} is not supported, ignored", ty);
+                return;
+            }
+        };
+        self.add_mesh(mesh);
+    }
+    fn convert_light(&mut self, ty: &str, params: &Params) {
+        let scale = params.float("scale", 1.0);
+        if params.get("power").is_some() {
+            println!(
+                "warning: power of {} light is ignored, using its spectrum",
+                ty
+            );
+        }
+        let point = |name: &str, default: Vec3| match params.floats(name).as_deref() {
+            Some([x, y, z]) => vec3(*x, *y, *z),
+            _ => default,
+        };
+        let m = self.world_transform(self.state.ctm);
+        let light = match ty {
+            "point" => node::Light::Point {
+                pos: m.transform_point3(point("from", Vec3::ZERO)).into(),
+                emission: scaled(self.spectrum_or(params, "I", 1.0), scale),
+            },
+            "spot" => {
+                let from = m.transform_point3(point("from", Vec3::ZERO));
+                let to = m.transform_point3(point("to", Vec3::Z));
+                let up = if (to - from).normalize().y.abs() < 0.99 {
+                    Vec3::Y
+                } else {
+                    Vec3::X
+                };
+                let cone = params.float("coneangle", 30.0);
+                node::Light::Spot {
+                    transform: node::Transform::LookAt(node::LookAt {
+                        eye: from.into(),
+                        center: to.into(),
+                        up: up.into(),
+                    }),
+                    emission: scaled(self.spectrum_or(params, "I", 1.0), scale),
+                    falloff: cone - params.float("conedelta", 5.0),
+                    max_angle: cone,
+                }
+            }
+            "distant" => node::Light::Directional {
+                direction: m
+                    .transform_vector3(point("to", Vec3::Z) - point("from", Vec3::ZERO))
+                    .normalize()
+                    .into(),
+                emission: scaled(self.spectrum_or(params, "L", 1.0), scale),
+                angular_diameter: 0.0,
+            },
+            "infinite" => {
+                if let Some(filename) = params.string("filename") {
+                    println!(
+                        "warning: {} uses pbrt's equal-area layout, convert it to a lat-long map and add it by hand",
+                        filename
+                    );
+                    return;
+                }
+                node::Light::Environment {
+                    emission: scaled(self.spectrum_or(params, "L", 1.0), scale),
+                    transform: None,
+                }
+            }
+            _ => {
+                println!("warning: {} light is not supported, ignored", ty);
+                return;
+            }
+        };
+        if self.object.is_some() {
+            println!("warning: light inside an object definition ignored");
+            return;
+        }
+        self.lights.push(light);
+    }
+    fn transform_directive(&mut self, d: &Directive) {
+        if self.state.end_time_only {
+            if !self.warned_motion {
+                println!("warning: motion blur is ignored, transforms are taken at the start time");
+                self.warned_motion = true;
+            }
+            return;
+        }
+        let n = &d.numbers;
+        let ctm = &mut self.state.ctm;
+        match d.name.as_str() {
+            "Translate" => *ctm = *ctm * Mat4::from_translation(vec3(n[0], n[1], n[2])),
+            "Scale" => *ctm = *ctm * Mat4::from_scale(vec3(n[0], n[1], n[2])),
+            "Rotate" => {
+                let axis = vec3(n[1], n[2], n[3]);
+                if axis.length() > 0.0 {
+                    *ctm = *ctm * Mat4::from_axis_angle(axis.normalize(), n[0].to_radians());
+                }
+            }
+            "LookAt" => {
+                let eye = vec3(n[0], n[1], n[2]);
+                let dir = (vec3(n[3], n[4], n[5]) - eye).normalize();
+                let right = vec3(n[6], n[7], n[8]).normalize().cross(dir).normalize();
+                let up = dir.cross(right);
+                let world_from_camera = Mat4::from_cols(
+                    right.extend(0.0),
+                    up.extend(0.0),
+                    dir.extend(0.0),
+                    eye.extend(1.0),
+                );
+                *ctm = *ctm * world_from_camera.inverse();
+            }
+            // the matrices are given column by column
+            "Transform" => *ctm = Mat4::from_cols_slice(n),
+            "ConcatTransform" => *ctm = *ctm * Mat4::from_cols_slice(n),
+            _ => unreachable!(),
+        }
+    }
+    fn directive(&mut self, d: Directive) {
+        let name = d.strings.first().cloned().unwrap_or_default();
+        match d.name.as_str() {
+            "Translate" | "Scale" | "Rotate" | "LookAt" | "Transform" | "ConcatTransform" => {
+                self.transform_directive(&d)
+            }
+            "Identity" => self.state.ctm = Mat4::IDENTITY,
+            "CoordinateSystem" => {
+                self.coordinate_systems.insert(name, self.state.ctm);
+            }
+            "CoordSysTransform" => match self.coordinate_systems.get(&name) {
+                Some(m) => self.state.ctm = *m,
+                None => println!("warning: coordinate system {} is not defined", name),
+            },
+            "TransformBegin" => self.transforms.push(self.state.ctm),
+            "TransformEnd" => match self.transforms.pop() {
+                Some(m) => self.state.ctm = m,
+                None => println!("warning: unmatched TransformEnd"),
+            },
+            "AttributeBegin" => self.attributes.push(self.state.clone()),
+            "AttributeEnd" => match self.attributes.pop() {
+                Some(state) => self.state = state,
+                None => println!("warning: unmatched AttributeEnd"),
+            },
+            "ReverseOrientation" => {
+                self.state.reverse_orientation = !self.state.reverse_orientation
+            }
+            "TransformTimes" => {}
+            "Camera" => {
+                let world_from_camera = self.state.ctm.inverse();
+                self.coordinate_systems
+                    .insert("camera".into(), world_from_camera);
+                self.camera_directive = Some((name, d.params, world_from_camera));
+            }
+            "Film" => {
+                self.res = (
+                    d.params.float("xresolution", 1280.0) as u32,
+                    d.params.float("yresolution", 720.0) as u32,
+                );
+            }
+            "Sampler" | "Integrator" | "PixelFilter" | "Accelerator" | "ColorSpace" | "Option" => {
+                println!("warning: {} {} is ignored", d.name, name)
+            }
+            "WorldBegin" => {
+                self.finish_camera();
+                self.state.ctm = Mat4::IDENTITY;
+                self.coordinate_systems
+                    .insert("world".into(), Mat4::IDENTITY);
+            }
+            "WorldEnd" => {}
+            "Include" | "Import" => self.parse_file(&self.dir.join(name.replace("\\", "/"))),
+            "MakeNamedMaterial" => match d.params.string("type") {
+                Some(ty) => {
+                    let bsdf = self.add_material(Some(&name), &ty, &d.params);
+                    self.materials.insert(name, bsdf);
+                }
+                None => println!("warning: material {} has no type, ignored", name),
+            },
+            "Material" => self.state.material = self.add_material(None, &name, &d.params),
+            "NamedMaterial" => match self.materials.get(&name) {
+                Some(bsdf) => self.state.material = bsdf.clone(),
+                None => println!("warning: material {} is not defined", name),
+            },
+            "Texture" => {
+                let class = &d.strings[2];
+                let texture = if class == "imagemap" {
+                    match d.params.string("filename") {
+                        Some(path) => {
+                            if d.params.float("scale", 1.0) != 1.0
+                                || d.params.boolean("invert", false)
+                            {
+                                println!(
+                                    "warning: scale and invert of texture {} are ignored",
+                                    name
+                                );
+                            }
+                            let linear = match d.params.string("encoding").as_deref() {
+                                Some("linear") => true,
+                                Some("sRGB") => false,
+                                Some(encoding) => {
+                                    println!(
+                                        "warning: encoding {} of texture {} is imported as sRGB",
+                                        encoding, name
+                                    );
+                                    false
+                                }
+                                None => {
+                                    let p = path.to_ascii_lowercase();
+                                    p.ends_with(".exr")
+                                        || p.ends_with(".pfm")
+                                        || p.ends_with(".hdr")
+                                }
+                            };
+                            Some(ImageMap {
+                                path: path.replace("\\", "/"),
+                                linear,
+                            })
+                        }
+                        None => None,
+                    }
+                } else {
+                    println!("warning: {} texture {} is not supported", class, name);
+                    None
+                };
+                self.textures.insert(name, texture);
+            }
+            "Shape" => self.convert_shape(&name, &d.params),
+            "LightSource" => self.convert_light(&name, &d.params),
+            "AreaLightSource" => {
+                if name != "diffuse" {
+                    println!("warning: area light {} is not supported", name);
+                    return;
+                }
+                if d.params.get("filename").is_some() {
+                    println!("warning: textured area light is imported with its L");
+                }
+                let emission = self.spectrum_or(&d.params, "L", 1.0);
+                self.state.area_light = Some(scaled(emission, d.params.float("scale", 1.0)));
+            }
+            "ObjectBegin" => {
+                if self.object.is_some() {
+                    println!("warning: nested ObjectBegin ignored");
+                    return;
+                }
+                self.attributes.push(self.state.clone());
+                self.object = Some(node::Group {
+                    name: self.group_name(&name),
+                    transform: None,
+                    hidden: true,
+                    instance: None,
+                    shapes: vec![],
+                    lights: vec![],
+                    groups: vec![],
+                });
+            }
+            "ObjectEnd" => match self.object.take() {
+                Some(group) => {
+                    self.groups.push(group);
+                    if let Some(state) = self.attributes.pop() {
+                        self.state = state;
+                    }
+                }
+                None => println!("warning: unmatched ObjectEnd"),
+            },
+            "ObjectInstance" => {
+                let group = self.group_name(&name);
+                if !self.groups.iter().any(|g| g.name == group) {
+                    println!("warning: object {} is not defined", name);
+                    return;
+                }
+                let m = self.world_transform(self.state.ctm);
+                let (trs, exact) = trs_from_matrix(m);
+                if !exact {
+                    println!(
+                        "warning: instance of {} is sheared, its transform is approximated",
+                        name
+                    );
+                }
+                self.instances += 1;
+                self.groups.push(node::Group {
+                    name: format!("{}_instance_{}", group, self.instances),
+                    transform: (m != Mat4::IDENTITY).then(|| node::Transform::TRS(trs)),
+                    hidden: false,
+                    instance: Some(group),
+                    shapes: vec![],
+                    lights: vec![],
+                    groups: vec![],
+                });
+            }
+            "MakeNamedMedium" | "MediumInterface" => {
+                println!(
+                    "warning: participating media are not supported, {} ignored",
+                    d.name
+                )
+            }
+            "Attribute" => println!("warning: Attribute {} is ignored", name),
+            _ => println!("warning: unknown directive {}", d.name),
+        }
+    }
+    fn parse_file(&mut self, path: &Path) {
+        let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.includes.contains(&canonical) || self.includes.len() >= MAX_INCLUDE_DEPTH {
+            println!(
+                "warning: {} is included recursively, ignored",
+                path.display()
+            );
+            return;
+        }
+        self.includes.push(canonical);
+        self.parse_tokens(path);
+        self.includes.pop();
+    }
+    fn parse_tokens(&mut self, path: &Path) {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("cannot read {}: {}", path.display(), e);
+                exit(1);
+            }
+        };
+        let tokens = tokenize(&text).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            exit(1);
+        });
+        let mut i = 0;
+        while i < tokens.len() {
+            let name = match &tokens[i] {
+                Token::Word(w) if is_directive(&tokens[i]) => w.clone(),
+                t => {
+                    eprintln!("{}: expected a directive, found {:?}", path.display(), t);
+                    exit(1);
+                }
+            };
+            i += 1;
+            // the only directive taking a bare word
+            if name == "ActiveTransform" {
+                match tokens.get(i) {
+                    Some(Token::Word(w)) => self.state.end_time_only = w == "EndTime",
+                    _ => println!("warning: ActiveTransform without a time"),
+                }
+                i += 1;
+                continue;
+            }
+            let end = tokens[i..]
+                .iter()
+                .position(is_directive)
+                .map_or(tokens.len(), |n| i + n);
+            let directive = Directive::parse(name, &tokens[i..end]).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path.display(), e);
+                exit(1);
+            });
+            i = end;
+            self.directive(directive);
+        }
+    }
+}
+
+pub(crate) fn import_pbrt(path: &str, scene: &mut node::Scene, forced: bool) {
+    let model_path = Path::new(path);
+    let stem: String = model_path.file_stem().unwrap().to_str().unwrap().into();
+    let default_material = format!("{}_default", stem);
+    let mut importer = PbrtImporter {
+        dir: model_path.parent().unwrap().into(),
+        stem,
+        mirror: Mat4::IDENTITY,
+        state: GraphicsState {
+            ctm: Mat4::IDENTITY,
+            material: Some(default_material.clone()),
+            area_light: None,
+            reverse_orientation: false,
+            end_time_only: false,
+        },
+        attributes: vec![],
+        transforms: vec![],
+        coordinate_systems: HashMap::new(),
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        res: (1280, 720),
+        camera_directive: None,
+        camera: None,
+        object: None,
+        bsdfs: HashMap::new(),
+        shapes: vec![],
+        lights: vec![],
+        groups: vec![],
+        anonymous_materials: 0,
+        meshes: 0,
+        instances: 0,
+        warned_motion: false,
+        includes: vec![],
+    };
+    importer.bsdfs.insert(default_material, default_diffuse());
+    importer.parse_file(model_path);
+    if importer.camera.is_none() {
+        println!("warning: no WorldBegin, the file is imported as a scene fragment");
+    }
+    if importer.object.is_some() {
+        println!("warning: unterminated ObjectBegin");
+    }
+    println!("# of meshes: {}", importer.meshes);
+    println!("# of materials: {}", importer.bsdfs.len());
+    println!("# of lights: {}", importer.lights.len());
+    println!("# of instances: {}", importer.instances);
+    merge_bsdfs(scene, &importer.bsdfs, forced);
+    scene.shapes.extend(importer.shapes);
+    scene.lights.extend(importer.lights);
+    scene.groups.extend(importer.groups);
+    if let Some(camera) = importer.camera {
+        scene.camera = Some(camera);
+    }
+}
+
+mod test {
+    #[test]
+    fn test_pbrt_directives() {
+        use super::*;
+        let text = r#"
+            # comment with "quotes" and [brackets]
+            LookAt 0 0 5  0 0 0  0 1 0
+            Camera "perspective" "float fov" [ 45 ]
+            Shape "trianglemesh" "point3 P" [0 0 0 1 0 0 1 1 0] "integer indices" 0 1 2
+                "string name" "tri"
+            MakeNamedMaterial "red wall" "string type" "diffuse" "rgb reflectance" [0.5 0 0]
+            ActiveTransform EndTime
+        "#;
+        let tokens = tokenize(text).unwrap();
+        assert!(matches!(&tokens[0], Token::Word(w) if w == "LookAt"));
+        let end = tokens[1..].iter().position(is_directive).unwrap() + 1;
+        let look_at = Directive::parse("LookAt".into(), &tokens[1..end]).unwrap();
+        assert_eq!(look_at.numbers.len(), 9);
+
+        let shape = tokens
+            .iter()
+            .position(|t| matches!(t, Token::Word(w) if w == "Shape"))
+            .unwrap();
+        let end = tokens[shape + 1..].iter().position(is_directive).unwrap() + shape + 1;
+        let shape = Directive::parse("Shape".into(), &tokens[shape + 1..end]).unwrap();
+        assert_eq!(shape.strings, vec!["trianglemesh".to_string()]);
+        assert_eq!(shape.params.floats("indices"), Some(vec![0.0, 1.0, 2.0]));
+        assert_eq!(shape.params.string("name").as_deref(), Some("tri"));
+
+        let material = tokens
+            .iter()
+            .position(|t| matches!(t, Token::Word(w) if w == "MakeNamedMaterial"))
+            .unwrap();
+        let end = tokens[material + 1..]
+            .iter()
+            .position(is_directive)
+            .unwrap()
+            + material
+            + 1;
+        let material =
+            Directive::parse("MakeNamedMaterial".into(), &tokens[material + 1..end]).unwrap();
+        assert_eq!(material.strings, vec!["red wall".to_string()]);
+        assert_eq!(material.params.get("reflectance").unwrap().ty, "rgb");
+
+        assert!(tokenize("Shape \"sphere").is_err());
+        assert!(Directive::parse("Translate".into(), &tokens[1..3]).is_err());
+    }
+    #[test]
+    fn test_pbrt_scene() {
+        use super::*;
+        use akari::util::binserde::Decode;
+        let dir = akari::util::TempDirGuard::new("pbrt");
+        let text = r#"
+            LookAt 0 0 5  0 0 0  0 1 0
+            Camera "perspective" "float fov" [ 45 ]
+            Film "rgb" "integer xresolution" [ 200 ] "integer yresolution" [ 100 ]
+            WorldBegin
+            MakeNamedMaterial "red" "string type" "diffuse" "rgb reflectance" [ 0.5 0 0 ]
+            MakeNamedMaterial "blank" "string type" "diffuse" "texture reflectance" [ ]
+            MakeNamedMaterial "clear" "string type" "dielectric" "spectrum eta" [ ]
+            AttributeBegin
+                NamedMaterial "red"
+                Translate 1 2 3
+                Rotate 90 0 1 0
+                Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ] "integer indices" [ 0 1 2 ]
+            AttributeEnd
+            ObjectBegin "tree"
+                Material "dielectric" "float eta" 1.33
+                Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ] "integer indices" [ 0 1 2 ]
+            ObjectEnd
+            Translate 5 0 0
+            ObjectInstance "tree"
+        "#;
+        let path = dir.join("scene.pbrt");
+        std::fs::write(&path, text).unwrap();
+        let mut scene = node::Scene::default();
+        import_pbrt(path.to_str().unwrap(), &mut scene, false);
+        // T * Ry * Rx * Rz * S, see trs_from_matrix
+        let matrix = |transform: &Option<node::Transform>| match transform {
+            Some(node::Transform::TRS(trs)) => {
+                let [x, y, z] = trs.rotate;
+                Mat4::from_translation(trs.translate.into())
+                    * Mat4::from_rotation_y(y.to_radians())
+                    * Mat4::from_rotation_x(x.to_radians())
+                    * Mat4::from_rotation_z(z.to_radians())
+                    * Mat4::from_scale(trs.scale.into())
+            }
+            None => Mat4::IDENTITY,
+            _ => panic!("unexpected transform"),
+        };
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-4;
+
+        // LookAt needs a reflection in akari's right handed space, so the scene is mirrored
+        // along x and the camera ends up unrotated at the mirrored eye
+        match &scene.camera {
+            Some(node::Camera::Perspective {
+                res,
+                fov,
+                transform,
+                ..
+            }) => {
+                assert_eq!(*res, (200, 100));
+                // 45 degrees over the shorter side of the film
+                let expected = 2.0 * ((22.5f32).to_radians().tan() * 2.0).atan();
+                assert!((fov.to_radians() - expected).abs() < 1e-4, "{}", fov);
+                let m = matrix(&Some(transform.clone()));
+                assert!(close(m.transform_point3(Vec3::ZERO), vec3(0.0, 0.0, 5.0)));
+                assert!(close(m.transform_vector3(-Vec3::Z), -Vec3::Z));
+                assert!(close(m.transform_vector3(Vec3::Y), Vec3::Y));
+            }
+            _ => panic!("camera not imported"),
+        }
+
+        let read_mesh = |path: &String| {
+            TriangleMesh::decode(&mut std::fs::File::open(dir.join(path)).unwrap()).unwrap()
+        };
+        assert_eq!(scene.shapes.len(), 1);
+        let node::Shape::Mesh {
+            path,
+            bsdf,
+            transform,
+        } = &scene.shapes[0];
+        assert_eq!(bsdf, "red");
+        match &scene.bsdfs["red"] {
+            node::Bsdf::Diffuse {
+                color: node::SpectrumTexture::SRgbLinear { values },
+            } => assert_eq!(*values, [0.5, 0.0, 0.0]),
+            _ => panic!("diffuse not mapped"),
+        }
+        // empty brackets fall back to the defaults
+        assert!(matches!(
+            &scene.bsdfs["blank"],
+            node::Bsdf::Diffuse {
+                color: node::SpectrumTexture::SRgbLinear { .. }
+            }
+        ));
+        assert!(matches!(&scene.bsdfs["clear"], node::Bsdf::Glass { ior, .. } if *ior == 1.5));
+        // mirrored vertices with the winding swapped to keep the facing
+        let mesh = read_mesh(path);
+        assert_eq!(
+            mesh.vertices,
+            vec![[0.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(mesh.indices, vec![[0, 2, 1]]);
+        // pbrt places (1, 0, 0) at (1, 2, 2), which is mirrored to (-1, 2, 2)
+        assert!(close(
+            matrix(transform).transform_point3(vec3(-1.0, 0.0, 0.0)),
+            vec3(-1.0, 2.0, 2.0)
+        ));
+
+        assert_eq!(scene.groups.len(), 2);
+        let object = &scene.groups[0];
+        assert_eq!(object.name, "scene_tree");
+        assert!(object.hidden && object.instance.is_none());
+        assert_eq!(object.shapes.len(), 1);
+        let node::Shape::Mesh { bsdf, .. } = &object.shapes[0];
+        assert!(matches!(&scene.bsdfs[bsdf], node::Bsdf::Glass { ior, .. } if *ior == 1.33));
+        let instance = &scene.groups[1];
+        assert!(!instance.hidden);
+        assert_eq!(instance.instance.as_deref(), Some("scene_tree"));
+        assert!(close(
+            matrix(&instance.transform).transform_point3(Vec3::ZERO),
+            vec3(-5.0, 0.0, 0.0)
+        ));
+    }
+    #[test]
+    fn test_pbrt_include_cycle() {
+        use super::*;
+        let dir = akari::util::TempDirGuard::new("pbrt-include");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let shape = r#"Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ] "integer indices" [ 0 1 2 ]"#;
+        std::fs::write(
+            dir.join("a.pbrt"),
+            format!("Include \"b.pbrt\"\n{}\n", shape),
+        )
+        .unwrap();
+        // the same file under another name is still a cycle
+        std::fs::write(
+            dir.join("b.pbrt"),
+            format!("Include \"sub/../a.pbrt\"\n{}\n", shape),
+        )
+        .unwrap();
+        let mut scene = node::Scene::default();
+        import_pbrt(dir.join("a.pbrt").to_str().unwrap(), &mut scene, false);
+        assert_eq!(scene.shapes.len(), 2);
+    }
+}
@@ -0,0 +1,539 @@
+use crate::{default_principled, merge_bsdfs, write_mesh};
+use akari::scenegraph::node;
+use akari::shape::TriangleMesh;
+use akari::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::process::exit;
+
+/* PLY import, ascii and binary
+ * vertex positions, normals, texcoords and colors are read, faces are triangulated as fans
+ * other elements and properties are skipped
+ */
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+#[derive(Clone, Copy, Debug)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+impl Scalar {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+    fn is_integer(self) -> bool {
+        !matches!(self, Scalar::F32 | Scalar::F64)
+    }
+    // value that maps to 1.0 for color channels
+    fn color_scale(self) -> f32 {
+        match self {
+            Scalar::U8 => 255.0,
+            Scalar::U16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+enum PropertyType {
+    Scalar(Scalar),
+    // count type, item type
+    List(Scalar, Scalar),
+}
+struct Property {
+    name: String,
+    ty: PropertyType,
+}
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn invalid_data(what: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+struct PlyReader<R> {
+    reader: R,
+    format: Format,
+    tokens: std::vec::IntoIter<String>,
+    line: String,
+}
+impl<R: BufRead> PlyReader<R> {
+    fn next_token(&mut self) -> io::Result<String> {
+        loop {
+            if let Some(token) = self.tokens.next() {
+                return Ok(token);
+            }
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.tokens = self
+                .line
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+                .into_iter();
+        }
+    }
+    fn read(&mut self, ty: Scalar) -> io::Result<f64> {
+        if self.format == Format::Ascii {
+            let token = self.next_token()?;
+            return token
+                .parse::<f64>()
+                .map_err(|_| invalid_data(format!("expected a number, found {}", token)));
+        }
+        let mut buf = [0u8; 8];
+        self.reader.read_exact(&mut buf[..ty.size()])?;
+        let le = self.format == Format::BinaryLittleEndian;
+        macro_rules! decode {
+            ($t:ty, $n:expr) => {{
+                let mut b = [0u8; $n];
+                b.copy_from_slice(&buf[..$n]);
+                (if le {
+                    <$t>::from_le_bytes(b)
+                } else {
+                    <$t>::from_be_bytes(b)
+                }) as f64
+            }};
+        }
+        Ok(match ty {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => decode!(i16, 2),
+            Scalar::U16 => decode!(u16, 2),
+            Scalar::I32 => decode!(i32, 4),
+            Scalar::U32 => decode!(u32, 4),
+            Scalar::F32 => decode!(f32, 4),
+            Scalar::F64 => decode!(f64, 8),
+        })
+    }
+    fn read_list(
+        &mut self,
+        count_ty: Scalar,
+        item_ty: Scalar,
+        out: &mut Vec<f64>,
+    ) -> io::Result<()> {
+        out.clear();
+        let count = self.read(count_ty)?;
+        if count < 0.0 {
+            return Err(invalid_data(format!("negative list length {}", count)));
+        }
+        for _ in 0..count as usize {
+            out.push(self.read(item_ty)?);
+        }
+        Ok(())
+    }
+}
+
+fn parse_header<R: BufRead>(reader: &mut R) -> io::Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    let mut next_line = |line: &mut String| -> io::Result<()> {
+        line.clear();
+        if reader.read_line(line)? == 0 {
+            return Err(invalid_data("unexpected end of header".into()));
+        }
+        Ok(())
+    };
+    next_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(invalid_data("not a ply file".into()));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    loop {
+        next_line(&mut line)?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["end_header"] => break,
+            ["format", fmt, _version] => {
+                format = Some(match *fmt {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid_data(format!("unknown format {}", fmt))),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["element", name, count] => {
+                let count = count
+                    .parse()
+                    .map_err(|_| invalid_data(format!("invalid element count {}", count)))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: vec![],
+                });
+            }
+            ["property", rest @ ..] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("property outside of an element".into()))?;
+                let scalar = |s: &str| {
+                    Scalar::parse(s)
+                        .ok_or_else(|| invalid_data(format!("unknown property type {}", s)))
+                };
+                let (ty, name) = match rest {
+                    ["list", count_ty, item_ty, name] => (
+                        PropertyType::List(scalar(*count_ty)?, scalar(*item_ty)?),
+                        name,
+                    ),
+                    [ty, name] => (PropertyType::Scalar(scalar(*ty)?), name),
+                    _ => {
+                        return Err(invalid_data(format!(
+                            "invalid property {}",
+                            line.trim_end()
+                        )))
+                    }
+                };
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    ty,
+                });
+            }
+            _ => {
+                return Err(invalid_data(format!(
+                    "invalid header line {}",
+                    line.trim_end()
+                )))
+            }
+        }
+    }
+    let format = format.ok_or_else(|| invalid_data("missing format".into()))?;
+    Ok((format, elements))
+}
+
+pub(crate) fn parse_ply<R: BufRead>(mut reader: R, name: &str) -> io::Result<TriangleMesh> {
+    let (format, elements) = parse_header(&mut reader)?;
+    let mut reader = PlyReader {
+        reader,
+        format,
+        tokens: vec![].into_iter(),
+        line: String::new(),
+    };
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut texcoords = vec![];
+    let mut colors = vec![];
+    // integer colors are stored in srgb, float colors are linear
+    let mut srgb_colors = false;
+    let mut indices = vec![];
+    let mut list = vec![];
+    for element in &elements {
+        // slot of every property: 0..3 position, 3..6 normal, 6..8 texcoord, 8..11 color
+        let slots: Vec<Option<usize>> = element
+            .properties
+            .iter()
+            .map(|p| match (element.name.as_str(), &p.ty, p.name.as_str()) {
+                ("vertex", PropertyType::Scalar(_), name) => match name {
+                    "x" => Some(0),
+                    "y" => Some(1),
+                    "z" => Some(2),
+                    "nx" => Some(3),
+                    "ny" => Some(4),
+                    "nz" => Some(5),
+                    "u" | "s" | "texture_u" | "texture_s" => Some(6),
+                    "v" | "t" | "texture_v" | "texture_t" => Some(7),
+                    "red" | "r" => Some(8),
+                    "green" | "g" => Some(9),
+                    "blue" | "b" => Some(10),
+                    _ => None,
+                },
+                ("face", PropertyType::List(..), "vertex_indices" | "vertex_index") => Some(0),
+                _ => None,
+            })
+            .collect();
+        let has = |slot: usize| slots.iter().any(|s| *s == Some(slot));
+        for (p, slot) in element.properties.iter().zip(slots.iter()) {
+            if slot.is_none() && ["vertex", "face"].contains(&element.name.as_str()) {
+                println!(
+                    "warning: {}: property {}.{} ignored",
+                    name, element.name, p.name
+                );
+            }
+        }
+        match element.name.as_str() {
+            "vertex" => {
+                if !(has(0) && has(1) && has(2)) {
+                    return Err(invalid_data("vertex element without x, y, z".into()));
+                }
+                let (has_normal, has_uv, has_color) = (
+                    has(3) && has(4) && has(5),
+                    has(6) && has(7),
+                    has(8) && has(9) && has(10),
+                );
+                srgb_colors = element
+                    .properties
+                    .iter()
+                    .zip(slots.iter())
+                    .any(|(p, slot)| match (&p.ty, slot) {
+                        (PropertyType::Scalar(ty), Some(8..=10)) => ty.is_integer(),
+                        _ => false,
+                    });
+                vertices.reserve(element.count);
+                let mut values = [0.0f32; 11];
+                for _ in 0..element.count {
+                    for (p, slot) in element.properties.iter().zip(slots.iter()) {
+                        match (&p.ty, slot) {
+                            (PropertyType::Scalar(ty), Some(slot)) => {
+                                let scale = if *slot >= 8 { ty.color_scale() } else { 1.0 };
+                                values[*slot] = reader.read(*ty)? as f32 / scale;
+                            }
+                            (PropertyType::Scalar(ty), None) => {
+                                reader.read(*ty)?;
+                            }
+                            (PropertyType::List(count_ty, item_ty), _) => {
+                                reader.read_list(*count_ty, *item_ty, &mut list)?;
+                            }
+                        }
+                    }
+                    vertices.push([values[0], values[1], values[2]]);
+                    if has_normal {
+                        normals.push([values[3], values[4], values[5]]);
+                    }
+                    if has_uv {
+                        texcoords.push([values[6], values[7]]);
+                    }
+                    if has_color {
+                        colors.push([values[8], values[9], values[10]]);
+                    }
+                }
+            }
+            "face" => {
+                if !has(0) {
+                    return Err(invalid_data("face element without vertex_indices".into()));
+                }
+                for (p, slot) in element.properties.iter().zip(slots.iter()) {
+                    match (&p.ty, slot) {
+                        (PropertyType::List(_, item_ty), Some(_)) if !item_ty.is_integer() => {
+                            return Err(invalid_data(format!(
+                                "vertex indices of type {:?} are not integers",
+                                item_ty
+                            )));
+                        }
+                        _ => {}
+                    }
+                }
+                indices.reserve(element.count);
+                let mut polygon = vec![];
+                for _ in 0..element.count {
+                    for (p, slot) in element.properties.iter().zip(slots.iter()) {
+                        match &p.ty {
+                            PropertyType::Scalar(ty) => {
+                                reader.read(*ty)?;
+                            }
+                            PropertyType::List(count_ty, item_ty) => {
+                                let out = if slot.is_some() {
+                                    &mut polygon
+                                } else {
+                                    &mut list
+                                };
+                                reader.read_list(*count_ty, *item_ty, out)?;
+                            }
+                        }
+                    }
+                    if let Some(i) = polygon
+                        .iter()
+                        .find(|i| !(0.0..=u32::MAX as f64).contains(*i) || i.fract() != 0.0)
+                    {
+                        return Err(invalid_data(format!("invalid vertex index {}", i)));
+                    }
+                    for i in 1..polygon.len().saturating_sub(1) {
+                        indices.push([polygon[0] as u32, polygon[i] as u32, polygon[i + 1] as u32]);
+                    }
+                }
+            }
+            _ => {
+                println!("warning: {}: element {} ignored", name, element.name);
+                for _ in 0..element.count {
+                    for p in &element.properties {
+                        match &p.ty {
+                            PropertyType::Scalar(ty) => {
+                                reader.read(*ty)?;
+                            }
+                            PropertyType::List(count_ty, item_ty) => {
+                                reader.read_list(*count_ty, *item_ty, &mut list)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if let Some(face) = indices
+        .iter()
+        .find(|f| f.iter().any(|i| *i as usize >= vertices.len()))
+    {
+        return Err(invalid_data(format!(
+            "face {:?} references a vertex out of {}",
+            face,
+            vertices.len()
+        )));
+    }
+    let colors = if srgb_colors {
+        colors
+            .into_iter()
+            .map(|c| srgb_to_linear(c.into()).into())
+            .collect()
+    } else {
+        colors
+    };
+    Ok(TriangleMesh {
+        name: name.into(),
+        vertices,
+        normal_indices: if normals.is_empty() {
+            vec![]
+        } else {
+            indices.clone()
+        },
+        normals,
+        texcoord_indices: if texcoords.is_empty() {
+            vec![]
+        } else {
+            indices.clone()
+        },
+        texcoords,
+        indices,
+        colors,
+    })
+}
+
+pub(crate) fn read_ply(path: &Path) -> io::Result<TriangleMesh> {
+    let name = path.file_stem().unwrap().to_string_lossy();
+    parse_ply(BufReader::new(File::open(path)?), &name)
+}
+
+pub(crate) fn import_ply(path: &str, scene: &mut node::Scene, forced: bool) {
+    let model_path = Path::new(path);
+    let mesh = match read_ply(model_path) {
+        Ok(mesh) => mesh,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path, e);
+            exit(1);
+        }
+    };
+    let stem = model_path.file_stem().unwrap().to_str().unwrap();
+    println!("# of vertices: {}", mesh.vertices.len());
+    println!("# of triangles: {}", mesh.indices.len());
+    let mut bsdf = default_principled();
+    if !mesh.colors.is_empty() {
+        println!("vertex colors are used as the material color");
+        if let node::Bsdf::Principled { color, .. } = &mut bsdf {
+            *color = node::SpectrumTexture::VertexColor {};
+        }
+    }
+    let bsdf_name = format!("{}_material", stem);
+    let mesh_name = format!("{}.mesh", stem);
+    write_mesh(&model_path.parent().unwrap().join(&mesh_name), &mesh);
+    let mut bsdfs = HashMap::new();
+    bsdfs.insert(bsdf_name.clone(), bsdf);
+    merge_bsdfs(scene, &bsdfs, forced);
+    scene.shapes.push(node::Shape::Mesh {
+        path: mesh_name,
+        bsdf: bsdf_name,
+        transform: None,
+    });
+}
+
+mod test {
+    #[test]
+    fn test_parse_ply() {
+        use super::*;
+        let ascii = "ply\nformat ascii 1.0\ncomment quad\nelement vertex 4\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float u\nproperty float v\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0 0 0 255 255 255\n1 0 0 1 0 255 255 255\n1 1 0 1 1 0 0 0\n0 1 0 0 1 0 0 0\n\
+            4 0 1 2 3\n";
+        let mesh = parse_ply(ascii.as_bytes(), "quad").unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.texcoords[2], [1.0, 1.0]);
+        assert!(mesh.normals.is_empty());
+        assert_eq!(mesh.colors.len(), 4);
+        assert!((mesh.colors[0][0] - 1.0).abs() < 1e-4);
+
+        // the same triangle in big endian, with an ignored element in between
+        let mut binary = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            element edge 1\nproperty int vertex1\nproperty int vertex2\n\
+            element face 1\nproperty list uchar uint vertex_indices\nend_header\n"
+            .to_vec();
+        for v in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]] {
+            for x in v.iter().chain([0.0f32, 0.0, 1.0].iter()) {
+                binary.extend_from_slice(&x.to_be_bytes());
+            }
+        }
+        binary.extend_from_slice(&0i32.to_be_bytes());
+        binary.extend_from_slice(&1i32.to_be_bytes());
+        binary.push(3);
+        for i in 0..3u32 {
+            binary.extend_from_slice(&i.to_be_bytes());
+        }
+        let mesh = parse_ply(binary.as_slice(), "tri").unwrap();
+        assert_eq!(mesh.vertices[2], [1.0, 1.0, 0.0]);
+        assert_eq!(mesh.normals[1], [0.0, 0.0, 1.0]);
+        assert_eq!(mesh.normal_indices, vec![[0, 1, 2]]);
+        assert!(mesh.colors.is_empty());
+
+        let truncated = &binary[..binary.len() - 2];
+        assert!(parse_ply(truncated, "tri").is_err());
+
+        // float colors are linear, face indices must be non negative integers
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float red\nproperty float green\nproperty float blue\n\
+            element face 1\n";
+        let vertices = "0 0 0 0.5 0.5 0.5\n1 0 0 0 0 0\n0 1 0 1 1 1\n";
+        let ply = format!(
+            "{}property list uchar int vertex_indices\nend_header\n{}3 0 1 2\n",
+            header, vertices
+        );
+        let mesh = parse_ply(ply.as_bytes(), "tri").unwrap();
+        assert_eq!(mesh.colors[0], [0.5, 0.5, 0.5]);
+        assert!(mesh.flags().contains(akari::shape::MeshFlags::COLORS));
+        let ply = format!(
+            "{}property list uchar int vertex_indices\nend_header\n{}3 0 -1 2\n",
+            header, vertices
+        );
+        assert!(parse_ply(ply.as_bytes(), "tri").is_err());
+        let ply = format!(
+            "{}property list uchar double vertex_indices\nend_header\n{}3 0 1 2\n",
+            header, vertices
+        );
+        assert!(parse_ply(ply.as_bytes(), "tri").is_err());
+    }
+}
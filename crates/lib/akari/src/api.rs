@@ -1,3 +1,4 @@
+use crate::accel::AccelCache;
 use crate::bsdf::*;
 use crate::camera::*;
 #[cfg(feature = "gpu")]
@@ -9,22 +10,28 @@ use crate::integrator::ao::RTAO;
 use crate::integrator::path::PathTracer;
 // use crate::integrator::spath::StreamPathTracer;
 
-use crate::bsdf::ltc::GgxLtcBsdf;
+use crate::bsdf::disney::DisneyBsdf;
 use crate::light::*;
 // use crate::sampler::*;
 use crate::scene::*;
 use crate::scenegraph::*;
 use crate::shape::*;
-use crate::texture::{ConstantFloatTexture, ConstantRgbTexture};
+use crate::texture::{ConstantFloatTexture, ConstantRgbTexture, ConstantSpectrumTexture};
 // use crate::texture::ImageTexture;
+use crate::shader::{NoisePattern, ShaderGraphTexture, ShaderNode};
 use crate::texture::FloatTexture;
 use crate::texture::SpectrumTexture;
-use crate::util::binserde::Decode;
+use crate::util::binserde::{Decode, Encode};
+use crate::util::bundle::{BundleFileResolver, BundleWriter};
+use crate::util::image::TiledImage;
+use crate::util::normalize_path;
 use crate::util::FileResolver;
 use crate::util::LocalFileResolver;
 use crate::*;
 use akari_core::scenegraph::node::CoordinateSystem;
-use akari_core::texture::ImageSpectrumTexture;
+use akari_core::texture::{
+    ImageColorSpace, ImageFloatTexture, ImageSpectrumTexture, NormalMapTexture, VertexColorTexture,
+};
 use core::panic;
 use glam::*;
 use integrator::bdpt;
@@ -35,16 +42,116 @@ use integrator::pssmlt;
 use integrator::Integrator;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
-use std::io::BufReader;
 use std::io::Read;
+use std::io::Write;
+use std::io::{BufReader, BufWriter};
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::exit;
 use std::rc::Rc;
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 
+#[derive(Debug)]
+pub enum SceneLoadErrorKind {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Image(akari_common::image::ImageError),
+    Exr(akari_common::exr::error::Error),
+    FileNotFound,
+    UndefinedBsdf(String),
+    UndefinedShader(String),
+    UndefinedGroup(String),
+    InvalidGroup(String),
+    InvalidShader(String),
+    InvalidSpectrum(String),
+    InvalidInclude(String),
+    InvalidKeyframes(String),
+    InvalidCamera(String),
+    MissingCamera,
+    NoEmitters,
+    Unsupported(String),
+}
+impl fmt::Display for SceneLoadErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneLoadErrorKind::Io(e) => write!(f, "io error: {}", e),
+            SceneLoadErrorKind::Json(e) => write!(f, "invalid scene description: {}", e),
+            SceneLoadErrorKind::Image(e) => write!(f, "cannot decode image: {}", e),
+            SceneLoadErrorKind::Exr(e) => write!(f, "cannot decode exr image: {}", e),
+            SceneLoadErrorKind::FileNotFound => write!(f, "cannot resolve file"),
+            SceneLoadErrorKind::UndefinedBsdf(name) if name.is_empty() => {
+                write!(f, "no bsdf is assigned")
+            }
+            SceneLoadErrorKind::UndefinedBsdf(name) => write!(f, "bsdf {} is not defined", name),
+            SceneLoadErrorKind::UndefinedShader(name) => {
+                write!(f, "shader {} is not defined", name)
+            }
+            SceneLoadErrorKind::UndefinedGroup(name) => write!(f, "group {} is not defined", name),
+            SceneLoadErrorKind::InvalidGroup(what) => write!(f, "invalid group: {}", what),
+            SceneLoadErrorKind::InvalidShader(what) => write!(f, "invalid shader graph: {}", what),
+            SceneLoadErrorKind::InvalidSpectrum(what) => write!(f, "invalid spectrum: {}", what),
+            SceneLoadErrorKind::InvalidInclude(what) => write!(f, "invalid include: {}", what),
+            SceneLoadErrorKind::InvalidKeyframes(what) => write!(f, "invalid keyframes: {}", what),
+            SceneLoadErrorKind::InvalidCamera(what) => write!(f, "invalid camera: {}", what),
+            SceneLoadErrorKind::MissingCamera => {
+                write!(f, "no camera is defined by the scene or its includes")
+            }
+            SceneLoadErrorKind::NoEmitters => {
+                write!(f, "scene has no light and no emissive shape")
+            }
+            SceneLoadErrorKind::Unsupported(what) => write!(f, "{} is not supported", what),
+        }
+    }
+}
+
+/// Error returned by [`load_scene`].
+/// `location` is the path to the offending node in the scene json, e.g. `shapes[3].bsdf`,
+/// and is empty when the error is not tied to a particular node.
+#[derive(Debug)]
+pub struct SceneLoadError {
+    pub location: String,
+    pub path: Option<PathBuf>,
+    pub kind: SceneLoadErrorKind,
+}
+impl SceneLoadError {
+    pub fn new(location: impl Into<String>, kind: SceneLoadErrorKind) -> Self {
+        Self {
+            location: location.into(),
+            path: None,
+            kind,
+        }
+    }
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+}
+impl fmt::Display for SceneLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.location.is_empty() {
+            write!(f, "{}: ", self.location)?;
+        }
+        write!(f, "{}", self.kind)?;
+        if let Some(path) = &self.path {
+            write!(f, " ({})", path.display())?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for SceneLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            SceneLoadErrorKind::Io(e) => Some(e),
+            SceneLoadErrorKind::Json(e) => Some(e),
+            SceneLoadErrorKind::Image(e) => Some(e),
+            SceneLoadErrorKind::Exr(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 struct ApiContext {
     parent_path: PathBuf,
     graph: Rc<node::Scene>,
@@ -52,63 +159,323 @@ struct ApiContext {
     camera: Option<Arc<dyn Camera>>,
     lights: Vec<Arc<dyn Light>>,
     bsdfs: HashMap<String, Arc<dyn Bsdf>>,
+    shaders: HashMap<String, Arc<ShaderGraphTexture>>,
     texture_power: HashMap<usize, f32>,
     mesh_cache: HashMap<String, Arc<TriangleMesh>>,
+    // keyed by location, kept across the frames of an animation
+    light_emissions: HashMap<String, Arc<dyn SpectrumTexture>>,
     file_resolver: Arc<dyn FileResolver + Send + Sync>,
-    gpu: bool,
     ooc: OocOptions,
+    // frame keyframed transforms are evaluated at
+    frame: f32,
 }
 impl ApiContext {
-    fn load_float_texture(&mut self, node: &node::FloatTexture) -> Arc<dyn FloatTexture> {
+    fn new(
+        graph: node::Scene,
+        parent_path: PathBuf,
+        file_resolver: Arc<dyn FileResolver + Send + Sync>,
+        ooc: OocOptions,
+    ) -> Self {
+        Self {
+            parent_path,
+            graph: Rc::new(graph),
+            shapes: vec![],
+            lights: vec![],
+            camera: None,
+            ooc,
+            file_resolver,
+            bsdfs: HashMap::new(),
+            shaders: HashMap::new(),
+            texture_power: HashMap::new(),
+            mesh_cache: HashMap::new(),
+            light_emissions: HashMap::new(),
+            frame: 0.0,
+        }
+    }
+    fn load_float_texture(
+        &mut self,
+        node: &node::FloatTexture,
+        loc: &str,
+    ) -> Result<Arc<dyn FloatTexture>, SceneLoadError> {
         match node {
-            node::FloatTexture::Float(f) => Arc::new(ConstantFloatTexture(*f)),
+            node::FloatTexture::Float(f) => Ok(Arc::new(ConstantFloatTexture(*f))),
             node::FloatTexture::Image(path) => {
-                todo!()
-                // let file = self.resolve_file(path);
-                // let reader = BufReader::new(file);
-                // let reader = image::io::Reader::new(reader)
-                //     .with_guessed_format()
-                //     .unwrap();
-                // let img = reader.decode().unwrap().into_rgb8();
-                // if cfg!(feature = "gpu") || !self.ooc.enable_ooc {
-                //     Arc::new(ImageTexture::<Spectrum>::from_rgb_image(&img, false))
-                // } else {
-                //     Arc::new(ImageTexture::<Spectrum>::from_rgb_image(&img, true))
-                // }
-            }
-            _ => todo!(),
-        }
-    }
-    fn load_spectrum_texture(&mut self, node: &node::SpectrumTexture) -> Arc<dyn SpectrumTexture> {
+                let img = self.load_image(path, &format!("{}.path", loc))?;
+                Ok(Arc::new(ImageFloatTexture::from_image(&img, None, true)))
+            }
+            node::FloatTexture::CachedImage {
+                path,
+                cache,
+                channel,
+            } => {
+                let channel = channel.map(|c| c.index());
+                // the cache holds the selected channel baked from the image,
+                // a change of path or channel makes it stale
+                let key = serde_json::to_value(node).unwrap().to_string();
+                if let Some(image) = cache
+                    .as_ref()
+                    .and_then(|cache| self.read_baked_cache(&cache.path, &key))
+                {
+                    return Ok(Arc::new(ImageFloatTexture::from_tiled_image(
+                        image, None, true,
+                    )));
+                }
+                let img = self.load_image(path, &format!("{}.path", loc))?;
+                let tex = ImageFloatTexture::from_image(&img, channel, true);
+                if let Some(cache) = cache {
+                    self.write_baked_cache(&cache.path, &key, tex.image());
+                }
+                Ok(Arc::new(tex))
+            }
+            node::FloatTexture::Shader { shader } => {
+                Ok(self.load_shader(shader, &format!("{}.shader", loc))?)
+            }
+        }
+    }
+    fn load_image(
+        &mut self,
+        path: &String,
+        loc: &str,
+    ) -> Result<akari_common::image::DynamicImage, SceneLoadError> {
+        let file = self.resolve_file(path, loc)?;
+        let reader = BufReader::new(file);
+        akari_common::image::io::Reader::new(reader)
+            .with_guessed_format()
+            .map_err(|e| SceneLoadError::new(loc, SceneLoadErrorKind::Io(e)).with_path(path))?
+            .decode()
+            .map_err(|e| SceneLoadError::new(loc, SceneLoadErrorKind::Image(e)).with_path(path))
+    }
+    // decodes an image texture to linear rgb in the primaries of the returned colorspace
+    fn load_rgb_image(
+        &mut self,
+        path: &String,
+        colorspace: &str,
+        loc: &str,
+    ) -> Result<(TiledImage, RgbColorSpace), SceneLoadError> {
+        let image_colorspace = ImageColorSpace::from_name(colorspace).unwrap();
+        let path_loc = format!("{}.path", loc);
+        let is_exr = Path::new(path)
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("exr"));
+        let image = if is_exr {
+            let file = self.resolve_file(path, &path_loc)?;
+            image_colorspace
+                .read_exr(BufReader::new(file))
+                .map_err(|e| {
+                    SceneLoadError::new(&path_loc, SceneLoadErrorKind::Exr(e)).with_path(path)
+                })?
+        } else {
+            image_colorspace.decode_image(&self.load_image(path, &path_loc)?)
+        };
+        Ok((
+            image,
+            RgbColorSpace::new(image_colorspace.rgb_colorspace_id()),
+        ))
+    }
+    fn load_spectrum_texture(
+        &mut self,
+        node: &node::SpectrumTexture,
+        loc: &str,
+    ) -> Result<Arc<dyn SpectrumTexture>, SceneLoadError> {
+        if let Some(e) = spectrum_problem(node, loc) {
+            return Err(e);
+        }
         let colorspace = RgbColorSpace::new(RgbColorSpaceId::SRgb);
+        if let Some(rgb) = constant_rgb(node) {
+            return Ok(Arc::new(ConstantRgbTexture::new(rgb, colorspace)));
+        }
         match node {
-            node::SpectrumTexture::SRgbLinear { values: f3 } => {
-                Arc::new(ConstantRgbTexture::new(Vec3::from(*f3), colorspace))
-            }
-            node::SpectrumTexture::SRgb { values: srgb } => Arc::new(ConstantRgbTexture::new(
-                srgb_to_linear(Vec3::from(*srgb)),
-                colorspace,
-            )),
-            node::SpectrumTexture::SRgbU8 { values: srgb } => Arc::new(ConstantRgbTexture::new(
-                srgb_to_linear(
-                    UVec3::from([srgb[0] as u32, srgb[1] as u32, srgb[2] as u32]).as_vec3() / 255.0,
-                ),
-                colorspace,
-            )),
             node::SpectrumTexture::Image {
                 path,
-                colorspace: _,
+                colorspace: name,
                 cache: _,
             } => {
-                let file = self.resolve_file(path);
-                let reader = BufReader::new(file);
-                let reader = image::io::Reader::new(reader)
-                    .with_guessed_format()
-                    .unwrap();
-                let img = reader.decode().unwrap().into_rgb8();
-                Arc::new(ImageSpectrumTexture::from_rgb_image(&img, true))
+                let (image, colorspace) = self.load_rgb_image(path, name, loc)?;
+                Ok(Arc::new(ImageSpectrumTexture::new(image, colorspace, true)))
+            }
+            node::SpectrumTexture::Shader { shader } => {
+                Ok(self.load_shader(shader, &format!("{}.shader", loc))?)
+            }
+            node::SpectrumTexture::VertexColor {} => {
+                Ok(Arc::new(VertexColorTexture::new(colorspace)))
+            }
+            node::SpectrumTexture::Blackbody { temperature, scale } => {
+                Ok(Arc::new(ConstantSpectrumTexture::new(
+                    Arc::new(BlackbodySpectrum::new(*temperature)),
+                    *scale,
+                )))
+            }
+            node::SpectrumTexture::Named { name, scale } => Ok(Arc::new(
+                ConstantSpectrumTexture::new(named_spectrum(name).unwrap(), *scale),
+            )),
+            node::SpectrumTexture::Tabulated {
+                values,
+                normalize,
+                scale,
+            } => {
+                let xy: Vec<f32> = values.iter().flatten().copied().collect();
+                let spectrum = PiecewiseLinearSpectrum::from_interleaved(&xy, *normalize);
+                Ok(Arc::new(ConstantSpectrumTexture::new(
+                    Arc::new(spectrum),
+                    *scale,
+                )))
+            }
+            _ => unreachable!(),
+        }
+    }
+    // `loc` is where the shader is referenced, errors inside the graph are
+    // reported relative to `shaders.<name>`
+    fn load_shader(
+        &mut self,
+        name: &String,
+        loc: &str,
+    ) -> Result<Arc<ShaderGraphTexture>, SceneLoadError> {
+        if let Some(shader) = self.shaders.get(name) {
+            return Ok(shader.clone());
+        }
+        let graph = self.graph.clone();
+        let shader = graph.shaders.get(name).ok_or_else(|| {
+            SceneLoadError::new(loc, SceneLoadErrorKind::UndefinedShader(name.clone()))
+        })?;
+        let loc = format!("shaders.{}", name);
+        let mut nodes = vec![];
+        let mut indices = HashMap::new();
+        let output =
+            self.compile_shader_node(shader, &shader.output, &loc, &mut nodes, &mut indices)?;
+        let mut tex = ShaderGraphTexture::new(nodes, output);
+        if shader.precompute {
+            if shader.resolution == 0 {
+                return Err(SceneLoadError::new(
+                    format!("{}.resolution", loc),
+                    SceneLoadErrorKind::InvalidShader("precompute requires resolution > 0".into()),
+                ));
             }
+            // the cache starts with the definition it was baked from, any change to the
+            // shader makes it stale. images the shader reads are not tracked
+            let key = serde_json::to_value(shader).unwrap().to_string();
+            let cached = shader
+                .cache
+                .as_ref()
+                .and_then(|cache| self.read_baked_cache(cache, &key));
+            let image = match cached {
+                Some(image) => image,
+                None => {
+                    log::info!(
+                        "baking shader {} at {}x{}",
+                        name,
+                        shader.resolution,
+                        shader.resolution
+                    );
+                    let image = tex.bake(shader.resolution as u32);
+                    if let Some(cache) = &shader.cache {
+                        self.write_baked_cache(cache, &key, &image);
+                    }
+                    image
+                }
+            };
+            tex = ShaderGraphTexture::from_baked(image);
         }
+        let tex = Arc::new(tex);
+        self.shaders.insert(name.clone(), tex.clone());
+        Ok(tex)
+    }
+    // None when the cache is missing, unreadable or was baked from another definition
+    fn read_baked_cache(&self, cache: &String, key: &str) -> Option<TiledImage> {
+        let file = self.resolve_file(cache, "").ok()?;
+        let mut reader = BufReader::new(file);
+        match String::decode(&mut reader) {
+            Ok(cached_key) if cached_key == key => {}
+            _ => return None,
+        }
+        match TiledImage::decode(&mut reader) {
+            Ok(image) => {
+                log::info!("using baked image from {}", cache);
+                Some(image)
+            }
+            Err(e) => {
+                log::warn!("cannot read cache {}: {}", cache, e);
+                None
+            }
+        }
+    }
+    // caches are optional, a cache that cannot be written is reported and left out
+    fn write_baked_cache(&self, cache: &String, key: &str, image: &TiledImage) {
+        let path = match self.file_resolver.writable_path(Path::new(cache)) {
+            Some(path) => path,
+            None => return,
+        };
+        let write = || -> std::io::Result<()> {
+            let mut writer = BufWriter::new(File::create(&path)?);
+            key.encode(&mut writer)?;
+            image.encode(&mut writer)?;
+            writer.flush()
+        };
+        if let Err(e) = write() {
+            log::warn!("cannot write cache {}: {}", path.display(), e);
+        }
+    }
+    // appends the node and its inputs to `nodes` and returns its index,
+    // `indices` maps visited nodes to their index or None while being compiled
+    fn compile_shader_node(
+        &mut self,
+        shader: &node::ShaderGraph,
+        name: &String,
+        loc: &str,
+        nodes: &mut Vec<ShaderNode>,
+        indices: &mut HashMap<String, Option<usize>>,
+    ) -> Result<usize, SceneLoadError> {
+        let invalid = |loc: &str, what: String| {
+            SceneLoadError::new(loc, SceneLoadErrorKind::InvalidShader(what))
+        };
+        match indices.get(name) {
+            Some(Some(idx)) => return Ok(*idx),
+            Some(None) => return Err(invalid(loc, format!("cycle through node {}", name))),
+            None => {}
+        }
+        let node = shader
+            .nodes
+            .get(name)
+            .ok_or_else(|| invalid(loc, format!("node {} is not defined", name)))?;
+        indices.insert(name.clone(), None);
+        let node_loc = format!("{}.nodes.{}", loc, name);
+        if let Some(e) = shader_node_problem(node, &node_loc) {
+            return Err(e);
+        }
+        let compiled = match node {
+            node::ShaderGraphNode::Float(node::FloatTexture::Float(f)) => {
+                ShaderNode::Constant(Vec3::splat(*f))
+            }
+            node::ShaderGraphNode::Float(tex) => {
+                ShaderNode::Float(self.load_float_texture(tex, &node_loc)?)
+            }
+            node::ShaderGraphNode::Spectrum(tex) => match tex {
+                node::SpectrumTexture::Image {
+                    path, colorspace, ..
+                } => {
+                    let (image, colorspace) = self.load_rgb_image(path, colorspace, &node_loc)?;
+                    ShaderNode::from_image(image, colorspace, true)
+                }
+                _ => ShaderNode::Constant(constant_rgb(tex).unwrap()),
+            },
+            node::ShaderGraphNode::Mix { frac, tex_a, tex_b } => {
+                let frac = self.compile_shader_node(shader, frac, loc, nodes, indices)?;
+                let a = self.compile_shader_node(shader, tex_a, loc, nodes, indices)?;
+                let b = self.compile_shader_node(shader, tex_b, loc, nodes, indices)?;
+                ShaderNode::Mix { frac, a, b }
+            }
+            node::ShaderGraphNode::Noise {
+                pattern,
+                dimension,
+                scale,
+            } => ShaderNode::Noise {
+                pattern: NoisePattern::from_name(pattern).unwrap(),
+                dimension: *dimension,
+                scale: *scale,
+            },
+        };
+        nodes.push(compiled);
+        indices.insert(name.clone(), Some(nodes.len() - 1));
+        Ok(nodes.len() - 1)
     }
     #[allow(dead_code)]
     fn power_f(&mut self, tex: Arc<dyn FloatTexture>) -> f32 {
@@ -130,24 +497,29 @@ impl ApiContext {
         self.texture_power.insert(addr, p);
         p
     }
-    fn load_bsdf_from_name(&mut self, name: &String) -> Arc<dyn Bsdf> {
+    // `loc` is where the bsdf is referenced, errors inside the bsdf itself are
+    // reported relative to `bsdfs.<name>`
+    fn load_bsdf_from_name(
+        &mut self,
+        name: &String,
+        loc: &str,
+    ) -> Result<Arc<dyn Bsdf>, SceneLoadError> {
         if self.bsdfs.contains_key(name) {
-            self.bsdfs.get(name).unwrap().clone()
+            Ok(self.bsdfs.get(name).unwrap().clone())
         } else {
             let graph = self.graph.clone();
-            let bsdf = graph.bsdfs.get(name).unwrap_or_else(|| {
-                log::error!("bsdf {} is not defined", name);
-                exit(-1);
-            });
-            let bsdf = self.load_bsdf(bsdf);
+            let bsdf = graph.bsdfs.get(name).ok_or_else(|| {
+                SceneLoadError::new(loc, SceneLoadErrorKind::UndefinedBsdf(name.clone()))
+            })?;
+            let bsdf = self.load_bsdf(bsdf, &format!("bsdfs.{}", name))?;
             self.bsdfs.insert(name.clone(), bsdf.clone());
-            bsdf
+            Ok(bsdf)
         }
     }
-    fn load_bsdf(&mut self, node: &node::Bsdf) -> Arc<dyn Bsdf> {
-        match node {
+    fn load_bsdf(&mut self, node: &node::Bsdf, loc: &str) -> Result<Arc<dyn Bsdf>, SceneLoadError> {
+        Ok(match node {
             node::Bsdf::Diffuse { color } => Arc::new(DiffuseBsdf {
-                color: self.load_spectrum_texture(color),
+                color: self.load_spectrum_texture(color, &format!("{}.color", loc))?,
             }),
             node::Bsdf::Glass {
                 kt,
@@ -155,85 +527,166 @@ impl ApiContext {
                 ior,
                 dispersion,
             } => Arc::new(FresnelSpecularBsdf {
-                kt: self.load_spectrum_texture(kt),
-                kr: self.load_spectrum_texture(kr),
+                kt: self.load_spectrum_texture(kt, &format!("{}.kt", loc))?,
+                kr: self.load_spectrum_texture(kr, &format!("{}.kr", loc))?,
                 a: *ior,
                 b: *dispersion,
             }),
             node::Bsdf::Principled {
                 color,
+                subsurface,
+                subsurface_color,
                 metallic,
+                specular,
+                specular_tint,
                 roughness,
+                anisotropic,
+                anisotropic_rotation,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_roughness,
+                ior,
+                transmission,
                 emission,
+                opacity,
+                normal_map,
                 ..
             } => {
-                if !self.gpu {
-                    let color = self.load_spectrum_texture(color);
-                    let emission = self.load_spectrum_texture(emission);
-
-                    let bsdf = Arc::new(MixBsdf {
-                        frac: self.load_float_texture(metallic),
-                        bsdf_a: DiffuseBsdf {
-                            color: color.clone(),
-                        },
-                        bsdf_b: GgxLtcBsdf {
-                            roughness: self.load_float_texture(roughness),
-                            color: color.clone(),
-                        },
-                    });
-                    let bsdf: Arc<dyn Bsdf> = if emission.power() > 0.0 {
-                        Arc::new(EmissiveBsdf {
+                let color = self.load_spectrum_texture(color, &format!("{}.color", loc))?;
+                let metallic = self.load_float_texture(metallic, &format!("{}.metallic", loc))?;
+                let roughness =
+                    self.load_float_texture(roughness, &format!("{}.roughness", loc))?;
+                let emission =
+                    self.load_spectrum_texture(emission, &format!("{}.emission", loc))?;
+                let bsdf = Arc::new(DisneyBsdf {
+                    color,
+                    subsurface: self
+                        .load_float_texture(subsurface, &format!("{}.subsurface", loc))?,
+                    subsurface_color: self.load_spectrum_texture(
+                        subsurface_color,
+                        &format!("{}.subsurface_color", loc),
+                    )?,
+                    metallic,
+                    specular: self.load_float_texture(specular, &format!("{}.specular", loc))?,
+                    specular_tint: self
+                        .load_float_texture(specular_tint, &format!("{}.specular_tint", loc))?,
+                    roughness,
+                    anisotropic: self
+                        .load_float_texture(anisotropic, &format!("{}.anisotropic", loc))?,
+                    anisotropic_rotation: self.load_float_texture(
+                        anisotropic_rotation,
+                        &format!("{}.anisotropic_rotation", loc),
+                    )?,
+                    sheen: self.load_float_texture(sheen, &format!("{}.sheen", loc))?,
+                    sheen_tint: self
+                        .load_float_texture(sheen_tint, &format!("{}.sheen_tint", loc))?,
+                    clearcoat: self.load_float_texture(clearcoat, &format!("{}.clearcoat", loc))?,
+                    clearcoat_roughness: self.load_float_texture(
+                        clearcoat_roughness,
+                        &format!("{}.clearcoat_roughness", loc),
+                    )?,
+                    ior: self.load_float_texture(ior, &format!("{}.ior", loc))?,
+                    transmission: self
+                        .load_float_texture(transmission, &format!("{}.transmission", loc))?,
+                });
+                let bsdf: Arc<dyn Bsdf> = match opacity {
+                    node::FloatTexture::Float(o) if *o >= 1.0 => bsdf,
+                    _ => Arc::new(OpacityBsdf {
+                        base: bsdf,
+                        opacity: self.load_float_texture(opacity, &format!("{}.opacity", loc))?,
+                    }),
+                };
+                let bsdf: Arc<dyn Bsdf> = match normal_map {
+                    Some(path) => {
+                        let img = self.load_image(path, &format!("{}.normal_map", loc))?;
+                        Arc::new(NormalMappedBsdf {
                             base: bsdf,
-                            emission,
+                            normal_map: NormalMapTexture::from_image(&img, true),
                         })
-                    } else {
-                        bsdf
-                    };
-                    bsdf
-                } else {
-                    Arc::new(GPUBsdfProxy {
-                        color: self.load_spectrum_texture(color),
-                        metallic: self.load_float_texture(metallic),
-                        roughness: self.load_float_texture(roughness),
-                        emission: self.load_spectrum_texture(emission),
+                    }
+                    None => bsdf,
+                };
+                if emission.power() > 0.0 {
+                    Arc::new(EmissiveBsdf {
+                        base: bsdf,
+                        emission,
                     })
+                } else {
+                    bsdf
                 }
             }
-        }
+        })
     }
-    fn load_shape(&mut self, node: &node::Shape) -> Arc<dyn Shape> {
+    fn load_shape(
+        &mut self,
+        node: &node::Shape,
+        parent: Option<Transform>,
+        loc: &str,
+    ) -> Result<Arc<dyn Shape>, SceneLoadError> {
         match node {
             node::Shape::Mesh {
                 path,
                 bsdf,
-                transform: _,
+                transform,
             } => {
                 let mesh = {
                     if let Some(cache) = self.mesh_cache.get(path) {
                         cache.clone()
                     } else {
-                        let mut file = self.resolve_file(path);
+                        let loc = format!("{}.path", loc);
+                        let mut file = self.resolve_file(path, &loc)?;
                         let model = Arc::new({
                             // let bson_data = bson::Document::from_reader(&mut file).unwrap();
                             // bson::from_document::<TriangleMesh>(bson_data).unwrap()
-                            TriangleMesh::decode(&mut file).unwrap()
+                            TriangleMesh::decode(&mut file).map_err(|e| {
+                                SceneLoadError::new(&loc, SceneLoadErrorKind::Io(e)).with_path(path)
+                            })?
                         });
                         self.mesh_cache.insert(path.clone(), model.clone());
                         model
                     }
                 };
 
-                let bsdf = self.load_bsdf_from_name(bsdf);
-                Arc::new(MeshInstanceProxy { mesh, bsdf })
+                let bsdf = self.load_bsdf_from_name(bsdf, &format!("{}.bsdf", loc))?;
+                reject_emissive_animation(transform.as_ref(), bsdf.emission().is_some(), loc)?;
+                let (transform, motion) = match transform {
+                    Some(t) if t.is_animated() => {
+                        validate_keyframes(t, &format!("{}.transform", loc))?;
+                        let motion = self.load_motion(t, false);
+                        let start = compose(parent, Some(motion.start)).unwrap();
+                        let end = compose(parent, Some(motion.end)).unwrap();
+                        (Some(start), Some(AnimatedTransform::new(start, end)))
+                    }
+                    _ => (
+                        compose(
+                            parent,
+                            transform.as_ref().map(|t| self.load_transform(t, false)),
+                        ),
+                        None,
+                    ),
+                };
+                Ok(Arc::new(MeshInstanceProxy {
+                    mesh,
+                    bsdf,
+                    transform,
+                    motion,
+                }))
             }
         }
     }
-    fn load_light(&mut self, node: &node::Light) -> Arc<dyn Light> {
-        match node {
+    fn load_light(
+        &mut self,
+        node: &node::Light,
+        parent: Option<Transform>,
+        loc: &str,
+    ) -> Result<Arc<dyn Light>, SceneLoadError> {
+        let parent_or_identity = parent.unwrap_or_else(Transform::identity);
+        Ok(match node {
             node::Light::Point { pos, emission } => {
-                let emission = self.load_spectrum_texture(emission);
+                let emission = self.load_light_emission(emission, loc)?;
                 Arc::new(PointLight {
-                    position: Vec3::from(*pos),
+                    position: parent_or_identity.transform_point(Vec3::from(*pos)),
                     emission: emission.clone(),
                     colorspace: emission.colorspace(),
                 })
@@ -244,10 +697,11 @@ impl ApiContext {
                 max_angle,
                 falloff,
             } => {
-                let emission = self.load_spectrum_texture(emission);
-                let transform = self.load_transform(*transform, true);
+                let emission = self.load_light_emission(emission, loc)?;
+                reject_animation(Some(transform), loc)?;
+                let transform = parent_or_identity * self.load_transform(transform, true);
                 let pos = transform.transform_point(Vec3::ZERO);
-                let dir = transform.transform_vector(vec3(0.0, 0.0, -1.0));
+                let dir = transform.transform_vector(vec3(0.0, 0.0, -1.0)).normalize();
                 Arc::new(SpotLight {
                     position: pos,
                     direction: dir,
@@ -257,9 +711,68 @@ impl ApiContext {
                     colorspace: emission.colorspace(),
                 })
             }
+            node::Light::Directional {
+                direction,
+                emission,
+                angular_diameter,
+            } => {
+                let emission = self.load_light_emission(emission, loc)?;
+                Arc::new(DirectionalLight::new(
+                    parent_or_identity.transform_vector(Vec3::from(*direction)),
+                    (angular_diameter * 0.5).to_radians(),
+                    emission.clone(),
+                    emission.colorspace(),
+                    self.world_bounds(),
+                ))
+            }
+            node::Light::Environment {
+                emission,
+                transform,
+            } => {
+                let emission = self.load_light_emission(emission, loc)?;
+                reject_animation(transform.as_ref(), loc)?;
+                let transform = compose(
+                    parent,
+                    transform.as_ref().map(|t| self.load_transform(t, false)),
+                )
+                .unwrap_or_else(Transform::identity);
+                Arc::new(EnvironmentLight::new(
+                    emission.clone(),
+                    emission.colorspace(),
+                    &transform,
+                    self.world_bounds(),
+                ))
+            }
+        })
+    }
+    fn load_light_emission(
+        &mut self,
+        node: &node::SpectrumTexture,
+        loc: &str,
+    ) -> Result<Arc<dyn SpectrumTexture>, SceneLoadError> {
+        let loc = format!("{}.emission", loc);
+        if let Some(emission) = self.light_emissions.get(&loc) {
+            return Ok(emission.clone());
         }
+        let emission = self.load_spectrum_texture(node, &loc)?;
+        self.light_emissions.insert(loc, emission.clone());
+        Ok(emission)
     }
-    fn load_transform(&self, t: node::Transform, is_camera: bool) -> Transform {
+    fn meshes(&self) -> Vec<Arc<TriangleMesh>> {
+        self.mesh_cache
+            .iter()
+            .map(|(_, cache)| cache.clone())
+            .collect()
+    }
+    // bounds of the shapes loaded so far, lights at infinity are placed around them
+    fn world_bounds(&self) -> Bounds3f {
+        let mut bounds = Bounds3f::default();
+        for shape in &self.shapes {
+            bounds.insert_box(shape.aabb());
+        }
+        bounds
+    }
+    fn load_transform(&self, t: &node::Transform, is_camera: bool) -> Transform {
         match t {
             node::Transform::TRS(trs) => {
                 let mut m = Mat4::IDENTITY;
@@ -268,7 +781,7 @@ impl ApiContext {
                     rotate: r,
                     scale: s,
                     coord_sys,
-                } = trs;
+                } = *trs;
                 let (t, r, s) = (t.into(), r.into(), s.into());
                 let r: Vec3 = r;
                 let r = vec3(r.x.to_radians(), r.y.to_radians(), r.z.to_radians());
@@ -296,84 +809,914 @@ impl ApiContext {
                 Transform::from_matrix(&m)
             }
             node::Transform::LookAt(node::LookAt { eye, center, up }) => Transform::from_matrix(
-                &Mat4::look_at_rh(eye.into(), center.into(), up.into()).inverse(),
+                &Mat4::look_at_rh((*eye).into(), (*center).into(), (*up).into()).inverse(),
             ),
+            // where only a single pose is needed, the motion is frozen at its start
+            node::Transform::Motion(motion) => self.load_transform(&motion.start.into(), is_camera),
+            node::Transform::Keyframes(keyframes) => {
+                self.keyframe_pose(keyframes, self.frame, is_camera)
+            }
         }
     }
-    fn load(&mut self) {
-        self.camera = Some(match self.graph.camera {
+    // keyframes are validated by `validate_keyframes`
+    fn keyframe_pose(
+        &self,
+        keyframed: &node::KeyframedTransform,
+        frame: f32,
+        is_camera: bool,
+    ) -> Transform {
+        let keys = &keyframed.keyframes;
+        let i = keys.partition_point(|k| k.frame <= frame);
+        if i == 0 || i == keys.len() {
+            let key = if i == 0 { keys[0] } else { keys[i - 1] };
+            return self.load_transform(&key.transform.into(), is_camera);
+        }
+        let (k0, k1) = (keys[i - 1], keys[i]);
+        AnimatedTransform::new(
+            self.load_transform(&k0.transform.into(), is_camera),
+            self.load_transform(&k1.transform.into(), is_camera),
+        )
+        .at((frame - k0.frame) / (k1.frame - k0.frame))
+    }
+    fn load_motion(&self, t: &node::Transform, is_camera: bool) -> AnimatedTransform {
+        match t {
+            node::Transform::Motion(motion) => AnimatedTransform::new(
+                self.load_transform(&motion.start.into(), is_camera),
+                self.load_transform(&motion.end.into(), is_camera),
+            ),
+            // the shutter interval spans from this frame to the next
+            node::Transform::Keyframes(keyframes) => AnimatedTransform::new(
+                self.keyframe_pose(keyframes, self.frame, is_camera),
+                self.keyframe_pose(keyframes, self.frame + 1.0, is_camera),
+            ),
+            _ => {
+                let t = self.load_transform(t, is_camera);
+                AnimatedTransform::new(t, t)
+            }
+        }
+    }
+    fn load(&mut self) -> Result<(), SceneLoadError> {
+        let camera = self
+            .graph
+            .camera
+            .clone()
+            .ok_or_else(|| SceneLoadError::new("camera", SceneLoadErrorKind::MissingCamera))?;
+        if let Some(e) = self.camera_problems(&camera).into_iter().next() {
+            return Err(e);
+        }
+        let shutter = camera.shutter();
+        // a moving camera or one with an open shutter is built at the origin
+        // and placed by AnimatedCamera for each ray
+        let animated = camera.transform().is_animated() || shutter != (0.0, 0.0);
+        let c2w = if animated {
+            Transform::identity()
+        } else {
+            self.load_transform(camera.transform(), true)
+        };
+        let inner: Arc<dyn Camera> = match camera.clone() {
             node::Camera::Perspective {
                 res,
                 fov,
-                lens_radius: _,
-                focal: _,
-                transform,
+                lens_radius,
+                focal,
+                ..
             } => Arc::new(PerspectiveCamera::new(
                 uvec2(res.0, res.1),
-                &self.load_transform(transform, true),
+                &c2w,
                 fov.to_radians() as f32,
+                lens_radius,
+                focal,
+            )),
+            node::Camera::Orthographic { res, width, .. } => {
+                Arc::new(OrthographicCamera::new(uvec2(res.0, res.1), &c2w, width))
+            }
+            node::Camera::Spherical { res, ipd, .. } => {
+                Arc::new(SphericalCamera::new(uvec2(res.0, res.1), &c2w, ipd))
+            }
+            node::Camera::Fisheye {
+                res,
+                fov,
+                projection,
+                ..
+            } => Arc::new(FisheyeCamera::new(
+                uvec2(res.0, res.1),
+                &c2w,
+                fov.to_radians(),
+                FisheyeProjection::from_name(&projection).unwrap(),
             )),
+        };
+        self.camera = Some(if animated {
+            Arc::new(AnimatedCamera::new(
+                inner,
+                self.load_motion(camera.transform(), true),
+                shutter,
+            ))
+        } else {
+            inner
         });
         let graph = self.graph.clone();
-        for node in graph.shapes.iter() {
-            let shape = self.load_shape(node);
+        let mut groups = FlattenedGroups::default();
+        {
+            let mut by_name = HashMap::new();
+            collect_group_names(&graph.groups, "groups", &mut by_name)?;
+            self.flatten_groups(
+                &graph.groups,
+                None,
+                "groups",
+                &by_name,
+                &mut vec![],
+                &mut groups,
+            )?;
+        }
+        for (i, node) in graph.shapes.iter().enumerate() {
+            let shape = self.load_shape(node, None, &format!("shapes[{}]", i))?;
+            self.shapes.push(shape);
+        }
+        for (node, parent, loc) in &groups.shapes {
+            let shape = self.load_shape(node, *parent, loc)?;
             self.shapes.push(shape);
         }
-        for light in graph.lights.iter() {
-            let light = self.load_light(light);
+        for (i, light) in graph.lights.iter().enumerate() {
+            let light = self.load_light(light, None, &format!("lights[{}]", i))?;
+            self.lights.push(light);
+        }
+        for (node, parent, loc) in &groups.lights {
+            let light = self.load_light(node, *parent, loc)?;
             self.lights.push(light);
         }
+        Ok(())
     }
-    fn resolve_file(&self, path: &String) -> File {
+    // everything that would keep the camera from being built, the camera constructors assert on these
+    fn camera_problems(&self, camera: &node::Camera) -> Vec<SceneLoadError> {
+        let mut problems = vec![];
+        let invalid = |field: &str, what: String| {
+            SceneLoadError::new(
+                format!("camera.{}", field),
+                SceneLoadErrorKind::InvalidCamera(what),
+            )
+        };
+        let shutter = camera.shutter();
+        if !(shutter.0 <= shutter.1) {
+            problems.push(SceneLoadError::new(
+                "camera.shutter",
+                SceneLoadErrorKind::Unsupported(format!(
+                    "shutter interval ({}, {})",
+                    shutter.0, shutter.1
+                )),
+            ));
+        }
+        let res = match camera {
+            node::Camera::Perspective { res, .. }
+            | node::Camera::Orthographic { res, .. }
+            | node::Camera::Spherical { res, .. }
+            | node::Camera::Fisheye { res, .. } => *res,
+        };
+        if res.0 == 0 || res.1 == 0 {
+            problems.push(invalid(
+                "res",
+                format!("resolution must be positive, got {}x{}", res.0, res.1),
+            ));
+        }
+        match camera {
+            node::Camera::Perspective {
+                fov,
+                lens_radius,
+                focal,
+                ..
+            } => {
+                if !(*fov > 0.0) {
+                    problems.push(invalid("fov", format!("fov must be positive, got {}", fov)));
+                }
+                if *lens_radius != 0.0 && !(*focal > 0.0) {
+                    problems.push(invalid(
+                        "focal",
+                        format!("thin lens requires focal > 0, got {}", focal),
+                    ));
+                }
+            }
+            node::Camera::Orthographic { width, .. } => {
+                if !(*width > 0.0) {
+                    problems.push(invalid(
+                        "width",
+                        format!("film width must be positive, got {}", width),
+                    ));
+                }
+            }
+            node::Camera::Spherical { ipd, .. } => {
+                if !(*ipd >= 0.0) {
+                    problems.push(invalid(
+                        "ipd",
+                        format!("ipd must not be negative, got {}", ipd),
+                    ));
+                } else if *ipd > 0.0 && res.1 % 2 != 0 {
+                    problems.push(invalid(
+                        "res",
+                        format!(
+                            "stereo panorama requires an even vertical resolution, got {}",
+                            res.1
+                        ),
+                    ));
+                }
+            }
+            node::Camera::Fisheye {
+                fov, projection, ..
+            } => {
+                if FisheyeProjection::from_name(projection).is_none() {
+                    problems.push(SceneLoadError::new(
+                        "camera.projection",
+                        SceneLoadErrorKind::Unsupported(format!(
+                            "fisheye projection {}",
+                            projection
+                        )),
+                    ));
+                }
+                if !(*fov > 0.0 && *fov <= 220.0) {
+                    problems.push(SceneLoadError::new(
+                        "camera.fov",
+                        SceneLoadErrorKind::Unsupported(format!("fisheye fov {}", fov)),
+                    ));
+                }
+            }
+        }
+        if let Err(e) = validate_keyframes(camera.transform(), "camera.transform") {
+            problems.push(e);
+            return problems;
+        }
+        // the camera must not scale or shear, whichever pose it is in
+        let poses: Vec<node::Transform> = match camera.transform() {
+            node::Transform::Motion(motion) => vec![motion.start.into(), motion.end.into()],
+            node::Transform::Keyframes(keyframed) => keyframed
+                .keyframes
+                .iter()
+                .map(|k| k.transform.into())
+                .collect(),
+            t => vec![t.clone()],
+        };
+        for pose in &poses {
+            let det = self.load_transform(pose, true).m3.determinant();
+            if !((det - 1.0).abs() < 0.01) {
+                problems.push(invalid(
+                    "transform",
+                    format!("transform has det = {} != 1", det),
+                ));
+                break;
+            }
+        }
+        problems
+    }
+    // every problem `load` could run into, without decoding meshes and images
+    // problems are ordered by the part of the scene they are found in
+    fn validate(&self) -> Vec<SceneLoadError> {
+        let graph = self.graph.clone();
+        let mut problems = vec![];
+        match &graph.camera {
+            Some(camera) => problems.extend(self.camera_problems(camera)),
+            None => problems.push(SceneLoadError::new(
+                "camera",
+                SceneLoadErrorKind::MissingCamera,
+            )),
+        }
+        let mut bsdf_names: Vec<&String> = graph.bsdfs.keys().collect();
+        bsdf_names.sort();
+        for name in bsdf_names {
+            let mut bsdf = graph.bsdfs[name].clone();
+            let loc = format!("bsdfs.{}", name);
+            // `foreach_texture` does not name the field, so bsdfs are checked as a whole
+            bsdf.foreach_texture(|tex| match tex {
+                node::GenericTextureRefMut::Float(tex) => {
+                    self.float_texture_problems(tex, &loc, &mut problems)
+                }
+                node::GenericTextureRefMut::Spectrum(tex) => {
+                    self.spectrum_texture_problems(tex, &loc, &mut problems)
+                }
+            });
+            if let node::Bsdf::Principled {
+                normal_map: Some(path),
+                ..
+            } = &bsdf
+            {
+                self.file_problems(path, &format!("{}.normal_map", loc), &mut problems);
+            }
+        }
+        let mut shader_names: Vec<&String> = graph.shaders.keys().collect();
+        shader_names.sort();
+        for name in shader_names {
+            self.shader_problems(
+                &graph.shaders[name],
+                &format!("shaders.{}", name),
+                &mut problems,
+            );
+        }
+        let mut groups = FlattenedGroups::default();
+        let mut by_name = HashMap::new();
+        if let Err(e) = collect_group_names(&graph.groups, "groups", &mut by_name).and_then(|_| {
+            self.flatten_groups(
+                &graph.groups,
+                None,
+                "groups",
+                &by_name,
+                &mut vec![],
+                &mut groups,
+            )
+        }) {
+            problems.push(e);
+        }
+        let shapes = graph
+            .shapes
+            .iter()
+            .enumerate()
+            .map(|(i, shape)| (shape, format!("shapes[{}]", i)))
+            .chain(
+                groups
+                    .shapes
+                    .iter()
+                    .map(|(shape, _, loc)| (*shape, loc.clone())),
+            );
+        let mut emissive_shapes = false;
+        for (shape, loc) in shapes {
+            match shape {
+                node::Shape::Mesh {
+                    path,
+                    bsdf,
+                    transform,
+                } => {
+                    self.file_problems(path, &format!("{}.path", loc), &mut problems);
+                    match graph.bsdfs.get(bsdf) {
+                        Some(bsdf) => {
+                            let emissive = is_emissive(bsdf);
+                            emissive_shapes |= emissive;
+                            if let Err(e) =
+                                reject_emissive_animation(transform.as_ref(), emissive, &loc)
+                            {
+                                problems.push(e);
+                            }
+                        }
+                        None => problems.push(SceneLoadError::new(
+                            format!("{}.bsdf", loc),
+                            SceneLoadErrorKind::UndefinedBsdf(bsdf.clone()),
+                        )),
+                    }
+                    if let Some(t) = transform {
+                        if let Err(e) = validate_keyframes(t, &format!("{}.transform", loc)) {
+                            problems.push(e);
+                        }
+                    }
+                }
+            }
+        }
+        let lights = graph
+            .lights
+            .iter()
+            .enumerate()
+            .map(|(i, light)| (light, format!("lights[{}]", i)))
+            .chain(
+                groups
+                    .lights
+                    .iter()
+                    .map(|(light, _, loc)| (*light, loc.clone())),
+            );
+        let mut has_lights = false;
+        for (light, loc) in lights {
+            has_lights = true;
+            let (emission, transform) = match light {
+                node::Light::Point { emission, .. } | node::Light::Directional { emission, .. } => {
+                    (emission, None)
+                }
+                node::Light::Spot {
+                    emission,
+                    transform,
+                    ..
+                } => (emission, Some(transform)),
+                node::Light::Environment {
+                    emission,
+                    transform,
+                } => (emission, transform.as_ref()),
+            };
+            if let Err(e) = reject_animation(transform, &loc) {
+                problems.push(e);
+            }
+            self.spectrum_texture_problems(emission, &format!("{}.emission", loc), &mut problems);
+        }
+        if !has_lights && !emissive_shapes {
+            problems.push(SceneLoadError::new("", SceneLoadErrorKind::NoEmitters));
+        }
+        problems
+    }
+    fn file_problems(&self, path: &String, loc: &str, problems: &mut Vec<SceneLoadError>) {
+        if let Err(e) = self.resolve_file(path, loc) {
+            problems.push(e);
+        }
+    }
+    fn float_texture_problems(
+        &self,
+        node: &node::FloatTexture,
+        loc: &str,
+        problems: &mut Vec<SceneLoadError>,
+    ) {
+        match node {
+            node::FloatTexture::Float(_) => {}
+            // a missing cache falls back to the image
+            node::FloatTexture::Image(path) | node::FloatTexture::CachedImage { path, .. } => {
+                self.file_problems(path, &format!("{}.path", loc), problems)
+            }
+            node::FloatTexture::Shader { shader } => {
+                if !self.graph.shaders.contains_key(shader) {
+                    problems.push(SceneLoadError::new(
+                        format!("{}.shader", loc),
+                        SceneLoadErrorKind::UndefinedShader(shader.clone()),
+                    ));
+                }
+            }
+        }
+    }
+    fn spectrum_texture_problems(
+        &self,
+        node: &node::SpectrumTexture,
+        loc: &str,
+        problems: &mut Vec<SceneLoadError>,
+    ) {
+        problems.extend(spectrum_problem(node, loc));
+        match node {
+            node::SpectrumTexture::Image { path, .. } => {
+                self.file_problems(path, &format!("{}.path", loc), problems)
+            }
+            node::SpectrumTexture::Shader { shader } => {
+                if !self.graph.shaders.contains_key(shader) {
+                    problems.push(SceneLoadError::new(
+                        format!("{}.shader", loc),
+                        SceneLoadErrorKind::UndefinedShader(shader.clone()),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    // checks the nodes reachable from the output, as `compile_shader_node` would visit them
+    fn shader_problems(
+        &self,
+        shader: &node::ShaderGraph,
+        loc: &str,
+        problems: &mut Vec<SceneLoadError>,
+    ) {
+        if shader.precompute && shader.resolution == 0 {
+            problems.push(SceneLoadError::new(
+                format!("{}.resolution", loc),
+                SceneLoadErrorKind::InvalidShader("precompute requires resolution > 0".into()),
+            ));
+        }
+        // false while the inputs of a node are visited
+        let mut visited: HashMap<&str, bool> = HashMap::new();
+        let mut stack: Vec<(&str, bool)> = vec![(shader.output.as_str(), false)];
+        while let Some((name, inputs_done)) = stack.pop() {
+            if inputs_done {
+                visited.insert(name, true);
+                continue;
+            }
+            match visited.get(name) {
+                Some(true) => continue,
+                Some(false) => {
+                    problems.push(SceneLoadError::new(
+                        loc,
+                        SceneLoadErrorKind::InvalidShader(format!("cycle through node {}", name)),
+                    ));
+                    return;
+                }
+                None => {}
+            }
+            let node = match shader.nodes.get(name) {
+                Some(node) => node,
+                None => {
+                    problems.push(SceneLoadError::new(
+                        loc,
+                        SceneLoadErrorKind::InvalidShader(format!("node {} is not defined", name)),
+                    ));
+                    continue;
+                }
+            };
+            visited.insert(name, false);
+            stack.push((name, true));
+            let node_loc = format!("{}.nodes.{}", loc, name);
+            if let Some(e) = shader_node_problem(node, &node_loc) {
+                problems.push(e);
+                continue;
+            }
+            match node {
+                node::ShaderGraphNode::Float(tex) => {
+                    self.float_texture_problems(tex, &node_loc, problems)
+                }
+                node::ShaderGraphNode::Spectrum(tex) => {
+                    self.spectrum_texture_problems(tex, &node_loc, problems)
+                }
+                node::ShaderGraphNode::Mix { frac, tex_a, tex_b } => {
+                    for input in [tex_b, tex_a, frac] {
+                        stack.push((input.as_str(), false));
+                    }
+                }
+                node::ShaderGraphNode::Noise { .. } => {}
+            }
+        }
+    }
+    // gathers the shapes and lights of visible groups along with the transform of their group
+    // `stack` holds the names of the enclosing groups and of the groups they instance
+    fn flatten_groups<'a>(
+        &self,
+        groups: &'a [node::Group],
+        parent: Option<Transform>,
+        loc: &str,
+        by_name: &HashMap<&'a str, &'a node::Group>,
+        stack: &mut Vec<&'a str>,
+        out: &mut FlattenedGroups<'a>,
+    ) -> Result<(), SceneLoadError> {
+        for (i, group) in groups.iter().enumerate() {
+            let loc = format!("{}[{}]", loc, i);
+            if group.hidden {
+                continue;
+            }
+            reject_animation(group.transform.as_ref(), &loc)?;
+            let transform = compose(
+                parent,
+                group
+                    .transform
+                    .as_ref()
+                    .map(|t| self.load_transform(t, false)),
+            );
+            let content = match &group.instance {
+                Some(name) => *by_name.get(name.as_str()).ok_or_else(|| {
+                    SceneLoadError::new(
+                        format!("{}.instance", loc),
+                        SceneLoadErrorKind::UndefinedGroup(name.clone()),
+                    )
+                })?,
+                None => group,
+            };
+            for name in [group.name.as_str(), content.name.as_str()] {
+                if stack.contains(&name) {
+                    return Err(SceneLoadError::new(
+                        loc,
+                        SceneLoadErrorKind::InvalidGroup(format!("group {} contains itself", name)),
+                    ));
+                }
+            }
+            for (j, shape) in content.shapes.iter().enumerate() {
+                out.shapes
+                    .push((shape, transform, format!("{}.shapes[{}]", loc, j)));
+            }
+            for (j, light) in content.lights.iter().enumerate() {
+                out.lights
+                    .push((light, transform, format!("{}.lights[{}]", loc, j)));
+            }
+            stack.push(&group.name);
+            stack.push(&content.name);
+            self.flatten_groups(
+                &content.groups,
+                transform,
+                &format!("{}.groups", loc),
+                by_name,
+                stack,
+                out,
+            )?;
+            stack.truncate(stack.len() - 2);
+        }
+        Ok(())
+    }
+    fn resolve_file(&self, path: &String, loc: &str) -> Result<File, SceneLoadError> {
         let path = if cfg!(target_os = "windows") {
             path.replace("/", "\\")
         } else {
             path.replace("\\", "/")
         };
         if let Some(file) = self.file_resolver.resolve(path.as_ref()) {
-            return file;
+            return Ok(file);
+        }
+        Err(SceneLoadError::new(loc, SceneLoadErrorKind::FileNotFound).with_path(path))
+    }
+}
+#[derive(Default)]
+struct FlattenedGroups<'a> {
+    shapes: Vec<(&'a node::Shape, Option<Transform>, String)>,
+    lights: Vec<(&'a node::Light, Option<Transform>, String)>,
+}
+fn collect_group_names<'a>(
+    groups: &'a [node::Group],
+    loc: &str,
+    by_name: &mut HashMap<&'a str, &'a node::Group>,
+) -> Result<(), SceneLoadError> {
+    for (i, group) in groups.iter().enumerate() {
+        let loc = format!("{}[{}]", loc, i);
+        if by_name.insert(group.name.as_str(), group).is_some() {
+            return Err(SceneLoadError::new(
+                format!("{}.name", loc),
+                SceneLoadErrorKind::InvalidGroup(format!(
+                    "group {} is defined more than once",
+                    group.name
+                )),
+            ));
+        }
+        collect_group_names(&group.groups, &format!("{}.groups", loc), by_name)?;
+    }
+    Ok(())
+}
+// motion blur and keyframes are only supported for shapes and the camera
+fn reject_animation(t: Option<&node::Transform>, loc: &str) -> Result<(), SceneLoadError> {
+    match t {
+        Some(t) if t.is_animated() => Err(SceneLoadError::new(
+            format!("{}.transform", loc),
+            SceneLoadErrorKind::Unsupported("animation outside of shapes and the camera".into()),
+        )),
+        _ => Ok(()),
+    }
+}
+// area lights are sampled at the start of the shutter interval, so emissive shapes cannot move
+fn reject_emissive_animation(
+    t: Option<&node::Transform>,
+    emissive: bool,
+    loc: &str,
+) -> Result<(), SceneLoadError> {
+    match t {
+        Some(t) if emissive && t.is_animated() => Err(SceneLoadError::new(
+            format!("{}.transform", loc),
+            SceneLoadErrorKind::Unsupported("animation of emissive shapes".into()),
+        )),
+        _ => Ok(()),
+    }
+}
+fn validate_keyframes(t: &node::Transform, loc: &str) -> Result<(), SceneLoadError> {
+    if let node::Transform::Keyframes(keyframed) = t {
+        let keys = &keyframed.keyframes;
+        if keys.is_empty() || keys.windows(2).any(|w| !(w[0].frame < w[1].frame)) {
+            return Err(SceneLoadError::new(
+                format!("{}.keyframes", loc),
+                SceneLoadErrorKind::InvalidKeyframes(
+                    "expected at least one keyframe with increasing frame numbers".into(),
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+// `child` followed by `parent`, None stands for the identity
+fn compose(parent: Option<Transform>, child: Option<Transform>) -> Option<Transform> {
+    match (parent, child) {
+        (Some(parent), Some(child)) => Some(parent * child),
+        (parent, child) => parent.or(child),
+    }
+}
+// linear rgb of constant spectrum textures, None for images and shaders
+fn constant_rgb(node: &node::SpectrumTexture) -> Option<Vec3> {
+    match node {
+        node::SpectrumTexture::SRgbLinear { values } => Some(Vec3::from(*values)),
+        node::SpectrumTexture::SRgb { values } => Some(srgb_to_linear(Vec3::from(*values))),
+        node::SpectrumTexture::SRgbU8 { values } => Some(srgb_to_linear(
+            UVec3::from([values[0] as u32, values[1] as u32, values[2] as u32]).as_vec3() / 255.0,
+        )),
+        node::SpectrumTexture::Image { .. }
+        | node::SpectrumTexture::Shader { .. }
+        | node::SpectrumTexture::VertexColor {}
+        | node::SpectrumTexture::Blackbody { .. }
+        | node::SpectrumTexture::Named { .. }
+        | node::SpectrumTexture::Tabulated { .. } => None,
+    }
+}
+// checks of a spectrum texture that do not need its files, shared by loading and validation
+fn spectrum_problem(node: &node::SpectrumTexture, loc: &str) -> Option<SceneLoadError> {
+    let invalid = |field: &str, what: String| {
+        Some(SceneLoadError::new(
+            format!("{}.{}", loc, field),
+            SceneLoadErrorKind::InvalidSpectrum(what),
+        ))
+    };
+    match node {
+        node::SpectrumTexture::Image { colorspace, .. }
+            if ImageColorSpace::from_name(colorspace).is_none() =>
+        {
+            Some(SceneLoadError::new(
+                format!("{}.colorspace", loc),
+                SceneLoadErrorKind::Unsupported(format!("colorspace {}", colorspace)),
+            ))
+        }
+        node::SpectrumTexture::Blackbody { temperature, .. } if !(*temperature > 0.0) => invalid(
+            "temperature",
+            format!("temperature must be positive, got {}", temperature),
+        ),
+        node::SpectrumTexture::Named { name, .. } if named_spectrum(name).is_none() => {
+            invalid("name", format!("unknown spectrum {}", name))
+        }
+        node::SpectrumTexture::Tabulated { values, .. }
+            if values.len() < 2 || values.windows(2).any(|w| !(w[0][0] < w[1][0])) =>
+        {
+            invalid(
+                "values",
+                "expected at least two samples with increasing wavelengths".into(),
+            )
+        }
+        _ => None,
+    }
+}
+// checks of a single shader graph node that do not need its files or its inputs
+fn shader_node_problem(node: &node::ShaderGraphNode, loc: &str) -> Option<SceneLoadError> {
+    let invalid = |what: String| {
+        Some(SceneLoadError::new(
+            loc,
+            SceneLoadErrorKind::InvalidShader(what),
+        ))
+    };
+    match node {
+        node::ShaderGraphNode::Float(node::FloatTexture::Shader { .. })
+        | node::ShaderGraphNode::Spectrum(node::SpectrumTexture::Shader { .. }) => {
+            invalid("nested shaders are not supported".into())
+        }
+        node::ShaderGraphNode::Spectrum(
+            node::SpectrumTexture::Blackbody { .. }
+            | node::SpectrumTexture::Named { .. }
+            | node::SpectrumTexture::Tabulated { .. },
+        ) => invalid("spectral textures are not supported".into()),
+        // shaders are evaluated over texcoords only and may be baked
+        node::ShaderGraphNode::Spectrum(node::SpectrumTexture::VertexColor {}) => {
+            invalid("vertex colors are not supported".into())
+        }
+        node::ShaderGraphNode::Noise { pattern, .. }
+            if NoisePattern::from_name(pattern).is_none() =>
+        {
+            invalid(format!("unknown noise pattern {}", pattern))
+        }
+        node::ShaderGraphNode::Noise { dimension, .. } if *dimension != 1 && *dimension != 3 => {
+            invalid(format!("noise dimension must be 1 or 3, got {}", dimension))
+        }
+        node::ShaderGraphNode::Spectrum(tex) => spectrum_problem(tex, loc),
+        _ => None,
+    }
+}
+// whether a shape with this bsdf is an area light
+fn is_emissive(bsdf: &node::Bsdf) -> bool {
+    match bsdf {
+        node::Bsdf::Principled { emission, .. } => match constant_rgb(emission) {
+            Some(rgb) => rgb.max_element() > 0.0,
+            None => true,
+        },
+        _ => false,
+    }
+}
+// serde only reports line/column, so on failure each top-level node is decoded
+// separately to find out which one is malformed
+fn parse_scene(serialized: &str) -> Result<node::Scene, SceneLoadError> {
+    let e = match serde_json::from_str::<node::Scene>(serialized) {
+        Ok(graph) => return Ok(graph),
+        Err(e) => e,
+    };
+    let json: Value = match serde_json::from_str(serialized) {
+        Ok(json) => json,
+        Err(_) => return Err(SceneLoadError::new("", SceneLoadErrorKind::Json(e))),
+    };
+    fn check<T: serde::de::DeserializeOwned>(
+        value: &Value,
+        loc: String,
+    ) -> Result<(), SceneLoadError> {
+        serde_json::from_value::<T>(value.clone())
+            .map(|_| ())
+            .map_err(|e| SceneLoadError::new(loc, SceneLoadErrorKind::Json(e)))
+    }
+    if let Some(bsdfs) = json.get("bsdfs").and_then(|v| v.as_object()) {
+        for (name, bsdf) in bsdfs {
+            check::<node::Bsdf>(bsdf, format!("bsdfs.{}", name))?;
+        }
+    }
+    if let Some(shaders) = json.get("shaders").and_then(|v| v.as_object()) {
+        for (name, shader) in shaders {
+            check::<node::ShaderGraph>(shader, format!("shaders.{}", name))?;
+        }
+    }
+    if let Some(camera) = json.get("camera") {
+        check::<node::Camera>(camera, "camera".into())?;
+    }
+    for (key, check_elem) in [
+        (
+            "shapes",
+            check::<node::Shape> as fn(&Value, String) -> Result<(), SceneLoadError>,
+        ),
+        ("lights", check::<node::Light>),
+        ("groups", check::<node::Group>),
+    ] {
+        if let Some(array) = json.get(key).and_then(|v| v.as_array()) {
+            for (i, elem) in array.iter().enumerate() {
+                check_elem(elem, format!("{}[{}]", key, i))?;
+            }
+        }
+    }
+    Err(SceneLoadError::new("", SceneLoadErrorKind::Json(e)))
+}
+// guards against include chains that never end, e.g. through "../" aliases of the same file
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+// replaces the includes of `graph` by the scenes they refer to, layered below `graph`
+// paths in `graph` are relative to the directory of the root scene, `stack` holds the
+// including files as canonicalized by `file_resolver`, `resolved` the layers resolved so far
+// so that files included more than once are read once
+fn resolve_includes(
+    mut graph: node::Scene,
+    file_resolver: &dyn FileResolver,
+    stack: &mut Vec<PathBuf>,
+    resolved: &mut HashMap<PathBuf, node::Scene>,
+) -> Result<node::Scene, SceneLoadError> {
+    let includes = std::mem::take(&mut graph.include);
+    let mut merged = node::Scene::default();
+    for (i, include) in includes.iter().enumerate() {
+        let loc = format!("include[{}]", i);
+        let path = normalize_path(Path::new(include));
+        let canonical = file_resolver.canonicalize(&path);
+        if stack.contains(&canonical) || stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(SceneLoadError::new(
+                loc,
+                SceneLoadErrorKind::InvalidInclude(format!("{} is included recursively", include)),
+            )
+            .with_path(path));
+        }
+        // the layer's paths depend on where it is included from, which `path` captures
+        if let Some(layer) = resolved.get(&path) {
+            merged.merge(layer.clone());
+            continue;
         }
-        panic!("cannot resolve path {}", path);
+        let mut file = file_resolver.resolve(&path).ok_or_else(|| {
+            SceneLoadError::new(&loc, SceneLoadErrorKind::FileNotFound).with_path(&path)
+        })?;
+        let mut serialized = String::new();
+        file.read_to_string(&mut serialized)
+            .map_err(|e| SceneLoadError::new(&loc, SceneLoadErrorKind::Io(e)).with_path(&path))?;
+        let mut layer = parse_scene(&serialized).map_err(|e| e.with_path(&path))?;
+        layer.rebase_paths(path.parent().unwrap_or_else(|| Path::new("")));
+        stack.push(canonical);
+        let layer = resolve_includes(layer, file_resolver, stack, resolved)?;
+        stack.pop();
+        resolved.insert(path, layer.clone());
+        merged.merge(layer);
     }
+    merged.merge(graph);
+    Ok(merged)
 }
 #[derive(Clone, Copy)]
 pub struct OocOptions {
     pub enable_ooc: bool,
 }
+// the scene at `path` with its includes resolved, the directory it is in and the resolver
+// for the files it refers to, `path` is either a scene file or a bundle written by `pack_scene`
+fn read_scene(
+    path: &Path,
+) -> Result<(node::Scene, PathBuf, Arc<dyn FileResolver + Send + Sync>), SceneLoadError> {
+    let io_err = |e| SceneLoadError::new("", SceneLoadErrorKind::Io(e)).with_path(path);
+    let canonical = std::fs::canonicalize(path).map_err(io_err)?;
+    let parent_path = PathBuf::from(canonical.parent().unwrap());
+    let file_resolver: Arc<dyn FileResolver + Send + Sync>;
+    let (serialized, scene_name) = if BundleFileResolver::is_bundle(path) {
+        let bundle = BundleFileResolver::open(path).map_err(io_err)?;
+        let scene = bundle.manifest().scene.clone();
+        let serialized = bundle.read_to_string(&scene).map_err(io_err)?;
+        file_resolver = Arc::new(bundle);
+        (serialized, PathBuf::from(scene))
+    } else {
+        file_resolver = Arc::new(LocalFileResolver::new(vec![parent_path.clone()]));
+        (
+            std::fs::read_to_string(path).map_err(io_err)?,
+            canonical.clone(),
+        )
+    };
+    let graph = parse_scene(&serialized).map_err(|e| e.with_path(path))?;
+    let root = file_resolver.canonicalize(&scene_name);
+    let graph = resolve_includes(
+        graph,
+        file_resolver.as_ref(),
+        &mut vec![root],
+        &mut HashMap::new(),
+    )?;
+    Ok((graph, parent_path, file_resolver))
+}
+fn create_context(path: &Path, accel: &str, ooc: OocOptions) -> Result<ApiContext, SceneLoadError> {
+    let (graph, parent_path, file_resolver) = read_scene(path)?;
+    if !crate::accel::is_accel_supported(accel) {
+        return Err(SceneLoadError::new(
+            "",
+            SceneLoadErrorKind::Unsupported(format!("accel {}", accel)),
+        ));
+    }
+    Ok(ApiContext::new(graph, parent_path, file_resolver, ooc))
+}
 pub fn load_scene<R: FileResolver + Send + Sync>(
     path: &Path,
     gpu_mode: bool,
     accel: &str,
     ooc: OocOptions,
-) -> Scene {
-    let serialized = std::fs::read_to_string(path).unwrap();
-    let canonical = std::fs::canonicalize(path).unwrap();
-    let graph: node::Scene = serde_json::from_str(&serialized).unwrap_or_else(|e| {
-        log::error!("error during scene loading:{:?}", e);
-        exit(-1);
-    });
-    let parent_path = canonical.parent().unwrap();
-    let mut ctx = ApiContext {
-        parent_path: PathBuf::from(parent_path),
-        graph: Rc::new(graph),
-        shapes: vec![],
-        lights: vec![],
-        camera: None,
-        ooc,
-        file_resolver: Arc::new(LocalFileResolver::new(vec![PathBuf::from(parent_path)])),
-        bsdfs: HashMap::new(),
-        texture_power: HashMap::new(),
-        mesh_cache: HashMap::new(),
-        gpu: gpu_mode,
-    };
-    ctx.load();
+) -> Result<Scene, SceneLoadError> {
+    // scenes are only built for the cpu, Scene::new has no gpu path
+    if gpu_mode {
+        return Err(SceneLoadError::new(
+            "",
+            SceneLoadErrorKind::Unsupported("gpu rendering".into()),
+        ));
+    }
+    let mut ctx = create_context(path, accel, ooc)?;
+    ctx.load()?;
     let scene = Scene::new(
-        ctx.camera.unwrap(),
+        ctx.camera.clone().unwrap(),
         ctx.shapes.clone(),
-        ctx.mesh_cache
-            .iter()
-            .map(|(_, cache)| cache.clone())
-            .collect(),
+        ctx.meshes(),
         ctx.lights.clone(),
         accel,
         gpu_mode,
@@ -381,7 +1724,120 @@ pub fn load_scene<R: FileResolver + Send + Sync>(
 
     log::info!("{} lights", scene.lights.len());
 
-    scene
+    Ok(scene)
+}
+
+// name of an external file inside a bundle,
+// files outside of the scene directory are moved below `external/`
+fn bundle_path(path: &str, index: usize) -> String {
+    use std::path::Component;
+    let path = PathBuf::from(path.replace('\\', "/"));
+    let mut parts = vec![];
+    for c in path.components() {
+        match c {
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+            Component::CurDir => {}
+            _ => {
+                let name = path
+                    .file_name()
+                    .map_or("file".into(), |n| n.to_string_lossy());
+                return format!("external/{}/{}", index, name);
+            }
+        }
+    }
+    parts.join("/")
+}
+/// Packs the scene at `path`, its includes and every file they refer to into a single bundle at `out`,
+/// which can be loaded in place of the scene file.
+/// Files that cannot be found are left out with a warning, as they may be optional texture caches.
+pub fn pack_scene(path: &Path, out: &Path) -> Result<(), SceneLoadError> {
+    const SCENE_ENTRY: &str = "scene.json";
+    let (mut graph, _, file_resolver) = read_scene(path)?;
+    let io_err = |e| SceneLoadError::new("", SceneLoadErrorKind::Io(e)).with_path(out);
+    let mut writer = BundleWriter::new().map_err(io_err)?;
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut result = Ok(());
+    graph.foreach_ext_files(|file| {
+        if result.is_err() {
+            return;
+        }
+        if let Some(name) = names.get(file.as_str()) {
+            *file = name.clone();
+            return;
+        }
+        let name = bundle_path(file, names.len());
+        if !writer.contains(&name) {
+            // the path is still rewritten, a cache that is missing from the bundle
+            // must not point back at the original file system
+            match file_resolver.resolve(Path::new(file.as_str())) {
+                Some(source) => {
+                    if let Err(e) = writer.add(&name, BufReader::new(source)) {
+                        let e = SceneLoadError::new("", SceneLoadErrorKind::Io(e));
+                        result = Err(e.with_path(&*file));
+                        return;
+                    }
+                }
+                None => log::warn!("{} not found, it is left out of the bundle", file),
+            }
+        }
+        names.insert(file.clone(), name.clone());
+        *file = name;
+    });
+    result?;
+    let serialized = serde_json::to_string_pretty(&graph)
+        .map_err(|e| SceneLoadError::new("", SceneLoadErrorKind::Json(e)))?;
+    writer
+        .add(SCENE_ENTRY, serialized.as_bytes())
+        .map_err(io_err)?;
+    let mut file = BufWriter::new(File::create(out).map_err(io_err)?);
+    writer.finish(SCENE_ENTRY, &mut file).map_err(io_err)?;
+    file.flush().map_err(io_err)?;
+    log::info!("packed {} files into {}", names.len(), out.display());
+    Ok(())
+}
+/// Checks the scene at `path` and its includes for everything [`load_scene`] could fail on,
+/// without decoding meshes and images or building acceleration structures.
+/// Returns all problems found, or the error that kept the scene from being parsed.
+pub fn validate_scene(path: &Path) -> Result<Vec<SceneLoadError>, SceneLoadError> {
+    let ctx = create_context(path, "bvh", OocOptions { enable_ooc: false })?;
+    Ok(ctx.validate())
+}
+
+/// A scene with keyframed transforms, built one frame at a time by [`SceneAnimation::scene_at`].
+/// Meshes, materials and the acceleration structures of instances that did not move
+/// are shared by all frames.
+pub struct SceneAnimation {
+    ctx: ApiContext,
+    accel: String,
+    cache: AccelCache,
+}
+impl SceneAnimation {
+    pub fn scene_at(&mut self, frame: i32) -> Result<Scene, SceneLoadError> {
+        let ctx = &mut self.ctx;
+        ctx.frame = frame as f32;
+        ctx.shapes.clear();
+        ctx.lights.clear();
+        ctx.camera = None;
+        ctx.load()?;
+        let accel = crate::accel::build_accel_cached(&ctx.shapes, &self.accel, &mut self.cache);
+        Ok(Scene::from_accel(
+            ctx.camera.clone().unwrap(),
+            accel,
+            ctx.meshes(),
+            ctx.lights.clone(),
+        ))
+    }
+}
+pub fn load_animation(
+    path: &Path,
+    accel: &str,
+    ooc: OocOptions,
+) -> Result<SceneAnimation, SceneLoadError> {
+    Ok(SceneAnimation {
+        ctx: create_context(path, accel, ooc)?,
+        accel: accel.into(),
+        cache: AccelCache::default(),
+    })
 }
 #[cfg(feature = "gpu")]
 pub fn load_gpu_integrator(path: &Path) -> WavefrontPathTracer {
@@ -548,3 +2004,495 @@ pub fn load_integrator(path: &Path) -> Box<dyn Integrator> {
         _ => unimplemented!(),
     }
 }
+
+mod test {
+    // an empty context for `graph`, files are looked up from the working directory
+    #[cfg(test)]
+    fn test_context(graph: super::node::Scene) -> super::ApiContext {
+        use super::*;
+        ApiContext::new(
+            graph,
+            PathBuf::new(),
+            Arc::new(LocalFileResolver::new(vec![])),
+            OocOptions { enable_ooc: false },
+        )
+    }
+    // replaces `CAMERA` in `scene` with a perspective camera looking at the origin
+    #[cfg(test)]
+    fn with_camera(scene: &str) -> String {
+        scene.replace(
+            "CAMERA",
+            r#""camera": {
+                "type": "perspective", "res": [64, 64], "fov": 60.0, "lens_radius": 0.0, "focal": 1.0,
+                "transform": { "eye": [0, 5, 5], "center": [0, 0, 0], "up": [0, 1, 0] }
+            }"#,
+        )
+    }
+    #[test]
+    fn test_parse_scene_error_location() {
+        use super::*;
+        let scene = r#"{
+            "bsdfs": {
+                "white": { "type": "diffuse", "color": { "type": "srgb", "values": [0.8, 0.8, 0.8] } }
+            },
+            CAMERA,
+            "lights": [],
+            "shapes": [
+                { "type": "mesh", "path": "a.mesh", "bsdf": "white" },
+                { "type": "mesh", "path": "b.mesh" }
+            ]
+        }"#;
+        let err = parse_scene(&with_camera(scene)).err().unwrap();
+        assert_eq!(err.location, "shapes[1]");
+        assert!(matches!(err.kind, SceneLoadErrorKind::Json(_)));
+    }
+    #[test]
+    fn test_scene_includes() {
+        use super::*;
+        use crate::util::TempDirGuard;
+        let dir = TempDirGuard::new("include");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let diffuse = |v: f32| {
+            format!(
+                r#"{{ "type": "diffuse", "color": {{ "type": "srgb", "values": [{v}, {v}, {v}] }} }}"#,
+                v = v
+            )
+        };
+        let write = |name: &str, content: String| std::fs::write(dir.join(name), content).unwrap();
+        write(
+            "lib/materials.json",
+            format!(
+                r#"{{ "bsdfs": {{ "white": {}, "grey": {} }},
+                     "shapes": [{{ "type": "mesh", "path": "lib.mesh", "bsdf": "grey" }}] }}"#,
+                diffuse(0.8),
+                diffuse(0.5)
+            ),
+        );
+        write(
+            "shot.json",
+            r#"{ "camera": {
+                "type": "orthographic", "res": [64, 64], "width": 2.0,
+                "transform": { "eye": [0, 0, 5], "center": [0, 0, 0], "up": [0, 1, 0] }
+            } }"#
+                .into(),
+        );
+        let root = format!(
+            r#"{{ "include": ["lib/materials.json", "shot.json"],
+                 "bsdfs": {{ "white": {} }},
+                 "shapes": [{{ "type": "mesh", "path": "root.mesh", "bsdf": "white" }}] }}"#,
+            diffuse(0.9)
+        );
+        let resolver = LocalFileResolver::new(vec![dir.to_path_buf()]);
+        let graph = parse_scene(&root).unwrap();
+        let mut graph = resolve_includes(
+            graph,
+            &resolver,
+            &mut vec![dir.join("root.json")],
+            &mut HashMap::new(),
+        )
+        .unwrap();
+        assert!(graph.include.is_empty());
+        assert!(matches!(
+            graph.camera,
+            Some(node::Camera::Orthographic { .. })
+        ));
+        assert_eq!(graph.bsdfs.len(), 2);
+        let white = serde_json::to_value(&graph.bsdfs["white"]).unwrap();
+        assert_eq!(white["color"]["values"][0].as_f64(), Some(0.9f32 as f64));
+        let mut paths = vec![];
+        graph.foreach_ext_files(|p| paths.push(p.clone()));
+        let lib_mesh = Path::new("lib").join("lib.mesh");
+        assert_eq!(paths, vec![lib_mesh.to_string_lossy(), "root.mesh".into()]);
+
+        // an alias of the including file is caught at once, not by the depth limit
+        write("shot.json", r#"{ "include": ["lib/../shot.json"] }"#.into());
+        let graph = parse_scene(&root).unwrap();
+        let err = resolve_includes(graph, &resolver, &mut vec![], &mut HashMap::new())
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, SceneLoadErrorKind::InvalidInclude(_)));
+        assert_eq!(err.path, Some(PathBuf::from("shot.json")));
+
+        // every level includes the next one twice, each file is still read once
+        use std::cell::RefCell;
+        struct CountingResolver {
+            inner: LocalFileResolver,
+            reads: RefCell<HashMap<PathBuf, usize>>,
+        }
+        impl FileResolver for CountingResolver {
+            fn resolve(&self, path: &Path) -> Option<File> {
+                *self.reads.borrow_mut().entry(path.into()).or_default() += 1;
+                self.inner.resolve(path)
+            }
+            fn canonicalize(&self, path: &Path) -> PathBuf {
+                self.inner.canonicalize(path)
+            }
+        }
+        for level in 0..16 {
+            write(
+                &format!("level{}.json", level),
+                format!(
+                    r#"{{ "include": ["level{n}.json", "./level{n}.json"] }}"#,
+                    n = level + 1
+                ),
+            );
+        }
+        write("level16.json", "{}".into());
+        let resolver = CountingResolver {
+            inner: LocalFileResolver::new(vec![dir.to_path_buf()]),
+            reads: RefCell::new(HashMap::new()),
+        };
+        let graph = parse_scene(r#"{ "include": ["level0.json"] }"#).unwrap();
+        resolve_includes(graph, &resolver, &mut vec![], &mut HashMap::new()).unwrap();
+        let reads = resolver.reads.into_inner();
+        assert_eq!(reads.len(), 17);
+        assert!(reads.values().all(|n| *n == 1));
+    }
+    #[test]
+    fn test_validate_scene() {
+        use super::*;
+        use crate::util::TempDirGuard;
+        let dir = TempDirGuard::new("validate");
+        std::fs::write(dir.join("present.mesh"), b"").unwrap();
+        let path = dir.join("scene.json");
+        std::fs::write(
+            &path,
+            r#"{
+            "bsdfs": {
+                "white": { "type": "diffuse", "color": { "type": "image", "path": "missing.png" } }
+            },
+            "camera": {
+                "type": "perspective", "res": [64, 64], "fov": 60.0, "lens_radius": 0.0, "focal": 1.0,
+                "transform": { "eye": [0, 5, 0], "center": [0, 0, 0], "up": [0, 1, 0] }
+            },
+            "shapes": [
+                { "type": "mesh", "path": "present.mesh", "bsdf": "white" },
+                { "type": "mesh", "path": "missing.mesh", "bsdf": "" }
+            ]
+        }"#,
+        )
+        .unwrap();
+        let problems = validate_scene(&path).unwrap();
+        let locations: Vec<&str> = problems.iter().map(|e| e.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            vec![
+                "camera.transform",
+                "bsdfs.white.path",
+                "shapes[1].path",
+                "shapes[1].bsdf",
+                ""
+            ]
+        );
+        assert!(matches!(
+            problems[0].kind,
+            SceneLoadErrorKind::InvalidCamera(_)
+        ));
+        assert!(matches!(problems[1].kind, SceneLoadErrorKind::FileNotFound));
+        assert_eq!(
+            problems[3].to_string(),
+            "shapes[1].bsdf: no bsdf is assigned"
+        );
+        assert!(matches!(problems[4].kind, SceneLoadErrorKind::NoEmitters));
+    }
+    #[test]
+    fn test_infinite_light_world_bounds() {
+        use super::*;
+        use crate::util::TempDirGuard;
+        let dir = TempDirGuard::new("bounds");
+        let quad = TriangleMesh {
+            name: "quad".into(),
+            vertices: vec![
+                [-1.0, 0.0, -1.0],
+                [1.0, 0.0, -1.0],
+                [1.0, 0.0, 1.0],
+                [-1.0, 0.0, 1.0],
+            ],
+            normals: vec![],
+            texcoords: vec![],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+            normal_indices: vec![],
+            texcoord_indices: vec![],
+            colors: vec![],
+        };
+        quad.encode(&mut File::create(dir.join("quad.mesh")).unwrap())
+            .unwrap();
+        let path = dir.join("scene.json");
+        let scene = with_camera(
+            r#"{
+            "bsdfs": {
+                "white": { "type": "diffuse", "color": { "type": "srgb", "values": [0.8, 0.8, 0.8] } }
+            },
+            CAMERA,
+            "shapes": [{
+                "type": "mesh", "path": "quad.mesh", "bsdf": "white",
+                "transform": { "translate": [0, 2, 0], "rotate": [0, 0, 0], "scale": [2, 1, 2] }
+            }],
+            "lights": [
+                { "type": "environment", "emission": { "type": "srgb", "values": [1, 1, 1] } },
+                {
+                    "type": "directional", "direction": [0, -1, 0], "angular_diameter": 0.5,
+                    "emission": { "type": "srgb", "values": [1, 1, 1] }
+                }
+            ]
+        }"#,
+        );
+        std::fs::write(&path, scene).unwrap();
+        let mut ctx = create_context(&path, "bvh", OocOptions { enable_ooc: false }).unwrap();
+        ctx.load().unwrap();
+        assert_eq!(ctx.lights.len(), 2);
+        let bounds = ctx.world_bounds();
+        assert!((Vec3::from(bounds.min) - vec3(-2.0, 2.0, -2.0)).length() < 1e-5);
+        assert!((Vec3::from(bounds.max) - vec3(2.0, 2.0, 2.0)).length() < 1e-5);
+    }
+    #[test]
+    fn test_shader_cache() {
+        use super::*;
+        use crate::texture::ShadingPoint;
+        use crate::util::image::PixelFormat;
+        use crate::util::TempDirGuard;
+        let dir = TempDirGuard::new("shader");
+        let path = dir.join("scene.json");
+        let write_scene = |scale: f32| {
+            let scene = r#"{
+                CAMERA,
+                "shaders": {
+                    "noise": {
+                        "nodes": {
+                            "output": { "type": "noise", "pattern": "perlin", "dimension": 3, "scale": SCALE }
+                        },
+                        "resolution": 8,
+                        "precompute": true,
+                        "cache": "noise.cache"
+                    }
+                }
+            }"#;
+            std::fs::write(
+                &path,
+                with_camera(scene).replace("SCALE", &scale.to_string()),
+            )
+            .unwrap();
+        };
+        let load = || {
+            let mut ctx = create_context(&path, "bvh", OocOptions { enable_ooc: false }).unwrap();
+            ctx.load_shader(&"noise".into(), "").unwrap()
+        };
+        let sp = ShadingPoint {
+            texcoord: vec2(0.5, 0.5),
+            color: Vec3::ONE,
+        };
+        write_scene(4.0);
+        load();
+        let cache = dir.join("noise.cache");
+        let key = String::decode(&mut File::open(&cache).unwrap()).unwrap();
+
+        // a cache baked from the same definition is used as is
+        let marker =
+            TiledImage::from_fn(8, 8, PixelFormat::Rgb32f, |_, _| vec4(0.25, 0.5, 0.75, 1.0));
+        let mut writer = File::create(&cache).unwrap();
+        key.encode(&mut writer).unwrap();
+        marker.encode(&mut writer).unwrap();
+        drop(writer);
+        let rgb = load().evaluate_rgb(&sp);
+        assert!((rgb - vec3(0.25, 0.5, 0.75)).length() < 1e-5, "{}", rgb);
+
+        // a changed shader bakes again and replaces the cache
+        write_scene(8.0);
+        let rgb = load().evaluate_rgb(&sp);
+        assert!((rgb - vec3(0.25, 0.5, 0.75)).length() > 1e-3);
+        let new_key = String::decode(&mut File::open(&cache).unwrap()).unwrap();
+        assert_ne!(key, new_key);
+    }
+    #[test]
+    fn test_float_texture_cache() {
+        use super::*;
+        use crate::texture::ShadingPoint;
+        use crate::util::image::PixelFormat;
+        use crate::util::TempDirGuard;
+        use akari_common::image::{ImageBuffer, Rgb};
+        let dir = TempDirGuard::new("texcache");
+        let path = dir.join("scene.json");
+        std::fs::write(&path, with_camera("{ CAMERA }")).unwrap();
+        let img: ImageBuffer<Rgb<u16>, Vec<u16>> =
+            ImageBuffer::from_pixel(4, 4, Rgb([0, 13107, 0]));
+        img.save(dir.join("roughness.png")).unwrap();
+        let node = |channel| node::FloatTexture::CachedImage {
+            path: "roughness.png".into(),
+            cache: Some(node::TextureCache {
+                path: "roughness.cache".into(),
+            }),
+            channel: Some(channel),
+        };
+        let load = |node: &node::FloatTexture| {
+            let mut ctx = create_context(&path, "bvh", OocOptions { enable_ooc: false }).unwrap();
+            ctx.load_float_texture(node, "").unwrap()
+        };
+        let sp = ShadingPoint {
+            texcoord: vec2(0.5, 0.5),
+            color: Vec3::ONE,
+        };
+        // the first load bakes the selected channel into the cache
+        assert!((load(&node(node::ImageChannel::G)).evaluate(&sp) - 0.2).abs() < 1e-3);
+        let cache = dir.join("roughness.cache");
+        let mut reader = BufReader::new(File::open(&cache).unwrap());
+        let key = String::decode(&mut reader).unwrap();
+        let baked = TiledImage::decode(&mut reader).unwrap();
+        assert!(baked.metadata().format == PixelFormat::R16f);
+        drop(reader);
+
+        // later loads read the cache instead of the image
+        let marker = TiledImage::from_fn(4, 4, PixelFormat::R16f, |_, _| vec4(0.5, 0.5, 0.5, 1.0));
+        let mut writer = File::create(&cache).unwrap();
+        key.encode(&mut writer).unwrap();
+        marker.encode(&mut writer).unwrap();
+        drop(writer);
+        assert!((load(&node(node::ImageChannel::G)).evaluate(&sp) - 0.5).abs() < 1e-3);
+
+        // another channel bakes again
+        assert!(load(&node(node::ImageChannel::R)).evaluate(&sp).abs() < 1e-3);
+        let new_key = String::decode(&mut File::open(&cache).unwrap()).unwrap();
+        assert_ne!(key, new_key);
+
+        // a cache that cannot be written does not fail the load
+        let unwritable = node::FloatTexture::CachedImage {
+            path: "roughness.png".into(),
+            cache: Some(node::TextureCache {
+                path: "missing/roughness.cache".into(),
+            }),
+            channel: None,
+        };
+        assert!((load(&unwritable).evaluate(&sp) - 0.2 / 3.0).abs() < 1e-3);
+    }
+    #[test]
+    fn test_pack_missing_cache() {
+        use super::*;
+        use crate::util::TempDirGuard;
+        let dir = TempDirGuard::new("pack");
+        let path = dir.join("scene.json");
+        std::fs::write(
+            &path,
+            with_camera(
+                r#"{
+                CAMERA,
+                "shaders": {
+                    "noise": {
+                        "nodes": {
+                            "output": { "type": "noise", "pattern": "perlin", "dimension": 1 }
+                        },
+                        "resolution": 8,
+                        "precompute": true,
+                        "cache": "CACHE"
+                    }
+                }
+            }"#,
+            )
+            .replace("CACHE", &dir.join("noise.cache").to_string_lossy()),
+        )
+        .unwrap();
+        let bundle = dir.join("scene.akb");
+        pack_scene(&path, &bundle).unwrap();
+
+        // the missing cache is renamed into the bundle, the bake is not written anywhere
+        let (graph, _, _) = read_scene(&bundle).unwrap();
+        assert_eq!(
+            graph.shaders["noise"].cache.as_deref(),
+            Some("external/0/noise.cache")
+        );
+        let mut ctx = create_context(&bundle, "bvh", OocOptions { enable_ooc: false }).unwrap();
+        ctx.load_shader(&"noise".into(), "").unwrap();
+        assert!(!dir.join("noise.cache").exists());
+    }
+    #[test]
+    fn test_reject_emissive_animation() {
+        use super::*;
+        let pose = |x: f32| {
+            node::Keyframe::TRS(node::TRS {
+                translate: [x, 0.0, 0.0],
+                ..Default::default()
+            })
+        };
+        let motion = node::Transform::Motion(node::MotionTransform {
+            start: pose(0.0),
+            end: pose(1.0),
+        });
+        let still = node::Transform::from(pose(0.0));
+        assert!(reject_emissive_animation(Some(&motion), false, "shapes[0]").is_ok());
+        assert!(reject_emissive_animation(Some(&still), true, "shapes[0]").is_ok());
+        let e = reject_emissive_animation(Some(&motion), true, "shapes[0]").unwrap_err();
+        assert_eq!(e.location, "shapes[0].transform");
+        assert!(matches!(e.kind, SceneLoadErrorKind::Unsupported(_)));
+    }
+    #[test]
+    fn test_group_transforms() {
+        use super::*;
+        let scene = r#"{
+            "groups": [
+                {
+                    "name": "furniture", "transform": { "translate": [1, 0, 0], "rotate": [0, 0, 0], "scale": [1, 1, 1] },
+                    "groups": [{
+                        "name": "lamp", "transform": { "translate": [0, 2, 0], "rotate": [0, 0, 0], "scale": [1, 1, 1] },
+                        "lights": [{ "type": "point", "pos": [0, 0, 3], "emission": { "type": "srgb", "values": [1, 1, 1] } }]
+                    }]
+                },
+                {
+                    "name": "copy", "instance": "furniture",
+                    "transform": { "translate": [0, 0, 10], "rotate": [0, 0, 0], "scale": [1, 1, 1] }
+                },
+                { "name": "outer", "groups": [{ "name": "inner", "instance": "outer" }] },
+                {
+                    "name": "hidden", "hidden": true,
+                    "lights": [{ "type": "point", "pos": [0, 0, 0], "emission": { "type": "srgb", "values": [1, 1, 1] } }]
+                }
+            ]
+        }"#;
+        let mut graph = parse_scene(scene).unwrap();
+        let ctx = test_context(graph.clone());
+        let flatten = |groups: &[node::Group]| {
+            let mut by_name = HashMap::new();
+            collect_group_names(groups, "groups", &mut by_name)?;
+            let mut out = FlattenedGroups::default();
+            ctx.flatten_groups(groups, None, "groups", &by_name, &mut vec![], &mut out)?;
+            Ok::<_, SceneLoadError>(
+                out.lights
+                    .iter()
+                    .map(|(light, parent, _)| match light {
+                        node::Light::Point { pos, .. } => parent
+                            .unwrap_or_else(Transform::identity)
+                            .transform_point(Vec3::from(*pos)),
+                        _ => unreachable!(),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let err = flatten(&graph.groups).err().unwrap();
+        assert_eq!(err.location, "groups[2].groups[0]");
+        graph.groups.remove(2);
+        let positions = flatten(&graph.groups).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert!((positions[0] - vec3(1.0, 2.0, 3.0)).length() < 1e-5);
+        assert!((positions[1] - vec3(0.0, 2.0, 13.0)).length() < 1e-5);
+    }
+    #[test]
+    fn test_keyframe_pose() {
+        use super::*;
+        let t: node::Transform = serde_json::from_str(
+            r#"{ "keyframes": [
+                { "frame": 0, "transform": { "translate": [0, 0, 0], "rotate": [0, 0, 0], "scale": [1, 1, 1] } },
+                { "frame": 4, "transform": { "translate": [4, 0, 0], "rotate": [0, 0, 0], "scale": [1, 1, 1] } }
+            ] }"#,
+        )
+        .unwrap();
+        assert!(t.is_animated());
+        let mut ctx = test_context(parse_scene("{}").unwrap());
+        ctx.frame = 1.0;
+        let x = |t: Transform| t.transform_point(Vec3::ZERO).x;
+        assert!((x(ctx.load_transform(&t, false)) - 1.0).abs() < 1e-5);
+        let motion = ctx.load_motion(&t, false);
+        assert!((x(motion.at(1.0)) - 2.0).abs() < 1e-5);
+        if let node::Transform::Keyframes(k) = &t {
+            assert!((x(ctx.keyframe_pose(k, 10.0, false)) - 4.0).abs() < 1e-5);
+            assert!(x(ctx.keyframe_pose(k, -3.0, false)).abs() < 1e-5);
+        }
+        assert!(validate_keyframes(&t, "shape.transform").is_ok());
+    }
+}
@@ -23,6 +23,7 @@ pub use half;
 pub use num_cpus;
 pub use statrs;
 pub use os_pipe;
+pub use sha2;
 mod test {
     #[test]
     fn test_endianess() {
@@ -558,8 +558,8 @@ macro_rules! impl_bvh_accel {
                 let uv = hit.uv;
                 let ng = hit.ng;
                 let shape = self.data.shapes[hit.geom_id as usize].as_ref();
-                let triangle = shape.shading_triangle(hit.prim_id);
-                let ns = triangle.ns(uv);
+                let triangle = shape.shading_triangle_at(hit.prim_id, hit.time);
+                let ns = triangle.shading_normal(uv);
                 let texcoord = triangle.texcoord(uv);
                 SurfaceInteraction::<'a> {
                     shape,
@@ -569,7 +569,10 @@ macro_rules! impl_bvh_accel {
                     uv,
                     ng,
                     ns,
-                    sp: ShadingPoint { texcoord },
+                    sp: ShadingPoint {
+                        texcoord,
+                        color: triangle.color(uv),
+                    },
                     texcoord,
                 }
             }
@@ -7,15 +7,17 @@ use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use rayon::prelude::*;
 use std::ffi::c_void;
-use std::{any::Any, collections::HashMap, convert::TryInto, sync::Arc};
+use std::{collections::HashMap, convert::TryInto, sync::Arc};
 use sys::RTCIntersectContext;
 
+use super::InstanceKey;
 use crate::{
     bsdf::Bsdf,
     distribution::Distribution1D,
     shape::{MeshInstanceProxy, Shape, SurfaceSample, TriangleMesh},
     Bounds3f, Ray, Vec3,
 };
+const MOTION_TIME_STEPS: u32 = 17;
 struct Device(sys::RTCDevice);
 unsafe impl Send for Device {}
 unsafe impl Sync for Device {}
@@ -116,17 +118,40 @@ impl EmbreeInstance {
         init_device();
         let device = DEVICE.lock();
         let device = device.0;
+        let mesh_ref = mesh
+            .as_ref()
+            .as_any()
+            .downcast_ref::<MeshInstanceProxy>()
+            .unwrap();
         let geometry = sys::rtcNewGeometry(device, sys::RTCGeometryType_RTC_GEOMETRY_TYPE_INSTANCE);
         sys::rtcSetGeometryInstancedScene(geometry, base);
+        if let Some(motion) = &mesh_ref.motion {
+            // embree interpolates linearly between time steps,
+            // so sample the motion densely enough to follow the rotation
+            sys::rtcSetGeometryTimeStepCount(geometry, MOTION_TIME_STEPS);
+            for step in 0..MOTION_TIME_STEPS {
+                let time = step as f32 / (MOTION_TIME_STEPS - 1) as f32;
+                let m = motion.at(time).m4.to_cols_array();
+                sys::rtcSetGeometryTransform(
+                    geometry,
+                    step,
+                    sys::RTCFormat_RTC_FORMAT_FLOAT4X4_COLUMN_MAJOR,
+                    m.as_ptr() as *const c_void,
+                );
+            }
+        } else if let Some(transform) = &mesh_ref.transform {
+            let m = transform.m4.to_cols_array();
+            sys::rtcSetGeometryTransform(
+                geometry,
+                0,
+                sys::RTCFormat_RTC_FORMAT_FLOAT4X4_COLUMN_MAJOR,
+                m.as_ptr() as *const c_void,
+            );
+        }
         sys::rtcCommitGeometry(geometry);
         let scene = sys::rtcNewScene(device);
         sys::rtcAttachGeometry(scene, geometry);
         sys::rtcCommitScene(scene);
-        let mesh_ref = mesh
-            .as_ref()
-            .as_any()
-            .downcast_ref::<MeshInstanceProxy>()
-            .unwrap();
         sys::rtcRetainScene(base);
         Self {
             base,
@@ -134,8 +159,27 @@ impl EmbreeInstance {
             mesh: mesh.clone(),
             mesh_ref: std::mem::transmute(mesh_ref),
             instance: geometry,
-            area: mesh_ref.mesh.area(),
-            dist: mesh_ref.mesh.area_distribution(),
+            area: mesh_ref.area(),
+            dist: mesh_ref
+                .mesh
+                .instance_area_distribution(mesh_ref.transform.as_ref()),
+        }
+    }
+    // false when the hit falls on a cut-out part of the surface
+    fn is_opaque(&self, ray: &Ray, prim_id: u32, uv: Vec2) -> bool {
+        let mesh_ref = self.mesh_ref;
+        mesh_ref
+            .mesh
+            .is_opaque(mesh_ref.bsdf.opacity(), prim_id, uv, ray)
+    }
+    // embree reports Ng of instanced geometry in object space
+    fn world_ng(&self, ng: Vec3, time: f32) -> Vec3 {
+        if let Some(motion) = &self.mesh_ref.motion {
+            motion.at(time).transform_normal(ng).normalize()
+        } else if let Some(transform) = &self.mesh_ref.transform {
+            transform.transform_normal(ng).normalize()
+        } else {
+            ng.normalize()
         }
     }
 }
@@ -149,50 +193,73 @@ impl Drop for EmbreeInstance {
     }
 }
 
+impl EmbreeInstance {
+    unsafe fn intersect_once(&self, ray: &Ray) -> Option<RayHit> {
+        let mut rayhit = sys::RTCRayHit {
+            ray: to_rtc_ray(ray),
+            hit: sys::RTCHit {
+                Ng_x: 0.0,
+                Ng_y: 0.0,
+                Ng_z: 0.0,
+                u: 0.0,
+                v: 0.0,
+                primID: u32::MAX,
+                geomID: u32::MAX,
+                instID: [u32::MAX],
+            },
+        };
+        let mut ctx = RTCIntersectContext {
+            flags: sys::RTCIntersectContextFlags_RTC_INTERSECT_CONTEXT_FLAG_INCOHERENT,
+            filter: None,
+            instID: [u32::MAX],
+        };
+        sys::rtcIntersect1(
+            self.instance_scene,
+            &mut ctx as *mut _,
+            &mut rayhit as *mut _,
+        );
+        if rayhit.hit.geomID != u32::MAX {
+            let uv = vec2(rayhit.hit.u, rayhit.hit.v);
+            let ng = self.world_ng(
+                vec3(rayhit.hit.Ng_x, rayhit.hit.Ng_y, rayhit.hit.Ng_z),
+                ray.time,
+            );
+            Some(RayHit {
+                uv,
+                t: rayhit.ray.tfar,
+                ng,
+                prim_id: rayhit.hit.primID,
+                geom_id: u32::MAX,
+                time: ray.time,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+// smallest float above t, cut-out hits are skipped by tracing again from there
+fn next_after(t: f32) -> f32 {
+    f32::from_bits(t.max(0.0).to_bits() + 1)
+}
+
 impl Shape for EmbreeInstance {
     fn intersect(&self, ray: &Ray, _: Option<Vec3A>) -> Option<RayHit> {
         let _profiler = scope("EmbreeInstance::intersect");
-        unsafe {
-            let mut rayhit = sys::RTCRayHit {
-                ray: to_rtc_ray(ray),
-                hit: sys::RTCHit {
-                    Ng_x: 0.0,
-                    Ng_y: 0.0,
-                    Ng_z: 0.0,
-                    u: 0.0,
-                    v: 0.0,
-                    primID: u32::MAX,
-                    geomID: u32::MAX,
-                    instID: [u32::MAX],
-                },
-            };
-            let mut ctx = RTCIntersectContext {
-                flags: sys::RTCIntersectContextFlags_RTC_INTERSECT_CONTEXT_FLAG_INCOHERENT,
-                filter: None,
-                instID: [u32::MAX],
-            };
-            sys::rtcIntersect1(
-                self.instance_scene,
-                &mut ctx as *mut _,
-                &mut rayhit as *mut _,
-            );
-            if rayhit.hit.geomID != u32::MAX {
-                let uv = vec2(rayhit.hit.u, rayhit.hit.v);
-                let ng = vec3(rayhit.hit.Ng_x, rayhit.hit.Ng_y, rayhit.hit.Ng_z).normalize();
-                Some(RayHit {
-                    uv,
-                    t: rayhit.ray.tfar,
-                    ng,
-                    prim_id: rayhit.hit.primID,
-                    geom_id: u32::MAX,
-                })
-            } else {
-                None
+        let mut ray = *ray;
+        loop {
+            let hit = unsafe { self.intersect_once(&ray)? };
+            if self.is_opaque(&ray, hit.prim_id, hit.uv) {
+                return Some(hit);
             }
+            ray.tmin = next_after(hit.t);
         }
     }
     fn occlude(&self, ray: &Ray, _: Option<Vec3A>) -> bool {
         let _profiler = scope("EmbreeInstance::occlude");
+        if self.mesh_ref.bsdf.opacity().is_some() {
+            return self.intersect(ray, None).is_some();
+        }
         unsafe {
             let mut ray = to_rtc_ray(ray);
             let mut ctx = RTCIntersectContext {
@@ -211,7 +278,9 @@ impl Shape for EmbreeInstance {
         todo!()
     }
     fn sample_surface(&self, u: Vec3) -> SurfaceSample {
-        self.mesh_ref.mesh.sample_surface(u, &self.dist)
+        self.mesh_ref
+            .mesh
+            .sample_surface(u, &self.dist, self.mesh_ref.transform.as_ref())
     }
     fn area(&self) -> f32 {
         self.area
@@ -221,6 +290,10 @@ impl Shape for EmbreeInstance {
         self.mesh_ref.shading_triangle(prim_id)
     }
 
+    fn shading_triangle_at<'a>(&'a self, prim_id: u32, time: f32) -> shape::ShadingTriangle<'a> {
+        self.mesh_ref.shading_triangle_at(prim_id, time)
+    }
+
     fn triangle(&self, prim_id: u32) -> shape::Triangle {
         self.mesh_ref.triangle(prim_id)
     }
@@ -228,6 +301,8 @@ impl Shape for EmbreeInstance {
 pub struct EmbreeTopLevelAccel {
     scene: sys::RTCScene,
     instances: Vec<Arc<EmbreeInstance>>,
+    // some instance has a cutout opacity, so hits need the alpha test
+    cutouts: bool,
 }
 unsafe impl Send for EmbreeTopLevelAccel {}
 unsafe impl Sync for EmbreeTopLevelAccel {}
@@ -239,30 +314,43 @@ impl Drop for EmbreeTopLevelAccel {
     }
 }
 
+// see AccelCache
+#[derive(Default)]
+pub(crate) struct EmbreeCache {
+    meshes: HashMap<usize, EmbreeMeshAccel>,
+    instances: HashMap<InstanceKey, Arc<EmbreeInstance>>,
+}
 impl EmbreeTopLevelAccel {
-    pub(crate) unsafe fn new(shapes: &Vec<Arc<dyn Shape>>) -> Self {
+    pub(crate) unsafe fn new(shapes: &Vec<Arc<dyn Shape>>, cache: &mut EmbreeCache) -> Self {
         init_device();
-        let mut cache: HashMap<*const dyn Any, EmbreeMeshAccel> = HashMap::new();
+        // instances not used by this build are dropped from the cache
+        let mut instances = HashMap::new();
         let shapes: Vec<_> = shapes
             .iter()
             .map(|shape_| {
                 let shape = shape_.as_ref().as_any();
                 if let Some(mesh) = shape.downcast_ref::<MeshInstanceProxy>() {
-                    let base = mesh.mesh.clone();
-                    if !cache.contains_key(&Arc::as_ptr(&(base.clone() as Arc<dyn Any>))) {
-                        let accel = EmbreeMeshAccel::new(base.clone());
-                        cache.insert(Arc::as_ptr(&(base.clone() as Arc<dyn Any>)), accel);
+                    let key = InstanceKey::new(mesh);
+                    if let Some(instance) = key.and_then(|key| cache.instances.get(&key)) {
+                        instances.insert(key.unwrap(), instance.clone());
+                        return instance.clone();
                     }
+                    let base = mesh.mesh.clone();
                     let accel = cache
-                        .get(&Arc::as_ptr(&(base.clone() as Arc<dyn Any>)))
-                        .unwrap()
-                        .clone();
-                    Arc::new(EmbreeInstance::new(accel.scene, shape_.clone()))
+                        .meshes
+                        .entry(Arc::as_ptr(&base) as usize)
+                        .or_insert_with(|| EmbreeMeshAccel::new(base.clone()));
+                    let instance = Arc::new(EmbreeInstance::new(accel.scene, shape_.clone()));
+                    if let Some(key) = key {
+                        instances.insert(key, instance.clone());
+                    }
+                    instance
                 } else {
                     unimplemented!()
                 }
             })
             .collect();
+        cache.instances = instances;
         let device = DEVICE.lock();
         let device = device.0;
         let scene = sys::rtcNewScene(device);
@@ -270,9 +358,52 @@ impl EmbreeTopLevelAccel {
             sys::rtcAttachGeometryByID(scene, shape.instance, id as u32);
         }
         sys::rtcCommitScene(scene);
+        let cutouts = shapes
+            .iter()
+            .any(|shape| shape.mesh_ref.bsdf.opacity().is_some());
         Self {
             scene,
             instances: shapes,
+            cutouts,
+        }
+    }
+    unsafe fn intersect_once(&self, ray: &Ray) -> Option<RayHit> {
+        let mut rayhit = sys::RTCRayHit {
+            ray: to_rtc_ray(ray),
+            hit: sys::RTCHit {
+                Ng_x: 0.0,
+                Ng_y: 0.0,
+                Ng_z: 0.0,
+                u: 0.0,
+                v: 0.0,
+                primID: u32::MAX,
+                geomID: u32::MAX,
+                instID: [u32::MAX],
+            },
+        };
+        let mut ctx = RTCIntersectContext {
+            flags: sys::RTCIntersectContextFlags_RTC_INTERSECT_CONTEXT_FLAG_INCOHERENT,
+            filter: None,
+            instID: [u32::MAX],
+        };
+        sys::rtcIntersect1(self.scene, &mut ctx as *mut _, &mut rayhit as *mut _);
+        if rayhit.hit.geomID != u32::MAX {
+            let instance = &self.instances[rayhit.hit.instID[0] as usize];
+            let ng = instance.world_ng(
+                vec3(rayhit.hit.Ng_x, rayhit.hit.Ng_y, rayhit.hit.Ng_z),
+                ray.time,
+            );
+            let uv = vec2(rayhit.hit.u, rayhit.hit.v);
+            Some(RayHit {
+                uv,
+                t: rayhit.ray.tfar,
+                ng,
+                prim_id: rayhit.hit.primID,
+                geom_id: rayhit.hit.instID[0],
+                time: ray.time,
+            })
+        } else {
+            None
         }
     }
 }
@@ -287,9 +418,9 @@ impl accel::Accel for EmbreeTopLevelAccel {
     }
     fn hit_to_iteraction<'a>(&'a self, rayhit: RayHit) -> SurfaceInteraction<'a> {
         let instance = &self.instances[rayhit.geom_id as usize];
-        let triangle = instance.shading_triangle(rayhit.prim_id);
+        let triangle = instance.shading_triangle_at(rayhit.prim_id, rayhit.time);
         let uv = rayhit.uv;
-        let ns = triangle.ns(uv);
+        let ns = triangle.shading_normal(uv);
         let texcoord = triangle.texcoord(uv);
         SurfaceInteraction {
             shape: instance.as_ref(),
@@ -299,12 +430,24 @@ impl accel::Accel for EmbreeTopLevelAccel {
             uv,
             ng: rayhit.ng,
             ns,
-            sp: ShadingPoint { texcoord },
+            sp: ShadingPoint {
+                texcoord,
+                color: triangle.color(uv),
+            },
             texcoord,
         }
     }
     fn intersect4(&self, rays: &[Ray; 4], mask: [bool; 4]) -> [Option<RayHit>; 4] {
         let _profiler = scope("EmbreeTopLevelAccel::intersect4");
+        if self.cutouts {
+            let mut hits = [None; 4];
+            for i in 0..4 {
+                if mask[i] {
+                    hits[i] = self.intersect(&rays[i]);
+                }
+            }
+            return hits;
+        }
         let mut rayhit4 = sys::RTCRayHit4 {
             ray: to_rtc_ray4(rays),
             hit: sys::RTCHit4 {
@@ -339,12 +482,15 @@ impl accel::Accel for EmbreeTopLevelAccel {
             let mut hits = [None; 4];
             for i in 0..4 {
                 hits[i] = if rayhit4.hit.geomID[i] != u32::MAX {
-                    let ng = vec3(
-                        rayhit4.hit.Ng_x[i],
-                        rayhit4.hit.Ng_y[i],
-                        rayhit4.hit.Ng_z[i],
-                    )
-                    .normalize();
+                    let instance = &self.instances[rayhit4.hit.instID[0][i] as usize];
+                    let ng = instance.world_ng(
+                        vec3(
+                            rayhit4.hit.Ng_x[i],
+                            rayhit4.hit.Ng_y[i],
+                            rayhit4.hit.Ng_z[i],
+                        ),
+                        rays[i].time,
+                    );
                     let uv = vec2(rayhit4.hit.u[i], rayhit4.hit.v[i]);
                     Some(RayHit {
                         uv,
@@ -352,6 +498,7 @@ impl accel::Accel for EmbreeTopLevelAccel {
                         ng,
                         prim_id: rayhit4.hit.primID[i],
                         geom_id: rayhit4.hit.instID[0][i],
+                        time: rays[i].time,
                     })
                 } else {
                     None
@@ -362,43 +509,26 @@ impl accel::Accel for EmbreeTopLevelAccel {
     }
     fn intersect(&self, ray: &Ray) -> Option<RayHit> {
         let _profiler = scope("EmbreeTopLevelAccel::intersect");
-        unsafe {
-            let mut rayhit = sys::RTCRayHit {
-                ray: to_rtc_ray(ray),
-                hit: sys::RTCHit {
-                    Ng_x: 0.0,
-                    Ng_y: 0.0,
-                    Ng_z: 0.0,
-                    u: 0.0,
-                    v: 0.0,
-                    primID: u32::MAX,
-                    geomID: u32::MAX,
-                    instID: [u32::MAX],
-                },
-            };
-            let mut ctx = RTCIntersectContext {
-                flags: sys::RTCIntersectContextFlags_RTC_INTERSECT_CONTEXT_FLAG_INCOHERENT,
-                filter: None,
-                instID: [u32::MAX],
-            };
-            sys::rtcIntersect1(self.scene, &mut ctx as *mut _, &mut rayhit as *mut _);
-            if rayhit.hit.geomID != u32::MAX {
-                let ng = vec3(rayhit.hit.Ng_x, rayhit.hit.Ng_y, rayhit.hit.Ng_z).normalize();
-                let uv = vec2(rayhit.hit.u, rayhit.hit.v);
-                Some(RayHit {
-                    uv,
-                    t: rayhit.ray.tfar,
-                    ng,
-                    prim_id: rayhit.hit.primID,
-                    geom_id: rayhit.hit.instID[0],
-                })
-            } else {
-                None
+        let mut ray = *ray;
+        loop {
+            let hit = unsafe { self.intersect_once(&ray)? };
+            if !self.cutouts
+                || self.instances[hit.geom_id as usize].is_opaque(&ray, hit.prim_id, hit.uv)
+            {
+                return Some(hit);
             }
+            ray.tmin = next_after(hit.t);
         }
     }
     fn occlude4(&self, rays: &[Ray; 4], mask: [bool; 4]) -> [bool; 4] {
         let _profiler = scope("EmbreeTopLevelAccel::occlude4");
+        if self.cutouts {
+            let mut occluded = [false; 4];
+            for i in 0..4 {
+                occluded[i] = mask[i] && self.intersect(&rays[i]).is_some();
+            }
+            return occluded;
+        }
         let mut ray4 = to_rtc_ray4(rays);
         let mut ctx = RTCIntersectContext {
             flags: sys::RTCIntersectContextFlags_RTC_INTERSECT_CONTEXT_FLAG_INCOHERENT,
@@ -427,6 +557,9 @@ impl accel::Accel for EmbreeTopLevelAccel {
     }
     fn occlude(&self, ray: &Ray) -> bool {
         let _profiler = scope("EmbreeTopLevelAccel::occlude");
+        if self.cutouts {
+            return self.intersect(ray).is_some();
+        }
         unsafe {
             let mut ray = to_rtc_ray(ray);
             let mut ctx = RTCIntersectContext {
@@ -440,6 +573,10 @@ impl accel::Accel for EmbreeTopLevelAccel {
     }
 }
 
+// embree expects ray time in [0, 1], which is also the keyframe range of AnimatedTransform
+fn rtc_time(ray: &Ray) -> f32 {
+    ray.time.clamp(0.0, 1.0)
+}
 fn to_rtc_ray4(ray: &[Ray; 4]) -> sys::RTCRay4 {
     sys::RTCRay4 {
         org_x: [ray[0].o.x, ray[1].o.x, ray[2].o.x, ray[3].o.x],
@@ -448,7 +585,12 @@ fn to_rtc_ray4(ray: &[Ray; 4]) -> sys::RTCRay4 {
         dir_x: [ray[0].d.x, ray[1].d.x, ray[2].d.x, ray[3].d.x],
         dir_y: [ray[0].d.y, ray[1].d.y, ray[2].d.y, ray[3].d.y],
         dir_z: [ray[0].d.z, ray[1].d.z, ray[2].d.z, ray[3].d.z],
-        time: [0.0; 4],
+        time: [
+            rtc_time(&ray[0]),
+            rtc_time(&ray[1]),
+            rtc_time(&ray[2]),
+            rtc_time(&ray[3]),
+        ],
         tnear: [ray[0].tmin, ray[1].tmin, ray[2].tmin, ray[3].tmin],
         tfar: [ray[0].tmax, ray[1].tmax, ray[2].tmax, ray[3].tmax],
         id: [0; 4],
@@ -466,7 +608,7 @@ fn to_rtc_ray(ray: &Ray) -> sys::RTCRay {
         dir_z: ray.d.z,
         tnear: ray.tmin,
         tfar: ray.tmax,
-        time: 0.0,
+        time: rtc_time(ray),
         mask: 0,
         id: 0,
         flags: 0,
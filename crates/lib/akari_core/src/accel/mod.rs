@@ -39,6 +39,10 @@ impl Shape for Arc<dyn Shape> {
         self.as_ref().shading_triangle(prim_id)
     }
 
+    fn shading_triangle_at<'a>(&'a self, prim_id: u32, time: f32) -> ShadingTriangle<'a> {
+        self.as_ref().shading_triangle_at(prim_id, time)
+    }
+
     fn triangle(&self, prim_id: u32) -> Triangle {
         self.as_ref().triangle(prim_id)
     }
@@ -79,54 +83,120 @@ pub trait Accel: Send + Sync {
     }
 }
 
+pub fn is_accel_supported(accel: &str) -> bool {
+    match accel {
+        "bvh" | "qbvh" => true,
+        "embree" => cfg!(feature = "embree"),
+        _ => false,
+    }
+}
 pub fn build_accel(shapes: &Vec<Arc<dyn Shape>>, accel: &str) -> Arc<dyn Accel> {
+    build_accel_cached(shapes, accel, &mut AccelCache::default())
+}
+// same as build_accel, reusing what `cache` holds from previous builds of the same accel type
+pub fn build_accel_cached(
+    shapes: &Vec<Arc<dyn Shape>>,
+    accel: &str,
+    cache: &mut AccelCache,
+) -> Arc<dyn Accel> {
     if accel == "bvh" || accel == "qbvh" {
-        build_accel_custom_bvh(shapes, accel)
+        build_accel_custom_bvh(shapes, accel, cache)
     } else if accel == "embree" {
-        build_accel_embree(shapes)
+        build_accel_embree(shapes, cache)
     } else {
         panic!("unrecognized accel {}", accel)
     }
 }
+
+// identifies an instance that can be reused as long as its mesh, bsdf and placement stay the same
+// moving instances are never reused
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct InstanceKey {
+    mesh: usize,
+    bsdf: usize,
+    transform: Option<[u32; 16]>,
+}
+impl InstanceKey {
+    pub(crate) fn new(proxy: &MeshInstanceProxy) -> Option<Self> {
+        if proxy.motion.is_some() {
+            return None;
+        }
+        Some(Self {
+            mesh: Arc::as_ptr(&proxy.mesh) as usize,
+            bsdf: Arc::as_ptr(&proxy.bsdf).cast::<()>() as usize,
+            transform: proxy
+                .transform
+                .map(|t| t.m4.to_cols_array().map(|x| x.to_bits())),
+        })
+    }
+}
+
+// keeps the acceleration structures of meshes and of the instances that did not change
+// between builds, so that rebuilding a scene where only a few instances moved is cheap
+#[derive(Default)]
+pub struct AccelCache {
+    meshes: HashMap<usize, Arc<MeshBvh>>,
+    instances: HashMap<InstanceKey, Arc<dyn Shape>>,
+    #[cfg(feature = "embree")]
+    embree: embree::EmbreeCache,
+}
+
 #[cfg(feature = "embree")]
-fn build_accel_embree(shapes: &Vec<Arc<dyn Shape>>) -> Arc<dyn Accel> {
+fn build_accel_embree(shapes: &Vec<Arc<dyn Shape>>, cache: &mut AccelCache) -> Arc<dyn Accel> {
     use embree::*;
-    Arc::new(unsafe { EmbreeTopLevelAccel::new(shapes) })
+    Arc::new(unsafe { EmbreeTopLevelAccel::new(shapes, &mut cache.embree) })
 }
 #[cfg(not(feature = "embree"))]
-fn build_accel_embree(shapes: &Vec<Arc<dyn Shape>>) -> Arc<dyn Accel> {
+fn build_accel_embree(shapes: &Vec<Arc<dyn Shape>>, _cache: &mut AccelCache) -> Arc<dyn Accel> {
     unimplemented!()
 }
-fn build_accel_custom_bvh(shapes: &Vec<Arc<dyn Shape>>, accel_type: &str) -> Arc<dyn Accel> {
-    let mut cache: HashMap<*const dyn Any, Arc<MeshBvh>> = HashMap::new();
+fn build_accel_custom_bvh(
+    shapes: &Vec<Arc<dyn Shape>>,
+    accel_type: &str,
+    cache: &mut AccelCache,
+) -> Arc<dyn Accel> {
+    // instances not used by this build are dropped from the cache
+    let mut instances = HashMap::new();
     let shapes: Vec<_> = shapes
         .iter()
         .map(|shape_| {
             let shape = shape_.as_ref().as_any();
             if let Some(mesh) = shape.downcast_ref::<MeshInstanceProxy>() {
-                let base = mesh.mesh.clone();
-                if !cache.contains_key(&Arc::as_ptr(&(base.clone() as Arc<dyn Any>))) {
-                    let accel = base.clone().build_accel();
-                    let accel = match accel_type {
-                        "bvh" => MeshBvh::Bvh(accel),
-                        "qbvh" => MeshBvh::QBvh(qbvh::QBvhAccelBuilder::new(accel).build()),
-                        _ => unreachable!(),
-                    };
-                    cache.insert(
-                        Arc::as_ptr(&(base.clone() as Arc<dyn Any>)),
-                        Arc::new(accel),
-                    );
+                let key = InstanceKey::new(mesh);
+                if let Some(instance) = key.and_then(|key| cache.instances.get(&key)) {
+                    instances.insert(key.unwrap(), instance.clone());
+                    return instance.clone();
                 }
+                let base = mesh.mesh.clone();
                 let accel = cache
-                    .get(&Arc::as_ptr(&(base.clone() as Arc<dyn Any>)))
-                    .unwrap()
+                    .meshes
+                    .entry(Arc::as_ptr(&base) as usize)
+                    .or_insert_with(|| {
+                        let accel = base.clone().build_accel();
+                        Arc::new(match accel_type {
+                            "bvh" => MeshBvh::Bvh(accel),
+                            "qbvh" => MeshBvh::QBvh(qbvh::QBvhAccelBuilder::new(accel).build()),
+                            _ => unreachable!(),
+                        })
+                    })
                     .clone();
-                TriangleMesh::create_instance(mesh.bsdf.clone(), accel, base.clone())
+                let instance = TriangleMesh::create_instance(
+                    mesh.bsdf.clone(),
+                    accel,
+                    base.clone(),
+                    mesh.transform,
+                    mesh.motion,
+                );
+                if let Some(key) = key {
+                    instances.insert(key, instance.clone());
+                }
+                instance
             } else {
                 shape_.clone()
             }
         })
         .collect();
+    cache.instances = instances;
     let bvh_data = TopLevelBvhData {
         shapes: shapes.clone(),
     };
@@ -0,0 +1,503 @@
+use std::sync::Arc;
+
+use crate::{
+    bsdf::{Bsdf, BsdfFlags, BsdfSample, LocalBsdfClosure},
+    texture::{FloatTexture, ShadingPoint, SpectrumTexture},
+    *,
+};
+use bumpalo::Bump;
+
+use super::TransportMode;
+
+/*
+Disney/Principled BSDF following Burley 2012/2015 and the Blender parameterization.
+Subsurface scattering is approximated with the Hanrahan-Krueger diffuse lobe,
+subsurface_radius is not used.
+*/
+pub struct DisneyBsdf {
+    pub color: Arc<dyn SpectrumTexture>,
+    pub subsurface: Arc<dyn FloatTexture>,
+    pub subsurface_color: Arc<dyn SpectrumTexture>,
+    pub metallic: Arc<dyn FloatTexture>,
+    pub specular: Arc<dyn FloatTexture>,
+    pub specular_tint: Arc<dyn FloatTexture>,
+    pub roughness: Arc<dyn FloatTexture>,
+    pub anisotropic: Arc<dyn FloatTexture>,
+    pub anisotropic_rotation: Arc<dyn FloatTexture>,
+    pub sheen: Arc<dyn FloatTexture>,
+    pub sheen_tint: Arc<dyn FloatTexture>,
+    pub clearcoat: Arc<dyn FloatTexture>,
+    pub clearcoat_roughness: Arc<dyn FloatTexture>,
+    pub ior: Arc<dyn FloatTexture>,
+    pub transmission: Arc<dyn FloatTexture>,
+}
+pub struct DisneyBsdfClosure {
+    color: SampledSpectrum,
+    metallic: f32,
+    subsurface: f32,
+    roughness: f32,
+    ax: f32,
+    ay: f32,
+    // (cos, sin) of the anisotropic rotation
+    rotation: Vec2,
+    spec0: SampledSpectrum,
+    sheen: SampledSpectrum,
+    clearcoat: f32,
+    clearcoat_alpha: f32,
+    ior: f32,
+    transmission: f32,
+    mode: TransportMode,
+}
+
+impl Bsdf for DisneyBsdf {
+    fn evaluate<'a, 'b: 'a>(
+        &'b self,
+        sp: &ShadingPoint,
+        mode: TransportMode,
+        lambda: &mut SampledWavelengths,
+        arena: &'a Bump,
+    ) -> &'a dyn LocalBsdfClosure {
+        let subsurface = self.subsurface.evaluate(sp).clamp(0.0, 1.0);
+        let color = SampledSpectrum::lerp(
+            self.color.evaluate(sp, lambda),
+            self.subsurface_color.evaluate(sp, lambda),
+            subsurface,
+        );
+        let metallic = self.metallic.evaluate(sp).clamp(0.0, 1.0);
+        let roughness = self.roughness.evaluate(sp).clamp(0.0, 1.0);
+        let anisotropic = self.anisotropic.evaluate(sp).clamp(0.0, 1.0);
+        let aspect = (1.0 - 0.9 * anisotropic).sqrt();
+        let alpha = roughness * roughness;
+        let phi = 2.0 * PI * self.anisotropic_rotation.evaluate(sp);
+        let tint = color_tint(color);
+        let spec0 = SampledSpectrum::lerp(
+            SampledSpectrum::lerp(
+                SampledSpectrum::one(),
+                tint,
+                self.specular_tint.evaluate(sp).clamp(0.0, 1.0),
+            ) * (0.08 * self.specular.evaluate(sp).max(0.0)),
+            color,
+            metallic,
+        );
+        let sheen = SampledSpectrum::lerp(
+            SampledSpectrum::one(),
+            tint,
+            self.sheen_tint.evaluate(sp).clamp(0.0, 1.0),
+        ) * self.sheen.evaluate(sp).max(0.0);
+        let clearcoat_roughness = self.clearcoat_roughness.evaluate(sp).clamp(0.0, 1.0);
+        arena.alloc(DisneyBsdfClosure {
+            color,
+            metallic,
+            subsurface,
+            roughness,
+            ax: (alpha / aspect).max(0.001),
+            ay: (alpha * aspect).max(0.001),
+            rotation: vec2(phi.cos(), phi.sin()),
+            spec0,
+            sheen,
+            clearcoat: self.clearcoat.evaluate(sp).max(0.0),
+            clearcoat_alpha: (clearcoat_roughness * clearcoat_roughness).max(0.001),
+            ior: self.ior.evaluate(sp).max(1.0 + 1e-4),
+            transmission: self.transmission.evaluate(sp).clamp(0.0, 1.0),
+            mode,
+        })
+    }
+}
+
+fn color_tint(color: SampledSpectrum) -> SampledSpectrum {
+    let luminance = color.values().dot(Vec4::splat(0.25));
+    if luminance > 0.0 {
+        color / luminance
+    } else {
+        SampledSpectrum::one()
+    }
+}
+pub fn schlick_weight(cos_theta: f32) -> f32 {
+    let m = (1.0 - cos_theta).clamp(0.0, 1.0);
+    let m2 = m * m;
+    m2 * m2 * m
+}
+fn gtr1(cos_theta_h: f32, a: f32) -> f32 {
+    if a >= 1.0 {
+        return FRAC_1_PI;
+    }
+    let a2 = a * a;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta_h * cos_theta_h))
+}
+fn sample_gtr1(u: Vec2, a: f32) -> Vec3 {
+    let a2 = a * a;
+    let cos2_theta = if a >= 1.0 {
+        1.0 - u[0]
+    } else {
+        (1.0 - a2.powf(1.0 - u[0])) / (1.0 - a2)
+    };
+    let cos_theta = cos2_theta.max(0.0).sqrt();
+    let sin_theta = (1.0 - cos2_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u[1];
+    vec3(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin())
+}
+fn smith_g1_isotropic(w: Vec3, a: f32) -> f32 {
+    let a2 = a * a;
+    let cos2_theta = Frame::cos2_theta(w);
+    2.0 / (1.0 + (1.0 + a2 * (1.0 - cos2_theta) / cos2_theta).sqrt())
+}
+fn ggx_d(wh: Vec3, ax: f32, ay: f32) -> f32 {
+    let cos2_theta = Frame::cos2_theta(wh);
+    if cos2_theta == 0.0 {
+        return 0.0;
+    }
+    let e = (wh.x * wh.x) / (ax * ax) + (wh.z * wh.z) / (ay * ay) + cos2_theta;
+    1.0 / (PI * ax * ay * e * e)
+}
+fn ggx_lambda(w: Vec3, ax: f32, ay: f32) -> f32 {
+    let abs_tan_theta = Frame::tan_theta(w).abs();
+    if abs_tan_theta.is_infinite() || abs_tan_theta.is_nan() {
+        return 0.0;
+    }
+    let alpha = (Frame::cos2_phi(w) * ax * ax + Frame::sin2_phi(w) * ay * ay).sqrt();
+    let alpha2_tan2 = (alpha * abs_tan_theta).powi(2);
+    0.5 * (-1.0 + (1.0 + alpha2_tan2).sqrt())
+}
+fn ggx_g(wo: Vec3, wi: Vec3, ax: f32, ay: f32) -> f32 {
+    1.0 / (1.0 + ggx_lambda(wo, ax, ay) + ggx_lambda(wi, ax, ay))
+}
+// samples wh proportional to D(wh) * cos(theta_h) in the upper hemisphere
+fn ggx_sample_wh(u: Vec2, ax: f32, ay: f32) -> Vec3 {
+    let (phi, alpha2) = if ax == ay {
+        (2.0 * PI * u[1], ax * ax)
+    } else {
+        let mut phi = (ay / ax * (2.0 * PI * u[1] + 0.5 * PI).tan()).atan();
+        if u[1] > 0.5 {
+            phi += PI;
+        }
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        (
+            phi,
+            1.0 / (cos_phi * cos_phi / (ax * ax) + sin_phi * sin_phi / (ay * ay)),
+        )
+    };
+    let tan2_theta = alpha2 * u[0] / (1.0 - u[0]).max(1e-7);
+    let cos_theta = 1.0 / (1.0 + tan2_theta).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    vec3(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin())
+}
+fn rotate(w: Vec3, rotation: Vec2) -> Vec3 {
+    vec3(
+        rotation.x * w.x - rotation.y * w.z,
+        w.y,
+        rotation.y * w.x + rotation.x * w.z,
+    )
+}
+
+impl DisneyBsdfClosure {
+    fn diffuse_weight(&self) -> f32 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+    fn transmission_weight(&self) -> f32 {
+        (1.0 - self.metallic) * self.transmission
+    }
+    // probabilities of sampling the diffuse, specular, clearcoat and transmission lobes
+    fn lobe_probs(&self) -> [f32; 4] {
+        let w = [
+            self.diffuse_weight(),
+            1.0,
+            0.25 * self.clearcoat.min(1.0),
+            self.transmission_weight(),
+        ];
+        let sum: f32 = w.iter().sum();
+        [w[0] / sum, w[1] / sum, w[2] / sum, w[3] / sum]
+    }
+    fn fresnel(&self, cos_theta_d: f32, entering: bool) -> SampledSpectrum {
+        let fw = schlick_weight(cos_theta_d.abs());
+        let schlick = SampledSpectrum::lerp(self.spec0, SampledSpectrum::one(), fw);
+        if self.transmission > 0.0 {
+            let cos = if entering {
+                cos_theta_d.abs()
+            } else {
+                -cos_theta_d.abs()
+            };
+            let dielectric = SampledSpectrum::splat(fr_dielectric(cos, 1.0, self.ior));
+            SampledSpectrum::lerp(
+                schlick,
+                dielectric,
+                self.transmission * (1.0 - self.metallic),
+            )
+        } else {
+            schlick
+        }
+    }
+    fn eta(&self, wo: Vec3) -> f32 {
+        if Frame::cos_theta(wo) > 0.0 {
+            self.ior
+        } else {
+            1.0 / self.ior
+        }
+    }
+    fn refraction_half_vector(&self, wo: Vec3, wi: Vec3) -> Option<Vec3> {
+        let eta = self.eta(wo);
+        let wh = (wo + wi * eta).normalize();
+        if !wh.is_finite() {
+            return None;
+        }
+        let wh = if wh.y < 0.0 { -wh } else { wh };
+        if wo.dot(wh) * wi.dot(wh) > 0.0 {
+            return None;
+        }
+        Some(wh)
+    }
+    fn evaluate_reflection(&self, wo: Vec3, wi: Vec3) -> SampledSpectrum {
+        let cos_o = Frame::abs_cos_theta(wo);
+        let cos_i = Frame::abs_cos_theta(wi);
+        let wh = (wo + wi).normalize();
+        if cos_o == 0.0 || cos_i == 0.0 || !wh.is_finite() {
+            return SampledSpectrum::zero();
+        }
+        let cos_d = wi.dot(wh).abs();
+        let mut f = SampledSpectrum::zero();
+
+        let diffuse_weight = self.diffuse_weight();
+        if diffuse_weight > 0.0 {
+            let fl = schlick_weight(cos_i);
+            let fv = schlick_weight(cos_o);
+            let rr = 2.0 * self.roughness * cos_d * cos_d;
+            let lambert = (1.0 - 0.5 * fl) * (1.0 - 0.5 * fv);
+            let retro = rr * (fl + fv + fl * fv * (rr - 1.0));
+            let fss90 = 0.5 * rr;
+            let fss = lerp(1.0, fss90, fl) * lerp(1.0, fss90, fv);
+            let ss = 1.25 * (fss * (1.0 / (cos_i + cos_o) - 0.5) + 0.5);
+            let fd = lerp(lambert + retro, ss, self.subsurface);
+            f += (self.color * (fd * FRAC_1_PI) + self.sheen * schlick_weight(cos_d))
+                * diffuse_weight;
+        }
+
+        let d = ggx_d(wh, self.ax, self.ay);
+        let g = ggx_g(wo, wi, self.ax, self.ay);
+        f += self.fresnel(cos_d, Frame::cos_theta(wo) > 0.0) * (d * g / (4.0 * cos_i * cos_o));
+
+        if self.clearcoat > 0.0 {
+            let d = gtr1(Frame::abs_cos_theta(wh), self.clearcoat_alpha);
+            let fr = lerp(0.04, 1.0, schlick_weight(cos_d));
+            let g = smith_g1_isotropic(wo, 0.25) * smith_g1_isotropic(wi, 0.25);
+            f += SampledSpectrum::splat(0.25 * self.clearcoat * d * fr * g / (4.0 * cos_i * cos_o));
+        }
+        f
+    }
+    fn evaluate_refraction(&self, wo: Vec3, wi: Vec3) -> SampledSpectrum {
+        let weight = self.transmission_weight();
+        let cos_o = Frame::cos_theta(wo);
+        let cos_i = Frame::cos_theta(wi);
+        if weight <= 0.0 || cos_o == 0.0 || cos_i == 0.0 {
+            return SampledSpectrum::zero();
+        }
+        let wh = match self.refraction_half_vector(wo, wi) {
+            Some(wh) => wh,
+            None => return SampledSpectrum::zero(),
+        };
+        let eta = self.eta(wo);
+        let f = fr_dielectric(wo.dot(wh), 1.0, self.ior);
+        let sqrt_denom = wo.dot(wh) + eta * wi.dot(wh);
+        let factor = if self.mode == TransportMode::CameraToLight {
+            1.0 / eta
+        } else {
+            1.0
+        };
+        let d = ggx_d(wh, self.ax, self.ay);
+        let g = ggx_g(wo, wi, self.ax, self.ay);
+        let c = self.color.values().max(Vec4::ZERO);
+        let t = SampledSpectrum::new(vec4(c.x.sqrt(), c.y.sqrt(), c.z.sqrt(), c.w.sqrt()));
+        t * (weight
+            * (1.0 - f)
+            * (d * g * eta * eta * wi.dot(wh).abs() * wo.dot(wh).abs() * factor * factor
+                / (cos_i * cos_o * sqrt_denom * sqrt_denom))
+                .abs())
+    }
+    fn pdf_lobes(&self, wo: Vec3, wi: Vec3) -> f32 {
+        let probs = self.lobe_probs();
+        if Frame::same_hemisphere(wo, wi) {
+            let wh = (wo + wi).normalize();
+            if !wh.is_finite() {
+                return 0.0;
+            }
+            let wh = if wh.y < 0.0 { -wh } else { wh };
+            let pdf_diffuse = Frame::abs_cos_theta(wi) * FRAC_1_PI;
+            let pdf_spec =
+                ggx_d(wh, self.ax, self.ay) * Frame::abs_cos_theta(wh) / (4.0 * wo.dot(wh).abs());
+            let pdf_clearcoat = gtr1(Frame::abs_cos_theta(wh), self.clearcoat_alpha)
+                * Frame::abs_cos_theta(wh)
+                / (4.0 * wo.dot(wh).abs());
+            probs[0] * pdf_diffuse + probs[1] * pdf_spec + probs[2] * pdf_clearcoat
+        } else {
+            if probs[3] <= 0.0 {
+                return 0.0;
+            }
+            let wh = match self.refraction_half_vector(wo, wi) {
+                Some(wh) => wh,
+                None => return 0.0,
+            };
+            let eta = self.eta(wo);
+            let sqrt_denom = wo.dot(wh) + eta * wi.dot(wh);
+            let dwh_dwi = (eta * eta * wi.dot(wh)).abs() / (sqrt_denom * sqrt_denom);
+            probs[3] * ggx_d(wh, self.ax, self.ay) * Frame::abs_cos_theta(wh) * dwh_dwi
+        }
+    }
+    fn sample_lobe(&self, u: Vec2, wo: Vec3) -> Option<Vec3> {
+        let probs = self.lobe_probs();
+        let mut u0 = u[0];
+        let mut lobe = 0;
+        while lobe < 3 && u0 >= probs[lobe] {
+            u0 -= probs[lobe];
+            lobe += 1;
+        }
+        let u = vec2((u0 / probs[lobe]).min(1.0 - 1e-6), u[1]);
+        let flip = |w: Vec3| if Frame::cos_theta(wo) < 0.0 { -w } else { w };
+        let wi = match lobe {
+            0 => flip(consine_hemisphere_sampling(u)),
+            1 | 2 => {
+                let wh = if lobe == 1 {
+                    ggx_sample_wh(u, self.ax, self.ay)
+                } else {
+                    sample_gtr1(u, self.clearcoat_alpha)
+                };
+                let wi = reflect(wo, flip(wh));
+                if !Frame::same_hemisphere(wo, wi) {
+                    return None;
+                }
+                wi
+            }
+            _ => {
+                let wh = ggx_sample_wh(u, self.ax, self.ay);
+                if wo.dot(wh) * Frame::cos_theta(wo) <= 0.0 {
+                    return None;
+                }
+                refract(wo, wh, self.ior)?
+            }
+        };
+        Some(wi)
+    }
+}
+impl LocalBsdfClosure for DisneyBsdfClosure {
+    fn flags(&self) -> BsdfFlags {
+        let mut flags = BsdfFlags::GLOSSY_REFLECTION;
+        if self.diffuse_weight() > 0.0 {
+            flags |= BsdfFlags::DIFFUSE_REFLECTION;
+        }
+        if self.transmission_weight() > 0.0 {
+            flags |= BsdfFlags::GLOSSY_REFRACTION;
+        }
+        flags
+    }
+    fn evaluate(&self, wo: Vec3, wi: Vec3) -> SampledSpectrum {
+        let wo = rotate(wo, self.rotation * vec2(1.0, -1.0));
+        let wi = rotate(wi, self.rotation * vec2(1.0, -1.0));
+        if Frame::same_hemisphere(wo, wi) {
+            self.evaluate_reflection(wo, wi)
+        } else {
+            self.evaluate_refraction(wo, wi)
+        }
+    }
+    fn evaluate_pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        let wo = rotate(wo, self.rotation * vec2(1.0, -1.0));
+        let wi = rotate(wi, self.rotation * vec2(1.0, -1.0));
+        self.pdf_lobes(wo, wi)
+    }
+    fn sample(&self, u: Vec2, wo: Vec3) -> Option<BsdfSample> {
+        if Frame::cos_theta(wo) == 0.0 {
+            return None;
+        }
+        let wi = rotate(
+            self.sample_lobe(u, rotate(wo, self.rotation * vec2(1.0, -1.0)))?,
+            self.rotation,
+        );
+        let pdf = self.evaluate_pdf(wo, wi);
+        if !(pdf > 0.0) {
+            return None;
+        }
+        let flag = if Frame::same_hemisphere(wo, wi) {
+            BsdfFlags::GLOSSY_REFLECTION
+        } else {
+            BsdfFlags::GLOSSY_REFRACTION
+        };
+        Some(BsdfSample {
+            wi,
+            f: self.evaluate(wo, wi),
+            pdf,
+            flag,
+        })
+    }
+}
+
+mod test {
+    #[allow(dead_code)]
+    fn closure(
+        metallic: f32,
+        roughness: f32,
+        anisotropic: f32,
+        clearcoat: f32,
+        transmission: f32,
+    ) -> super::DisneyBsdfClosure {
+        use super::*;
+        let aspect = (1.0 - 0.9 * anisotropic).sqrt();
+        let alpha = roughness * roughness;
+        DisneyBsdfClosure {
+            color: SampledSpectrum::one(),
+            metallic,
+            subsurface: 0.0,
+            roughness,
+            ax: (alpha / aspect).max(0.001),
+            ay: (alpha * aspect).max(0.001),
+            rotation: vec2(0.6, 0.8),
+            spec0: SampledSpectrum::splat(lerp(0.04, 1.0, metallic)),
+            sheen: SampledSpectrum::zero(),
+            clearcoat,
+            clearcoat_alpha: 0.09,
+            ior: 1.5,
+            transmission,
+            // no 1/eta^2 radiance scaling so that refraction conserves energy
+            mode: TransportMode::LightToCamera,
+        }
+    }
+    #[test]
+    fn test_disney_sample_pdf() {
+        use super::*;
+        use crate::sampler::Pcg;
+        let params = [
+            (0.0, 0.5, 0.0, 0.0, 0.0),
+            (1.0, 0.4, 0.8, 0.0, 0.0),
+            (0.3, 0.6, 0.3, 1.0, 0.0),
+            (0.0, 0.5, 0.0, 0.0, 1.0),
+        ];
+        let mut rng = Pcg::new(0);
+        let mut next2d = || {
+            vec2(
+                rng.pcg32() as f32 / std::u32::MAX as f32,
+                rng.pcg32() as f32 / std::u32::MAX as f32,
+            )
+        };
+        for &(metallic, roughness, anisotropic, clearcoat, transmission) in &params {
+            let bsdf = closure(metallic, roughness, anisotropic, clearcoat, transmission);
+            let wo = vec3(0.3, 0.8, -0.2).normalize();
+            // the pdf must integrate to at most one over the sphere
+            let n = 200000;
+            let mut integral = 0.0;
+            for _ in 0..n {
+                let wi = uniform_sample_sphere(next2d());
+                integral += bsdf.evaluate_pdf(wo, wi) / uniform_sphere_pdf();
+            }
+            integral /= n as f32;
+            assert!(
+                integral > 0.8 && integral < 1.05,
+                "pdf integral = {}",
+                integral
+            );
+            // a white surface must not reflect more energy than it receives
+            let mut albedo = 0.0;
+            for _ in 0..n {
+                if let Some(sample) = bsdf.sample(next2d(), wo) {
+                    albedo += sample.f.max_element() * Frame::abs_cos_theta(sample.wi) / sample.pdf;
+                }
+            }
+            albedo /= n as f32;
+            assert!(albedo > 0.5 && albedo < 1.05, "albedo = {}", albedo);
+        }
+    }
+}
@@ -2,8 +2,9 @@ use std::sync::Arc;
 
 use bumpalo::Bump;
 
-use crate::texture::{FloatTexture, ShadingPoint, SpectrumTexture};
+use crate::texture::{FloatTexture, NormalMapTexture, ShadingPoint, SpectrumTexture};
 use crate::*;
+pub mod disney;
 pub mod ltc;
 use bitflags::bitflags;
 bitflags! {
@@ -61,6 +62,14 @@ pub trait Bsdf: Sync + Send + AsAny {
     fn emission(&self) -> Option<Arc<dyn SpectrumTexture>> {
         None
     }
+    // perturbs the shading normal of the surfaces using this bsdf
+    fn normal_map(&self) -> Option<&NormalMapTexture> {
+        None
+    }
+    // cutout opacity, tested by the accels when a ray hits the surface
+    fn opacity(&self) -> Option<&dyn FloatTexture> {
+        None
+    }
 }
 pub trait LocalBsdfClosure: Sync + Send {
     fn evaluate(&self, wo: Vec3, wi: Vec3) -> SampledSpectrum;
@@ -141,6 +150,66 @@ impl Bsdf for EmissiveBsdf {
     fn emission(&self) -> Option<Arc<dyn SpectrumTexture>> {
         Some(self.emission.clone())
     }
+    fn normal_map(&self) -> Option<&NormalMapTexture> {
+        self.base.normal_map()
+    }
+    fn opacity(&self) -> Option<&dyn FloatTexture> {
+        self.base.opacity()
+    }
+}
+pub struct NormalMappedBsdf {
+    pub base: Arc<dyn Bsdf>,
+    pub normal_map: NormalMapTexture,
+}
+impl Bsdf for NormalMappedBsdf {
+    fn evaluate<'a, 'b: 'a>(
+        &'b self,
+        sp: &ShadingPoint,
+        mode: TransportMode,
+        lambda: &mut SampledWavelengths,
+        arena: &'a Bump,
+    ) -> &'a dyn LocalBsdfClosure {
+        self.base.evaluate(sp, mode, lambda, arena)
+    }
+    fn emission(&self) -> Option<Arc<dyn SpectrumTexture>> {
+        self.base.emission()
+    }
+    fn normal_map(&self) -> Option<&NormalMapTexture> {
+        Some(&self.normal_map)
+    }
+    fn opacity(&self) -> Option<&dyn FloatTexture> {
+        self.base.opacity()
+    }
+}
+
+/*
+cutout opacity, rays pass through the surface with probability 1 - opacity
+the test is done during traversal (see TriangleMesh::is_opaque), so cut out parts
+neither cast shadows nor count as a bounce, and the bsdf itself is the base one
+*/
+pub struct OpacityBsdf {
+    pub base: Arc<dyn Bsdf>,
+    pub opacity: Arc<dyn FloatTexture>,
+}
+impl Bsdf for OpacityBsdf {
+    fn evaluate<'a, 'b: 'a>(
+        &'b self,
+        sp: &ShadingPoint,
+        mode: TransportMode,
+        lambda: &mut SampledWavelengths,
+        arena: &'a Bump,
+    ) -> &'a dyn LocalBsdfClosure {
+        self.base.evaluate(sp, mode, lambda, arena)
+    }
+    fn emission(&self) -> Option<Arc<dyn SpectrumTexture>> {
+        self.base.emission()
+    }
+    fn normal_map(&self) -> Option<&NormalMapTexture> {
+        self.base.normal_map()
+    }
+    fn opacity(&self) -> Option<&dyn FloatTexture> {
+        Some(self.opacity.as_ref())
+    }
 }
 pub struct MixBsdf<A: Bsdf, B: Bsdf> {
     pub bsdf_a: A,
@@ -249,98 +318,6 @@ impl<'a> LocalBsdfClosure for MixBsdfClosure<'a> {
         }
     }
 }
-// pub struct DisneyBsdf {
-//     pub color: Arc<dyn Texture>,
-//     pub roughness: Arc<dyn Texture>,
-//     pub tint: Arc<dyn Texture>,
-//     pub sheen: Arc<dyn Texture>,
-// }
-// struct DisneyBsdfClosure {
-//     color: SampledSpectrum,
-//     roughness: f32,
-//     sheen: f32,
-//     tint: SampledSpectrum,
-//     sheen_tint: f32,
-// }
-// pub fn schlick_weight(cos_theta: f32) -> f32 {
-//     let m = (1.0 - cos_theta).clamp(0.0, 1.0);
-//     let m2 = m * m;
-//     m2 * m2 * m
-// }
-// pub fn f_schlick_(f0: f32, cos_theta: f32) -> f32 {
-//     f0 + (1.0 - f0) * (1.0 - cos_theta).powi(5)
-// }
-// impl DisneyBsdfClosure {
-//     fn gtr1(dot_hl: f32, a: f32) -> f32 {
-//         if a >= 1.0 {
-//             FRAC_1_PI
-//         } else {
-//             let a2 = a * a;
-//             (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * dot_hl * dot_hl))
-//         }
-//     }
-//     fn separable_ggx_g1(w: Vec3, a: f32) -> f32 {
-//         let a2 = a * a;
-//         let cos_theta = Frame::abs_cos_theta(w);
-//         1.0 / (cos_theta + (a2 + cos_theta - a2 * cos_theta * cos_theta).sqrt())
-//     }
-//     fn evaluate_clearcoat(clearcoat: f32, alpha: f32, wo: Vec3, wi: Vec3, wh: Vec3) -> f32 {
-//         if clearcoat <= 0.0 {
-//             return 0.0;
-//         }
-//         let abs_dot_nh = Frame::abs_cos_theta(wh);
-//         let abs_dot_hl = Frame::abs_cos_theta(wi);
-//         let abs_dot_hv = Frame::abs_cos_theta(wo);
-//         let dot_hl = wh.dot(wi);
-//         let d = Self::gtr1(abs_dot_nh, lerp_scalar(0.1, 0.001, alpha));
-//         let f = f_schlick_(0.04, dot_hl);
-//         let gl = Self::separable_ggx_g1(wi, 0.25);
-//         let gv = Self::separable_ggx_g1(wo, 0.25);
-
-//         0.25 * clearcoat * d * f * gl * gv
-//     }
-//     fn evaluate_tint(color: SampledSpectrum) -> SampledSpectrum {
-//         let rgb = color.to_rgb_linear();
-//         let luminance = vec3(0.3, 0.6, 0.1).dot(rgb);
-//         if luminance > 0.0 {
-//             color * (1.0 / luminance)
-//         } else {
-//             SampledSpectrum::from_rgb_linear(vec3(1.0, 1.0, 1.0))
-//         }
-//     }
-//     fn evaluateheen(&self, sp: &ShadingPoint, wo: Vec3, wi: Vec3, wm: Vec3) -> SampledSpectrum {
-//         let sheen = self.sheen;
-//         if sheen <= 0.0 {
-//             return SampledSpectrum::zero();
-//         }
-//         let dot_hl = wm.dot(wi);
-//         let tint = Self::evaluate_tint(self.color);
-//         SampledSpectrum::lerp(SampledSpectrum::one(), tint, self.sheen_tint) * self.sheen * schlick_weight(dot_hl)
-//     }
-//     fn ggx_aniso_d(wh: Vec3, ax: f32, ay: f32) -> f32 {
-//         let dot_hx2 = wh.x * wh.x;
-//         let dot_hy2 = wh.y * wh.y;
-//         let cos2 = Frame::cos2_theta(wh);
-//         let ax2 = ax * ax;
-//         let ay2 = ay * ay;
-//         1.0 / (PI * ax * ay * (dot_hx2 / ax2 + dot_hy2 / ay2 + cos2).powi(2))
-//     }
-//     fn separable_smith_ggx_g1(w: Vec3, wh: Vec3, ax: f32, ay: f32) -> f32 {
-//         let dot_hw = wh.dot(w);
-//         if dot_hw <= 0.0 {
-//             return 0.0;
-//         }
-//         let abs_tan_theta = Frame::tan_theta(w).abs();
-//         if abs_tan_theta.is_infinite() {
-//             return 0.0;
-//         }
-//         let a = (Frame::cos2_phi(w) * ax * ax + Frame::sin2_phi(w) * ay * ay).sqrt();
-//         let a2_tan = (a * abs_tan_theta).powi(2);
-//         let lambda = 0.5 * (-1.0 + (1.0 + a2_tan).sqrt());
-//         1.0 / (1.0 + lambda)
-//     }
-// }
-
 pub struct DiffuseBsdf {
     pub color: Arc<dyn SpectrumTexture>,
 }
@@ -1,5 +1,6 @@
 use crate::*;
 use sampler::*;
+use std::sync::Arc;
 #[derive(Clone, Copy)]
 pub struct CameraSample {
     pub p: Vec3,
@@ -25,9 +26,20 @@ pub trait Camera: Sync + Send + AsAny {
         &self,
         u: Vec2,
         p: &ReferencePoint,
+        time: f32, // time of the path being connected to the camera
         lambda: &SampledWavelengths,
     ) -> Option<CameraSample>;
     fn n(&self) -> Vec3;
+    // cameras that only see along a single direction (e.g. orthographic) sample rays by
+    // position on the film; pdf_we then reports that area density in place of the
+    // directional one, which is converted to area by projection instead of by distance
+    fn is_delta_direction(&self) -> bool {
+        false
+    }
+    // whether `pixel` is imaged at all, pixels outside are masked out of the output
+    fn mask(&self, _pixel: UVec2) -> bool {
+        true
+    }
 }
 
 pub struct PerspectiveCamera {
@@ -38,10 +50,18 @@ pub struct PerspectiveCamera {
     pub r2c: Transform,
     pub c2r: Transform,
     pub a: f32,
+    pub lens_radius: f32,
+    pub focal: f32,
 }
 
 impl PerspectiveCamera {
-    pub fn new(resolution: UVec2, transform: &Transform, fov: f32) -> Self {
+    pub fn new(
+        resolution: UVec2,
+        transform: &Transform,
+        fov: f32,
+        lens_radius: f32,
+        focal: f32,
+    ) -> Self {
         {
             let det = transform.m3.determinant();
             assert!(
@@ -72,6 +92,11 @@ impl PerspectiveCamera {
             ((p_max.x - p_min.x) * (p_max.y - p_min.y)).abs()
         };
         assert!(a > 0.0);
+        assert!(
+            lens_radius == 0.0 || focal > 0.0,
+            "thin lens requires focal > 0, got {}",
+            focal
+        );
         Self {
             resolution,
             c2w: *transform,
@@ -80,8 +105,41 @@ impl PerspectiveCamera {
             c2r: r2c.inverse(),
             fov,
             a,
+            lens_radius: lens_radius.max(0.0),
+            focal,
+        }
+    }
+    fn lens_area(&self) -> f32 {
+        if self.lens_radius > 0.0 {
+            PI * self.lens_radius * self.lens_radius
+        } else {
+            1.0
         }
     }
+    // returns the raster position hit by a ray leaving the lens and cos(theta) wrt. the view direction
+    fn raster_position(&self, ray: &Ray) -> Option<(Vec2, f32)> {
+        let cos_theta = ray.d.dot(self.c2w.transform_vector(vec3(0.0, 0.0, -1.0)));
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let ft = if self.lens_radius > 0.0 {
+            self.focal
+        } else {
+            1.0
+        };
+        let p_focus = self.w2c.transform_point(ray.at(ft / cos_theta));
+        // project the point on the plane of focus back onto the z = -1 plane through the pinhole
+        let p_film = p_focus / -p_focus.z;
+        let p_raster = self.c2r.transform_point(p_film);
+        if p_raster.x < 0.0
+            || p_raster.x >= self.resolution().x as f32
+            || p_raster.y < 0.0
+            || p_raster.y >= self.resolution().y as f32
+        {
+            return None;
+        }
+        Some((vec2(p_raster.x, p_raster.y), cos_theta))
+    }
 }
 impl Camera for PerspectiveCamera {
     fn generate_ray(
@@ -90,7 +148,6 @@ impl Camera for PerspectiveCamera {
         sampler: &mut dyn Sampler,
         _lambda: &SampledWavelengths,
     ) -> (Ray, SampledSpectrum) {
-        // let p_lens = consine_hemisphere_sampling(sampler.next2d())
         let fpixel: Vec2 = pixel.as_vec2();
         let p_film = sampler.next2d() + fpixel;
 
@@ -100,6 +157,13 @@ impl Camera for PerspectiveCamera {
                 .transform_point(vec3(p_film.x, p_film.y, 0.0))
                 .normalize(),
         );
+        if self.lens_radius > 0.0 {
+            let p_lens = self.lens_radius * concentric_sample_disk(sampler.next2d());
+            let ft = self.focal / -ray.d.z;
+            let p_focus = ray.at(ft);
+            ray.o = p_lens.extend(0.0);
+            ray.d = (p_focus - ray.o).normalize();
+        }
 
         // ray.tmin = (1.0 / ray.d.z).abs();
         ray.o = self.c2w.transform_point(ray.o);
@@ -111,19 +175,19 @@ impl Camera for PerspectiveCamera {
     }
     fn sample_wi(
         &self,
-        _u: Vec2,
+        u: Vec2,
         ref_: &ReferencePoint,
+        _time: f32,
         lambda: &SampledWavelengths,
     ) -> Option<CameraSample> {
-        // TODO: area lens
-        let p_lens = Vec2::ZERO;
+        let p_lens = self.lens_radius * concentric_sample_disk(u);
         let p_lens_world = self.c2w.transform_point(p_lens.extend(0.0));
 
         let wi = p_lens_world - ref_.p;
         let dist = wi.length();
         let wi = wi.normalize();
 
-        let lens_area = 1.0f32;
+        let lens_area = self.lens_area();
         let n = self.n();
         let pdf = (dist * dist) / (n.dot(wi).abs() * lens_area);
         let ray = Ray::spawn_to(p_lens_world, ref_.p);
@@ -141,45 +205,815 @@ impl Camera for PerspectiveCamera {
         })
     }
     fn we(&self, ray: &Ray, _lambda: &SampledWavelengths) -> (Option<UVec2>, SampledSpectrum) {
-        let cos_theta = ray.d.dot(self.c2w.transform_vector(vec3(0.0, 0.0, -1.0)));
-        if cos_theta <= 0.0 {
-            return (None, SampledSpectrum::zero());
+        let (p_raster, cos_theta) = match self.raster_position(ray) {
+            Some(x) => x,
+            None => return (None, SampledSpectrum::zero()),
+        };
+        let lens_area = self.lens_area();
+        (
+            Some(uvec2(p_raster.x as u32, p_raster.y as u32)),
+            SampledSpectrum::one() / (self.a * lens_area * cos_theta.powi(4)),
+        )
+    }
+    fn pdf_we(&self, ray: &Ray) -> (f32, f32) {
+        let (_, cos_theta) = match self.raster_position(ray) {
+            Some(x) => x,
+            None => return (0.0, 0.0),
+        };
+        let lens_area = self.lens_area();
+        (1.0 / lens_area, 1.0 / (self.a * cos_theta.powi(3)))
+    }
+    fn n(&self) -> Vec3 {
+        self.c2w.transform_normal(vec3(0.0, 0.0, -1.0))
+    }
+}
+
+pub struct OrthographicCamera {
+    pub resolution: UVec2,
+    pub c2w: Transform,
+    pub w2c: Transform,
+    pub r2c: Transform,
+    pub c2r: Transform,
+    // world-space film extent
+    pub width: f32,
+    pub height: f32,
+}
+
+impl OrthographicCamera {
+    pub fn new(resolution: UVec2, transform: &Transform, width: f32) -> Self {
+        {
+            let det = transform.m3.determinant();
+            assert!(
+                (det - 1.0).abs() < 0.01,
+                "transform has det = {} != 1.0",
+                det
+            );
         }
-        // assert!(cos_theta <= 1.0 + 1e-3);
-        let p_focus = ray.at(1.0 / cos_theta);
-        let p_raster = self.c2r.transform_point(self.w2c.transform_point(p_focus));
-        // assert!(p_raster.z.abs() < 1e-3);
+        assert!(width > 0.0, "film width must be positive, got {}", width);
+        let fres = vec2(resolution.x as f32, resolution.y as f32);
+        let height = width * fres.y / fres.x;
+        let mut m = Mat4::IDENTITY;
+        m = Mat4::from_scale(vec3(1.0 / fres.x, 1.0 / fres.y, 1.0)) * m;
+        m = Mat4::from_translation(vec3(-0.5, -0.5, 0.0)) * m;
+        m = Mat4::from_scale(vec3(width, -height, 1.0)) * m;
+        let r2c = Transform::from_matrix(&m);
+        Self {
+            resolution,
+            c2w: *transform,
+            w2c: transform.inverse(),
+            r2c,
+            c2r: r2c.inverse(),
+            width,
+            height,
+        }
+    }
+    fn film_area(&self) -> f32 {
+        self.width * self.height
+    }
+    fn view_dir(&self) -> Vec3 {
+        self.c2w.transform_vector(vec3(0.0, 0.0, -1.0)).normalize()
+    }
+    // returns the raster position of the ray origin if the ray leaves the film along the view direction
+    fn raster_position(&self, ray: &Ray) -> Option<Vec2> {
+        // rays reconstructed from vertex positions only match the view direction up to rounding
+        if ray.d.dot(self.view_dir()) < 1.0 - 1e-3 {
+            return None;
+        }
+        let p_raster = self.c2r.transform_point(self.w2c.transform_point(ray.o));
         if p_raster.x < 0.0
-            || p_raster.x >= self.resolution().x as f32
+            || p_raster.x >= self.resolution.x as f32
             || p_raster.y < 0.0
-            || p_raster.y >= self.resolution().y as f32
+            || p_raster.y >= self.resolution.y as f32
         {
-            return (None, SampledSpectrum::zero());
+            return None;
+        }
+        Some(vec2(p_raster.x, p_raster.y))
+    }
+}
+impl Camera for OrthographicCamera {
+    fn generate_ray(
+        &self,
+        pixel: UVec2,
+        sampler: &mut dyn Sampler,
+        _lambda: &SampledWavelengths,
+    ) -> (Ray, SampledSpectrum) {
+        let p_film = sampler.next2d() + pixel.as_vec2();
+        let o = self.r2c.transform_point(vec3(p_film.x, p_film.y, 0.0));
+        let ray = Ray::spawn(self.c2w.transform_point(o), self.view_dir());
+        (ray, SampledSpectrum::one())
+    }
+    fn resolution(&self) -> UVec2 {
+        self.resolution
+    }
+    fn sample_wi(
+        &self,
+        _u: Vec2,
+        ref_: &ReferencePoint,
+        _time: f32,
+        lambda: &SampledWavelengths,
+    ) -> Option<CameraSample> {
+        // the only film point that sees `ref_` is its projection along the view direction
+        let p_camera = self.w2c.transform_point(ref_.p);
+        if p_camera.z >= 0.0 {
+            return None;
+        }
+        let p_film = self.c2w.transform_point(vec3(p_camera.x, p_camera.y, 0.0));
+        let wi = -self.view_dir();
+        let ray = Ray::spawn(p_film, -wi);
+        let (raster, we) = self.we(&ray, lambda);
+        let vis_ray = Ray::spawn_to(ref_.p, p_film).offset_along_normal(ref_.n);
+        Some(CameraSample {
+            p: p_film,
+            wi,
+            pdf: 1.0,
+            ray,
+            vis_ray,
+            raster: raster?,
+            we,
+            n: self.n(),
+        })
+    }
+    fn we(&self, ray: &Ray, _lambda: &SampledWavelengths) -> (Option<UVec2>, SampledSpectrum) {
+        match self.raster_position(ray) {
+            Some(p_raster) => (
+                Some(uvec2(p_raster.x as u32, p_raster.y as u32)),
+                SampledSpectrum::one() / self.film_area(),
+            ),
+            None => (None, SampledSpectrum::zero()),
         }
-        let lens_area = 1.0;
-        (
-            Some(uvec2(p_raster.x as u32, p_raster.y as u32)),
-            SampledSpectrum::one() / (self.a * lens_area * cos_theta.powi(4)),
-        )
     }
     fn pdf_we(&self, ray: &Ray) -> (f32, f32) {
-        let cos_theta = ray.d.dot(self.c2w.transform_vector(vec3(0.0, 0.0, -1.0)));
-        if cos_theta <= 0.0 {
-            return (0.0, 0.0);
+        match self.raster_position(ray) {
+            Some(_) => (1.0, 1.0 / self.film_area()),
+            None => (0.0, 0.0),
+        }
+    }
+    fn n(&self) -> Vec3 {
+        self.c2w.transform_normal(vec3(0.0, 0.0, -1.0))
+    }
+    fn is_delta_direction(&self) -> bool {
+        true
+    }
+}
+
+// latitude-longitude panorama, the center of the image looks down -z
+// with ipd > 0 an omni-directional stereo pair is rendered, left eye on top
+pub struct SphericalCamera {
+    pub resolution: UVec2,
+    pub c2w: Transform,
+    pub w2c: Transform,
+    pub ipd: f32,
+}
+
+impl SphericalCamera {
+    pub fn new(resolution: UVec2, transform: &Transform, ipd: f32) -> Self {
+        {
+            let det = transform.m3.determinant();
+            assert!(
+                (det - 1.0).abs() < 0.01,
+                "transform has det = {} != 1.0",
+                det
+            );
         }
-        let p_focus = ray.at(1.0 / cos_theta);
-        let p_raster = self.c2r.transform_point(self.w2c.transform_point(p_focus));
+        assert!(ipd >= 0.0, "ipd must not be negative, got {}", ipd);
+        assert!(
+            ipd == 0.0 || resolution.y % 2 == 0,
+            "stereo panorama requires an even vertical resolution, got {}",
+            resolution.y
+        );
+        Self {
+            resolution,
+            c2w: *transform,
+            w2c: transform.inverse(),
+            ipd,
+        }
+    }
+    fn eyes(&self) -> u32 {
+        if self.ipd > 0.0 {
+            2
+        } else {
+            1
+        }
+    }
+    fn eye_height(&self) -> f32 {
+        (self.resolution.y / self.eyes()) as f32
+    }
+    // the panorama frame is Y-up with phi = PI looking down -z of the camera
+    fn to_local(v: Vec3) -> Vec3 {
+        vec3(-v.z, v.y, v.x)
+    }
+    fn from_local(v: Vec3) -> Vec3 {
+        vec3(v.z, v.y, -v.x)
+    }
+    // eye position in the panorama frame for rays leaving with azimuth `phi`
+    fn eye_offset(&self, eye: u32, phi: f32) -> Vec3 {
+        let d = spherical_to_dir(vec2(0.5 * PI, phi));
+        let left = vec3(d.z, 0.0, -d.x);
+        let sign = if eye == 0 { 1.0 } else { -1.0 };
+        left * (sign * 0.5 * self.ipd)
+    }
+    // returns the raster position of a ray leaving one of the eyes and sin(theta) of its direction
+    fn raster_position(&self, ray: &Ray) -> Option<(Vec2, f32)> {
+        let d = Self::to_local(self.w2c.transform_vector(ray.d).normalize());
+        let d = vec3(d.x, d.y.clamp(-1.0, 1.0), d.z);
+        let spherical = dir_to_spherical(d);
+        let sin_theta = spherical.x.sin();
+        if !(sin_theta > 0.0) {
+            return None;
+        }
+        let eye = if self.ipd > 0.0 {
+            let o = Self::to_local(self.w2c.transform_point(ray.o));
+            if o.dot(self.eye_offset(0, spherical.y)) >= 0.0 {
+                0
+            } else {
+                1
+            }
+        } else {
+            0
+        };
+        let uv = spherical_to_uv(spherical);
+        let p_raster = vec2(
+            uv.y * self.resolution.x as f32,
+            (uv.x + eye as f32) * self.eye_height(),
+        );
         if p_raster.x < 0.0
-            || p_raster.x >= self.resolution().x as f32
+            || p_raster.x >= self.resolution.x as f32
             || p_raster.y < 0.0
-            || p_raster.y >= self.resolution().y as f32
+            || p_raster.y >= self.resolution.y as f32
         {
-            return (0.0, 0.0);
+            return None;
+        }
+        Some((p_raster, sin_theta))
+    }
+    // directional density of a pixel position uniformly distributed over the whole film
+    fn pdf_dir(&self, sin_theta: f32) -> f32 {
+        1.0 / (self.eyes() as f32 * 2.0 * PI * PI * sin_theta)
+    }
+    // point on the circle of eye positions whose tangent ray for `eye` passes through `p`
+    fn tangent_eye(&self, eye: u32, p: Vec3) -> Option<Vec3> {
+        if self.ipd == 0.0 {
+            return Some(Vec3::ZERO);
+        }
+        let r = 0.5 * self.ipd;
+        let ph = vec2(p.x, p.z);
+        let rho = ph.length();
+        if rho <= r {
+            return None;
+        }
+        let beta = (r / rho).acos();
+        for angle in [beta, -beta] {
+            let (s, c) = angle.sin_cos();
+            let o = vec2(c * ph.x - s * ph.y, s * ph.x + c * ph.y) * (r / rho);
+            let d = ph - o;
+            let left = vec2(d.y, -d.x);
+            if (o.dot(left) >= 0.0) == (eye == 0) {
+                return Some(vec3(o.x, 0.0, o.y));
+            }
+        }
+        None
+    }
+}
+impl Camera for SphericalCamera {
+    fn generate_ray(
+        &self,
+        pixel: UVec2,
+        sampler: &mut dyn Sampler,
+        _lambda: &SampledWavelengths,
+    ) -> (Ray, SampledSpectrum) {
+        let p_film = sampler.next2d() + pixel.as_vec2();
+        let eye_height = self.eye_height();
+        let eye = ((p_film.y / eye_height) as u32).min(self.eyes() - 1);
+        let uv = vec2(
+            (p_film.y - eye as f32 * eye_height) / eye_height,
+            p_film.x / self.resolution.x as f32,
+        );
+        let spherical = uv_to_spherical(uv);
+        let d = spherical_to_dir(spherical);
+        let o = self.eye_offset(eye, spherical.y);
+        let ray = Ray::spawn(
+            self.c2w.transform_point(Self::from_local(o)),
+            self.c2w.transform_vector(Self::from_local(d)).normalize(),
+        );
+        (ray, SampledSpectrum::one())
+    }
+    fn resolution(&self) -> UVec2 {
+        self.resolution
+    }
+    fn sample_wi(
+        &self,
+        u: Vec2,
+        ref_: &ReferencePoint,
+        _time: f32,
+        lambda: &SampledWavelengths,
+    ) -> Option<CameraSample> {
+        let eyes = self.eyes();
+        let eye = ((u.x * eyes as f32) as u32).min(eyes - 1);
+        let p_local = Self::to_local(self.w2c.transform_point(ref_.p));
+        let o = self.tangent_eye(eye, p_local)?;
+        let p_eye = self.c2w.transform_point(Self::from_local(o));
+
+        let wi = p_eye - ref_.p;
+        let dist = wi.length();
+        let wi = wi / dist;
+        // the eye is chosen uniformly
+        let pdf = dist * dist / eyes as f32;
+        let ray = Ray::spawn_to(p_eye, ref_.p);
+        let (raster, we) = self.we(&ray, lambda);
+        let vis_ray = Ray::spawn_to(ref_.p, p_eye).offset_along_normal(ref_.n);
+        Some(CameraSample {
+            p: p_eye,
+            wi,
+            pdf,
+            ray,
+            vis_ray,
+            raster: raster?,
+            we,
+            n: self.n(),
+        })
+    }
+    fn we(&self, ray: &Ray, _lambda: &SampledWavelengths) -> (Option<UVec2>, SampledSpectrum) {
+        match self.raster_position(ray) {
+            Some((p_raster, sin_theta)) => (
+                Some(uvec2(p_raster.x as u32, p_raster.y as u32)),
+                SampledSpectrum::one() * self.pdf_dir(sin_theta),
+            ),
+            None => (None, SampledSpectrum::zero()),
+        }
+    }
+    fn pdf_we(&self, ray: &Ray) -> (f32, f32) {
+        match self.raster_position(ray) {
+            Some((_, sin_theta)) => (1.0, self.pdf_dir(sin_theta)),
+            None => (0.0, 0.0),
+        }
+    }
+    fn n(&self) -> Vec3 {
+        self.c2w.transform_normal(vec3(0.0, 0.0, -1.0))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FisheyeProjection {
+    Equidistant,
+    Equisolid,
+    Stereographic,
+}
+
+impl FisheyeProjection {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "equidistant" => Some(Self::Equidistant),
+            "equisolid" => Some(Self::Equisolid),
+            "stereographic" => Some(Self::Stereographic),
+            _ => None,
+        }
+    }
+    // image radius of a ray at angle `theta` to the optical axis, up to the focal length
+    fn radius(&self, theta: f32) -> f32 {
+        match self {
+            Self::Equidistant => theta,
+            Self::Equisolid => 2.0 * (0.5 * theta).sin(),
+            Self::Stereographic => 2.0 * (0.5 * theta).tan(),
+        }
+    }
+    fn theta(&self, radius: f32) -> f32 {
+        match self {
+            Self::Equidistant => radius,
+            Self::Equisolid => 2.0 * (0.5 * radius).clamp(-1.0, 1.0).asin(),
+            Self::Stereographic => 2.0 * (0.5 * radius).atan(),
+        }
+    }
+    // d(radius) / d(theta)
+    fn radius_derivative(&self, theta: f32) -> f32 {
+        match self {
+            Self::Equidistant => 1.0,
+            Self::Equisolid => (0.5 * theta).cos(),
+            Self::Stereographic => 1.0 / (0.5 * theta).cos().powi(2),
+        }
+    }
+}
+
+// the image circle is inscribed in the film, pixels outside of it are masked
+pub struct FisheyeCamera {
+    pub resolution: UVec2,
+    pub c2w: Transform,
+    pub w2c: Transform,
+    pub fov: f32,
+    pub projection: FisheyeProjection,
+    // image circle in raster space
+    center: Vec2,
+    radius: f32,
+    // image radius at the edge of the field of view
+    max_radius: f32,
+}
+
+impl FisheyeCamera {
+    pub const MAX_FOV: f32 = 220.0 * PI / 180.0;
+    pub fn new(
+        resolution: UVec2,
+        transform: &Transform,
+        fov: f32,
+        projection: FisheyeProjection,
+    ) -> Self {
+        {
+            let det = transform.m3.determinant();
+            assert!(
+                (det - 1.0).abs() < 0.01,
+                "transform has det = {} != 1.0",
+                det
+            );
+        }
+        assert!(
+            fov > 0.0 && fov <= Self::MAX_FOV,
+            "fisheye fov must be in (0, 220] degrees, got {}",
+            fov.to_degrees()
+        );
+        let fres = resolution.as_vec2();
+        Self {
+            resolution,
+            c2w: *transform,
+            w2c: transform.inverse(),
+            fov,
+            projection,
+            center: 0.5 * fres,
+            radius: 0.5 * fres.x.min(fres.y),
+            max_radius: projection.radius(0.5 * fov),
+        }
+    }
+    fn film_area(&self) -> f32 {
+        (self.resolution.x * self.resolution.y) as f32
+    }
+    // returns the raster position hit by a ray leaving the camera and its angle to the optical axis
+    fn raster_position(&self, ray: &Ray) -> Option<(Vec2, f32)> {
+        let d = self.w2c.transform_vector(ray.d).normalize();
+        let theta = (-d.z).clamp(-1.0, 1.0).acos();
+        if theta > 0.5 * self.fov {
+            return None;
+        }
+        let psi = f32::atan2(d.y, d.x);
+        let r = self.radius * self.projection.radius(theta) / self.max_radius;
+        let p_raster = self.center + r * vec2(psi.cos(), -psi.sin());
+        if p_raster.x < 0.0
+            || p_raster.x >= self.resolution.x as f32
+            || p_raster.y < 0.0
+            || p_raster.y >= self.resolution.y as f32
+        {
+            return None;
+        }
+        Some((p_raster, theta))
+    }
+    // directional density of a raster position uniformly distributed over the whole film
+    fn pdf_dir(&self, theta: f32) -> f32 {
+        // radius(theta) / sin(theta) -> 1 at the optical axis for all projections
+        let r_over_sin = if theta < 1e-4 {
+            1.0
+        } else {
+            self.projection.radius(theta) / theta.sin()
+        };
+        let scale = self.radius / self.max_radius;
+        scale * scale * r_over_sin * self.projection.radius_derivative(theta) / self.film_area()
+    }
+}
+impl Camera for FisheyeCamera {
+    fn generate_ray(
+        &self,
+        pixel: UVec2,
+        sampler: &mut dyn Sampler,
+        _lambda: &SampledWavelengths,
+    ) -> (Ray, SampledSpectrum) {
+        let p_film = sampler.next2d() + pixel.as_vec2();
+        let offset = p_film - self.center;
+        // samples outside of the image circle see nothing, the ray is only kept valid
+        let r = offset.length() / self.radius;
+        let weight = if r > 1.0 {
+            SampledSpectrum::zero()
+        } else {
+            SampledSpectrum::one()
+        };
+        let r = r.min(1.0);
+        let psi = f32::atan2(-offset.y, offset.x);
+        let theta = self.projection.theta(r * self.max_radius);
+        let d = vec3(
+            theta.sin() * psi.cos(),
+            theta.sin() * psi.sin(),
+            -theta.cos(),
+        );
+        let ray = Ray::spawn(
+            self.c2w.transform_point(Vec3::ZERO),
+            self.c2w.transform_vector(d).normalize(),
+        );
+        (ray, weight)
+    }
+    fn resolution(&self) -> UVec2 {
+        self.resolution
+    }
+    fn sample_wi(
+        &self,
+        _u: Vec2,
+        ref_: &ReferencePoint,
+        _time: f32,
+        lambda: &SampledWavelengths,
+    ) -> Option<CameraSample> {
+        let p = self.c2w.transform_point(Vec3::ZERO);
+        let wi = p - ref_.p;
+        let dist = wi.length();
+        let wi = wi / dist;
+        let ray = Ray::spawn_to(p, ref_.p);
+        let (raster, we) = self.we(&ray, lambda);
+        let vis_ray = Ray::spawn_to(ref_.p, p).offset_along_normal(ref_.n);
+        Some(CameraSample {
+            p,
+            wi,
+            pdf: dist * dist,
+            ray,
+            vis_ray,
+            raster: raster?,
+            we,
+            n: self.n(),
+        })
+    }
+    fn we(&self, ray: &Ray, _lambda: &SampledWavelengths) -> (Option<UVec2>, SampledSpectrum) {
+        match self.raster_position(ray) {
+            Some((p_raster, theta)) => (
+                Some(uvec2(p_raster.x as u32, p_raster.y as u32)),
+                SampledSpectrum::one() * self.pdf_dir(theta),
+            ),
+            None => (None, SampledSpectrum::zero()),
+        }
+    }
+    fn pdf_we(&self, ray: &Ray) -> (f32, f32) {
+        match self.raster_position(ray) {
+            Some((_, theta)) => (1.0, self.pdf_dir(theta)),
+            None => (0.0, 0.0),
         }
-        let lens_area = 1.0;
-        (1.0 / lens_area, 1.0 / (self.a * cos_theta.powi(3)))
     }
     fn n(&self) -> Vec3 {
         self.c2w.transform_normal(vec3(0.0, 0.0, -1.0))
     }
+    fn mask(&self, pixel: UVec2) -> bool {
+        (pixel.as_vec2() + 0.5 - self.center).length() < self.radius
+    }
+}
+// moves a camera built at the origin along c2w, and gives each ray a time
+// uniformly sampled within the shutter interval
+pub struct AnimatedCamera {
+    pub inner: Arc<dyn Camera>,
+    pub c2w: AnimatedTransform,
+    pub shutter: (f32, f32),
+}
+impl AnimatedCamera {
+    pub fn new(inner: Arc<dyn Camera>, c2w: AnimatedTransform, shutter: (f32, f32)) -> Self {
+        assert!(shutter.0 <= shutter.1, "shutter closes before it opens");
+        Self {
+            inner,
+            c2w,
+            shutter,
+        }
+    }
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        self.c2w.at(ray.time).inverse().transform_ray(ray)
+    }
+}
+impl Camera for AnimatedCamera {
+    fn generate_ray(
+        &self,
+        pixel: UVec2,
+        sampler: &mut dyn Sampler,
+        lambda: &SampledWavelengths,
+    ) -> (Ray, SampledSpectrum) {
+        let (ray, w) = self.inner.generate_ray(pixel, sampler, lambda);
+        let (open, close) = self.shutter;
+        let time = open + (close - open) * sampler.next1d();
+        let ray = self.c2w.at(time).transform_ray(&ray);
+        (
+            Ray {
+                d: ray.d.normalize(),
+                ..ray.with_time(time)
+            },
+            w,
+        )
+    }
+    fn resolution(&self) -> UVec2 {
+        self.inner.resolution()
+    }
+    fn we(&self, ray: &Ray, lambda: &SampledWavelengths) -> (Option<UVec2>, SampledSpectrum) {
+        self.inner.we(&self.local_ray(ray), lambda)
+    }
+    fn pdf_we(&self, ray: &Ray) -> (f32, f32) {
+        self.inner.pdf_we(&self.local_ray(ray))
+    }
+    fn sample_wi(
+        &self,
+        u: Vec2,
+        ref_: &ReferencePoint,
+        time: f32,
+        lambda: &SampledWavelengths,
+    ) -> Option<CameraSample> {
+        let c2w = self.c2w.at(time);
+        let w2c = c2w.inverse();
+        let local_ref = ReferencePoint {
+            p: w2c.transform_point(ref_.p),
+            n: w2c.transform_normal(ref_.n).normalize(),
+        };
+        let sample = self.inner.sample_wi(u, &local_ref, time, lambda)?;
+        let p = c2w.transform_point(sample.p);
+        // offset in world space, where the ray is traced
+        let vis_ray = Ray::spawn_to(ref_.p, p)
+            .offset_along_normal(ref_.n)
+            .with_time(time);
+        Some(CameraSample {
+            p,
+            wi: c2w.transform_vector(sample.wi).normalize(),
+            n: c2w.transform_normal(sample.n).normalize(),
+            ray: c2w.transform_ray(&sample.ray).with_time(time),
+            vis_ray,
+            ..sample
+        })
+    }
+    // the camera at the opening of the shutter
+    fn n(&self) -> Vec3 {
+        self.c2w
+            .at(self.shutter.0)
+            .transform_normal(self.inner.n())
+            .normalize()
+    }
+    fn is_delta_direction(&self) -> bool {
+        self.inner.is_delta_direction()
+    }
+    fn mask(&self, pixel: UVec2) -> bool {
+        self.inner.mask(pixel)
+    }
+}
+mod test {
+    #[test]
+    fn test_thin_lens_we_matches_generate_ray() {
+        use super::*;
+        let transform = Transform::from_matrix(&Mat4::from_translation(vec3(1.0, 2.0, 3.0)));
+        let camera = PerspectiveCamera::new(uvec2(64, 48), &transform, 0.8, 0.1, 4.0);
+        let lambda = SampledWavelengths::none();
+        let mut sampler = PCGSampler::new(0);
+        for y in (0..48).step_by(7) {
+            for x in (0..64).step_by(9) {
+                let pixel = uvec2(x, y);
+                let (ray, _) = camera.generate_ray(pixel, &mut sampler, &lambda);
+                let (raster, we) = camera.we(&ray, &lambda);
+                assert_eq!(raster, Some(pixel));
+                assert!(we.max_element() > 0.0);
+                let (pdf_pos, pdf_dir) = camera.pdf_we(&ray);
+                assert!((pdf_pos * PI * 0.1 * 0.1 - 1.0).abs() < 1e-4);
+                assert!(pdf_dir > 0.0);
+
+                // connecting the point in focus back to the lens must land on the same pixel
+                let cos_theta = ray.d.dot(vec3(0.0, 0.0, -1.0));
+                let ref_ = ReferencePoint {
+                    p: ray.at(4.0 / cos_theta),
+                    n: -ray.d,
+                };
+                let sample = camera
+                    .sample_wi(sampler.next2d(), &ref_, 0.0, &lambda)
+                    .unwrap();
+                assert!(sample.pdf > 0.0);
+                assert_eq!(sample.raster, pixel);
+            }
+        }
+    }
+    #[test]
+    fn test_orthographic_we_matches_generate_ray() {
+        use super::*;
+        let transform = Transform::from_matrix(&Mat4::from_translation(vec3(1.0, 2.0, 3.0)));
+        let camera = OrthographicCamera::new(uvec2(64, 48), &transform, 4.0);
+        let lambda = SampledWavelengths::none();
+        let mut sampler = PCGSampler::new(0);
+        for y in (0..48).step_by(7) {
+            for x in (0..64).step_by(9) {
+                let pixel = uvec2(x, y);
+                let (ray, _) = camera.generate_ray(pixel, &mut sampler, &lambda);
+                assert!((ray.d - vec3(0.0, 0.0, -1.0)).length() < 1e-5);
+                let (raster, we) = camera.we(&ray, &lambda);
+                assert_eq!(raster, Some(pixel));
+                assert!((we.max_element() * 4.0 * 3.0 - 1.0).abs() < 1e-4);
+
+                let ref_ = ReferencePoint {
+                    p: ray.at(5.0),
+                    n: -ray.d,
+                };
+                let sample = camera
+                    .sample_wi(sampler.next2d(), &ref_, 0.0, &lambda)
+                    .unwrap();
+                assert_eq!(sample.raster, pixel);
+                assert!((sample.p - ray.o).length() < 1e-4);
+                let (pdf_pos, pdf_dir) = camera.pdf_we(&sample.ray);
+                assert!(pdf_pos > 0.0 && pdf_dir > 0.0);
+            }
+        }
+    }
+    #[test]
+    fn test_stereo_panorama_we_matches_generate_ray() {
+        use super::*;
+        let transform = Transform::from_matrix(&Mat4::from_translation(vec3(1.0, 2.0, 3.0)));
+        let camera = SphericalCamera::new(uvec2(64, 64), &transform, 0.065);
+        let lambda = SampledWavelengths::none();
+        let mut sampler = PCGSampler::new(0);
+        for y in (0..64).step_by(5) {
+            for x in (0..64).step_by(7) {
+                let pixel = uvec2(x, y);
+                let (ray, _) = camera.generate_ray(pixel, &mut sampler, &lambda);
+                let (raster, we) = camera.we(&ray, &lambda);
+                assert_eq!(raster, Some(pixel));
+                assert!(we.max_element() > 0.0);
+
+                // the eye is picked by u.x, left eye renders the upper half
+                let eye_u = if y < 32 { 0.25 } else { 0.75 };
+                let ref_ = ReferencePoint {
+                    p: ray.at(3.0),
+                    n: -ray.d,
+                };
+                let sample = camera
+                    .sample_wi(vec2(eye_u, 0.5), &ref_, 0.0, &lambda)
+                    .unwrap();
+                assert_eq!(sample.raster, pixel);
+                assert!((sample.p - ray.o).length() < 1e-4);
+            }
+        }
+    }
+    #[test]
+    fn test_fisheye_we_matches_generate_ray() {
+        use super::*;
+        let transform = Transform::from_matrix(&Mat4::from_translation(vec3(1.0, 2.0, 3.0)));
+        let lambda = SampledWavelengths::none();
+        let mut sampler = PCGSampler::new(0);
+        for projection in [
+            FisheyeProjection::Equidistant,
+            FisheyeProjection::Equisolid,
+            FisheyeProjection::Stereographic,
+        ] {
+            let camera =
+                FisheyeCamera::new(uvec2(64, 48), &transform, 220.0f32.to_radians(), projection);
+            assert!(!camera.mask(uvec2(0, 0)));
+            assert!(camera.mask(uvec2(32, 24)));
+            // pixels outside of the image circle stay black
+            let (_, weight) = camera.generate_ray(uvec2(0, 0), &mut sampler, &lambda);
+            assert_eq!(weight.max_element(), 0.0);
+            for y in (0..48).step_by(5) {
+                for x in (0..64).step_by(7) {
+                    let pixel = uvec2(x, y);
+                    if !camera.mask(pixel) {
+                        continue;
+                    }
+                    let (ray, _) = camera.generate_ray(pixel, &mut sampler, &lambda);
+                    let (raster, we) = camera.we(&ray, &lambda);
+                    assert_eq!(raster, Some(pixel));
+                    assert!(we.max_element() > 0.0);
+                    let ref_ = ReferencePoint {
+                        p: ray.at(2.0),
+                        n: -ray.d,
+                    };
+                    let sample = camera
+                        .sample_wi(sampler.next2d(), &ref_, 0.0, &lambda)
+                        .unwrap();
+                    assert_eq!(sample.raster, pixel);
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_animated_camera_follows_motion() {
+        use super::*;
+        let start = Transform::from_matrix(&Mat4::from_translation(vec3(0.0, 0.0, 0.0)));
+        let end = Transform::from_matrix(&Mat4::from_translation(vec3(2.0, 0.0, 0.0)));
+        let inner = Arc::new(PerspectiveCamera::new(
+            uvec2(64, 48),
+            &Transform::identity(),
+            0.8,
+            0.0,
+            1.0,
+        ));
+        let camera = AnimatedCamera::new(inner, AnimatedTransform::new(start, end), (0.25, 0.75));
+        let lambda = SampledWavelengths::none();
+        let mut sampler = PCGSampler::new(0);
+        for y in (0..48).step_by(7) {
+            for x in (0..64).step_by(9) {
+                let pixel = uvec2(x, y);
+                let (ray, _) = camera.generate_ray(pixel, &mut sampler, &lambda);
+                assert!(ray.time >= 0.25 && ray.time <= 0.75);
+                assert!((ray.o - vec3(2.0 * ray.time, 0.0, 0.0)).length() < 1e-4);
+                let (raster, _) = camera.we(&ray, &lambda);
+                assert_eq!(raster, Some(pixel));
+
+                // the same point seen at another time lands elsewhere on the film
+                let ref_ = ReferencePoint {
+                    p: ray.at(5.0),
+                    n: -ray.d,
+                };
+                let sample = camera
+                    .sample_wi(sampler.next2d(), &ref_, ray.time, &lambda)
+                    .unwrap();
+                assert_eq!(sample.raster, pixel);
+                assert_eq!(sample.vis_ray.time, ray.time);
+                let later = camera.sample_wi(sampler.next2d(), &ref_, 0.75, &lambda);
+                if ray.time < 0.5 {
+                    assert!(later.map_or(true, |s| s.raster != pixel));
+                }
+            }
+        }
+    }
 }
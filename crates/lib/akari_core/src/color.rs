@@ -44,6 +44,16 @@ pub fn srgb_to_xyz(srgb: Vec3) -> Vec3 {
     let m = Mat3::from_cols_array(&m);
     m * srgb
 }
+// ACEScg (AP1) to ACES2065-1 (AP0), both share the ACES white point
+pub fn acescg_to_aces2065_1(rgb: Vec3) -> Vec3 {
+    let m = [
+        0.6954522414, 0.0447945634, -0.0055258826, //.
+        0.1406786965, 0.8596711185, 0.0040252103, //.
+        0.1638690622, 0.0955343182, 1.0015006723,
+    ];
+    let m = Mat3::from_cols_array(&m);
+    m * rgb
+}
 
 #[macro_export]
 macro_rules! impl_color_like {
@@ -294,4 +304,25 @@ impl RgbColorSpace {
     pub fn id(&self) -> RgbColorSpaceId {
         self.id
     }
+    pub fn to_xyz(&self, rgb: Vec3) -> Vec3 {
+        let m = match self.id {
+            RgbColorSpaceId::SRgb => return srgb_to_xyz(rgb),
+            RgbColorSpaceId::Aces2065_1 => [
+                0.9525523959, 0.3439664498, 0.0, //.
+                0.0, 0.7281660966, 0.0, //.
+                0.0000936786, -0.0721325464, 1.0088251844,
+            ],
+            RgbColorSpaceId::Rec2020 => [
+                0.6369580, 0.2627002, 0.0, //.
+                0.1446169, 0.6779981, 0.0280727, //.
+                0.1688810, 0.0593017, 1.0609851,
+            ],
+            RgbColorSpaceId::DCIP3 => [
+                0.4865709, 0.2289746, 0.0, //.
+                0.2656677, 0.6917385, 0.0451134, //.
+                0.1982173, 0.0792869, 1.0439444,
+            ],
+        };
+        Mat3::from_cols_array(&m) * rgb
+    }
 }
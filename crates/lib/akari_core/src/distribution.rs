@@ -157,6 +157,14 @@ impl Distribution2D {
         let (y, pdf_yx) = self.p_yx[ix].sample_continuous(u.y);
         (vec2(x, y), pdf_x * pdf_yx)
     }
+    // density of sample_continuous at `x`
+    pub fn pdf(&self, x: &Vec2) -> f32 {
+        let nx = self.p_x.pmf.len();
+        let ix = (x.x.clamp(0.0, 1.0 - 1e-7) * nx as f32) as usize;
+        let ny = self.p_yx[ix].pmf.len();
+        let iy = (x.y.clamp(0.0, 1.0 - 1e-7) * ny as f32) as usize;
+        self.p_x.pmf[ix] * nx as f32 * self.p_yx[ix].pmf[iy] * ny as f32
+    }
     pub fn invert(&self, x: &Vec2) -> Vec2 {
         let ix = (x.x.clamp(0.0, 1.0 - 1e-7) * self.p_x.pmf.len() as f32) as usize;
         let y = self.p_yx[ix].invert(x.y);
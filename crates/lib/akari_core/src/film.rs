@@ -1,4 +1,5 @@
 use crate::*;
+use camera::Camera;
 use color::XYZ;
 use serde::{Serialize, Deserialize};
 use util::RobustSum;
@@ -19,6 +20,8 @@ impl Pixel {
 pub struct Film {
     pixels: Vec<RwLock<Pixel>>,
     resolution: UVec2,
+    // pixels not imaged by the camera, written out with zero alpha
+    mask: Option<Vec<bool>>,
 }
 impl Film {
     pub fn pixels(&self) -> &[RwLock<Pixel>] {
@@ -39,8 +42,29 @@ impl Film {
                 })
                 .collect(),
             resolution: *resolution,
+            mask: None,
         }
     }
+    pub fn apply_mask(&mut self, camera: &dyn Camera) {
+        let resolution = self.resolution;
+        let mask: Vec<bool> = (0..resolution.x * resolution.y)
+            .map(|i| camera.mask(uvec2(i % resolution.x, i / resolution.x)))
+            .collect();
+        self.mask = if mask.iter().all(|x| *x) {
+            None
+        } else {
+            Some(mask)
+        };
+    }
+    pub fn alpha(&self, pixel: UVec2) -> f32 {
+        match &self.mask {
+            Some(mask) if !mask[(pixel.x + pixel.y * self.resolution.x) as usize] => 0.0,
+            _ => 1.0,
+        }
+    }
+    pub fn is_masked(&self) -> bool {
+        self.mask.is_some()
+    }
     pub fn add_sample(
         &self,
         pixel: UVec2,
@@ -73,8 +97,7 @@ impl Film {
     pub fn to_rgb_image(&self) -> image::RgbImage {
         let image = image::ImageBuffer::from_fn(self.resolution.x, self.resolution.y, |x, y| {
             let pixel = self.get_pixel(uvec2(x, y));
-            let value = pixel.color();
-            let srgb: SRgb = value.into();
+            let srgb: SRgb = pixel.color().into();
             let srgb = linear_to_srgb(srgb.values().clamp(Vec3::ZERO, Vec3::ONE)) * 255.0;
             // let srgb = value.to_srgb() * 255.0;
             image::Rgb([srgb.x as u8, srgb.y as u8, srgb.z as u8])
@@ -82,6 +105,13 @@ impl Film {
 
         image
     }
+    pub fn to_rgba_image(&self) -> image::RgbaImage {
+        let rgb = self.to_rgb_image();
+        image::ImageBuffer::from_fn(self.resolution.x, self.resolution.y, |x, y| {
+            let [r, g, b] = rgb.get_pixel(x, y).0;
+            image::Rgba([r, g, b, (self.alpha(uvec2(x, y)) * 255.0) as u8])
+        })
+    }
     pub fn write_exr(&self, path: &str) {
         exr::prelude::write_rgba_file(
             path,
@@ -89,9 +119,9 @@ impl Film {
             self.resolution.y as usize,
             |x, y| {
                 let pixel = *self.pixels[x + y * self.resolution.x as usize].read();
-                let value = pixel.color();
-                let srgb: SRgb = value.into();
-                (srgb.values().x, srgb.values().y, srgb.values().z, 1.0)
+                let alpha = self.alpha(uvec2(x as u32, y as u32));
+                let srgb: SRgb = pixel.color().into();
+                (srgb.values().x, srgb.values().y, srgb.values().z, alpha)
             },
         )
         .unwrap();
@@ -103,8 +133,9 @@ impl Film {
             self.resolution.y as usize,
             |x, y| {
                 let pixel = *self.pixels[x + y * self.resolution.x as usize].read();
+                let alpha = self.alpha(uvec2(x as u32, y as u32));
                 let value = pixel.color();
-                (value.values().x, value.values().y, value.values().z, 1.0)
+                (value.values().x, value.values().y, value.values().z, alpha)
             },
         )
         .unwrap();
@@ -102,6 +102,9 @@ impl Function1D for PiecewiseLinear1D {
         (self.xs[0], *self.xs.last().unwrap())
     }
     fn evaluate(&self, x: f32) -> f32 {
+        if x < self.xs[0] || x > *self.xs.last().unwrap() {
+            return 0.0;
+        }
         let i = find_largest(&self.xs, |x_| *x_ <= x);
         lerp(
             self.ys[i],
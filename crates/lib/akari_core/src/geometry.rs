@@ -1,5 +1,6 @@
 use std::ops::Mul;
 
+use akari_common::glam::Quat;
 use akari_utils::{float_bits_to_int, int_bits_to_float};
 use serde::{Deserialize, Serialize};
 
@@ -10,7 +11,7 @@ pub struct Ray {
     pub d: Vec3,
     pub tmin: f32,
     pub tmax: f32,
-    // pub time: f32,
+    pub time: f32,
 }
 #[derive(Clone, Copy)]
 pub struct Ray4 {
@@ -45,6 +46,7 @@ impl Default for Ray {
             d: Vec3::ZERO,
             tmin: RAY_EPSILON,
             tmax: -f32::INFINITY,
+            time: 0.0,
         }
     }
 }
@@ -97,8 +99,12 @@ impl Ray {
             d,
             tmin: RAY_EPSILON,
             tmax: f32::INFINITY,
+            time: 0.0,
         }
     }
+    pub fn with_time(&self, time: f32) -> Self {
+        Self { time, ..*self }
+    }
     pub fn offset_along_normal(&self, n: Vec3) -> Self {
         let p = offset_ray(self.o, if self.d.dot(n) > 0.0 { n } else { -n });
         let diff = (p - self.o).length() / self.d.length();
@@ -213,6 +219,26 @@ impl Transform {
     pub fn transform_normal(&self, n: Vec3) -> Vec3 {
         self.inv_m3.transpose() * n
     }
+    pub fn transform_aabb(&self, aabb: Bounds3f) -> Bounds3f {
+        let mut transformed = Bounds3f::default();
+        for i in 0..8 {
+            let corner = vec3(
+                if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+            );
+            transformed.insert_point(self.transform_point(corner));
+        }
+        transformed
+    }
+    // d is not normalized so that t is the same in both spaces
+    pub fn transform_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            o: self.transform_point(ray.o),
+            d: self.transform_vector(ray.d),
+            ..*ray
+        }
+    }
 }
 impl Mul for Transform {
     type Output = Transform;
@@ -221,6 +247,61 @@ impl Mul for Transform {
     }
 }
 
+// a transform keyframed at time 0 (start) and time 1 (end)
+// in between, scale and translation are interpolated linearly and rotation is slerped
+#[derive(Clone, Copy)]
+pub struct AnimatedTransform {
+    pub start: Transform,
+    pub end: Transform,
+    decomposed: [(Vec3, Quat, Vec3); 2],
+}
+impl AnimatedTransform {
+    pub fn new(start: Transform, end: Transform) -> Self {
+        let decomposed = [
+            start.m4.to_scale_rotation_translation(),
+            end.m4.to_scale_rotation_translation(),
+        ];
+        Self {
+            start,
+            end,
+            decomposed,
+        }
+    }
+    pub fn is_animated(&self) -> bool {
+        self.start.m4 != self.end.m4
+    }
+    pub fn at(&self, time: f32) -> Transform {
+        if !self.is_animated() || time <= 0.0 {
+            return self.start;
+        }
+        if time >= 1.0 {
+            return self.end;
+        }
+        let [(s0, r0, t0), (s1, r1, t1)] = self.decomposed;
+        Transform::from_matrix(&Mat4::from_scale_rotation_translation(
+            s0.lerp(s1, time),
+            r0.slerp(r1, time),
+            t0.lerp(t1, time),
+        ))
+    }
+    // conservative bound of aabb over the whole motion
+    pub fn motion_aabb(&self, aabb: Bounds3f) -> Bounds3f {
+        if !self.is_animated() {
+            return self.start.transform_aabb(aabb);
+        }
+        const STEPS: usize = 64;
+        let mut bound = Bounds3f::default();
+        for i in 0..=STEPS {
+            bound.insert_box(self.at(i as f32 / STEPS as f32).transform_aabb(aabb));
+        }
+        // account for the path bending between samples
+        let pad = Vec3A::from(bound.diagonal()) * (0.5 / STEPS as f32);
+        bound.min -= pad;
+        bound.max += pad;
+        bound
+    }
+}
+
 pub fn dir_to_spherical(v: Vec3) -> Vec2 {
     let theta = v.y.acos();
     let phi = f32::atan2(v.z, v.x) + PI;
@@ -232,6 +313,13 @@ pub fn spherical_to_uv(v: Vec2) -> Vec2 {
 pub fn dir_to_uv(v: Vec3) -> Vec2 {
     spherical_to_uv(dir_to_spherical(v))
 }
+pub fn uv_to_spherical(uv: Vec2) -> Vec2 {
+    vec2(uv.x * PI, uv.y * (2.0 * PI))
+}
+pub fn spherical_to_dir(v: Vec2) -> Vec3 {
+    let (theta, phi) = (v.x, v.y - PI);
+    vec3(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+}
 
 impl Frame {
     #[inline]
@@ -331,6 +419,7 @@ pub struct RayHit {
     pub ng: Vec3,
     pub prim_id: u32,
     pub geom_id: u32,
+    pub time: f32,
 }
 impl RayHit {
     pub fn is_invalid(&self) -> bool {
@@ -345,6 +434,7 @@ impl Default for RayHit {
             ng: Vec3::ZERO,
             prim_id: u32::MAX,
             geom_id: u32::MAX,
+            time: 0.0,
         }
     }
 }
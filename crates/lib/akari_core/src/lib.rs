@@ -17,6 +17,7 @@ pub mod light;
 pub mod rgb2spec;
 pub mod sampling;
 pub mod scene;
+pub mod shader;
 pub mod shape;
 pub mod net;
 pub mod spmd;
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::process::exit;
 use std::sync::Arc;
 
-use crate::distribution::Distribution1D;
+use crate::distribution::{Distribution1D, Distribution2D};
 use crate::shape::Shape;
 use crate::texture::ShadingPoint;
 use crate::texture::SpectrumTexture;
@@ -32,6 +32,7 @@ bitflags! {
         const DELTA_POSITION = 0b1;
         const DELTA_DIRECTION = 0b10;
         const DELTA = Self::DELTA_POSITION.bits | Self::DELTA_DIRECTION.bits;
+        const INFINITE = 0b100;
     }
 }
 
@@ -157,6 +158,7 @@ impl Light for AreaLight {
             le: self.evaluate(
                 &ShadingPoint {
                     texcoord: p.texcoords,
+                    color: p.color,
                 },
                 lambda,
             ),
@@ -177,6 +179,7 @@ impl Light for AreaLight {
         let li = self.evaluate(
             &ShadingPoint {
                 texcoord: surface_sample.texcoords,
+                color: surface_sample.color,
             },
             lambda,
         );
@@ -262,7 +265,10 @@ impl SpotLight {
     }
     fn evaluate(&self, w: Vec3, lambda: &SampledWavelengths) -> SampledSpectrum {
         let uv = spherical_to_uv(dir_to_spherical(w));
-        let sp = ShadingPoint { texcoord: uv };
+        let sp = ShadingPoint {
+            texcoord: uv,
+            color: Vec3::ONE,
+        };
         let s = self.emission.evaluate(&sp, lambda);
         let falloff = self.falloff(w);
         if let Some(colorspace) = self.colorspace {
@@ -340,7 +346,10 @@ pub struct PointLight {
 impl PointLight {
     fn evaluate(&self, w: Vec3, lambda: &SampledWavelengths) -> SampledSpectrum {
         let uv = spherical_to_uv(dir_to_spherical(w));
-        let sp = ShadingPoint { texcoord: uv };
+        let sp = ShadingPoint {
+            texcoord: uv,
+            color: Vec3::ONE,
+        };
         let s = self.emission.evaluate(&sp, lambda);
         if let Some(colorspace) = self.colorspace {
             let illuminant = colorspace.illuminant();
@@ -402,3 +411,422 @@ impl Light for PointLight {
         self.emission.power() * 4.0 * PI
     }
 }
+
+// sphere enclosing the scene that lights at infinity emit from, unit sphere for empty scenes
+fn bounding_sphere(world_bounds: Bounds3f) -> (Vec3, f32) {
+    let radius = world_bounds.diagonal().length() * 0.5;
+    if radius.is_finite() && radius > 0.0 {
+        (world_bounds.centroid(), radius)
+    } else {
+        (Vec3::ZERO, 1.0)
+    }
+}
+/*
+Infinitely far away light surrounding the scene, radiance is looked up from an equirectangular map.
+In the light's local frame +y is up, the top row of the map is +y and u = phi / 2pi.
+Emitted rays start on a disk of the scene's bounding sphere.
+*/
+pub struct EnvironmentLight {
+    pub texture: Arc<dyn SpectrumTexture>,
+    pub colorspace: Option<RgbColorSpace>,
+    pub l2w: Transform,
+    pub w2l: Transform,
+    pub world_center: Vec3,
+    pub world_radius: f32,
+    distr: Distribution2D,
+}
+impl EnvironmentLight {
+    const DISTR_RES: (usize, usize) = (256, 128);
+    pub fn new(
+        texture: Arc<dyn SpectrumTexture>,
+        colorspace: Option<RgbColorSpace>,
+        transform: &Transform,
+        world_bounds: Bounds3f,
+    ) -> Self {
+        let (world_center, world_radius) = bounding_sphere(world_bounds);
+        let (nu, nv) = Self::DISTR_RES;
+        let lambdas: Vec<_> = (0..4)
+            .map(|i| SampledWavelengths::sample_visible((i as f32 + 0.5) / 4.0))
+            .collect();
+        let mut f: Vec<Vec<f32>> = (0..nu)
+            .map(|iu| {
+                (0..nv)
+                    .map(|iv| {
+                        let sp = ShadingPoint {
+                            texcoord: vec2(
+                                (iu as f32 + 0.5) / nu as f32,
+                                1.0 - (iv as f32 + 0.5) / nv as f32,
+                            ),
+                            color: Vec3::ONE,
+                        };
+                        let v: f32 = lambdas
+                            .iter()
+                            .map(|lambda| {
+                                let s = texture.evaluate(&sp, lambda).values();
+                                s.x.max(0.0) + s.y.max(0.0) + s.z.max(0.0) + s.w.max(0.0)
+                            })
+                            .sum();
+                        let sin_theta = (PI * (iv as f32 + 0.5) / nv as f32).sin();
+                        v * sin_theta
+                    })
+                    .collect()
+            })
+            .collect();
+        // keep every direction samplable so that the pdf is never zero where the map is black
+        let avg = f.iter().flatten().sum::<f32>() / (nu * nv) as f32;
+        let floor = if avg > 0.0 { avg * 1e-3 } else { 1.0 };
+        f.iter_mut().flatten().for_each(|x| *x += floor);
+        Self {
+            texture,
+            colorspace,
+            l2w: *transform,
+            w2l: transform.inverse(),
+            world_center,
+            world_radius,
+            distr: Distribution2D::new(&f).unwrap(),
+        }
+    }
+    fn uv_to_dir(&self, uv: Vec2) -> (Vec3, f32) {
+        let theta = uv.y * PI;
+        let phi = uv.x * 2.0 * PI;
+        let sin_theta = theta.sin();
+        let w = vec3(-sin_theta * phi.cos(), theta.cos(), -sin_theta * phi.sin());
+        (self.l2w.transform_vector(w).normalize(), sin_theta)
+    }
+    fn dir_to_uv(&self, w: Vec3) -> Vec2 {
+        let w = self.w2l.transform_vector(w).normalize();
+        let theta = w.y.clamp(-1.0, 1.0).acos();
+        let phi = f32::atan2(w.z, w.x) + PI;
+        vec2(phi / (2.0 * PI), theta / PI).clamp(Vec2::ZERO, Vec2::ONE)
+    }
+    // solid angle density of sampling `w`
+    fn pdf_dir(&self, w: Vec3) -> f32 {
+        let uv = self.dir_to_uv(w);
+        let sin_theta = (uv.y * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distr.pdf(&uv) / (2.0 * PI * PI * sin_theta)
+    }
+    // radiance arriving from direction `w`
+    fn evaluate(&self, w: Vec3, lambda: &SampledWavelengths) -> SampledSpectrum {
+        let uv = self.dir_to_uv(w);
+        let sp = ShadingPoint {
+            texcoord: vec2(uv.x, 1.0 - uv.y),
+            color: Vec3::ONE,
+        };
+        let s = self.texture.evaluate(&sp, lambda);
+        if let Some(colorspace) = self.colorspace {
+            let illuminant = colorspace.illuminant();
+            let i = illuminant.sample(lambda);
+            s * i
+        } else {
+            s
+        }
+    }
+}
+impl Light for EnvironmentLight {
+    fn sample_emission(&self, u0: Vec3, u1: Vec2, lambda: &SampledWavelengths) -> LightRaySample {
+        let (uv, pdf_uv) = self.distr.sample_continuous(&u1);
+        let (w, sin_theta) = self.uv_to_dir(uv);
+        let pdf_dir = if sin_theta > 0.0 {
+            pdf_uv / (2.0 * PI * PI * sin_theta)
+        } else {
+            0.0
+        };
+        let frame = Frame::from_normal(w);
+        let d = concentric_sample_disk(vec2(u0.x, u0.y));
+        let p_disk = self.world_center + self.world_radius * frame.to_world(vec3(d.x, 0.0, d.y));
+        let o = p_disk + w * self.world_radius;
+        LightRaySample {
+            le: self.evaluate(w, lambda),
+            pdf_pos: 1.0 / (PI * self.world_radius * self.world_radius),
+            pdf_dir,
+            ray: Ray::spawn(o, -w),
+            n: -w,
+        }
+    }
+
+    fn sample_direct(
+        &self,
+        u: Vec3,
+        ref_: &ReferencePoint,
+        lambda: &SampledWavelengths,
+    ) -> LightSample {
+        let (uv, pdf_uv) = self.distr.sample_continuous(&vec2(u.x, u.y));
+        let (wi, sin_theta) = self.uv_to_dir(uv);
+        let pdf = if sin_theta > 0.0 {
+            pdf_uv / (2.0 * PI * PI * sin_theta)
+        } else {
+            0.0
+        };
+        LightSample {
+            li: self.evaluate(wi, lambda),
+            pdf,
+            shadow_ray: Ray::spawn(ref_.p, wi).offset_along_normal(ref_.n),
+            wi,
+            p: ref_.p + wi * (2.0 * self.world_radius),
+            n: -wi,
+        }
+    }
+
+    fn pdf_emission(&self, ray: &Ray, _n: Vec3) -> (f32, f32) {
+        (
+            1.0 / (PI * self.world_radius * self.world_radius),
+            self.pdf_dir(-ray.d),
+        )
+    }
+
+    fn pdf_direct(&self, wi: Vec3, _p: &ReferencePoint) -> (f32, f32) {
+        (0.0, self.pdf_dir(wi))
+    }
+
+    fn emission(&self, ray: &Ray, lambda: &SampledWavelengths) -> SampledSpectrum {
+        self.evaluate(ray.d, lambda)
+    }
+
+    fn flags(&self) -> LightFlags {
+        LightFlags::INFINITE
+    }
+
+    fn power(&self) -> f32 {
+        PI * self.world_radius * self.world_radius * 4.0 * PI * self.texture.power()
+    }
+}
+/*
+Sun-like light arriving from a cone of directions around `-direction`.
+`emission` is the irradiance on a surface facing the light, a zero angular radius
+gives a delta light with parallel rays.
+*/
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub cos_max: f32,
+    pub emission: Arc<dyn SpectrumTexture>,
+    pub colorspace: Option<RgbColorSpace>,
+    pub world_center: Vec3,
+    pub world_radius: f32,
+}
+impl DirectionalLight {
+    pub fn new(
+        direction: Vec3,
+        angular_radius: f32,
+        emission: Arc<dyn SpectrumTexture>,
+        colorspace: Option<RgbColorSpace>,
+        world_bounds: Bounds3f,
+    ) -> Self {
+        let (world_center, world_radius) = bounding_sphere(world_bounds);
+        Self {
+            direction: direction.normalize(),
+            cos_max: angular_radius.max(0.0).cos(),
+            emission,
+            colorspace,
+            world_center,
+            world_radius,
+        }
+    }
+    fn is_delta(&self) -> bool {
+        self.cos_max >= 1.0
+    }
+    // samples a direction towards the light, returns (w, pdf)
+    fn sample_wi(&self, u: Vec2) -> (Vec3, f32) {
+        if self.is_delta() {
+            (-self.direction, 1.0)
+        } else {
+            let frame = Frame::from_normal(-self.direction);
+            let w = frame.to_world(uniform_sample_cone(u, self.cos_max));
+            (w, uniform_cone_pdf(self.cos_max))
+        }
+    }
+    fn pdf_wi(&self, w: Vec3) -> f32 {
+        if self.is_delta() || -self.direction.dot(w) < self.cos_max {
+            0.0
+        } else {
+            uniform_cone_pdf(self.cos_max)
+        }
+    }
+    // irradiance for delta lights, radiance otherwise
+    fn evaluate(&self, w: Vec3, lambda: &SampledWavelengths) -> SampledSpectrum {
+        let uv = spherical_to_uv(dir_to_spherical(w));
+        let sp = ShadingPoint {
+            texcoord: uv,
+            color: Vec3::ONE,
+        };
+        let mut s = self.emission.evaluate(&sp, lambda);
+        if !self.is_delta() {
+            s = s * uniform_cone_pdf(self.cos_max);
+        }
+        if let Some(colorspace) = self.colorspace {
+            let illuminant = colorspace.illuminant();
+            let i = illuminant.sample(lambda);
+            s * i
+        } else {
+            s
+        }
+    }
+}
+impl Light for DirectionalLight {
+    fn sample_emission(&self, u0: Vec3, u1: Vec2, lambda: &SampledWavelengths) -> LightRaySample {
+        let (w, pdf_dir) = self.sample_wi(u1);
+        let frame = Frame::from_normal(w);
+        let d = concentric_sample_disk(vec2(u0.x, u0.y));
+        let p_disk = self.world_center + self.world_radius * frame.to_world(vec3(d.x, 0.0, d.y));
+        LightRaySample {
+            le: self.evaluate(w, lambda),
+            pdf_pos: 1.0 / (PI * self.world_radius * self.world_radius),
+            pdf_dir,
+            ray: Ray::spawn(p_disk + w * self.world_radius, -w),
+            n: -w,
+        }
+    }
+
+    fn sample_direct(
+        &self,
+        u: Vec3,
+        ref_: &ReferencePoint,
+        lambda: &SampledWavelengths,
+    ) -> LightSample {
+        let (wi, pdf) = self.sample_wi(vec2(u.x, u.y));
+        LightSample {
+            li: self.evaluate(wi, lambda),
+            pdf,
+            shadow_ray: Ray::spawn(ref_.p, wi).offset_along_normal(ref_.n),
+            wi,
+            p: ref_.p + wi * (2.0 * self.world_radius),
+            n: -wi,
+        }
+    }
+
+    fn pdf_emission(&self, ray: &Ray, _n: Vec3) -> (f32, f32) {
+        (
+            1.0 / (PI * self.world_radius * self.world_radius),
+            self.pdf_wi(-ray.d),
+        )
+    }
+
+    fn pdf_direct(&self, wi: Vec3, _p: &ReferencePoint) -> (f32, f32) {
+        (0.0, self.pdf_wi(wi))
+    }
+
+    fn emission(&self, ray: &Ray, lambda: &SampledWavelengths) -> SampledSpectrum {
+        if self.pdf_wi(ray.d) > 0.0 {
+            self.evaluate(ray.d, lambda)
+        } else {
+            SampledSpectrum::zero()
+        }
+    }
+
+    fn flags(&self) -> LightFlags {
+        if self.is_delta() {
+            LightFlags::DELTA_DIRECTION
+        } else {
+            LightFlags::INFINITE
+        }
+    }
+
+    fn power(&self) -> f32 {
+        self.emission.power() * PI * self.world_radius * self.world_radius
+    }
+}
+mod test {
+    #[test]
+    fn test_environment_light_sample_pdf() {
+        use super::*;
+        use crate::sampler::{PCGSampler, Sampler};
+        // a bright sun near the horizon on top of a dim sky
+        struct Sky;
+        impl SpectrumTexture for Sky {
+            fn evaluate(&self, sp: &ShadingPoint, _: &SampledWavelengths) -> SampledSpectrum {
+                let d = sp.texcoord - vec2(0.3, 0.6);
+                SampledSpectrum::one() * if d.length() < 0.05 { 100.0 } else { 0.5 }
+            }
+            fn power(&self) -> f32 {
+                1.0
+            }
+            fn colorspace(&self) -> Option<RgbColorSpace> {
+                None
+            }
+        }
+        let transform =
+            Transform::from_matrix(&Mat4::from_axis_angle(vec3(1.0, 0.0, 0.0), 0.7));
+        let mut bounds = Bounds3f::default();
+        bounds.insert_point(vec3(-1.0, -1.0, -1.0));
+        bounds.insert_point(vec3(1.0, 1.0, 1.0));
+        let light = EnvironmentLight::new(Arc::new(Sky), None, &transform, bounds);
+        let ref_ = ReferencePoint {
+            p: Vec3::ZERO,
+            n: vec3(0.0, 1.0, 0.0),
+        };
+        let lambda = SampledWavelengths::none();
+        let mut sampler = PCGSampler::new(0);
+        let n = 200000;
+        let mut solid_angle = 0.0;
+        let mut mismatch = 0;
+        for _ in 0..n {
+            let sample = light.sample_direct(sampler.next3d(), &ref_, &lambda);
+            if sample.pdf <= 0.0 {
+                continue;
+            }
+            // directions can round into a neighbouring cell of the distribution
+            let pdf = light.pdf_direct(sample.wi, &ref_).1;
+            if (pdf - sample.pdf).abs() > 1e-2 * sample.pdf {
+                mismatch += 1;
+            }
+            solid_angle += 1.0 / sample.pdf as f64;
+        }
+        assert!(mismatch < n / 1000, "{} mismatched pdfs", mismatch);
+        let solid_angle = solid_angle / n as f64;
+        assert!((solid_angle - 4.0 * PI as f64).abs() < 0.3, "{}", solid_angle);
+    }
+    #[test]
+    fn test_directional_light_irradiance() {
+        use super::*;
+        use crate::sampler::{PCGSampler, Sampler};
+        struct Constant;
+        impl SpectrumTexture for Constant {
+            fn evaluate(&self, _: &ShadingPoint, _: &SampledWavelengths) -> SampledSpectrum {
+                SampledSpectrum::one() * 3.0
+            }
+            fn power(&self) -> f32 {
+                3.0
+            }
+            fn colorspace(&self) -> Option<RgbColorSpace> {
+                None
+            }
+        }
+        let direction = vec3(1.0, -2.0, 0.5).normalize();
+        let mut bounds = Bounds3f::default();
+        bounds.insert_point(Vec3::ZERO);
+        bounds.insert_point(Vec3::ONE);
+        let ref_ = ReferencePoint {
+            p: Vec3::ZERO,
+            n: -direction,
+        };
+        let lambda = SampledWavelengths::none();
+        let mut sampler = PCGSampler::new(0);
+
+        let delta = DirectionalLight::new(direction, 0.0, Arc::new(Constant), None, bounds);
+        assert!(delta.is_delta());
+        let sample = delta.sample_direct(sampler.next3d(), &ref_, &lambda);
+        assert!((sample.wi + direction).length() < 1e-5);
+        assert!((sample.li.max_element() - 3.0).abs() < 1e-5);
+
+        // the soft light delivers the same irradiance to a surface facing it
+        let sun = DirectionalLight::new(direction, 0.1, Arc::new(Constant), None, bounds);
+        assert!(!sun.is_delta());
+        let n = 10000;
+        let mut irradiance = 0.0;
+        for _ in 0..n {
+            let sample = sun.sample_direct(sampler.next3d(), &ref_, &lambda);
+            assert!((sun.pdf_direct(sample.wi, &ref_).1 - sample.pdf).abs() < 1e-3 * sample.pdf);
+            let ray = Ray::spawn(ref_.p, sample.wi);
+            assert!(
+                (sun.emission(&ray, &lambda).max_element() - sample.li.max_element()).abs() < 1e-3
+            );
+            irradiance += sample.li.max_element() * sample.wi.dot(ref_.n) / sample.pdf;
+        }
+        assert!((irradiance / n as f32 - 3.0).abs() < 0.01);
+        assert!(sun
+            .emission(&Ray::spawn(Vec3::ZERO, direction), &lambda)
+            .is_black());
+    }
+}
@@ -12,6 +12,7 @@ pub struct Scene {
     pub accel: Arc<dyn Accel>,
     pub camera: Arc<dyn Camera>,
     pub lights: Vec<Arc<dyn Light>>,
+    pub infinite_lights: Vec<Arc<dyn Light>>,
     pub light_distr: Arc<dyn LightDistribution>,
     pub ray_counter: AtomicU64,
     pub shape_to_light: HashMap<usize, Arc<dyn Light>>,
@@ -23,7 +24,7 @@ impl Scene {
         camera: Arc<dyn Camera>,
         shapes: Vec<Arc<dyn Shape>>,
         meshes: Vec<Arc<TriangleMesh>>,
-        mut lights: Vec<Arc<dyn Light>>,
+        lights: Vec<Arc<dyn Light>>,
         accel: &str,
         is_gpu: bool,
     ) -> Self {
@@ -33,6 +34,15 @@ impl Scene {
         } else {
             accel::build_accel(&shapes, accel)
         };
+        Self::from_accel(camera, toplevel, meshes, lights)
+    }
+    // area lights are created for the emissive shapes of `toplevel`
+    pub fn from_accel(
+        camera: Arc<dyn Camera>,
+        toplevel: Arc<dyn Accel>,
+        meshes: Vec<Arc<TriangleMesh>>,
+        mut lights: Vec<Arc<dyn Light>>,
+    ) -> Self {
         let mut shape_to_light = HashMap::new();
         for shape in toplevel.shapes() {
             if let Some(bsdf) = shape.bsdf() {
@@ -51,10 +61,16 @@ impl Scene {
             }
         }
 
+        let infinite_lights = lights
+            .iter()
+            .filter(|light| light.flags().contains(LightFlags::INFINITE))
+            .cloned()
+            .collect();
         Self {
             ray_counter: AtomicU64::new(0),
             camera,
             lights: lights.clone(),
+            infinite_lights,
             shape_to_light,
             light_distr: Arc::new(PowerLightDistribution::new(lights)),
             accel: toplevel,
@@ -34,11 +34,50 @@ pub mod node {
         pub center: [f32; 3],
         pub up: [f32; 3],
     }
-    #[derive(Clone, Copy, Serialize, Deserialize)]
+    #[derive(Clone, Serialize, Deserialize)]
     #[serde(untagged)]
     pub enum Transform {
         LookAt(LookAt),
         TRS(TRS),
+        Motion(MotionTransform),
+        Keyframes(KeyframedTransform),
+    }
+    #[derive(Clone, Copy, Serialize, Deserialize)]
+    #[serde(untagged)]
+    pub enum Keyframe {
+        LookAt(LookAt),
+        TRS(TRS),
+    }
+    // moves from `start` at time 0 to `end` at time 1, seen through the camera shutter
+    #[derive(Clone, Copy, Serialize, Deserialize)]
+    pub struct MotionTransform {
+        pub start: Keyframe,
+        pub end: Keyframe,
+    }
+    // poses at given frames of an animation. Between two keyframes the pose is interpolated,
+    // before the first and after the last keyframe it is held.
+    // The camera shutter is measured in frames and stays open from a frame to the next.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct KeyframedTransform {
+        pub keyframes: Vec<TimedKeyframe>,
+    }
+    #[derive(Clone, Copy, Serialize, Deserialize)]
+    pub struct TimedKeyframe {
+        pub frame: f32,
+        pub transform: Keyframe,
+    }
+    impl Transform {
+        pub fn is_animated(&self) -> bool {
+            matches!(self, Transform::Motion(_) | Transform::Keyframes(_))
+        }
+    }
+    impl From<Keyframe> for Transform {
+        fn from(k: Keyframe) -> Self {
+            match k {
+                Keyframe::LookAt(x) => Transform::LookAt(x),
+                Keyframe::TRS(x) => Transform::TRS(x),
+            }
+        }
     }
     impl Default for TRS {
         fn default() -> Self {
@@ -53,6 +92,9 @@ pub mod node {
     fn default_colorspace() -> String {
         "srgb".into()
     }
+    fn default_fisheye_projection() -> String {
+        "equidistant".into()
+    }
     #[derive(Clone, Serialize, Deserialize)]
     #[serde(tag = "type")]
     pub enum ShaderGraphNode {
@@ -67,19 +109,55 @@ pub mod node {
             tex_b: String,
         },
         #[serde(rename = "noise")]
-        Noise { pattern: String, dimension: u8 },
+        Noise {
+            pattern: String,
+            // number of output channels, 1 for gray noise or 3 for a separate noise per rgb channel
+            dimension: u8,
+            #[serde(default = "default_noise_scale")]
+            scale: f32,
+        },
+    }
+    fn default_noise_scale() -> f32 {
+        1.0
+    }
+    fn default_shader_output() -> String {
+        "output".into()
     }
     #[derive(Clone, Serialize, Deserialize)]
     pub struct ShaderGraph {
         pub nodes: HashMap<String, ShaderGraphNode>,
+        #[serde(default = "default_shader_output")]
+        pub output: String,
+        #[serde(default)]
         pub resolution: usize,
+        #[serde(default)]
         pub precompute: bool,
+        // file the baked image is kept in, baked again when the shader definition changes.
+        // edits to the images the shader reads are not detected, delete the cache to rebake
         pub cache: Option<String>,
     }
     #[derive(Clone, Serialize, Deserialize)]
     pub struct TextureCache {
         pub path: String,
     }
+    #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ImageChannel {
+        R,
+        G,
+        B,
+        A,
+    }
+    impl ImageChannel {
+        pub fn index(self) -> usize {
+            match self {
+                Self::R => 0,
+                Self::G => 1,
+                Self::B => 2,
+                Self::A => 3,
+            }
+        }
+    }
     #[derive(Clone, Serialize, Deserialize)]
     #[serde(untagged)]
     pub enum FloatTexture {
@@ -89,6 +167,12 @@ pub mod node {
             path: String,
             #[serde(default)]
             cache: Option<TextureCache>,
+            // defaults to the average of rgb
+            #[serde(default)]
+            channel: Option<ImageChannel>,
+        },
+        Shader {
+            shader: String,
         },
     }
     #[derive(Clone, Serialize, Deserialize)]
@@ -108,6 +192,38 @@ pub mod node {
             #[serde(default)]
             cache: Option<TextureCache>,
         },
+        #[serde(rename = "shader")]
+        Shader { shader: String },
+        // per vertex colors of the mesh, white for meshes without colors
+        #[serde(rename = "vertex_color")]
+        VertexColor {},
+        // Planck blackbody at `temperature` kelvin, normalized to a peak of 1
+        #[serde(rename = "blackbody")]
+        Blackbody {
+            temperature: f32,
+            #[serde(default = "default_spectrum_scale")]
+            scale: f32,
+        },
+        // spectrum known to the renderer by name, e.g. "stdillum-D65"
+        #[serde(rename = "named")]
+        Named {
+            name: String,
+            #[serde(default = "default_spectrum_scale")]
+            scale: f32,
+        },
+        // (wavelength in nm, value) pairs, zero outside of the given range
+        #[serde(rename = "tabulated")]
+        Tabulated {
+            values: Vec<[f32; 2]>,
+            // scale to unit luminance before applying `scale`
+            #[serde(default)]
+            normalize: bool,
+            #[serde(default = "default_spectrum_scale")]
+            scale: f32,
+        },
+    }
+    fn default_spectrum_scale() -> f32 {
+        1.0
     }
     fn default_ior() -> f32 {
         1.502
@@ -115,6 +231,9 @@ pub mod node {
     fn default_dispersion() -> f32 {
         0.0
     }
+    fn default_opacity() -> FloatTexture {
+        FloatTexture::Float(1.0)
+    }
     #[derive(Clone, Serialize, Deserialize)]
     #[serde(tag = "type")]
     pub enum Bsdf {
@@ -149,6 +268,12 @@ pub mod node {
             ior: FloatTexture,
             transmission: FloatTexture,
             emission: SpectrumTexture,
+            // cutout opacity, 1 is fully opaque
+            #[serde(default = "default_opacity")]
+            opacity: FloatTexture,
+            // tangent space normal map image
+            #[serde(default)]
+            normal_map: Option<String>,
         },
     }
 
@@ -178,6 +303,22 @@ pub mod node {
             falloff: f32,
             max_angle: f32,
         },
+        // light travelling along `direction`, emission is the irradiance it delivers
+        #[serde(rename = "directional")]
+        Directional {
+            direction: [f32; 3],
+            emission: SpectrumTexture,
+            // in degrees, 0 for perfectly parallel rays
+            #[serde(default)]
+            angular_diameter: f32,
+        },
+        // equirectangular map, the top row is +y of the light's transform
+        #[serde(rename = "environment")]
+        Environment {
+            emission: SpectrumTexture,
+            #[serde(default)]
+            transform: Option<Transform>,
+        },
     }
     #[derive(Clone, Serialize, Deserialize)]
     #[serde(tag = "type")]
@@ -189,21 +330,119 @@ pub mod node {
             lens_radius: f32,
             focal: f32,
             transform: Transform,
+            // open and close time, rays are spread uniformly in between
+            #[serde(default)]
+            shutter: (f32, f32),
+        },
+        #[serde(rename = "orthographic")]
+        Orthographic {
+            res: (u32, u32),
+            width: f32, // world-space film width
+            transform: Transform,
+            // open and close time, rays are spread uniformly in between
+            #[serde(default)]
+            shutter: (f32, f32),
+        },
+        #[serde(rename = "spherical")]
+        Spherical {
+            res: (u32, u32),
+            // interpupillary distance, renders a top-bottom stereo pair when > 0
+            #[serde(default)]
+            ipd: f32,
+            transform: Transform,
+            // open and close time, rays are spread uniformly in between
+            #[serde(default)]
+            shutter: (f32, f32),
+        },
+        #[serde(rename = "fisheye")]
+        Fisheye {
+            res: (u32, u32),
+            fov: f32, // in degress, up to 220
+            // one of "equidistant", "equisolid", "stereographic"
+            #[serde(default = "default_fisheye_projection")]
+            projection: String,
+            transform: Transform,
+            // open and close time, rays are spread uniformly in between
+            #[serde(default)]
+            shutter: (f32, f32),
         },
     }
 
+    impl Camera {
+        pub fn transform(&self) -> &Transform {
+            match self {
+                Camera::Perspective { transform, .. }
+                | Camera::Orthographic { transform, .. }
+                | Camera::Spherical { transform, .. }
+                | Camera::Fisheye { transform, .. } => transform,
+            }
+        }
+        pub fn shutter(&self) -> (f32, f32) {
+            match self {
+                Camera::Perspective { shutter, .. }
+                | Camera::Orthographic { shutter, .. }
+                | Camera::Spherical { shutter, .. }
+                | Camera::Fisheye { shutter, .. } => *shutter,
+            }
+        }
+    }
+
     pub enum GenericTextureRefMut<'a> {
         Float(&'a mut FloatTexture),
         Spectrum(&'a mut SpectrumTexture),
     }
-    #[derive(Clone, Serialize, Deserialize)]
+    #[derive(Clone, Default, Serialize, Deserialize)]
     pub struct Scene {
+        // scene files layered below this one, see `Scene::merge`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub include: Vec<String>,
+        #[serde(default)]
         pub bsdfs: HashMap<String, Bsdf>,
-        pub camera: Camera,
+        // may be left to an included file
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub camera: Option<Camera>,
+        #[serde(default)]
         pub lights: Vec<Light>,
+        #[serde(default)]
+        pub shapes: Vec<Shape>,
+        #[serde(default)]
+        pub shaders: HashMap<String, ShaderGraph>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub groups: Vec<Group>,
+    }
+    // named collection of shapes, lights and nested groups that move and hide together
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Group {
+        pub name: String,
+        // composed with the transforms of the enclosing groups and of the children
+        #[serde(default)]
+        pub transform: Option<Transform>,
+        #[serde(default)]
+        pub hidden: bool,
+        // takes the children of the named group instead of its own,
+        // they are placed by this group's transform rather than by that of the named group
+        #[serde(default)]
+        pub instance: Option<String>,
+        #[serde(default)]
         pub shapes: Vec<Shape>,
-        // #[serde(default = "Vec::new")]
-        // pub shaders: Vec<ShaderGraph>,
+        #[serde(default)]
+        pub lights: Vec<Light>,
+        #[serde(default)]
+        pub groups: Vec<Group>,
+    }
+    impl Group {
+        // shapes and lights of `groups` and all their descendants
+        pub fn collect_nodes<'a>(
+            groups: &'a mut [Group],
+            shapes: &mut Vec<&'a mut Shape>,
+            lights: &mut Vec<&'a mut Light>,
+        ) {
+            for group in groups {
+                shapes.extend(group.shapes.iter_mut());
+                lights.extend(group.lights.iter_mut());
+                Self::collect_nodes(&mut group.groups, shapes, lights);
+            }
+        }
     }
     impl Bsdf {
         pub fn foreach_texture<F: FnMut(GenericTextureRefMut<'_>)>(&mut self, mut f: F) {
@@ -232,6 +471,8 @@ pub mod node {
                     ior,
                     transmission,
                     emission,
+                    opacity,
+                    ..
                 } => {
                     f(GenericTextureRefMut::Spectrum(color));
                     f(GenericTextureRefMut::Float(subsurface));
@@ -251,28 +492,110 @@ pub mod node {
                     f(GenericTextureRefMut::Float(ior));
                     f(GenericTextureRefMut::Float(transmission));
                     f(GenericTextureRefMut::Spectrum(emission));
+                    f(GenericTextureRefMut::Float(opacity));
                 }
             }
         }
     }
     impl Scene {
+        /// Layers `other` on top of this scene:
+        /// `bsdfs` and `shaders` with the same name are replaced by the ones in `other`,
+        /// `shapes` and `lights` are appended, and the camera of `other` wins if it has one.
+        /// Top level `groups` with the same name are replaced as a whole, others are appended.
+        pub fn merge(&mut self, other: Scene) {
+            self.include.extend(other.include);
+            self.bsdfs.extend(other.bsdfs);
+            self.shaders.extend(other.shaders);
+            self.shapes.extend(other.shapes);
+            self.lights.extend(other.lights);
+            if other.camera.is_some() {
+                self.camera = other.camera;
+            }
+            for group in other.groups {
+                match self.groups.iter_mut().find(|g| g.name == group.name) {
+                    Some(g) => *g = group,
+                    None => self.groups.push(group),
+                }
+            }
+        }
+        // prefixes relative paths of includes and external files with `dir`
+        pub fn rebase_paths(&mut self, dir: &std::path::Path) {
+            let mut rebase = |path: &mut String| {
+                if std::path::Path::new(path.as_str()).is_relative() {
+                    *path = dir.join(path.as_str()).to_string_lossy().into_owned();
+                }
+            };
+            for include in &mut self.include {
+                rebase(include);
+            }
+            self.foreach_ext_files(rebase);
+        }
         pub fn foreach_ext_files<F: FnMut(&mut String)>(&mut self, mut f: F) {
-            for shape in &mut self.shapes {
+            let mut shapes: Vec<&mut Shape> = self.shapes.iter_mut().collect();
+            let mut lights: Vec<&mut Light> = self.lights.iter_mut().collect();
+            Group::collect_nodes(&mut self.groups, &mut shapes, &mut lights);
+            for shape in shapes {
                 match shape {
                     Shape::Mesh { path, .. } => f(path),
                 }
             }
+            // not a texture, the normal map is read as raw rgb
             for (_, bsdf) in &mut self.bsdfs {
-                bsdf.foreach_texture(|tex| match tex {
-                    GenericTextureRefMut::Float(tex) => match tex {
-                        FloatTexture::Image(img) => f(img),
-                        _ => {}
-                    },
-                    GenericTextureRefMut::Spectrum(tex) => match tex {
-                        SpectrumTexture::Image { path, .. } => f(path),
+                if let Bsdf::Principled {
+                    normal_map: Some(path),
+                    ..
+                } = bsdf
+                {
+                    f(path);
+                }
+            }
+            let mut f_tex = |tex: GenericTextureRefMut<'_>| match tex {
+                GenericTextureRefMut::Float(tex) => match tex {
+                    FloatTexture::Image(img) => f(img),
+                    FloatTexture::CachedImage { path, cache, .. } => {
+                        f(path);
+                        if let Some(cache) = cache {
+                            f(&mut cache.path);
+                        }
+                    }
+                    _ => {}
+                },
+                GenericTextureRefMut::Spectrum(tex) => match tex {
+                    SpectrumTexture::Image { path, cache, .. } => {
+                        f(path);
+                        if let Some(cache) = cache {
+                            f(&mut cache.path);
+                        }
+                    }
+                    _ => {}
+                },
+            };
+            for (_, bsdf) in &mut self.bsdfs {
+                bsdf.foreach_texture(&mut f_tex);
+            }
+            for light in lights {
+                match light {
+                    Light::Point { emission, .. }
+                    | Light::Spot { emission, .. }
+                    | Light::Directional { emission, .. }
+                    | Light::Environment { emission, .. } => {
+                        f_tex(GenericTextureRefMut::Spectrum(emission))
+                    }
+                }
+            }
+            for (_, shader) in &mut self.shaders {
+                if let Some(cache) = &mut shader.cache {
+                    f(cache);
+                }
+                for (_, node) in &mut shader.nodes {
+                    match node {
+                        ShaderGraphNode::Float(tex) => f_tex(GenericTextureRefMut::Float(tex)),
+                        ShaderGraphNode::Spectrum(tex) => {
+                            f_tex(GenericTextureRefMut::Spectrum(tex))
+                        }
                         _ => {}
-                    },
-                });
+                    }
+                }
             }
         }
     }
@@ -0,0 +1,280 @@
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use util::RobustSum;
+
+use crate::{
+    texture::{FloatTexture, ShadingPoint, SpectrumTexture},
+    util::image::{PixelFormat, TiledImage, WrappingMode},
+    *,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoisePattern {
+    Perlin,
+    Value,
+}
+impl NoisePattern {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "perlin" => Some(Self::Perlin),
+            "value" => Some(Self::Value),
+            _ => None,
+        }
+    }
+}
+
+/*
+Compiled shader graph node, every node outputs linear rgb (floats are splatted).
+Inputs refer to other nodes by their index in ShaderGraphTexture::nodes.
+*/
+pub enum ShaderNode {
+    Constant(Vec3),
+    Float(Arc<dyn FloatTexture>),
+    Image {
+        image: TiledImage,
+        invert_y: bool,
+    },
+    Mix {
+        frac: usize,
+        a: usize,
+        b: usize,
+    },
+    Noise {
+        pattern: NoisePattern,
+        dimension: u8,
+        scale: f32,
+    },
+}
+
+impl ShaderNode {
+    // `image` holds linear rgb in the primaries of `colorspace`,
+    // it is converted to the linear srgb the nodes work in
+    pub fn from_image(image: TiledImage, colorspace: RgbColorSpace, invert_y: bool) -> Self {
+        let image = if colorspace.id() == RgbColorSpaceId::SRgb {
+            image
+        } else {
+            let dim = image.dimension();
+            TiledImage::from_fn(dim.x, dim.y, PixelFormat::Rgb32f, |x, y| {
+                let rgb = image
+                    .load(uvec2(x, y).as_ivec2(), WrappingMode::Clamp)
+                    .xyz();
+                xyz_to_srgb(colorspace.to_xyz(rgb)).extend(1.0)
+            })
+        };
+        ShaderNode::Image { image, invert_y }
+    }
+}
+
+lazy_static! {
+    static ref COLORSPACE: RgbColorSpace = RgbColorSpace::new(RgbColorSpaceId::SRgb);
+}
+pub struct ShaderGraphTexture {
+    nodes: Vec<ShaderNode>,
+    output: usize,
+}
+impl ShaderGraphTexture {
+    pub fn new(nodes: Vec<ShaderNode>, output: usize) -> Self {
+        assert!(output < nodes.len());
+        Self { nodes, output }
+    }
+    // texture that looks up a graph previously baked by `bake`
+    pub fn from_baked(image: TiledImage) -> Self {
+        Self::new(
+            vec![ShaderNode::Image {
+                image,
+                invert_y: false,
+            }],
+            0,
+        )
+    }
+    pub fn bake(&self, resolution: u32) -> TiledImage {
+        let fres = resolution as f32;
+        TiledImage::from_fn(resolution, resolution, PixelFormat::Rgb32f, |x, y| {
+            let sp = ShadingPoint {
+                texcoord: vec2((x as f32 + 0.5) / fres, (y as f32 + 0.5) / fres),
+                color: Vec3::ONE,
+            };
+            self.evaluate_rgb(&sp).extend(1.0)
+        })
+    }
+    pub fn evaluate_rgb(&self, sp: &ShadingPoint) -> Vec3 {
+        self.evaluate_node(self.output, sp)
+    }
+    fn evaluate_node(&self, idx: usize, sp: &ShadingPoint) -> Vec3 {
+        match &self.nodes[idx] {
+            ShaderNode::Constant(rgb) => *rgb,
+            ShaderNode::Float(tex) => Vec3::splat(tex.evaluate(sp)),
+            ShaderNode::Image { image, invert_y } => {
+                let mut tc = sp.texcoord;
+                if *invert_y {
+                    tc.y = 1.0 - tc.y;
+                }
+                image.loadf(tc, WrappingMode::Repeat).truncate()
+            }
+            ShaderNode::Mix { frac, a, b } => {
+                let frac = self.evaluate_node(*frac, sp);
+                let frac = ((frac.x + frac.y + frac.z) / 3.0).clamp(0.0, 1.0);
+                lerp(self.evaluate_node(*a, sp), self.evaluate_node(*b, sp), frac)
+            }
+            ShaderNode::Noise {
+                pattern,
+                dimension,
+                scale,
+            } => {
+                let p = sp.texcoord * *scale;
+                let noise = |seed| match pattern {
+                    NoisePattern::Perlin => perlin_noise(p, seed),
+                    NoisePattern::Value => value_noise(p, seed),
+                };
+                if *dimension == 1 {
+                    Vec3::splat(noise(0))
+                } else {
+                    vec3(noise(0), noise(1), noise(2))
+                }
+            }
+        }
+    }
+    fn average<F: Fn(Vec3) -> f32>(&self, f: F) -> f32 {
+        const N: u32 = 64;
+        let mut sum = RobustSum::new(0.0);
+        for y in 0..N {
+            for x in 0..N {
+                let sp = ShadingPoint {
+                    texcoord: vec2(x as f32 + 0.5, y as f32 + 0.5) / N as f32,
+                    color: Vec3::ONE,
+                };
+                sum.add(f(self.evaluate_rgb(&sp)));
+            }
+        }
+        sum.sum() / (N * N) as f32
+    }
+}
+impl FloatTexture for ShaderGraphTexture {
+    fn evaluate(&self, sp: &ShadingPoint) -> f32 {
+        let rgb = self.evaluate_rgb(sp);
+        (rgb.x + rgb.y + rgb.z) / 3.0
+    }
+    fn power(&self) -> f32 {
+        self.average(|rgb| (rgb.x + rgb.y + rgb.z) / 3.0)
+    }
+}
+impl SpectrumTexture for ShaderGraphTexture {
+    fn evaluate(&self, sp: &ShadingPoint, lambda: &SampledWavelengths) -> SampledSpectrum {
+        let rgb = self.evaluate_rgb(sp).max(Vec3::ZERO);
+        let scale = rgb.max_element().max(1.0);
+        COLORSPACE.rgb2spec(rgb / scale).sample(lambda) * scale
+    }
+    fn power(&self) -> f32 {
+        self.average(|rgb| srgb_to_xyz(rgb.max(Vec3::ZERO)).y)
+    }
+    fn colorspace(&self) -> Option<RgbColorSpace> {
+        Some(*COLORSPACE)
+    }
+}
+
+fn hash(x: i32, y: i32, seed: u32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6b343)
+        ^ (y as u32).wrapping_mul(0xd8163841)
+        ^ seed.wrapping_mul(0xcb1ab31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^= h >> 16;
+    h
+}
+fn fade(t: Vec2) -> Vec2 {
+    t * t * t * (t * (t * 6.0 - Vec2::splat(15.0)) + Vec2::splat(10.0))
+}
+// value noise in [0, 1]
+pub fn value_noise(p: Vec2, seed: u32) -> f32 {
+    let i = p.floor();
+    let f = fade(p - i);
+    let (x, y) = (i.x as i32, i.y as i32);
+    let v = |dx: i32, dy: i32| hash(x + dx, y + dy, seed) as f32 / std::u32::MAX as f32;
+    lerp(
+        lerp(v(0, 0), v(1, 0), f.x),
+        lerp(v(0, 1), v(1, 1), f.x),
+        f.y,
+    )
+}
+// 2d gradient (Perlin) noise remapped to [0, 1]
+pub fn perlin_noise(p: Vec2, seed: u32) -> f32 {
+    let i = p.floor();
+    let t = p - i;
+    let f = fade(t);
+    let (x, y) = (i.x as i32, i.y as i32);
+    let g = |dx: i32, dy: i32| {
+        let phi = hash(x + dx, y + dy, seed) as f32 / std::u32::MAX as f32 * 2.0 * PI;
+        vec2(phi.cos(), phi.sin()).dot(t - vec2(dx as f32, dy as f32))
+    };
+    let n = lerp(
+        lerp(g(0, 0), g(1, 0), f.x),
+        lerp(g(0, 1), g(1, 1), f.x),
+        f.y,
+    );
+    (n * std::f32::consts::FRAC_1_SQRT_2 + 0.5).clamp(0.0, 1.0)
+}
+
+mod test {
+    #[test]
+    fn test_shader_graph_bake() {
+        use super::*;
+        let graph = ShaderGraphTexture::new(
+            vec![
+                ShaderNode::Constant(vec3(1.0, 0.0, 0.0)),
+                ShaderNode::Constant(vec3(0.0, 0.0, 1.0)),
+                ShaderNode::Noise {
+                    pattern: NoisePattern::Perlin,
+                    dimension: 1,
+                    scale: 8.0,
+                },
+                ShaderNode::Mix {
+                    frac: 2,
+                    a: 0,
+                    b: 1,
+                },
+            ],
+            3,
+        );
+        let baked = ShaderGraphTexture::from_baked(graph.bake(32));
+        for y in 0..32 {
+            for x in 0..32 {
+                let sp = ShadingPoint {
+                    texcoord: vec2(x as f32 + 0.5, y as f32 + 0.5) / 32.0,
+                    color: Vec3::ONE,
+                };
+                let rgb = graph.evaluate_rgb(&sp);
+                assert!((rgb.x + rgb.z - 1.0).abs() < 1e-4);
+                assert!((baked.evaluate_rgb(&sp) - rgb).abs().max_element() < 1e-4);
+            }
+        }
+    }
+    #[test]
+    fn test_shader_image_colorspace() {
+        use super::*;
+        let image = TiledImage::from_fn(2, 1, PixelFormat::Rgb32f, |x, _| {
+            if x == 0 {
+                vec4(1.0, 1.0, 1.0, 1.0)
+            } else {
+                vec4(1.0, 0.0, 0.0, 1.0)
+            }
+        });
+        let node =
+            ShaderNode::from_image(image, RgbColorSpace::new(RgbColorSpaceId::Rec2020), false);
+        let image = match node {
+            ShaderNode::Image { image, .. } => image,
+            _ => unreachable!(),
+        };
+        // white stays white, rec2020 red lies outside of the srgb gamut
+        let white = image.load(ivec2(0, 0), WrappingMode::Clamp).xyz();
+        let red = image.load(ivec2(1, 0), WrappingMode::Clamp).xyz();
+        assert!((white - Vec3::ONE).abs().max_element() < 1e-2, "{}", white);
+        assert!(
+            (red - vec3(1.6605, -0.1246, -0.0182)).abs().max_element() < 1e-2,
+            "{}",
+            red
+        );
+    }
+}
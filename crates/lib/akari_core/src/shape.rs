@@ -4,13 +4,15 @@ use crate::accel::qbvh::QBvhAccel;
 use crate::bsdf::BsdfClosure;
 use crate::bsdf::TransportMode;
 use crate::distribution::Distribution1D;
-use crate::texture::ShadingPoint;
+use crate::texture::{FloatTexture, ShadingPoint};
 use crate::util::binserde::Decode;
 use crate::util::binserde::Encode;
 use crate::*;
 use crate::{accel::bvh, bsdf::Bsdf};
 
+use akari_common::flate2::Crc;
 use akari_common::lazy_static::lazy_static;
+use bitflags::bitflags;
 use bumpalo::Bump;
 use glam::BVec4A;
 use serde::{Deserialize, Serialize};
@@ -57,12 +59,17 @@ pub struct SurfaceSample {
     pub pdf: f32,
     pub ng: Vec3,
     pub ns: Vec3,
+    pub color: Vec3,
 }
 pub trait Shape: Sync + Send + AsAny {
     fn intersect(&self, ray: &Ray, invd: Option<Vec3A>) -> Option<RayHit>;
     fn occlude(&self, ray: &Ray, invd: Option<Vec3A>) -> bool;
     fn bsdf<'a>(&'a self) -> Option<&'a dyn Bsdf>;
     fn shading_triangle<'a>(&'a self, prim_id: u32) -> ShadingTriangle<'a>;
+    // shading triangle as seen by a ray at `time`, only differs for moving shapes
+    fn shading_triangle_at<'a>(&'a self, prim_id: u32, _time: f32) -> ShadingTriangle<'a> {
+        self.shading_triangle(prim_id)
+    }
     fn triangle(&self, prim_id: u32) -> Triangle;
     fn aabb(&self) -> Bounds3f;
     fn sample_surface(&self, u: Vec3) -> SurfaceSample;
@@ -76,6 +83,11 @@ pub struct Triangle {
 }
 
 impl Triangle {
+    pub fn transform(&self, transform: &Transform) -> Self {
+        Self {
+            vertices: self.vertices.map(|v| transform.transform_point(v)),
+        }
+    }
     fn area(&self) -> f32 {
         let e0 = self.vertices[1] - self.vertices[0];
         let e1 = self.vertices[2] - self.vertices[0];
@@ -177,15 +189,61 @@ pub struct ShadingTriangle<'a> {
     pub vertices: [Vec3; 3],
     pub texcoords: [Vec2; 3],
     pub normals: [Vec3; 3],
+    // white when the mesh has no vertex colors
+    pub colors: [Vec3; 3],
     pub bsdf: Option<&'a dyn Bsdf>,
 }
 impl<'a> ShadingTriangle<'a> {
+    pub fn transform(&self, transform: &Transform) -> Self {
+        Self {
+            vertices: self.vertices.map(|v| transform.transform_point(v)),
+            normals: self
+                .normals
+                .map(|n| transform.transform_normal(n).normalize()),
+            ..*self
+        }
+    }
     pub fn texcoord(&self, uv: Vec2) -> Vec2 {
         lerp3(self.texcoords[0], self.texcoords[1], self.texcoords[2], uv)
     }
+    pub fn color(&self, uv: Vec2) -> Vec3 {
+        lerp3(self.colors[0], self.colors[1], self.colors[2], uv)
+    }
     pub fn ns(&self, uv: Vec2) -> Vec3 {
         lerp3(self.normals[0], self.normals[1], self.normals[2], uv).normalize()
     }
+    // interpolated normal, perturbed by the normal map of the bsdf if any
+    pub fn shading_normal(&self, uv: Vec2) -> Vec3 {
+        let ns = self.ns(uv);
+        let normal_map = match self.bsdf.and_then(|bsdf| bsdf.normal_map()) {
+            Some(normal_map) => normal_map,
+            None => return ns,
+        };
+        let dp1 = self.vertices[1] - self.vertices[0];
+        let dp2 = self.vertices[2] - self.vertices[0];
+        let duv1 = self.texcoords[1] - self.texcoords[0];
+        let duv2 = self.texcoords[2] - self.texcoords[0];
+        let det = duv1.x * duv2.y - duv1.y * duv2.x;
+        if det.abs() < 1e-12 {
+            return ns;
+        }
+        let dpdu = (dp1 * duv2.y - dp2 * duv1.y) / det;
+        let dpdv = (dp2 * duv1.x - dp1 * duv2.x) / det;
+        let t = dpdu - ns * ns.dot(dpdu);
+        if t.length_squared() < 1e-12 {
+            return ns;
+        }
+        let t = t.normalize();
+        let mut b = ns.cross(t);
+        if b.dot(dpdv) < 0.0 {
+            b = -b;
+        }
+        let n = normal_map.evaluate(&ShadingPoint {
+            texcoord: self.texcoord(uv),
+            color: self.color(uv),
+        });
+        (t * n.x + b * n.y + ns * n.z).normalize()
+    }
     pub fn p(&self, uv: Vec2) -> Vec3 {
         lerp3(self.vertices[0], self.vertices[1], self.vertices[2], uv)
     }
@@ -250,16 +308,38 @@ impl bvh::BvhData for TriangleMeshAccelData {
     }
 }
 
+// object to world, None when the mesh is already in world space
+// when the instance moves, transform is the start of the motion
+// and is what sample_surface() and area() use
 pub struct TriangleMeshInstance {
     pub accel: Arc<MeshBvh>,
     pub bsdf: Arc<dyn Bsdf>,
+    pub transform: Option<Transform>,
+    pub motion: Option<AnimatedTransform>,
     pub area: f32,
     pub dist: Distribution1D,
 }
+impl TriangleMeshInstance {
+    fn transform_at(&self, time: f32) -> Option<Transform> {
+        match &self.motion {
+            Some(motion) => Some(motion.at(time)),
+            None => self.transform,
+        }
+    }
+    fn object_ray(&self, ray: &Ray, inv_d: Option<Vec3A>) -> (Ray, Option<Vec3A>) {
+        if let Some(transform) = self.transform_at(ray.time) {
+            (transform.inverse().transform_ray(ray), None)
+        } else {
+            (*ray, inv_d)
+        }
+    }
+}
 
 pub struct MeshInstanceProxy {
     pub mesh: Arc<TriangleMesh>,
     pub bsdf: Arc<dyn Bsdf>,
+    pub transform: Option<Transform>,
+    pub motion: Option<AnimatedTransform>,
 }
 
 
@@ -277,18 +357,34 @@ impl Shape for MeshInstanceProxy {
     }
 
     fn shading_triangle<'a>(&'a self, prim_id: u32) -> ShadingTriangle<'a> {
-        ShadingTriangle {
-            bsdf: self.bsdf(),
-            ..self.mesh.shading_triangle(prim_id as usize)
+        self.mesh
+            .instance_shading_triangle(prim_id as usize, self.transform.as_ref(), self.bsdf())
+    }
+
+    fn shading_triangle_at<'a>(&'a self, prim_id: u32, time: f32) -> ShadingTriangle<'a> {
+        match &self.motion {
+            Some(motion) => self.mesh.instance_shading_triangle(
+                prim_id as usize,
+                Some(&motion.at(time)),
+                self.bsdf(),
+            ),
+            None => self.shading_triangle(prim_id),
         }
     }
 
     fn triangle(&self, prim_id: u32) -> Triangle {
-        self.mesh.triangle(prim_id as usize)
+        self.mesh
+            .instance_triangle(prim_id as usize, self.transform.as_ref())
     }
 
     fn aabb(&self) -> Bounds3f {
-        panic!("shouldn't be called")
+        if let Some(motion) = &self.motion {
+            motion.motion_aabb(self.mesh.aabb())
+        } else if let Some(transform) = &self.transform {
+            transform.transform_aabb(self.mesh.aabb())
+        } else {
+            self.mesh.aabb()
+        }
     }
 
     fn sample_surface(&self, _u: Vec3) -> SurfaceSample {
@@ -296,44 +392,79 @@ impl Shape for MeshInstanceProxy {
     }
 
     fn area(&self) -> f32 {
-        self.mesh.area()
+        self.mesh.instance_area(self.transform.as_ref())
+    }
+}
+
+// uniform number in [0, 1) from the ray and the face it hits
+// so that the cutout test gives the same answer every time the same ray is traced
+pub(crate) fn hash_hit(ray: &Ray, prim_id: u32) -> f32 {
+    let fmix = |mut h: u32| {
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0xc2b2_ae35);
+        h ^ (h >> 16)
+    };
+    let mut h = fmix(prim_id.wrapping_add(0x9e37_79b9));
+    for x in [ray.o.x, ray.o.y, ray.o.z, ray.d.x, ray.d.y, ray.d.z] {
+        h = fmix(h ^ x.to_bits());
     }
+    (h >> 8) as f32 / (1u32 << 24) as f32
 }
 
 impl Shape for TriangleMeshInstance {
     fn aabb(&self) -> Bounds3f {
-        self.accel.aabb()
+        if let Some(motion) = &self.motion {
+            motion.motion_aabb(self.accel.aabb())
+        } else if let Some(transform) = &self.transform {
+            transform.transform_aabb(self.accel.aabb())
+        } else {
+            self.accel.aabb()
+        }
     }
     fn intersect(&self, ray: &Ray, inv_d: Option<Vec3A>) -> Option<RayHit> {
         let mut hit = None;
-        self.accel.traverse(*ray, inv_d, |ray, inv_d, prim_id| {
-            let triangle = self.triangle(prim_id);
+        let mesh = &self.accel.data().mesh;
+        let opacity = self.bsdf.opacity();
+        let time = ray.time;
+        let (ray, inv_d) = self.object_ray(ray, inv_d);
+        self.accel.traverse(ray, inv_d, |ray, _inv_d, prim_id| {
+            let triangle = mesh.triangle(prim_id as usize);
             if let Some((t, uv)) = triangle.intersect(ray) {
-                ray.tmax = t;
-                hit = Some((t, uv, prim_id));
+                if mesh.is_opaque(opacity, prim_id, uv, ray) {
+                    ray.tmax = t;
+                    hit = Some((t, uv, prim_id));
+                }
             }
             true
         });
         hit.map(|(t, uv, prim_id)| {
-            let triangle = self.triangle(prim_id);
+            let transform = self.transform_at(time);
+            let triangle = mesh.instance_triangle(prim_id as usize, transform.as_ref());
             RayHit {
                 t,
                 uv,
                 prim_id,
                 geom_id: 0,
                 ng: triangle.ng(),
+                time,
             }
         })
     }
     fn occlude(&self, ray: &Ray, inv_d: Option<Vec3A>) -> bool {
         let mut occluded = false;
-        self.accel.traverse(*ray, inv_d, |ray, inv_d, prim_id| {
-            let triangle = self.triangle(prim_id);
-            if triangle.intersect(ray).is_some() {
-                occluded = true;
-                false
-            } else {
-                true
+        let mesh = &self.accel.data().mesh;
+        let opacity = self.bsdf.opacity();
+        let (ray, inv_d) = self.object_ray(ray, inv_d);
+        self.accel.traverse(ray, inv_d, |ray, _inv_d, prim_id| {
+            let triangle = mesh.triangle(prim_id as usize);
+            match triangle.intersect(ray) {
+                Some((_, uv)) if mesh.is_opaque(opacity, prim_id, uv, ray) => {
+                    occluded = true;
+                    false
+                }
+                _ => true,
             }
         });
         occluded
@@ -345,24 +476,54 @@ impl Shape for TriangleMeshInstance {
         self.area
     }
     fn sample_surface(&self, u: Vec3) -> SurfaceSample {
-        self.accel.data().mesh.sample_surface(u, &self.dist)
+        self.accel
+            .data()
+            .mesh
+            .sample_surface(u, &self.dist, self.transform.as_ref())
     }
 
     fn shading_triangle<'a>(&'a self, prim_id: u32) -> ShadingTriangle<'a> {
-        ShadingTriangle {
-            bsdf: self.bsdf(),
-            ..self.accel.data().mesh.shading_triangle(prim_id as usize)
-        }
+        self.accel.data().mesh.instance_shading_triangle(
+            prim_id as usize,
+            self.transform.as_ref(),
+            self.bsdf(),
+        )
+    }
+
+    fn shading_triangle_at<'a>(&'a self, prim_id: u32, time: f32) -> ShadingTriangle<'a> {
+        self.accel.data().mesh.instance_shading_triangle(
+            prim_id as usize,
+            self.transform_at(time).as_ref(),
+            self.bsdf(),
+        )
     }
 
     fn triangle(&self, prim_id: u32) -> Triangle {
-        self.accel.data().mesh.triangle(prim_id as usize)
+        self.accel
+            .data()
+            .mesh
+            .instance_triangle(prim_id as usize, self.transform.as_ref())
     }
 }
-#[derive(Serialize, Deserialize, Clone)]
-pub struct MeshFlags {
-    pub reuse_texcoord_indices: bool,
-    pub reuse_normal_indices: bool,
+/* .mesh layout
+ * magic, format version, MeshFlags, payload size and crc32, then the payload:
+ * name, vertices, normals, texcoords, indices, normal_indices, texcoord_indices, colors
+ * attributes missing from the flags are not stored, neither are reused index buffers
+ * files without the magic are version 0, the payload alone with every field stored
+ */
+pub const MESH_MAGIC: [u8; 8] = *b"AKRMESH\0";
+pub const MESH_VERSION: u32 = 1;
+
+bitflags! {
+    pub struct MeshFlags : u32 {
+        const NORMALS = 0b1;
+        const TEXCOORDS = 0b10;
+        // normal_indices are the same as indices and not stored
+        const REUSE_NORMAL_INDICES = 0b100;
+        const REUSE_TEXCOORD_INDICES = 0b1000;
+        // per vertex colors, indexed by indices
+        const COLORS = 0b10000;
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -374,16 +535,45 @@ pub struct TriangleMesh {
     pub indices: Vec<[u32; 3]>,
     pub normal_indices: Vec<[u32; 3]>,
     pub texcoord_indices: Vec<[u32; 3]>,
+    // linear rgb of every vertex, empty if the mesh has none
+    #[serde(default)]
+    pub colors: Vec<[f32; 3]>,
+}
+fn invalid_mesh(what: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, what)
 }
 impl Encode for TriangleMesh {
     fn encode<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
-        self.name.encode(writer)?;
-        self.vertices.encode(writer)?;
-        self.normals.encode(writer)?;
-        self.texcoords.encode(writer)?;
-        self.indices.encode(writer)?;
-        self.normal_indices.encode(writer)?;
-        self.texcoord_indices.encode(writer)?;
+        let flags = self.flags();
+        let mut payload = vec![];
+        self.name.encode(&mut payload)?;
+        self.vertices.encode(&mut payload)?;
+        if flags.contains(MeshFlags::NORMALS) {
+            self.normals.encode(&mut payload)?;
+        }
+        if flags.contains(MeshFlags::TEXCOORDS) {
+            self.texcoords.encode(&mut payload)?;
+        }
+        self.indices.encode(&mut payload)?;
+        if flags.contains(MeshFlags::NORMALS) && !flags.contains(MeshFlags::REUSE_NORMAL_INDICES) {
+            self.normal_indices.encode(&mut payload)?;
+        }
+        if flags.contains(MeshFlags::TEXCOORDS)
+            && !flags.contains(MeshFlags::REUSE_TEXCOORD_INDICES)
+        {
+            self.texcoord_indices.encode(&mut payload)?;
+        }
+        if flags.contains(MeshFlags::COLORS) {
+            self.colors.encode(&mut payload)?;
+        }
+        let mut crc = Crc::new();
+        crc.update(&payload);
+        writer.write_all(&MESH_MAGIC)?;
+        MESH_VERSION.encode(writer)?;
+        flags.bits().encode(writer)?;
+        writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+        crc.sum().encode(writer)?;
+        writer.write_all(&payload)?;
         Ok(())
     }
 }
@@ -392,13 +582,114 @@ impl Decode for TriangleMesh {
     where
         Self: Sized,
     {
+        use std::io::Read;
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        let mesh = if magic != MESH_MAGIC {
+            // version 0, the bytes read are the length of the name
+            Self::decode_payload(&mut (&magic[..]).chain(reader), MeshFlags::all_stored())?
+        } else {
+            let version = u32::decode(reader)?;
+            if version > MESH_VERSION {
+                return Err(invalid_mesh(format!(
+                    "mesh version {} is newer than the supported version {}",
+                    version, MESH_VERSION
+                )));
+            }
+            let bits = u32::decode(reader)?;
+            let flags = MeshFlags::from_bits(bits)
+                .ok_or_else(|| invalid_mesh(format!("unknown mesh flags {:#x}", bits)))?;
+            let mut size = [0u8; 8];
+            reader.read_exact(&mut size)?;
+            let size = u64::from_le_bytes(size);
+            let crc32 = u32::decode(reader)?;
+            let mut payload = vec![];
+            reader.take(size).read_to_end(&mut payload)?;
+            if payload.len() as u64 != size {
+                return Err(invalid_mesh(format!(
+                    "mesh is truncated, {} of {} bytes",
+                    payload.len(),
+                    size
+                )));
+            }
+            let mut crc = Crc::new();
+            crc.update(&payload);
+            if crc.sum() != crc32 {
+                return Err(invalid_mesh("mesh checksum mismatch".into()));
+            }
+            let mut slice = payload.as_slice();
+            let mesh = Self::decode_payload(&mut slice, flags)?;
+            if !slice.is_empty() {
+                return Err(invalid_mesh(format!(
+                    "{} trailing bytes after the mesh",
+                    slice.len()
+                )));
+            }
+            mesh
+        };
+        mesh.validate().map_err(invalid_mesh)?;
+        Ok(mesh)
+    }
+}
+impl MeshFlags {
+    // layout of version 0 files
+    fn all_stored() -> Self {
+        Self::NORMALS | Self::TEXCOORDS
+    }
+}
+impl TriangleMesh {
+    pub fn flags(&self) -> MeshFlags {
+        let mut flags = MeshFlags::empty();
+        if !self.normals.is_empty() {
+            flags |= MeshFlags::NORMALS;
+            if self.normal_indices == self.indices {
+                flags |= MeshFlags::REUSE_NORMAL_INDICES;
+            }
+        }
+        if !self.texcoords.is_empty() {
+            flags |= MeshFlags::TEXCOORDS;
+            if self.texcoord_indices == self.indices {
+                flags |= MeshFlags::REUSE_TEXCOORD_INDICES;
+            }
+        }
+        if !self.colors.is_empty() {
+            flags |= MeshFlags::COLORS;
+        }
+        flags
+    }
+    fn decode_payload<R: std::io::Read>(reader: &mut R, flags: MeshFlags) -> std::io::Result<Self> {
         let name = Decode::decode(reader)?;
         let vertices = Decode::decode(reader)?;
-        let normals = Decode::decode(reader)?;
-        let texcoords = Decode::decode(reader)?;
-        let indices = Decode::decode(reader)?;
-        let normal_indices = Decode::decode(reader)?;
-        let texcoord_indices = Decode::decode(reader)?;
+        let normals = if flags.contains(MeshFlags::NORMALS) {
+            Decode::decode(reader)?
+        } else {
+            vec![]
+        };
+        let texcoords = if flags.contains(MeshFlags::TEXCOORDS) {
+            Decode::decode(reader)?
+        } else {
+            vec![]
+        };
+        let indices: Vec<[u32; 3]> = Decode::decode(reader)?;
+        let normal_indices = if flags.contains(MeshFlags::REUSE_NORMAL_INDICES) {
+            indices.clone()
+        } else if flags.contains(MeshFlags::NORMALS) {
+            Decode::decode(reader)?
+        } else {
+            vec![]
+        };
+        let texcoord_indices = if flags.contains(MeshFlags::REUSE_TEXCOORD_INDICES) {
+            indices.clone()
+        } else if flags.contains(MeshFlags::TEXCOORDS) {
+            Decode::decode(reader)?
+        } else {
+            vec![]
+        };
+        let colors = if flags.contains(MeshFlags::COLORS) {
+            Decode::decode(reader)?
+        } else {
+            vec![]
+        };
         Ok(Self {
             name,
             vertices,
@@ -407,11 +698,60 @@ impl Decode for TriangleMesh {
             texcoords,
             indices,
             normals,
+            colors,
         })
     }
+    // every face must index into the attributes it uses
+    pub fn validate(&self) -> Result<(), String> {
+        let check = |what: &str, indices: &[[u32; 3]], count: usize| {
+            if indices.len() != self.indices.len() {
+                return Err(format!(
+                    "{} has {} faces, expected {}",
+                    what,
+                    indices.len(),
+                    self.indices.len()
+                ));
+            }
+            match indices
+                .iter()
+                .enumerate()
+                .find(|(_, face)| face.iter().any(|i| *i as usize >= count))
+            {
+                Some((f, face)) => Err(format!(
+                    "face {} of {} is {:?}, out of range of {} elements",
+                    f, what, face, count
+                )),
+                None => Ok(()),
+            }
+        };
+        check("indices", &self.indices, self.vertices.len())?;
+        if !self.normals.is_empty() {
+            check("normal_indices", &self.normal_indices, self.normals.len())?;
+        }
+        if !self.texcoords.is_empty() {
+            check(
+                "texcoord_indices",
+                &self.texcoord_indices,
+                self.texcoords.len(),
+            )?;
+        }
+        if !self.colors.is_empty() && self.colors.len() != self.vertices.len() {
+            return Err(format!(
+                "{} colors for {} vertices",
+                self.colors.len(),
+                self.vertices.len()
+            ));
+        }
+        Ok(())
+    }
 }
 impl TriangleMesh {
-    pub fn sample_surface(&self, u: Vec3, dist: &Distribution1D) -> SurfaceSample {
+    pub fn sample_surface(
+        &self,
+        u: Vec3,
+        dist: &Distribution1D,
+        transform: Option<&Transform>,
+    ) -> SurfaceSample {
         let (idx, pdf_idx) = dist.sample_discrete(u[2]);
         // let face: UVec3 = self.indices[idx as usize].into();
         // let v0 = self.vertices[face[0] as usize].into();
@@ -420,7 +760,7 @@ impl TriangleMesh {
         // let trig = Triangle {
         //     vertices: [v0, v1, v2],
         // };
-        let trig = self.shading_triangle(idx);
+        let trig = self.instance_shading_triangle(idx, transform, None);
         let uv = uniform_sample_triangle(vec2(u.x, u.y));
         let p = trig.p(uv);
         SurfaceSample {
@@ -428,9 +768,54 @@ impl TriangleMesh {
             ng: trig.ng(),
             ns: trig.ns(uv),
             texcoords: trig.texcoord(uv),
-            pdf: 1.0 / self.triangle(idx).area() * pdf_idx,
+            color: trig.color(uv),
+            pdf: 1.0 / self.instance_triangle(idx, transform).area() * pdf_idx,
+        }
+    }
+    pub fn instance_triangle(&self, i: usize, transform: Option<&Transform>) -> Triangle {
+        let triangle = self.triangle(i);
+        if let Some(transform) = transform {
+            triangle.transform(transform)
+        } else {
+            triangle
+        }
+    }
+    pub fn instance_shading_triangle<'a>(
+        &self,
+        i: usize,
+        transform: Option<&Transform>,
+        bsdf: Option<&'a dyn Bsdf>,
+    ) -> ShadingTriangle<'a> {
+        let triangle = ShadingTriangle {
+            bsdf,
+            ..self.shading_triangle(i)
+        };
+        if let Some(transform) = transform {
+            triangle.transform(transform)
+        } else {
+            triangle
         }
     }
+    // cutout test of a hit on face `prim_id`, the surface is there with probability opacity
+    pub fn is_opaque(
+        &self,
+        opacity: Option<&dyn FloatTexture>,
+        prim_id: u32,
+        uv: Vec2,
+        ray: &Ray,
+    ) -> bool {
+        let opacity = match opacity {
+            Some(opacity) => opacity,
+            None => return true,
+        };
+        let [t0, t1, t2] = self.texcoords(prim_id as usize);
+        let [c0, c1, c2] = self.colors(prim_id as usize);
+        let alpha = opacity.evaluate(&ShadingPoint {
+            texcoord: lerp3(t0, t1, t2, uv),
+            color: lerp3(c0, c1, c2, uv),
+        });
+        alpha >= 1.0 || (alpha > 0.0 && hash_hit(ray, prim_id) < alpha)
+    }
     pub fn triangle(&self, i: usize) -> Triangle {
         let face = self.indices[i];
         let v0 = self.vertices[face[0] as usize].into();
@@ -452,7 +837,16 @@ impl TriangleMesh {
             texcoords: self.texcoords(i),
             bsdf: None,
             normals: self.normals(i, ng),
+            colors: self.colors(i),
+        }
+    }
+    // object space bounds of the vertices
+    pub fn aabb(&self) -> Bounds3f {
+        let mut aabb = Bounds3f::default();
+        for v in &self.vertices {
+            aabb.insert_point((*v).into());
         }
+        aabb
     }
     pub fn area(&self) -> f32 {
         self.indices
@@ -462,11 +856,21 @@ impl TriangleMesh {
             .sum()
     }
     pub fn area_distribution(&self) -> Distribution1D {
+        self.instance_area_distribution(None)
+    }
+    pub fn instance_area(&self, transform: Option<&Transform>) -> f32 {
+        self.indices
+            .iter()
+            .enumerate()
+            .map(|(i, _face)| self.instance_triangle(i, transform).area())
+            .sum()
+    }
+    pub fn instance_area_distribution(&self, transform: Option<&Transform>) -> Distribution1D {
         let f: Vec<_> = self
             .indices
             .iter()
             .enumerate()
-            .map(|(i, _face)| self.triangle(i).area())
+            .map(|(i, _face)| self.instance_triangle(i, transform).area())
             .collect();
         Distribution1D::new(f.as_slice()).unwrap()
     }
@@ -484,6 +888,13 @@ impl TriangleMesh {
             [tc0, tc1, tc2]
         }
     }
+    pub fn colors(&self, i: usize) -> [Vec3; 3] {
+        if self.colors.is_empty() {
+            [Vec3::ONE; 3]
+        } else {
+            self.indices[i].map(|v| self.colors[v as usize].into())
+        }
+    }
     pub fn normals(&self, i: usize, ng: Vec3) -> [Vec3; 3] {
         if self.normals.is_empty() {
             [ng; 3]
@@ -510,12 +921,16 @@ impl TriangleMesh {
         bsdf: Arc<dyn Bsdf>,
         accel: Arc<MeshBvh>,
         mesh: Arc<TriangleMesh>,
+        transform: Option<Transform>,
+        motion: Option<AnimatedTransform>,
     ) -> Arc<dyn Shape> {
         let instance = TriangleMeshInstance {
             accel,
             bsdf,
-            area: mesh.area(),
-            dist: mesh.area_distribution(),
+            area: mesh.instance_area(transform.as_ref()),
+            dist: mesh.instance_area_distribution(transform.as_ref()),
+            transform,
+            motion,
         };
         Arc::new(instance)
     }
@@ -577,6 +992,93 @@ pub fn compute_normals(model: &mut TriangleMesh, angle: f32) {
         .collect();
 }
 
+// converts a model loaded by tobj with `triangulate` set
+pub fn mesh_from_obj_model(m: &tobj::Model, generate_normal: Option<f32>) -> TriangleMesh {
+    let mesh = &m.mesh;
+    // println!("model[{}].name = \'{}\'", i, m.name);
+    // println!("model[{}].mesh.material_id = {:?}", i, mesh.material_id);
+
+    // println!(
+    //     "Size of model[{}].num_face_indices: {}",
+    //     i,
+    //     mesh.num_face_indices.len()
+    // );
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut texcoords = vec![];
+    // let mut indices = vec![];
+    assert!(mesh.positions.len() % 3 == 0);
+    for v in 0..mesh.positions.len() / 3 {
+        vertices.push([
+            mesh.positions[3 * v],
+            mesh.positions[3 * v + 1],
+            mesh.positions[3 * v + 2],
+        ]);
+    }
+    let mut indices = vec![];
+    let mut normal_indices = vec![];
+    let mut texcoord_indices = vec![];
+    for f in 0..mesh.indices.len() / 3 {
+        indices.push([
+            mesh.indices[3 * f],
+            mesh.indices[3 * f + 1],
+            mesh.indices[3 * f + 2],
+        ]);
+    }
+    if !mesh.normals.is_empty() && generate_normal.is_none() {
+        for i in 0..mesh.normals.len() / 3 {
+            normals.push([
+                mesh.normals[3 * i],
+                mesh.normals[3 * i + 1],
+                mesh.normals[3 * i + 2],
+            ]);
+        }
+        for i in 0..mesh.normal_indices.len() / 3 {
+            normal_indices.push([
+                mesh.normal_indices[3 * i],
+                mesh.normal_indices[3 * i + 1],
+                mesh.normal_indices[3 * i + 2],
+            ]);
+        }
+    }
+    if !mesh.texcoords.is_empty() {
+        for i in 0..mesh.texcoords.len() / 2 {
+            texcoords.push([mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]]);
+        }
+        for i in 0..mesh.texcoord_indices.len() / 3 {
+            texcoord_indices.push([
+                mesh.texcoord_indices[3 * i],
+                mesh.texcoord_indices[3 * i + 1],
+                mesh.texcoord_indices[3 * i + 2],
+            ]);
+        }
+    }
+    let mut imported = TriangleMesh {
+        name: m.name.clone(),
+        vertices,
+        normals,
+        indices,
+        texcoords,
+        texcoord_indices,
+        normal_indices,
+        colors: vec![],
+    };
+    if mesh.normals.is_empty() && generate_normal.is_some() {
+        // todo!()
+        println!("computing normals for {}", m.name);
+        compute_normals(&mut imported, generate_normal.unwrap());
+    }
+
+    // let mut next_face = 0;
+    // for f in 0..mesh.num_face_indices.len() {
+    //     assert!(mesh.num_face_indices[f] == 3);
+    //     let end = next_face + mesh.num_face_indices[f] as usize;
+    //     let face_indices: Vec<_> = mesh.indices[next_face..end].iter().collect();
+    //     println!("    face[{}] = {:?}", f, face_indices);
+    //     next_face = end;
+    // }
+    imported
+}
 pub fn load_model(
     obj_file: &str,
     generate_normal: Option<f32>,
@@ -594,7 +1096,6 @@ pub fn load_model(
     // println!("# of models: {}", models.len());
     // println!("# of materials: {}", materials.len());
     for (_i, m) in models.iter().enumerate() {
-        let mesh = &m.mesh;
         if m.name.is_empty() {
             log::error!(
                 "{} has model with empty name! all model must have a name",
@@ -602,89 +1103,147 @@ pub fn load_model(
             );
             exit(1);
         }
-        // println!("model[{}].name = \'{}\'", i, m.name);
-        // println!("model[{}].mesh.material_id = {:?}", i, mesh.material_id);
-
-        // println!(
-        //     "Size of model[{}].num_face_indices: {}",
-        //     i,
-        //     mesh.num_face_indices.len()
-        // );
-        let mut vertices = vec![];
-        let mut normals = vec![];
-        let mut texcoords = vec![];
-        // let mut indices = vec![];
-        assert!(mesh.positions.len() % 3 == 0);
-        for v in 0..mesh.positions.len() / 3 {
-            vertices.push([
-                mesh.positions[3 * v],
-                mesh.positions[3 * v + 1],
-                mesh.positions[3 * v + 2],
-            ]);
-        }
-        let mut indices = vec![];
-        let mut normal_indices = vec![];
-        let mut texcoord_indices = vec![];
-        for f in 0..mesh.indices.len() / 3 {
-            indices.push([
-                mesh.indices[3 * f],
-                mesh.indices[3 * f + 1],
-                mesh.indices[3 * f + 2],
-            ]);
-        }
-        if !mesh.normals.is_empty() && generate_normal.is_none() {
-            for i in 0..mesh.normals.len() / 3 {
-                normals.push([
-                    mesh.normals[3 * i],
-                    mesh.normals[3 * i + 1],
-                    mesh.normals[3 * i + 2],
-                ]);
-            }
-            for i in 0..mesh.normal_indices.len() / 3 {
-                normal_indices.push([
-                    mesh.normal_indices[3 * i],
-                    mesh.normal_indices[3 * i + 1],
-                    mesh.normal_indices[3 * i + 2],
-                ]);
-            }
-        }
-        if !mesh.texcoords.is_empty() {
-            for i in 0..mesh.texcoords.len() / 2 {
-                texcoords.push([mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]]);
-            }
-            for i in 0..mesh.texcoord_indices.len() / 3 {
-                texcoord_indices.push([
-                    mesh.texcoord_indices[3 * i],
-                    mesh.texcoord_indices[3 * i + 1],
-                    mesh.texcoord_indices[3 * i + 2],
-                ]);
-            }
-        }
-        let mut imported = TriangleMesh {
-            name: m.name.clone(),
-            vertices,
-            normals,
-            indices,
-            texcoords,
-            texcoord_indices,
-            normal_indices,
-        };
-        if mesh.normals.is_empty() && generate_normal.is_some() {
-            // todo!()
-            println!("computing normals for {}", m.name);
-            compute_normals(&mut imported, generate_normal.unwrap());
-        }
-
-        // let mut next_face = 0;
-        // for f in 0..mesh.num_face_indices.len() {
-        //     assert!(mesh.num_face_indices[f] == 3);
-        //     let end = next_face + mesh.num_face_indices[f] as usize;
-        //     let face_indices: Vec<_> = mesh.indices[next_face..end].iter().collect();
-        //     println!("    face[{}] = {:?}", f, face_indices);
-        //     next_face = end;
-        // }
-        imported_models.push(imported);
+        imported_models.push(mesh_from_obj_model(m, generate_normal));
     }
 
     (imported_models, models, materials)
 }
+
+mod test {
+    #[test]
+    fn test_instance_transform() {
+        use super::*;
+        let mesh = TriangleMesh {
+            name: "triangle".into(),
+            vertices: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            normals: vec![],
+            texcoords: vec![],
+            indices: vec![[0, 1, 2]],
+            normal_indices: vec![],
+            texcoord_indices: vec![],
+            colors: vec![],
+        };
+        let transform = Transform::from_matrix(
+            &(Mat4::from_translation(vec3(0.0, 0.0, -5.0)) * Mat4::from_scale(Vec3::splat(2.0))),
+        );
+        let ray = Ray::spawn(vec3(0.5, 0.5, 0.0), vec3(0.0, 0.0, -1.0));
+        let (t_world, _) = mesh
+            .instance_triangle(0, Some(&transform))
+            .intersect(&ray)
+            .unwrap();
+        let (t_object, uv) = mesh
+            .triangle(0)
+            .intersect(&transform.inverse().transform_ray(&ray))
+            .unwrap();
+        assert!((t_world - 5.0).abs() < 1e-4);
+        assert!((t_world - t_object).abs() < 1e-4);
+        assert!((uv - vec2(0.25, 0.25)).length() < 1e-4);
+        assert!((mesh.instance_area(Some(&transform)) - 4.0 * mesh.area()).abs() < 1e-4);
+    }
+    #[test]
+    fn test_cutout() {
+        use super::*;
+        use crate::texture::ConstantFloatTexture;
+        let mesh = TriangleMesh {
+            name: "triangle".into(),
+            vertices: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            normals: vec![],
+            texcoords: vec![],
+            indices: vec![[0, 1, 2]],
+            normal_indices: vec![],
+            texcoord_indices: vec![],
+            colors: vec![],
+        };
+        let rays: Vec<_> = (0..1000)
+            .map(|i| {
+                let x = (i % 40) as f32 / 100.0 + 0.01;
+                let y = (i / 40) as f32 / 100.0 + 0.01;
+                Ray::spawn(vec3(x, y, 1.0), vec3(0.0, 0.0, -1.0))
+            })
+            .collect();
+        let count = |opacity: Option<&dyn FloatTexture>| {
+            rays.iter()
+                .filter(|ray| {
+                    let (_, uv) = mesh.triangle(0).intersect(ray).unwrap();
+                    let opaque = mesh.is_opaque(opacity, 0, uv, ray);
+                    // the same ray must always get the same answer
+                    assert_eq!(opaque, mesh.is_opaque(opacity, 0, uv, ray));
+                    opaque
+                })
+                .count()
+        };
+        assert_eq!(count(None), rays.len());
+        assert_eq!(count(Some(&ConstantFloatTexture(1.0))), rays.len());
+        assert_eq!(count(Some(&ConstantFloatTexture(0.0))), 0);
+        let half = count(Some(&ConstantFloatTexture(0.5)));
+        assert!(half > 400 && half < 600, "{}", half);
+    }
+    #[test]
+    fn test_mesh_format() {
+        use super::*;
+        let mesh = TriangleMesh {
+            name: "quad".into(),
+            vertices: vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+            normals: vec![[0.0, 0.0, 1.0]],
+            texcoords: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+            normal_indices: vec![[0, 0, 0], [0, 0, 0]],
+            texcoord_indices: vec![[0, 1, 2], [0, 2, 3]],
+            colors: vec![],
+        };
+        assert_eq!(
+            mesh.flags(),
+            MeshFlags::NORMALS | MeshFlags::TEXCOORDS | MeshFlags::REUSE_TEXCOORD_INDICES
+        );
+        let mut data = vec![];
+        mesh.encode(&mut data).unwrap();
+        assert_eq!(data[..8], MESH_MAGIC);
+        let decoded = TriangleMesh::decode(&mut data.as_slice()).unwrap();
+        assert_eq!(decoded.vertices, mesh.vertices);
+        assert_eq!(decoded.normal_indices, mesh.normal_indices);
+        assert_eq!(decoded.texcoord_indices, mesh.texcoord_indices);
+
+        // unversioned files store every field without a header
+        let mut legacy = vec![];
+        mesh.name.encode(&mut legacy).unwrap();
+        mesh.vertices.encode(&mut legacy).unwrap();
+        mesh.normals.encode(&mut legacy).unwrap();
+        mesh.texcoords.encode(&mut legacy).unwrap();
+        mesh.indices.encode(&mut legacy).unwrap();
+        mesh.normal_indices.encode(&mut legacy).unwrap();
+        mesh.texcoord_indices.encode(&mut legacy).unwrap();
+        let decoded = TriangleMesh::decode(&mut legacy.as_slice()).unwrap();
+        assert_eq!(decoded.name, "quad");
+        assert_eq!(decoded.indices, mesh.indices);
+
+        assert!(TriangleMesh::decode(&mut &data[..data.len() - 4]).is_err());
+        let mut corrupted = data.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        assert!(TriangleMesh::decode(&mut corrupted.as_slice()).is_err());
+
+        let mut bad = mesh.clone();
+        bad.indices[1] = [0, 2, 4];
+        let mut data = vec![];
+        bad.encode(&mut data).unwrap();
+        assert!(TriangleMesh::decode(&mut data.as_slice()).is_err());
+        bad.indices[1] = [0, 2, 3];
+        bad.normal_indices.pop();
+        assert!(bad.validate().is_err());
+
+        let mut colored = mesh.clone();
+        colored.colors = vec![[1.0, 0.5, 0.0]; 4];
+        let mut data = vec![];
+        colored.encode(&mut data).unwrap();
+        let decoded = TriangleMesh::decode(&mut data.as_slice()).unwrap();
+        assert!(decoded.flags().contains(MeshFlags::COLORS));
+        assert_eq!(decoded.colors, colored.colors);
+        assert_eq!(decoded.colors(1)[2], vec3(1.0, 0.5, 0.0));
+        colored.colors.pop();
+        assert!(colored.validate().is_err());
+    }
+}
@@ -7,6 +7,7 @@ use crate::{
 };
 use akari_const::{CIE_LAMBDA_MAX, CIE_LAMBDA_MIN, CIE_SAMPLES, CIE_Y};
 use lazy_static::lazy_static;
+use util::RobustSum;
 pub const SPECTRUM_SAMPLES: usize = 4;
 #[derive(Clone, Copy, Debug, Default)]
 pub struct SampledSpectrum {
@@ -44,6 +45,20 @@ mod test {
         use crate::spectrum::cie_y_integral;
         assert_almost_eq!(cie_y_integral() as f64, CIE_Y_INTEGRAL as f64, 0.001);
     }
+    #[test]
+    fn test_emission_spectra() {
+        use super::*;
+        let bb = BlackbodySpectrum::new(6500.0);
+        let peak = 2.8977721e-3 / 6500.0 * 1e9;
+        assert!((bb.evaluate(peak) - 1.0).abs() < 1e-4);
+        assert!(bb.evaluate(peak - 20.0) < 1.0 && bb.evaluate(peak + 20.0) < 1.0);
+        // named illuminants are normalized to unit luminance
+        let d65 = named_spectrum("stdillum-D65").unwrap();
+        assert!((spectrum_to_y(d65.as_ref()) - 1.0).abs() < 1e-2);
+        assert!(named_spectrum("stdillum-F1").is_none());
+        let flat = PiecewiseLinearSpectrum::from_interleaved(&[500.0, 1.0, 600.0, 1.0], true);
+        assert!((spectrum_to_y(&flat) - 1.0).abs() < 1e-2);
+    }
 }
 pub const CIE_Y_INTEGRAL: f32 = 106.85694885253906;
 pub const INV_CIE_Y_INTEGRAL: f32 = 1.0 / CIE_Y_INTEGRAL;
@@ -225,6 +240,66 @@ impl PiecewiseLinearSpectrum {
 pub trait Spectrum: AsAny + Send + Sync {
     fn sample(&self, lambda: &SampledWavelengths) -> SampledSpectrum;
 }
+// Planck's law, lambda in nm
+pub fn blackbody(lambda: f32, temperature: f32) -> f32 {
+    if temperature <= 0.0 {
+        return 0.0;
+    }
+    const C: f64 = 299792458.0;
+    const H: f64 = 6.62606957e-34;
+    const KB: f64 = 1.3806488e-23;
+    let l = lambda as f64 * 1e-9;
+    let le = (2.0 * H * C * C) / (l.powi(5) * ((H * C) / (l * KB * temperature as f64)).exp_m1());
+    le as f32
+}
+// blackbody emission normalized to a peak value of 1
+#[derive(Clone, Copy, Debug)]
+pub struct BlackbodySpectrum {
+    temperature: f32,
+    norm: f32,
+}
+impl BlackbodySpectrum {
+    pub fn new(temperature: f32) -> Self {
+        // Wien's displacement law
+        let lambda_max = 2.8977721e-3 / temperature * 1e9;
+        Self {
+            temperature,
+            norm: 1.0 / blackbody(lambda_max, temperature),
+        }
+    }
+    pub fn evaluate(&self, lambda: f32) -> f32 {
+        blackbody(lambda, self.temperature) * self.norm
+    }
+}
+impl Spectrum for BlackbodySpectrum {
+    fn sample(&self, swl: &SampledWavelengths) -> SampledSpectrum {
+        SampledSpectrum::new(vec4(
+            self.evaluate(swl[0]),
+            self.evaluate(swl[1]),
+            self.evaluate(swl[2]),
+            self.evaluate(swl[3]),
+        ))
+    }
+}
+// luminance of a spectrum
+pub fn spectrum_to_y(s: &dyn Spectrum) -> f32 {
+    let mut sum = RobustSum::new(0.0);
+    for i in (0..CIE_SAMPLES).step_by(SPECTRUM_SAMPLES) {
+        let l = |j: usize| CIE_LAMBDA_MIN + (i + j).min(CIE_SAMPLES - 1) as f32;
+        let swl = SampledWavelengths {
+            lambda: vec4(l(0), l(1), l(2), l(3)),
+            pdf: Vec4::ONE,
+            secondary_terminated: false,
+        };
+        let v = s.sample(&swl).values();
+        for j in 0..SPECTRUM_SAMPLES {
+            if i + j < CIE_SAMPLES {
+                sum.add(v[j] * CIE_Y[i + j]);
+            }
+        }
+    }
+    sum.sum() / CIE_Y_INTEGRAL
+}
 pub fn inner_product<F1: Function1D, F2: Function1D>(
     s1: &GenericSpectrum<F1>,
     s2: &GenericSpectrum<F2>,
@@ -243,6 +318,9 @@ fn init_spectrum_data() -> HashMap<&'static str, Arc<dyn Spectrum>> {
 lazy_static! {
     static ref NAMED_SPECTRUM: HashMap<&'static str, Arc<dyn Spectrum>> = init_spectrum_data();
 }
+pub fn named_spectrum(name: &str) -> Option<Arc<dyn Spectrum>> {
+    NAMED_SPECTRUM.get(name).cloned()
+}
 pub fn spectrum_from_name(name: &str) -> &'static dyn Spectrum {
     NAMED_SPECTRUM
         .get(name)
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use akari_common::glam::Vec4Swizzles;
 use util::RobustSum;
 
@@ -10,12 +12,15 @@ use crate::{
 #[derive(Clone, Copy)]
 pub struct ShadingPoint {
     pub texcoord: Vec2,
+    // interpolated vertex color, white where there is none
+    pub color: Vec3,
 }
 impl ShadingPoint {
     pub fn from_rayhit(shape: &dyn Shape, ray_hit: RayHit) -> Self {
-        let triangle = shape.shading_triangle(ray_hit.prim_id);
+        let triangle = shape.shading_triangle_at(ray_hit.prim_id, ray_hit.time);
         Self {
             texcoord: triangle.texcoord(ray_hit.uv),
+            color: triangle.color(ray_hit.uv),
         }
     }
 }
@@ -74,6 +79,151 @@ impl SpectrumTexture for ConstantRgbTexture {
     }
 }
 
+// the interpolated vertex color of the mesh, linear rgb
+pub struct VertexColorTexture {
+    colorspace: RgbColorSpace,
+}
+impl VertexColorTexture {
+    pub fn new(colorspace: RgbColorSpace) -> Self {
+        Self { colorspace }
+    }
+}
+impl SpectrumTexture for VertexColorTexture {
+    fn evaluate(&self, sp: &ShadingPoint, lambda: &SampledWavelengths) -> SampledSpectrum {
+        let rgb = sp.color.max(Vec3::ZERO);
+        let scale = rgb.max_element().max(1.0);
+        let rep = self.colorspace.rgb2spec(rgb / scale);
+        rep.sample(lambda) * scale
+    }
+    // meshes without colors are white
+    fn power(&self) -> f32 {
+        1.0
+    }
+    fn colorspace(&self) -> Option<RgbColorSpace> {
+        Some(self.colorspace)
+    }
+}
+
+/// Colorspace of the values stored in an image file, as named by `colorspace` in the scene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageColorSpace {
+    SRgb,
+    LinearSRgb,
+    AcesCg,
+    Aces2065_1,
+    Rec2020,
+    DCIP3,
+}
+impl ImageColorSpace {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "srgb" => Some(Self::SRgb),
+            "linear" | "srgb-linear" => Some(Self::LinearSRgb),
+            "acescg" => Some(Self::AcesCg),
+            "aces2065-1" => Some(Self::Aces2065_1),
+            "rec2020" => Some(Self::Rec2020),
+            "dci-p3" => Some(Self::DCIP3),
+            _ => None,
+        }
+    }
+    // the colorspace used for spectral uplifting, ACEScg is converted to ACES2065-1
+    pub fn rgb_colorspace_id(&self) -> RgbColorSpaceId {
+        match self {
+            Self::SRgb | Self::LinearSRgb => RgbColorSpaceId::SRgb,
+            Self::AcesCg | Self::Aces2065_1 => RgbColorSpaceId::Aces2065_1,
+            Self::Rec2020 => RgbColorSpaceId::Rec2020,
+            Self::DCIP3 => RgbColorSpaceId::DCIP3,
+        }
+    }
+    // converts a stored value to linear rgb in the primaries of `rgb_colorspace_id`.
+    // float data is always linear, the srgb transfer function only applies to integer data
+    pub fn to_linear(&self, rgb: Vec3, integer: bool) -> Vec3 {
+        match self {
+            Self::SRgb if integer => srgb_to_linear(rgb),
+            Self::AcesCg => acescg_to_aces2065_1(rgb),
+            _ => rgb,
+        }
+    }
+    // decodes an 8/16 bit image, 8 bit srgb data stays at 8 bit and everything else
+    // is stored as fp32 so 16 bit inputs keep their precision
+    pub fn decode_image(&self, image: &akari_common::image::DynamicImage) -> TiledImage {
+        use akari_common::image::ColorType;
+        let is_8bit = matches!(
+            image.color(),
+            ColorType::L8
+                | ColorType::La8
+                | ColorType::Rgb8
+                | ColorType::Rgba8
+                | ColorType::Bgr8
+                | ColorType::Bgra8
+        );
+        let rgb = image.to_rgb16();
+        TiledImage::from_fn(
+            rgb.width(),
+            rgb.height(),
+            if is_8bit && *self == Self::SRgb {
+                util::image::PixelFormat::SRgb8
+            } else {
+                util::image::PixelFormat::Rgb32f
+            },
+            |x, y| {
+                let px = rgb.get_pixel(x, y);
+                let v = vec3(px[0] as f32, px[1] as f32, px[2] as f32) / 65535.0;
+                self.to_linear(v, true).extend(1.0)
+            },
+        )
+    }
+    pub fn read_exr<R: std::io::Read + std::io::Seek>(
+        &self,
+        reader: R,
+    ) -> akari_common::exr::error::Result<TiledImage> {
+        use akari_common::exr::prelude::*;
+        let colorspace = *self;
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .rgba_channels(
+                |resolution, _: &RgbaChannels| {
+                    TiledImage::new(
+                        resolution.width() as u32,
+                        resolution.height() as u32,
+                        util::image::PixelFormat::Rgb32f,
+                    )
+                },
+                move |image: &mut TiledImage, pos: Vec2<usize>, (r, g, b, _): (f32, f32, f32, f32)| {
+                    let rgb = colorspace.to_linear(vec3(r, g, b), false);
+                    image.store(uvec2(pos.x() as u32, pos.y() as u32), rgb.extend(1.0));
+                },
+            )
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(reader)?;
+        Ok(image.layer_data.channel_data.pixels)
+    }
+}
+// constant emission given as a spectrum, not tied to any rgb colorspace
+pub struct ConstantSpectrumTexture {
+    spectrum: Arc<dyn Spectrum>,
+    scale: f32,
+    y: f32,
+}
+impl ConstantSpectrumTexture {
+    pub fn new(spectrum: Arc<dyn Spectrum>, scale: f32) -> Self {
+        let y = spectrum_to_y(spectrum.as_ref());
+        Self { spectrum, scale, y }
+    }
+}
+impl SpectrumTexture for ConstantSpectrumTexture {
+    fn evaluate(&self, _sp: &ShadingPoint, lambda: &SampledWavelengths) -> SampledSpectrum {
+        self.spectrum.sample(lambda) * self.scale
+    }
+    fn power(&self) -> f32 {
+        self.y * self.scale
+    }
+    fn colorspace(&self) -> Option<RgbColorSpace> {
+        None
+    }
+}
 
 pub struct ImageSpectrumTexture {
     image: TiledImage,
@@ -81,6 +231,14 @@ pub struct ImageSpectrumTexture {
     invert_y: bool,
 }
 impl ImageSpectrumTexture {
+    // `image` holds linear rgb in the primaries of `colorspace`
+    pub fn new(image: TiledImage, colorspace: RgbColorSpace, invert_y: bool) -> Self {
+        Self {
+            image,
+            colorspace,
+            invert_y,
+        }
+    }
     pub fn from_rgb_image(image: &akari_common::image::RgbImage, invert_y: bool) -> Self {
         let colorspace = RgbColorSpace::new(RgbColorSpaceId::SRgb);
         Self {
@@ -106,8 +264,10 @@ impl SpectrumTexture for ImageSpectrumTexture {
             tc.y = 1.0 - tc.y;
         }
         let rgba = self.image.loadf(tc, util::image::WrappingMode::Repeat);
-        let rep = self.colorspace.rgb2spec(rgba.xyz());
-        rep.sample(lambda)
+        let rgb = rgba.xyz().max(Vec3::ZERO);
+        let scale = rgb.max_element().max(1.0);
+        let rep = self.colorspace.rgb2spec(rgb / scale);
+        rep.sample(lambda) * scale
     }
 
     fn power(&self) -> f32 {
@@ -118,7 +278,7 @@ impl SpectrumTexture for ImageSpectrumTexture {
                     .image
                     .load(uvec2(x, y).as_ivec2(), util::image::WrappingMode::Clamp)
                     .xyz();
-                let xyz = srgb_to_xyz(rgb);
+                let xyz = self.colorspace.to_xyz(rgb.max(Vec3::ZERO));
                 sum.add(xyz.y);
             }
         }
@@ -129,3 +289,174 @@ impl SpectrumTexture for ImageSpectrumTexture {
         Some(self.colorspace)
     }
 }
+
+pub struct ImageFloatTexture {
+    image: TiledImage,
+    channel: Option<usize>,
+    invert_y: bool,
+}
+impl ImageFloatTexture {
+    // bakes the selected channel (or the average of rgb if None) into a single channel image
+    pub fn from_image(
+        image: &akari_common::image::DynamicImage,
+        channel: Option<usize>,
+        invert_y: bool,
+    ) -> Self {
+        use akari_common::image::ColorType;
+        let is_8bit = matches!(
+            image.color(),
+            ColorType::L8
+                | ColorType::La8
+                | ColorType::Rgb8
+                | ColorType::Rgba8
+                | ColorType::Bgr8
+                | ColorType::Bgra8
+        );
+        let rgba = image.to_rgba16();
+        Self {
+            image: TiledImage::from_fn(
+                rgba.width(),
+                rgba.height(),
+                if is_8bit {
+                    util::image::PixelFormat::R8
+                } else {
+                    util::image::PixelFormat::R16f
+                },
+                |x, y| {
+                    let px = rgba.get_pixel(x, y);
+                    let v = match channel {
+                        Some(c) => px[c] as f32,
+                        None => (px[0] as f32 + px[1] as f32 + px[2] as f32) / 3.0,
+                    } / 65535.0;
+                    Vec3::splat(v).extend(1.0)
+                },
+            ),
+            channel: None,
+            invert_y,
+        }
+    }
+    pub fn from_tiled_image(image: TiledImage, channel: Option<usize>, invert_y: bool) -> Self {
+        Self {
+            image,
+            channel,
+            invert_y,
+        }
+    }
+    pub fn image(&self) -> &TiledImage {
+        &self.image
+    }
+    fn select(&self, rgba: Vec4) -> f32 {
+        match self.channel {
+            Some(c) => rgba[c],
+            None => (rgba.x + rgba.y + rgba.z) / 3.0,
+        }
+    }
+}
+impl FloatTexture for ImageFloatTexture {
+    fn evaluate(&self, sp: &ShadingPoint) -> f32 {
+        let mut tc = sp.texcoord;
+        if self.invert_y {
+            tc.y = 1.0 - tc.y;
+        }
+        self.select(self.image.loadf(tc, util::image::WrappingMode::Repeat))
+    }
+
+    fn power(&self) -> f32 {
+        let mut sum = RobustSum::new(0.0);
+        for y in 0..self.image.dimension().y {
+            for x in 0..self.image.dimension().x {
+                sum.add(
+                    self.select(
+                        self.image
+                            .load(uvec2(x, y).as_ivec2(), util::image::WrappingMode::Clamp),
+                    ),
+                );
+            }
+        }
+        sum.sum() / (self.image.dimension().x * self.image.dimension().y) as f32
+    }
+}
+// tangent space normal map, rgb = (n + 1) / 2 with +z along the surface normal
+// and +y along increasing v (OpenGL convention)
+pub struct NormalMapTexture {
+    image: TiledImage,
+    invert_y: bool,
+}
+impl NormalMapTexture {
+    // normal maps hold directions, 8 bit images are not sRGB decoded
+    pub fn from_image(image: &akari_common::image::DynamicImage, invert_y: bool) -> Self {
+        let rgba = image.to_rgba16();
+        Self {
+            image: TiledImage::from_fn(
+                rgba.width(),
+                rgba.height(),
+                util::image::PixelFormat::Rgb16f,
+                |x, y| {
+                    let px = rgba.get_pixel(x, y);
+                    vec3(px[0] as f32, px[1] as f32, px[2] as f32).extend(65535.0) / 65535.0
+                },
+            ),
+            invert_y,
+        }
+    }
+    pub fn evaluate(&self, sp: &ShadingPoint) -> Vec3 {
+        let mut tc = sp.texcoord;
+        if self.invert_y {
+            tc.y = 1.0 - tc.y;
+        }
+        let rgb = self
+            .image
+            .loadf(tc, util::image::WrappingMode::Repeat)
+            .xyz();
+        let n = rgb * 2.0 - Vec3::ONE;
+        if n.z <= 0.0 || n.length_squared() == 0.0 {
+            Vec3::Z
+        } else {
+            n.normalize()
+        }
+    }
+}
+mod test {
+    #[test]
+    fn test_image_float_texture_channel() {
+        use super::*;
+        use akari_common::image::{DynamicImage, ImageBuffer, Rgb, RgbImage};
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([255, 51, 0])));
+        let sp = ShadingPoint {
+            texcoord: vec2(0.3, 0.6),
+            color: Vec3::ONE,
+        };
+        let g = ImageFloatTexture::from_image(&img, Some(1), true);
+        assert!((g.evaluate(&sp) - 0.2).abs() < 1e-2);
+        assert!((g.power() - 0.2).abs() < 1e-2);
+        let avg = ImageFloatTexture::from_image(&img, None, true);
+        assert!((avg.evaluate(&sp) - 0.4).abs() < 1e-2);
+
+        // 16 bit images keep their precision in a single fp16 channel
+        let img = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(4, 4, Rgb([0, 1000, 0])));
+        let g = ImageFloatTexture::from_image(&img, Some(1), true);
+        assert!(g.image().metadata().format == util::image::PixelFormat::R16f);
+        assert!((g.evaluate(&sp) - 1000.0 / 65535.0).abs() < 1e-5);
+    }
+    #[test]
+    fn test_image_colorspace_decode() {
+        use super::*;
+        use akari_common::image::{DynamicImage, ImageBuffer, Rgb};
+        // 16 bit values that would collapse to the same 8 bit value
+        let img = DynamicImage::ImageRgb16(ImageBuffer::from_fn(2, 1, |x, _| {
+            Rgb([1000 + x as u16 * 100, 0, 65535])
+        }));
+        let linear = ImageColorSpace::from_name("linear").unwrap();
+        let decoded = linear.decode_image(&img);
+        let a = decoded.load(uvec2(0, 0).as_ivec2(), util::image::WrappingMode::Clamp);
+        let b = decoded.load(uvec2(1, 0).as_ivec2(), util::image::WrappingMode::Clamp);
+        assert!((a.x - 1000.0 / 65535.0).abs() < 1e-6);
+        assert!((b.x - a.x - 100.0 / 65535.0).abs() < 1e-6);
+
+        // ACEScg white maps to ACES2065-1 white
+        let acescg = ImageColorSpace::from_name("ACEScg").unwrap();
+        assert!(acescg.rgb_colorspace_id() == RgbColorSpaceId::Aces2065_1);
+        let white = acescg.to_linear(Vec3::ONE, false);
+        assert!((white - Vec3::ONE).abs().max_element() < 1e-4);
+    }
+}
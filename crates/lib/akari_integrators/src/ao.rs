@@ -24,8 +24,7 @@ impl Integrator for RTAO {
             for _ in 0..self.spp {
                 sampler.start_next_sample();
                 let lambda = SampledWavelengths::sample_visible(sampler.next1d());
-                let (mut ray, _ray_weight) =
-                    scene.camera.generate_ray(pixel, &mut sampler, &lambda);
+                let (mut ray, ray_weight) = scene.camera.generate_ray(pixel, &mut sampler, &lambda);
                 let mut li = SampledSpectrum::zero();
                 {
                     if let Some(si) = scene.intersect(&ray) {
@@ -39,13 +38,15 @@ impl Integrator for RTAO {
 
                         // li = SampledSpectrum{samples:wi};
                         let p = ray.at(si.t);
-                        ray = Ray::spawn(p, wi).offset_along_normal(ng);
+                        ray = Ray::spawn(p, wi)
+                            .offset_along_normal(ng)
+                            .with_time(ray.time);
                         if !scene.occlude(&ray) {
                             li = SampledSpectrum::one();
                         }
                     }
                 }
-                acc_li = acc_li + lambda.cie_xyz(li);
+                acc_li = acc_li + lambda.cie_xyz(li * ray_weight);
             }
             acc_li = acc_li / (self.spp as f32);
             {
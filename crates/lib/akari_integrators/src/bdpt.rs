@@ -72,6 +72,7 @@ impl Integrator for Bdpt {
                         scene,
                         &mut sampler,
                         &mut lambda,
+                        camera_path.time,
                         self.max_depth + 1,
                         &mut light_path,
                         arena,
@@ -44,6 +44,8 @@ pub struct Path<'a> {
     vertices: *mut Vertex<'a>,
     len: usize,
     capacity: usize,
+    // the time every vertex is seen at, sampled by the camera within its shutter interval
+    pub time: f32,
 }
 impl<'a> std::ops::Deref for Path<'a> {
     type Target = [Vertex<'a>];
@@ -61,6 +63,7 @@ impl<'a> Path<'a> {
         Self {
             capacity,
             len: 0,
+            time: 0.0,
             vertices: arena
                 .alloc_layout(std::alloc::Layout::array::<Vertex<'a>>(capacity).unwrap())
                 .as_ptr() as *mut Vertex<'a>,
@@ -154,6 +157,16 @@ impl<'a> Vertex<'a> {
         v.base_mut().pdf_fwd = pdf_fwd;
         v
     }
+    // lights at infinity, their vertices are placed far away along the direction of emission
+    pub fn is_infinite_light(&self) -> bool {
+        match self {
+            Self::Light(v) => v
+                .light
+                .flags()
+                .intersects(LightFlags::INFINITE | LightFlags::DELTA_DIRECTION),
+            _ => false,
+        }
+    }
     pub fn is_delta_light(&self) -> bool {
         match self {
             Self::Light(v) => v.light.flags().intersects(LightFlags::DELTA), //(v.light.flags() | LightFlags::DELTA) != LightFlags::NONE,
@@ -201,6 +214,9 @@ impl<'a> Vertex<'a> {
     }
     pub fn pdf_emission_origin(&self, scene: &Scene, next: &Vertex) -> f32 {
         match self {
+            Vertex::Light(_) if self.is_infinite_light() => {
+                infinite_light_density(scene, (self.p() - next.p()).normalize())
+            }
             Vertex::Light(v) => {
                 let light_pdf = scene.light_distr.pdf(v.light);
                 let (pdf_pos, _) = v
@@ -213,6 +229,9 @@ impl<'a> Vertex<'a> {
     }
     pub fn pdf_direct_origin(&self, scene: &Scene, next: &Vertex) -> f32 {
         match self {
+            Vertex::Light(_) if self.is_infinite_light() => {
+                infinite_light_density(scene, (self.p() - next.p()).normalize())
+            }
             Vertex::Light(v) => {
                 let light_pdf = scene.light_distr.pdf(v.light);
                 let (pdf_pos, _) = v.light.pdf_direct(
@@ -231,8 +250,17 @@ impl<'a> Vertex<'a> {
         match self {
             Vertex::Light(v) => {
                 let ray = Ray::spawn_to(self.p(), next.p());
-                let (_pdf_pos, pdf_dir) = v.light.pdf_emission(&ray, self.n());
-                self.convert_pdf_to_area(pdf_dir, next)
+                let (pdf_pos, pdf_dir) = v.light.pdf_emission(&ray, self.n());
+                if self.is_infinite_light() {
+                    // rays leaving an infinite light are sampled by position on a disk
+                    if next.on_surface() {
+                        pdf_pos * next.n().dot(ray.d).abs()
+                    } else {
+                        pdf_pos
+                    }
+                } else {
+                    self.convert_pdf_to_area(pdf_dir, next)
+                }
             }
             _ => unreachable!(),
         }
@@ -284,11 +312,18 @@ impl<'a> Vertex<'a> {
     }
     pub fn le(
         &self,
-        _scene: &'a Scene,
+        scene: &'a Scene,
         prev: &Vertex<'a>,
         lambda: &SampledWavelengths,
     ) -> SampledSpectrum {
-        if let Some(v) = self.as_light() {
+        if self.is_infinite_light() {
+            let ray = Ray::spawn(prev.p(), (self.p() - prev.p()).normalize());
+            let mut le = SampledSpectrum::zero();
+            for light in &scene.infinite_lights {
+                le += light.emission(&ray, lambda);
+            }
+            le
+        } else if let Some(v) = self.as_light() {
             let mut ray = Ray::spawn_to(prev.p(), self.p());
             ray.tmax *= 1.0 + 1e-3;
             v.light.emission(&ray, lambda)
@@ -297,6 +332,19 @@ impl<'a> Vertex<'a> {
         }
     }
     pub fn convert_pdf_to_area(&self, mut pdf: f32, v2: &Vertex) -> f32 {
+        // infinite lights keep their densities in solid angle
+        if v2.is_infinite_light() {
+            return pdf;
+        }
+        // rays leaving a delta direction camera are sampled by position on the film
+        if let Some(v) = self.as_camera() {
+            if v.camera.is_delta_direction() {
+                if v2.on_surface() {
+                    pdf *= v2.n().dot((v2.p() - self.p()).normalize()).abs();
+                }
+                return pdf;
+            }
+        }
         let w = v2.p() - self.p();
         let inv_dist2 = 1.0 / w.length_squared();
         if v2.on_surface() {
@@ -317,6 +365,18 @@ impl<'a> Vertex<'a> {
     }
 }
 
+// combined solid angle density of sampling direction `w` from any of the infinite lights
+pub fn infinite_light_density(scene: &Scene, w: Vec3) -> f32 {
+    let mut pdf = 0.0;
+    for light in &scene.infinite_lights {
+        let p = ReferencePoint {
+            p: Vec3::ZERO,
+            n: w,
+        };
+        pdf += scene.light_distr.pdf(light.as_ref()) * light.pdf_direct(w, &p).1;
+    }
+    pdf
+}
 #[inline]
 pub fn correct_shading_normal(ng: Vec3, ns: Vec3, wo: Vec3, wi: Vec3, mode: TransportMode) -> f32 {
     if mode == TransportMode::LightToCamera {
@@ -406,13 +466,29 @@ pub fn random_walk<'a, 'b>(
                     pdf_rev = bsdf.evaluate_pdf(wi, wo);
                     prev.base_mut().pdf_rev = vertex.convert_pdf_to_area(pdf_rev, prev);
                 }
-                ray = Ray::spawn(p, wi).offset_along_normal(ng);
+                ray = Ray::spawn(p, wi)
+                    .offset_along_normal(ng)
+                    .with_time(ray.time);
                 beta *= bsdf_sample.f * wi.dot(ng).abs() / bsdf_sample.pdf;
                 beta *= correct_shading_normal(ng, si.ns, wo, wi, mode);
             } else {
                 break;
             }
         } else {
+            if mode == TransportMode::CameraToLight {
+                if let Some(light) = scene.infinite_lights.first() {
+                    // the vertex sits one unit past the last vertex so that the direction
+                    // towards it is the escaped ray's direction
+                    let vertex = Vertex::create_light_vertex(
+                        light.as_ref(),
+                        path[depth].p() + ray.d,
+                        -ray.d,
+                        beta,
+                        pdf_fwd,
+                    );
+                    path.push(vertex);
+                }
+            }
             break;
         }
     }
@@ -432,6 +508,7 @@ pub fn generate_camera_path<'a, 'b>(
     path.clear();
     let camera = scene.camera.as_ref();
     let (ray, beta) = camera.generate_ray(pixel, sampler, lambda);
+    path.time = ray.time;
     let vertex = Vertex::create_camera_vertex(camera, &ray, beta, 1.0);
     path.push(vertex);
     let (_pdf_pos, pdf_dir) = camera.pdf_we(&ray);
@@ -451,10 +528,12 @@ pub fn generate_camera_path<'a, 'b>(
         arena,
     );
 }
+// `time` should be that of the camera path the light path is to be connected to
 pub fn generate_light_path<'a, 'b>(
     scene: &'a Scene,
     sampler: &mut dyn Sampler,
     lambda: &mut SampledWavelengths,
+    time: f32,
     max_depth: usize,
     path: &mut Path<'b>,
     arena: &'b Bump,
@@ -465,6 +544,7 @@ pub fn generate_light_path<'a, 'b>(
         return;
     }
     path.clear();
+    path.time = time;
     let (light, light_pdf) = scene.light_distr.sample(sampler.next1d());
     let sample = light.sample_emission(sampler.next3d(), sampler.next2d(), lambda);
     let le = sample.le;
@@ -483,7 +563,7 @@ pub fn generate_light_path<'a, 'b>(
     }
     random_walk(
         scene,
-        sample.ray,
+        sample.ray.with_time(time),
         sampler,
         lambda,
         beta,
@@ -493,12 +573,25 @@ pub fn generate_light_path<'a, 'b>(
         path,
         arena,
     );
+    if path[0].is_infinite_light() {
+        // the first vertex is sampled by position on the disk, the light by direction
+        if path.len() > 1 {
+            let mut pdf_fwd = sample.pdf_pos;
+            if path[1].on_surface() {
+                pdf_fwd *= sample.ray.d.dot(path[1].n()).abs();
+            }
+            path[1].base_mut().pdf_fwd = pdf_fwd;
+        }
+        path[0].base_mut().pdf_fwd = infinite_light_density(scene, -sample.ray.d);
+    }
 }
-pub fn geometry_term(scene: &Scene, v1: &Vertex, v2: &Vertex) -> f32 {
+pub fn geometry_term(scene: &Scene, v1: &Vertex, v2: &Vertex, time: f32) -> f32 {
     let mut wi = v1.p() - v2.p();
     let dist2: f32 = wi.length_squared();
     wi /= dist2.sqrt();
-    let mut ray = Ray::spawn_to(v1.p(), v2.p()).offset_along_normal(v1.n());
+    let mut ray = Ray::spawn_to(v1.p(), v2.p())
+        .offset_along_normal(v1.n())
+        .with_time(time);
     ray.tmax *= 0.997;
     if scene.occlude(&ray) {
         0.0
@@ -683,12 +776,17 @@ where
         l = pt.beta() * pt.le(scene, &eye_path[t - 2], lambda);
     } else if t == 1 {
         let qs = &light_path[s - 1];
-        if qs.connectible() {
+        // a camera seeing along a single direction cannot be connected to a vertex at infinity
+        if qs.connectible() && !(qs.is_infinite_light() && scene.camera.is_delta_direction()) {
             let p_ref = ReferencePoint {
                 p: qs.p(),
                 n: qs.n(),
             };
-            if let Some(sample) = scene.camera.sample_wi(sampler.next2d(), &p_ref, lambda) {
+            if let Some(sample) =
+                scene
+                    .camera
+                    .sample_wi(sampler.next2d(), &p_ref, eye_path.time, lambda)
+            {
                 if sample.pdf > 0.0 && !sample.we.is_black() {
                     let v = Vertex::create_camera_vertex(
                         scene.camera.as_ref(),
@@ -741,7 +839,7 @@ where
                     l *= light_sample.wi.dot(p_ref.n).abs();
                 }
                 if !l.is_black() {
-                    if scene.occlude(&light_sample.shadow_ray) {
+                    if scene.occlude(&light_sample.shadow_ray.with_time(eye_path.time)) {
                         l *= 0.0;
                     }
                 }
@@ -761,7 +859,7 @@ where
                 * pt.f(qs, TransportMode::CameraToLight, lambda)
                 * qs.f(pt, TransportMode::LightToCamera, lambda);
             if !l.is_black() {
-                l *= geometry_term(scene, pt, qs);
+                l *= geometry_term(scene, pt, qs, eye_path.time);
             }
         }
     }
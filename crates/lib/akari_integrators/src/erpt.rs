@@ -58,7 +58,7 @@ impl Integrator for Erpt {
             for _ in 0..self.spp {
                 sampler.start_next_sample();
                 let mut lambda = SampledWavelengths::sample_visible(sampler.next1d());
-                let (ray, _ray_weight) = scene.camera.generate_ray(pixel, &mut sampler, &lambda);
+                let (ray, ray_weight) = scene.camera.generate_ray(pixel, &mut sampler, &lambda);
                 let li = PathTracer::li(
                     ray,
                     &mut lambda,
@@ -67,7 +67,7 @@ impl Integrator for Erpt {
                     self.max_depth as usize,
                     true,
                     arena,
-                );
+                ) * ray_weight;
                 let e = target_function(li, &lambda);
                 let mean_chains = e / (self.mutations_per_chain as f32 * e_d);
                 let dep_energy =
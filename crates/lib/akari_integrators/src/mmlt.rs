@@ -140,6 +140,7 @@ impl Chain {
             scene,
             &mut self.sampler,
             &mut lambda,
+            camera_path.time,
             s,
             &mut light_path,
             arena,
@@ -107,7 +107,8 @@ impl PathTracer {
                                 && light_pdf.is_finite()
                             {
                                 if !light_sample.li.is_black()
-                                    && !scene.occlude(&light_sample.shadow_ray)
+                                    && !scene
+                                        .occlude(&light_sample.shadow_ray.with_time(ray.time))
                                 {
                                     let bsdf_pdf = bsdf.evaluate_pdf(wo, light_sample.wi);
                                     let weight = if light.is_delta() {
@@ -134,7 +135,9 @@ impl PathTracer {
                         if let Some(bsdf_sample) = bsdf.sample(sampler.next2d(), wo) {
                             is_delta = bsdf_sample.flag.contains(BsdfFlags::SPECULAR);
                             let wi = bsdf_sample.wi;
-                            ray = Ray::spawn(p, wi).offset_along_normal(ng);
+                            ray = Ray::spawn(p, wi)
+                                .offset_along_normal(ng)
+                                .with_time(ray.time);
                             beta *= bsdf_sample.f * wi.dot(ns).abs() / bsdf_sample.pdf;
                             prev_bsdf_pdf = Some(bsdf_sample.pdf);
                             prev_n = Some(si.ng);
@@ -143,6 +146,30 @@ impl PathTracer {
                         }
                     }
                 } else {
+                    for light in &scene.infinite_lights {
+                        if depth == 0 {
+                            if !indirect_only {
+                                li += beta * light.emission(&ray, lambda);
+                            }
+                        } else if !indirect_only || depth > 1 {
+                            let light_pdf = scene.light_distr.pdf(light.as_ref())
+                                * light
+                                    .pdf_direct(
+                                        ray.d,
+                                        &ReferencePoint {
+                                            p: ray.o,
+                                            n: prev_n.unwrap(),
+                                        },
+                                    )
+                                    .1;
+                            let weight = if is_delta {
+                                1.0
+                            } else {
+                                mis_weight(prev_bsdf_pdf.unwrap(), light_pdf)
+                            };
+                            li += beta * light.emission(&ray, lambda) * weight;
+                        }
+                    }
                     break;
                 }
             }
@@ -177,8 +204,7 @@ impl Integrator for PathTracer {
                     if self.single_wavelength {
                         lambda.terminate_secondary();
                     }
-                    let (ray, _ray_weight) =
-                        scene.camera.generate_ray(pixel, &mut sampler, &lambda);
+                    let (ray, ray_weight) = scene.camera.generate_ray(pixel, &mut sampler, &lambda);
                     let li = Self::li(
                         ray,
                         &mut lambda,
@@ -188,7 +214,7 @@ impl Integrator for PathTracer {
                         false,
                         arena,
                     );
-                    film.add_sample(uvec2(x, y), li, lambda, 1.0);
+                    film.add_sample(uvec2(x, y), li * ray_weight, lambda, 1.0);
                 }
                 arena.reset();
             }
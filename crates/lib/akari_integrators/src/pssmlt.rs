@@ -35,7 +35,7 @@ impl Chain {
         mut lambda: SampledWavelengths,
         arena: &Bump,
     ) -> FRecord {
-        let (ray, _) = scene.camera.generate_ray(pixel, &mut self.sampler, &lambda);
+        let (ray, ray_weight) = scene.camera.generate_ray(pixel, &mut self.sampler, &lambda);
         let l = PathTracer::li(
             ray,
             &mut lambda,
@@ -44,7 +44,7 @@ impl Chain {
             self.max_depth,
             true,
             arena,
-        );
+        ) * ray_weight;
         FRecord {
             pixel,
             f: lambda.clone().cie_xyz(l).values().y.clamp(0.0, 100.0),
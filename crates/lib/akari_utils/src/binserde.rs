@@ -58,12 +58,22 @@ macro_rules! impl_binserde {
                 let mut len: [u8; 8] = [0; 8];
                 reader.read_exact(&mut len)?;
                 let len = u64::from_le_bytes(len) as usize;
-                let mut data = Vec::<$t>::with_capacity(len);
-                let slice = unsafe {
-                    std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, len * stride)
-                };
-                reader.read_exact(slice)?;
-                unsafe { data.set_len(len) }
+                // grows as the data arrives so that a corrupted length fails
+                // with an eof instead of a huge allocation
+                let chunk = (1 << 20) / stride.max(1);
+                let mut data = Vec::<$t>::with_capacity(len.min(chunk));
+                while data.len() < len {
+                    let n = (len - data.len()).min(chunk);
+                    data.reserve(n);
+                    let slice = unsafe {
+                        std::slice::from_raw_parts_mut(
+                            data.as_mut_ptr().add(data.len()) as *mut u8,
+                            n * stride,
+                        )
+                    };
+                    reader.read_exact(slice)?;
+                    unsafe { data.set_len(data.len() + n) }
+                }
                 Ok(data)
             }
         }
@@ -0,0 +1,279 @@
+use crate::binserde::{Decode, Encode};
+use crate::FileResolver;
+use akari_common::flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use akari_common::sha2::{Digest, Sha256};
+use akari_common::{log, serde_json, tempfile};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/* single file scene archive
+ * magic, format version, the manifest as json, then the deflated files back to back
+ * entry offsets are relative to the end of the manifest
+ */
+pub const BUNDLE_MAGIC: [u8; 8] = *b"AKRBNDL\0";
+// version 1 had crc32 checksums instead of digests
+pub const BUNDLE_VERSION: u32 = 2;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BundleEntry {
+    // '/' separated, relative to the scene
+    pub path: String,
+    pub offset: u64,
+    pub compressed_size: u64,
+    pub size: u64,
+    // hex sha-256 of the uncompressed content, unlike a crc it also tells entries with
+    // the same content apart from ones that were swapped or tampered with
+    pub sha256: String,
+}
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub version: u32,
+    // entry holding the scene description
+    pub scene: String,
+    pub entries: Vec<BundleEntry>,
+}
+
+fn invalid_data(what: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+// hashes everything read through it
+struct DigestReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+impl<R: Read> DigestReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+    fn hex_digest(self) -> String {
+        self.hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+fn normalize(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Collects files into a scene bundle, staging the compressed data in a temporary file
+/// until [`BundleWriter::finish`] writes the archive.
+pub struct BundleWriter {
+    data: BufWriter<File>,
+    manifest: BundleManifest,
+}
+impl BundleWriter {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            data: BufWriter::new(tempfile::tempfile()?),
+            manifest: BundleManifest {
+                version: BUNDLE_VERSION,
+                ..Default::default()
+            },
+        })
+    }
+    pub fn contains(&self, path: &str) -> bool {
+        self.manifest.entries.iter().any(|e| e.path == path)
+    }
+    pub fn add<R: Read>(&mut self, path: &str, reader: R) -> io::Result<()> {
+        if self.contains(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is already in the bundle", path),
+            ));
+        }
+        let offset = self.data.stream_position()?;
+        let mut reader = DigestReader::new(reader);
+        let mut encoder = DeflateEncoder::new(&mut self.data, Compression::default());
+        let size = io::copy(&mut reader, &mut encoder)?;
+        encoder.finish()?;
+        let compressed_size = self.data.stream_position()? - offset;
+        self.manifest.entries.push(BundleEntry {
+            path: path.into(),
+            offset,
+            compressed_size,
+            size,
+            sha256: reader.hex_digest(),
+        });
+        Ok(())
+    }
+    pub fn finish<W: Write>(self, scene: &str, writer: &mut W) -> io::Result<()> {
+        let mut manifest = self.manifest;
+        if !manifest.entries.iter().any(|e| e.path == scene) {
+            return Err(invalid_data(format!(
+                "scene {} is not in the bundle",
+                scene
+            )));
+        }
+        manifest.scene = scene.into();
+        let mut data = self.data.into_inner().map_err(|e| e.into_error())?;
+        data.seek(SeekFrom::Start(0))?;
+        writer.write_all(&BUNDLE_MAGIC)?;
+        BUNDLE_VERSION.encode(writer)?;
+        serde_json::to_string(&manifest)?.encode(writer)?;
+        io::copy(&mut data, writer)?;
+        Ok(())
+    }
+}
+
+/// Resolves files from a scene bundle written by [`BundleWriter`].
+/// Each resolved file is decompressed into an anonymous temporary file and checked against its digest.
+pub struct BundleFileResolver {
+    path: PathBuf,
+    manifest: BundleManifest,
+    entries: HashMap<String, usize>,
+    data_start: u64,
+}
+impl BundleFileResolver {
+    pub fn is_bundle(path: &Path) -> bool {
+        let mut magic = [0u8; 8];
+        File::open(path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .map_or(false, |_| magic == BUNDLE_MAGIC)
+    }
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != BUNDLE_MAGIC {
+            return Err(invalid_data(format!(
+                "{} is not a scene bundle",
+                path.display()
+            )));
+        }
+        let version = u32::decode(&mut reader)?;
+        if version > BUNDLE_VERSION {
+            return Err(invalid_data(format!(
+                "bundle version {} is newer than the supported version {}",
+                version, BUNDLE_VERSION
+            )));
+        }
+        if version < BUNDLE_VERSION {
+            return Err(invalid_data(format!(
+                "bundle version {} has no digests, pack the scene again",
+                version
+            )));
+        }
+        let manifest = String::decode(&mut reader)?;
+        let data_start = reader.stream_position()?;
+        let manifest: BundleManifest = serde_json::from_str(&manifest)?;
+        let entries = manifest
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.path.clone(), i))
+            .collect();
+        Ok(Self {
+            path: path.into(),
+            manifest,
+            entries,
+            data_start,
+        })
+    }
+    pub fn manifest(&self) -> &BundleManifest {
+        &self.manifest
+    }
+    pub fn extract(&self, path: &str) -> io::Result<File> {
+        let entry = self
+            .entries
+            .get(path)
+            .map(|i| &self.manifest.entries[*i])
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not in the bundle", path),
+                )
+            })?;
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.data_start + entry.offset))?;
+        let mut reader = DigestReader::new(DeflateDecoder::new(
+            BufReader::new(file).take(entry.compressed_size),
+        ));
+        let mut out = tempfile::tempfile()?;
+        let size = io::copy(&mut reader, &mut out)?;
+        if size != entry.size || reader.hex_digest() != entry.sha256 {
+            return Err(invalid_data(format!("{} is corrupted", path)));
+        }
+        out.seek(SeekFrom::Start(0))?;
+        Ok(out)
+    }
+    pub fn read_to_string(&self, path: &str) -> io::Result<String> {
+        let mut s = String::new();
+        self.extract(path)?.read_to_string(&mut s)?;
+        Ok(s)
+    }
+}
+impl FileResolver for BundleFileResolver {
+    fn resolve(&self, path: &Path) -> Option<File> {
+        match self.extract(&normalize(path)) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::error!("cannot read {}: {}", self.path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_bundle() {
+        use super::*;
+        let mut writer = BundleWriter::new().unwrap();
+        let mesh: Vec<u8> = (0..4096u32).flat_map(|i| (i % 7).to_le_bytes()).collect();
+        writer.add("scene.json", "{}".as_bytes()).unwrap();
+        writer.add("meshes/a.mesh", mesh.as_slice()).unwrap();
+        assert!(writer.add("scene.json", "{}".as_bytes()).is_err());
+        let mut archive = vec![];
+        writer.finish("scene.json", &mut archive).unwrap();
+
+        let path = std::env::temp_dir().join(format!("akari-bundle-{}.akb", std::process::id()));
+        std::fs::write(&path, &archive).unwrap();
+        assert!(BundleFileResolver::is_bundle(&path));
+        let bundle = BundleFileResolver::open(&path).unwrap();
+        assert_eq!(bundle.manifest().scene, "scene.json");
+        // sha-256 of "{}"
+        assert_eq!(
+            bundle.manifest().entries[0].sha256,
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+        assert_eq!(bundle.read_to_string("scene.json").unwrap(), "{}");
+        let mut extracted = vec![];
+        bundle
+            .resolve(Path::new("meshes/a.mesh"))
+            .unwrap()
+            .read_to_end(&mut extracted)
+            .unwrap();
+        assert_eq!(extracted, mesh);
+        assert!(bundle.resolve(Path::new("missing.mesh")).is_none());
+
+        // flip a byte in the compressed mesh
+        let entry = &bundle.manifest().entries[1];
+        let pos = (bundle.data_start + entry.offset + entry.compressed_size / 2) as usize;
+        archive[pos] ^= 0xff;
+        std::fs::write(&path, &archive).unwrap();
+        let bundle = BundleFileResolver::open(&path).unwrap();
+        assert!(bundle.extract("meshes/a.mesh").is_err());
+
+        // bundles from before the digests are refused
+        archive[8..12].copy_from_slice(&1u32.to_le_bytes());
+        std::fs::write(&path, &archive).unwrap();
+        assert!(BundleFileResolver::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -19,9 +19,10 @@ pub enum PixelFormat {
     Rgba16f, // linear space, fp16
     Rgb32f,  // linear space, fp32
     Rgba32f, // linear space, fp32
+    R16f,    // linear space, fp16
 }
 impl PixelFormat {
-    pub const fn formats() -> [PixelFormat; 10] {
+    pub const fn formats() -> [PixelFormat; 11] {
         [
             PixelFormat::R8,
             PixelFormat::SR8,
@@ -33,12 +34,14 @@ impl PixelFormat {
             PixelFormat::Rgba16f,
             PixelFormat::Rgba32f,
             PixelFormat::Rgba8,
+            PixelFormat::R16f,
         ]
     }
     #[inline(always)]
     pub const fn size(self) -> usize {
         match self {
             PixelFormat::R8 | PixelFormat::SR8 => 1,
+            PixelFormat::R16f => 2,
             PixelFormat::Rgb8 | PixelFormat::SRgb8 => 3,
             PixelFormat::Rgba8 | PixelFormat::SRgba8 => 4,
             PixelFormat::Rgb16f => 6,
@@ -60,6 +63,7 @@ impl PixelFormat {
             PixelFormat::Rgba16f => 4,
             PixelFormat::Rgba32f => 4,
             PixelFormat::Rgba8 => 4,
+            PixelFormat::R16f => 1,
         }
     }
 }
@@ -117,6 +121,9 @@ fn load4(bytes_: &[u8], format: PixelFormat) -> Vec4 {
     match format {
         PixelFormat::R8 => Vec3::splat(bytes[0] as f32 / 255.0).extend(1.0),
         PixelFormat::SR8 => Vec3::splat(srgb_to_linear1_u8(bytes[0])).extend(1.0),
+        PixelFormat::R16f => {
+            Vec3::splat(f16::from_le_bytes([bytes[0], bytes[1]]).to_f32()).extend(1.0)
+        }
         PixelFormat::Rgb8 => vec3(
             bytes[0] as f32 / 255.0,
             bytes[1] as f32 / 255.0,
@@ -209,6 +216,9 @@ fn store4(bytes_: &mut [u8], value: Vec4, format: PixelFormat) {
             bytes[2] = (rgba.z * 255.0).clamp(0.0, 255.0) as u8;
             bytes[3] = (rgba.w * 255.0).clamp(0.0, 255.0) as u8;
         }
+        PixelFormat::R16f => {
+            bytes[..2].copy_from_slice(&f16::from_f32(value.x).to_le_bytes());
+        }
         PixelFormat::Rgb16f => {
             let rgb: [f16; 3] = [
                 f16::from_f32(value.x),
@@ -19,6 +19,7 @@ pub mod nn_v2;
 pub mod profile;
 #[macro_use]
 pub mod binserde;
+pub mod bundle;
 pub mod cli;
 pub mod fastdiv;
 pub mod filecache;
@@ -58,6 +59,31 @@ impl Drop for CurrentDirGuard {
         std::env::set_current_dir(&self.current_dir).unwrap();
     }
 }
+// a fresh directory below the system temp dir, removed with its contents when dropped
+// so that failing tests do not leave it behind
+pub struct TempDirGuard {
+    path: PathBuf,
+}
+impl TempDirGuard {
+    // `name` is made unique per process
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("akari-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+impl std::ops::Deref for TempDirGuard {
+    type Target = std::path::Path;
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+impl Drop for TempDirGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
 pub struct ProgressBarWrapper {
     inner: Option<ProgressBar>,
 }
@@ -198,6 +224,35 @@ pub fn erf(x: f32) -> f32 {
 
 pub trait FileResolver {
     fn resolve(&self, path: &std::path::Path) -> Option<std::fs::File>;
+    // identifies the file `resolve` opens for `path`, equal for every alias of one file
+    fn canonicalize(&self, path: &std::path::Path) -> PathBuf {
+        normalize_path(path)
+    }
+    // where a file written for `path` is found by `resolve`, None if the resolver is read only
+    fn writable_path(&self, _path: &std::path::Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+// removes the `.` and `..` components of `path` without touching the file system,
+// `..` at the start of a relative path is kept
+pub fn normalize_path(path: &std::path::Path) -> PathBuf {
+    use std::path::Component;
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => out.push(".."),
+            },
+            c => out.push(c),
+        }
+    }
+    out
 }
 
 pub struct LocalFileResolver {
@@ -221,6 +276,23 @@ impl FileResolver for LocalFileResolver {
         }
         None
     }
+    // same lookup order as `resolve`, symlinks and `..` are resolved by the file system
+    fn canonicalize(&self, path: &std::path::Path) -> PathBuf {
+        std::iter::once(path.to_path_buf())
+            .chain(self.paths.iter().map(|p| p.join(path)))
+            .find_map(|p| std::fs::canonicalize(p).ok())
+            .unwrap_or_else(|| normalize_path(path))
+    }
+    // an existing file is replaced, new files go into the first search path
+    fn writable_path(&self, path: &std::path::Path) -> Option<PathBuf> {
+        let existing = std::iter::once(path.to_path_buf())
+            .chain(self.paths.iter().map(|p| p.join(path)))
+            .find(|p| p.is_file());
+        Some(existing.unwrap_or_else(|| match self.paths.first() {
+            Some(p) => p.join(path),
+            None => path.to_path_buf(),
+        }))
+    }
 }
 
 pub fn par_permute<T: Clone + Send + Sync, F: Fn(usize) -> usize + Sync + Send>(